@@ -0,0 +1,118 @@
+import os
+import re
+import subprocess
+import time
+import backend
+import config
+import fsoptions
+import units
+
+# Acceptance test of a freshly provisioned array: write and read back a test file on its mounted
+# filesystem and compare the throughput with what the members should manage together. A result far
+# below the estimate usually means something is wrong with the build, not with the disks.
+
+# Sustained sequential rate assumed per member, by kind of drive
+MEMBER_RATES = {'hdd': 120 * 1000**2, 'ssd': 450 * 1000**2, 'nvme': 1500 * 1000**2}
+
+TEST_FILE = ".liveraid-throughput-test"
+
+def member_rate(drive):
+    if drive is None:
+        return MEMBER_RATES['hdd']
+    if drive['name'].startswith("/dev/nvme"):
+        return MEMBER_RATES['nvme']
+    return MEMBER_RATES['hdd'] if drive['rotational'] else MEMBER_RATES['ssd']
+
+def expected_throughput(level, drives):
+    """
+    Estimates sequential throughput from the members: every member runs at the pace of the slowest one,
+    a single stream of writes or reads goes to the data disks of each stripe (fsoptions.data_disks(), the
+    model bench.py compares with), which for a mirror is one disk.
+    Returns: {'write': 240000000, 'read': 240000000} in bytes per second
+    """
+    rate = min((member_rate(d) for d in drives), default=0)
+    disks = max(fsoptions.data_disks(level, len(drives)) or 1, 1)
+    return {'write': rate * disks, 'read': rate * disks}
+
+def run_dd(args):
+    """
+    Runs dd and returns its throughput in bytes per second from the summary line
+    ('1073741824 bytes (1.1 GB, 1.0 GiB) copied, 4.2 s, 256 MB/s'), or None if it failed.
+    """
+    started = time.monotonic()
+    result = subprocess.run(["dd"] + args, capture_output=True, text=True)
+    if result.returncode != 0:
+        return None
+    match = re.search(r"^(\d+) bytes.*copied, ([\d.,]+) s", result.stderr, re.MULTILINE)
+    if not match:
+        return None
+    seconds = float(match.group(2).replace(",", ".")) or (time.monotonic() - started)
+    return int(match.group(1)) / max(seconds, 0.001)
+
+def measure(mount_point, size_bytes):
+    """
+    Writes size_bytes to a test file (O_DIRECT, flushed) and reads it back, then removes it.
+    Returns: {'write': 231000000.0, 'read': 342000000.0}, None for a pass that failed.
+    """
+    path = os.path.join(mount_point, TEST_FILE)
+    count = max(size_bytes // 1024**2, 1)
+    try:
+        # /dev/urandom would be the bottleneck itself, zeros are fine as long as the filesystem doesn't compress them
+        written = run_dd(["if=/dev/zero", f"of={path}", "bs=1M", f"count={count}", "oflag=direct", "conv=fsync"])
+        read = run_dd([f"if={path}", "of=/dev/null", "bs=1M", "iflag=direct"]) if written else None
+    finally:
+        if os.path.exists(path):
+            os.remove(path)
+    return {'write': written, 'read': read}
+
+def find_causes(array_name):
+    """
+    Known reasons for an array to fall short of its estimate.
+    Returns: ['the array is degraded (2/3 members)', ...]
+    """
+    causes = []
+    status = next(iter(backend.get_arrays_status([array_name])), None)
+    if status:
+        health = status['health']
+        if health in ("resyncing", "rebuilding"):
+            causes.append(f"a {status['sync']['action'] if status['sync'] else 'sync'} is running and competes with the test, run it again once it finished")
+        elif health == "degraded":
+            causes.append(f"the array is degraded ({status['working']}/{status['raid_disks']} members), parity is computed on every read")
+        for member in status['members']:
+            device = member['device']
+            start = backend.read_block_attribute(device, "start") if device else None
+            if start and start * 512 % (1024**2):
+                causes.append(f"{device} starts at sector {start}, not on a 1 MiB boundary, so writes straddle physical sectors")
+    name = os.path.basename(os.path.realpath(array_name))
+    start = backend.read_block_attribute(f"/dev/{name}p1", "start")
+    if start and start * 512 % (1024**2):
+        causes.append(f"the partition on {array_name} starts at sector {start}, not on a 1 MiB boundary")
+    try:
+        with open("/proc/mounts", 'r') as f:
+            mounts = f.read()
+    except OSError:
+        mounts = ""
+    # The array's partition or its LUKS mapping, mounted with compress=/compress-force=
+    if re.search(rf"^/dev/(mapper/)?{re.escape(name)}\S*\s\S+\s\S+\s\S*compress", mounts, re.MULTILINE):
+        causes.append("the filesystem compresses, the test file is all zeros so its numbers are not meaningful")
+    return causes
+
+def check(expected, measured, min_ratio=None):
+    """
+    Compares measured with expected throughput. A pass needs at least min_ratio of the estimate
+    (throughput_min_ratio in config.toml).
+    Returns: {'write': {'expected': ..., 'measured': ..., 'passed': True}, 'read': {...}, 'passed': True}
+    """
+    min_ratio = config.get_setting('throughput_min_ratio') if min_ratio is None else min_ratio
+    report = {}
+    for kind in ("write", "read"):
+        value = measured[kind]
+        report[kind] = {'expected': expected[kind], 'measured': value,
+                        'passed': value is not None and value >= expected[kind] * min_ratio}
+    report['passed'] = report['write']['passed'] and report['read']['passed']
+    return report
+
+def format_result(kind, result):
+    measured = f"{units.format_size(result['measured'])}/s" if result['measured'] is not None else "failed"
+    verdict = "PASS" if result['passed'] else "FAIL"
+    return f"{kind:<6} {measured:>12}  (estimate {units.format_size(result['expected'])}/s)  {verdict}"
@@ -0,0 +1,104 @@
+import os
+import re
+import backend
+import config
+import files
+
+# Picks the device a new md array is created as. Numbered arrays get the lowest <raid_device_prefix>N from
+# md_start_index that isn't reserved (md_reserved), running (/proc/mdstat), left behind in /dev or claimed by
+# an ARRAY line of mdadm.conf, whose array would otherwise be assembled under the same name at boot.
+# md_naming = "named" creates /dev/md/<name> instead (the plan's name, or liveraid0, liveraid1, ...),
+# checked the same way. Names already given to other plans of the same session (the GUI's planned arrays,
+# 'cli.py plan --alongside') count as taken too, so several plans can be made before any of them is applied.
+
+NAMED_DIR = "/dev/md"
+
+# Names of named arrays when the plan doesn't give one
+NAME_STEM = "liveraid"
+
+def running_arrays():
+    """
+    Returns: {'/dev/md0', '/dev/md127'}
+    """
+    try:
+        return {f"/dev/{line.split()[0]}" for line in backend.read_mdstat().splitlines() if re.match(r"md\d+\s*:", line)}
+    except OSError:
+        return set()
+
+def conf_arrays():
+    """
+    The devices the ARRAY lines of mdadm.conf claim, a name= as the /dev/md/<name> it is assembled as.
+    Returns: {'/dev/md0', '/dev/md/data'}
+    """
+    claimed = set()
+    for line in files.read_file(files.get_mdadm_conf_path()).splitlines():
+        fields = line.split()
+        if len(fields) < 2 or fields[0] != "ARRAY":
+            continue
+        if fields[1].startswith("/dev/"):
+            claimed.add(fields[1])
+        for field in fields[2:]:
+            if field.startswith("name="):
+                # 'name=nas:data', the host part only decides whether mdadm treats the array as foreign
+                claimed.add(f"{NAMED_DIR}/{field[5:].split(':')[-1]}")
+    return claimed
+
+def array_number(array_name):
+    match = re.fullmatch(re.escape(config.get_setting('raid_device_prefix')) + r"(\d+)", array_name or "")
+    return int(match.group(1)) if match else None
+
+def collision(array_name, running=None, claimed=None, planned=()):
+    """
+    Why a new array can't be created as array_name, or None when the name is free. planned are the arrays
+    of the session's other plans.
+    Returns: 'in use by a running array', 'reserved by md_reserved', 'claimed by an ARRAY line of mdadm.conf',
+             'already present in /dev', 'planned for another array' or None
+    """
+    running = running_arrays() if running is None else running
+    claimed = conf_arrays() if claimed is None else claimed
+    if array_number(array_name) in config.get_setting('md_reserved'):
+        return "reserved by md_reserved"
+    if array_name in running:
+        return "in use by a running array"
+    if array_name in claimed:
+        return f"claimed by an ARRAY line of {files.get_mdadm_conf_path()}"
+    if os.path.exists(array_name) or os.path.islink(array_name):
+        return "already present in /dev"
+    if array_name in planned:
+        return "planned for another array"
+    return None
+
+def allocate(name=None, planned=()):
+    """
+    Picks the device of a new array, name is the plan's array name (used by md_naming = "named"), planned the
+    arrays of the session's other plans.
+    Returns: ('/dev/md1', [{'array': '/dev/md0', 'reason': 'in use by a running array'}]), the names passed over and why
+    """
+    named = config.get_setting('md_naming') == "named"
+    if named and name:
+        # An explicit name is used as given, validate_plan() reports it if it collides
+        return f"{NAMED_DIR}/{name}", []
+    prefix = f"{NAMED_DIR}/{NAME_STEM}" if named else config.get_setting('raid_device_prefix')
+    running, claimed = running_arrays(), conf_arrays()
+    skipped = []
+    number = config.get_setting('md_start_index')
+    while True:
+        reason = collision(f"{prefix}{number}", running, claimed, planned)
+        if reason is None:
+            return f"{prefix}{number}", skipped
+        skipped.append({'array': f"{prefix}{number}", 'reason': reason})
+        number += 1
+
+def next_array_name(name=None, planned=()):
+    return allocate(name, planned)[0]
+
+def describe(array_name, skipped):
+    """
+    Returns: '/dev/md2 (lowest free number from 0; passed over /dev/md0: in use by a running array, /dev/md1: ...)'
+    """
+    if config.get_setting('md_naming') == "named":
+        how = "named array"
+    else:
+        how = f"lowest free number from {config.get_setting('md_start_index')}"
+    passed = ", ".join(f"{s['array']}: {s['reason']}" for s in skipped)
+    return f"{array_name} ({how}" + (f"; passed over {passed})" if passed else ")")
@@ -1,55 +1,350 @@
+import concurrent.futures
+import fcntl
 import subprocess
 import json
+import logging
+import struct
+import tempfile
 import time
+import os
+import re
+import shutil
+import config
+import runner
+import script
+import units
+
+log = logging.getLogger("liveraid")
 
 # Set to False to actually execute formatting on physical disks!
 DRY_RUN = False
 
+# What runs the commands that change the system (see runner.py), a runner.MockRunner in simulations
+RUNNER = runner.SubprocessRunner()
+
+# Drive dicts standing in for discovery while simulating (simulate.py), None on a real system
+SIMULATED_DRIVES = None
+
+# Seconds smartctl gets to answer for one drive
+SMART_TIMEOUT = 20
+
+# The SMART summary of a drive that wasn't asked (smart_discovery off, no smartctl, a hot-add poll)
+NO_SMART = {'health': None, 'reallocated': None, 'pending': None, 'power_on_hours': None}
+
+# GPT partition type GUID of an EFI System Partition
+ESP_PARTTYPE = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b"
+
+# Block device classes (as named in /proc/devices) that never hold an array member. Live sessions and
+# container hosts can have hundreds of these, so lsblk leaves them out instead of listing and parsing them.
+EXCLUDED_BLOCK_CLASSES = ["ramdisk", "loop", "sr", "md", "mdp", "device-mapper", "zram", "nbd"]
+
+# Upper bound on concurrent `mdadm --detail` calls when many arrays are polled at once, and on smartctl calls
+# when many drives are discovered
+STATUS_WORKERS = 8
+
 def run_command(cmd_list, dry_run=None):
     if dry_run is None:
         dry_run = DRY_RUN
     
     cmd_str = " ".join(cmd_list)
     if dry_run:
-        return True, f"[DRY RUN] Would execute: {cmd_str}\n"
+        return would_execute(cmd_list)
 
     try:
-        result = subprocess.run(cmd_list, capture_output=True, text=True, check=True)
-        return True, (result.stdout + result.stderr).strip() + "\n"
-    except subprocess.CalledProcessError as e:
-        return False, f"Command failed: {cmd_str}\nError: {e.stderr}\n"
+        result = RUNNER.run(cmd_list)
     except Exception as e:
         return False, f"Execution failed: {e}\n"
+    if result.returncode != 0:
+        return False, f"Command failed: {cmd_str}\nError: {result.stderr}\n"
+    return True, (result.stdout + result.stderr).strip() + "\n"
+
+def query(cmd_list):
+    """
+    Runs a read-only query (mdadm --detail/--examine, pvs, btrfs filesystem show) through RUNNER, a runner.MockRunner
+    answers it from the arrays it simulates. Returns its stdout, raises subprocess.CalledProcessError when it fails.
+    """
+    result = RUNNER.query(cmd_list)
+    if result.returncode != 0:
+        raise subprocess.CalledProcessError(result.returncode, cmd_list, result.stdout, result.stderr)
+    return result.stdout
+
+def read_mdstat():
+    # /proc/mdstat as RUNNER sees it, raises OSError when the md driver isn't loaded
+    return RUNNER.read("/proc/mdstat")
+
+def would_execute(cmd):
+    # The dry-run stand-in for running a command, which also goes into the script being recorded (script.py)
+    script.record_command(cmd)
+    return True, f"[DRY RUN] Would execute: {' '.join(cmd)}\n"
+
+def commands_recorded():
+    # A runner.MockRunner (simulations, tests) only records the commands, nothing needs to be installed
+    return isinstance(RUNNER, runner.MockRunner)
+
+def nothing_created(dry_run=None):
+    # Dry runs and recorded commands create no array or filesystem, so there is no UUID to read back from one
+    return (DRY_RUN if dry_run is None else dry_run) or commands_recorded()
+
+def write_sysfs(path, value, dry_run=None):
+    """
+    Writes a value to a sysfs attribute, mirroring run_command's (success, log) contract.
+    """
+    if dry_run is None:
+        dry_run = DRY_RUN
+
+    if dry_run:
+        script.record_sysfs(path, value)
+        return True, f"[DRY RUN] Would write '{value}' to {path}\n"
+
+    try:
+        RUNNER.write(path, value)
+        return True, f"Wrote '{value}' to {path}\n"
+    except Exception as e:
+        return False, f"Failed to write '{value}' to {path}\nError: {e}\n"
+
+def md_sysfs_dir(array_name):
+    # /dev/md/name is a symlink to /dev/mdX, sysfs only knows the kernel name
+    return f"/sys/block/{os.path.basename(os.path.realpath(array_name))}/md"
 
 def get_used_raid_drives():
     drives = set()
     try:
-        with open('/proc/mdstat', 'r') as f:
+        for line in read_mdstat().splitlines():
+            if line.startswith('md') and ':' in line:
+                parts = line.split()
+                for p in parts[3:]:  # the devices come after 'active raid1' etc e.g., sda[0]
+                    if '[' in p:
+                        dev_name = p.split('[')[0]
+                        drives.add(dev_name)
+    except Exception:
+        pass
+    return drives
+
+def get_excluded_majors():
+    """
+    Returns the major numbers of EXCLUDED_BLOCK_CLASSES on this kernel, e.g. ['1', '7', '9', '11', '253'].
+    zram and device-mapper get a dynamic major, so they are looked up in /proc/devices.
+    """
+    majors = []
+    try:
+        with open('/proc/devices', 'r') as f:
+            in_block = False
+            for line in f:
+                if line.startswith("Block devices:"):
+                    in_block = True
+                    continue
+                parts = line.split()
+                if in_block and len(parts) == 2 and parts[1] in EXCLUDED_BLOCK_CLASSES and parts[0] not in majors:
+                    majors.append(parts[0])
+    except Exception:
+        pass
+    # ramdisk and loop have fixed majors
+    return majors or ["1", "7"]
+
+# BLKGETSIZE64 from <linux/fs.h>, _IOR(0x12, 114, size_t): a block device's exact size in bytes
+BLKGETSIZE64 = 0x80081272
+
+# Where udev keeps the properties of every device it has processed (the same records lsblk reads)
+UDEV_DATA_DIR = "/run/udev/data"
+
+def get_device_size(dev_name):
+    """
+    Returns a block device's size in bytes from the BLKGETSIZE64 ioctl, or from /sys/block/<name>/size
+    (always counted in 512-byte sectors) when the device node can't be opened.
+    """
+    try:
+        fd = os.open(f"/dev/{dev_name}", os.O_RDONLY)
+        try:
+            return struct.unpack("Q", fcntl.ioctl(fd, BLKGETSIZE64, b"\0" * 8))[0]
+        finally:
+            os.close(fd)
+    except OSError:
+        sectors = read_sysfs_text(f"/sys/block/{dev_name}/size")
+        return int(sectors) * 512 if sectors and sectors.isdigit() else 0
+
+def read_udev_properties(major_minor):
+    """
+    Returns the udev properties of a block device, e.g. {'ID_SERIAL_SHORT': 'WD-XYZ', 'ID_PART_TABLE_TYPE': 'gpt'},
+    or None when udev has no record of it (no udev in containers, or not processed yet).
+    """
+    try:
+        with open(os.path.join(UDEV_DATA_DIR, f"b{major_minor}"), 'r') as f:
+            return dict(line[2:].rstrip("\n").split("=", 1) for line in f if line.startswith("E:") and "=" in line)
+    except OSError:
+        return None
+
+def get_transport(dev_name):
+    # The bus a disk hangs off shows in its sysfs path, the way lsblk reports it as TRAN
+    path = os.path.realpath(f"/sys/block/{dev_name}")
+    if "/nvme" in path:
+        return "nvme"
+    if "/usb" in path:
+        return "usb"
+    if "/end_device-" in path:
+        return "sas"
+    if "/ata" in path:
+        return "sata"
+    if "/mmc_host/" in path:
+        return "mmc"
+    return ""
+
+def get_block_mountpoints():
+    """
+    Returns the mount points (and '[SWAP]' for active swap) of every block device by major:minor,
+    e.g. {'8:1': ['/boot/efi'], '8:2': ['[SWAP]']}, from /proc/self/mountinfo and /proc/swaps.
+    """
+    mounts = {}
+    try:
+        with open('/proc/self/mountinfo', 'r') as f:
             for line in f:
-                if line.startswith('md') and ':' in line:
-                    parts = line.split()
-                    for p in parts[3:]:  # the devices come after 'active raid1' etc e.g., sda[0]
-                        if '[' in p:
-                            dev_name = p.split('[')[0]
-                            drives.add(dev_name)
+                fields = line.split()
+                # Mount points escape spaces and tabs as octal (\040)
+                mounts.setdefault(fields[2], []).append(re.sub(r"\\([0-7]{3})", lambda m: chr(int(m.group(1), 8)), fields[4]))
     except Exception:
         pass
+    try:
+        with open('/proc/swaps', 'r') as f:
+            for line in f.readlines()[1:]:
+                rdev = os.stat(line.split()[0]).st_rdev
+                mounts.setdefault(f"{os.major(rdev)}:{os.minor(rdev)}", []).append("[SWAP]")
+    except Exception:
+        pass
+    return mounts
+
+def read_sysfs_block_device(dev_name, mounts=None):
+    """
+    Describes a disk from /sys/block, the udev database and BLKGETSIZE64, in the shape of an `lsblk -J -b` entry.
+    Returns None when udev has no record of it, so callers fall back to lsblk.
+    """
+    base = f"/sys/block/{dev_name}"
+    major_minor = read_sysfs_text(f"{base}/dev")
+    props = read_udev_properties(major_minor) if major_minor else None
+    if props is None:
+        return None
+    mounts = get_block_mountpoints() if mounts is None else mounts
+    return {
+        'name': dev_name,
+        # SCSI peripheral type 5 is a CD/DVD drive
+        'type': "rom" if read_sysfs_text(f"{base}/device/type") == "5" else "disk",
+        'size': get_device_size(dev_name),
+        'mountpoints': mounts.get(major_minor, []),
+        'tran': get_transport(dev_name),
+        'rm': read_sysfs_text(f"{base}/removable") == "1",
+        'rota': read_sysfs_text(f"{base}/queue/rotational") == "1",
+        'fstype': props.get('ID_FS_TYPE') or None,
+        'pttype': props.get('ID_PART_TABLE_TYPE') or None,
+        'serial': props.get('ID_SERIAL_SHORT') or read_sysfs_text(f"{base}/device/serial"),
+        'wwn': props.get('ID_WWN_WITH_EXTENSION') or props.get('ID_WWN'),
+        'model': read_sysfs_text(f"{base}/device/model") or props.get('ID_MODEL'),
+        'rev': read_sysfs_text(f"{base}/device/rev"),
+    }
+
+def discover_sysfs():
+    """
+    Lists whole disks natively from /sys/block, without the EXCLUDED_BLOCK_CLASSES.
+    Returns lsblk-style entries (see read_sysfs_block_device()), or None when the udev database
+    doesn't cover every disk and lsblk has to be used instead.
+    """
+    excluded = set(get_excluded_majors())
+    mounts = get_block_mountpoints()
+    try:
+        names = sorted(os.listdir("/sys/block"))
+    except OSError:
+        return None
+    devices = []
+    for name in names:
+        major_minor = read_sysfs_text(f"/sys/block/{name}/dev")
+        if not major_minor or major_minor.split(":")[0] in excluded:
+            continue
+        entry = read_sysfs_block_device(name, mounts)
+        if entry is None:
+            return None
+        devices.append(entry)
+    return devices
+
+def discover_lsblk(devices=()):
+    # Fallback for discover_sysfs(): whole disks only (-d), without the loop/dm/zram/... classes that can't be members (-e)
+    result = subprocess.run(['lsblk', '-J', '-b', '-d', '-e', ",".join(get_excluded_majors()),
+                             '-o', 'NAME,TYPE,SIZE,MOUNTPOINTS,TRAN,RM,ROTA,FSTYPE,PTTYPE,SERIAL,WWN,MODEL,REV'] + list(devices),
+                            capture_output=True, text=True, check=True)
+    return json.loads(result.stdout).get('blockdevices', [])
+
+def get_partition_types(disk_name):
+    # Only needed for disks that have a partition table, so it's asked for per disk instead of for every partition on the host
+    base = f"/sys/block/{disk_name}"
+    try:
+        partitions = [p for p in sorted(os.listdir(base)) if os.path.exists(f"{base}/{p}/partition")]
+    except OSError:
+        partitions = []
+    props = [read_udev_properties(read_sysfs_text(f"{base}/{p}/dev")) for p in partitions]
+    if partitions and None not in props:
+        return [(p.get('ID_PART_ENTRY_TYPE') or '').lower() for p in props]
+    try:
+        result = subprocess.run(['lsblk', '-J', '-n', '-o', 'NAME,PARTTYPE', f"/dev/{disk_name}"], capture_output=True, text=True, check=True)
+        nodes = json.loads(result.stdout).get('blockdevices', [])
+    except Exception:
+        return []
+    return [(c.get('parttype') or '').lower() for node in nodes for c in node.get('children', [])]
+
+def describe_drive(block_device, smart=True):
+    """
+    Turns an lsblk-style entry (discover_sysfs() or discover_lsblk()) into the drive dict get_available_drives() returns.
+    Without smart the SMART summary is left empty (see attach_smart()).
+    """
+    name = block_device.get('name')
+    # Partitions are only looked at when there is a partition table to hold them
+    parttypes = get_partition_types(name) if block_device.get('pttype') else []
+    link = get_link_info(name, block_device.get('tran') or '')
+    firmware = link.pop('firmware')
+    size = int(block_device.get('size', 0))
+    return {
+        'name': f"/dev/{name}",
+        'size_gb': round(size / (1024**3), 2),
+        'size_bytes': size,
+        'serial': (block_device.get('serial') or '').strip() or None,
+        'wwn': block_device.get('wwn') or None,
+        'model': (block_device.get('model') or '').strip() or None,
+        'firmware': (block_device.get('rev') or '').strip() or firmware,
+        'link': link,
+        'transport': block_device.get('tran') or '',
+        'removable': bool(block_device.get('rm')),
+        'rotational': bool(block_device.get('rota')),
+        'has_data': bool(block_device.get('pttype') or block_device.get('fstype')),
+        'has_esp': ESP_PARTTYPE in parttypes,
+        'controller': get_controller(name),
+        'smart': get_drive_health(f"/dev/{name}") if smart else dict(NO_SMART)
+    }
+
+def attach_smart(drives):
+    """
+    Fills in the SMART summary ('smart') of drives, asking smartctl about all of them at once since each takes a
+    moment to answer. Returns the drives.
+    """
+    if drives:
+        with concurrent.futures.ThreadPoolExecutor(max_workers=min(STATUS_WORKERS, len(drives))) as pool:
+            for drive, health in zip(drives, pool.map(lambda d: get_drive_health(d['name']), drives)):
+                drive['smart'] = health
     return drives
 
-def get_available_drives():
+def get_available_drives(smart=True):
     """
     Returns a list of dictionaries with 'name' and 'size_gb' for unmounted block devices.
-    Uses lsblk JSON output parsing. Filters out drives that are actively in a RAID array.
+    Reads sysfs and the udev database directly (exact sizes via BLKGETSIZE64), falling back to lsblk
+    where udev's records aren't available. Filters out drives that are actively in a RAID array.
+    Without smart, smartctl isn't asked (for polls that only look for new disks).
     """
+    if SIMULATED_DRIVES is not None:
+        return [dict(d) for d in SIMULATED_DRIVES]
+    started = time.monotonic()
     try:
-        # Fetch block devices in JSON format, excluding loop devices (which LiveCDs use heavily)
-        result = subprocess.run(['lsblk', '-J', '-b', '-o', 'NAME,TYPE,SIZE,MOUNTPOINTS'], capture_output=True, text=True, check=True)
-        data = json.loads(result.stdout)
+        block_devices, method = discover_sysfs(), "sysfs"
+        if block_devices is None:
+            block_devices, method = discover_lsblk(), "lsblk"
         
         used_raid_drives = get_used_raid_drives()
         
         drives = []
-        for block_device in data.get('blockdevices', []):
+        for block_device in block_devices:
             if block_device.get('type') == 'disk':
                 name = block_device.get('name')
                 
@@ -64,94 +359,1361 @@ def get_available_drives():
                 
                 # Heuristics for blank, unmounted hard drives
                 if not name.startswith('loop') and not name.startswith('md') and not mountpoints and size > 0:
-                    drives.append({
-                        'name': f"/dev/{name}",
-                        'size_gb': round(size / (1024**3), 2)
-                    })
+                    drives.append(describe_drive(block_device, smart=False))
+        if smart:
+            attach_smart(drives)
+        log.debug(f"Drive discovery ({method}) took {(time.monotonic() - started) * 1000:.0f} ms for {len(drives)} drives")
         return drives
     except Exception as e:
-        print(f"Error fetching physical drives: {e}")
+        log.error(f"Failed to fetch physical drives: {e}")
+        return []
+
+def parse_link_rate(text):
+    """
+    Returns the rate in sysfs/smartctl link speed strings as a number: '6.0 Gbps' -> 6.0, '8.0 GT/s PCIe' -> 8.0,
+    '12.0 Gbit' -> 12.0, '<unknown>' -> None.
+    """
+    match = re.search(r"([\d.]+)\s*G", text or "")
+    return float(match.group(1)) if match else None
+
+def read_sysfs_text(path):
+    try:
+        with open(path, 'r') as f:
+            return f.read().strip()
+    except Exception:
+        return None
+
+def get_controller(dev_name):
+    """
+    Returns the PCI address of the controller (HBA, SATA/SAS controller, NVMe or USB host) a disk hangs off,
+    e.g. '0000:00:17.0', taken from the last PCI function in its sysfs path. None for virtual disks.
+    Members on the same controller share one failure domain: a dead HBA or cable takes them out together.
+    """
+    path = os.path.realpath(f"/sys/block/{dev_name}/device")
+    functions = re.findall(r"/([0-9a-f]{4}:[0-9a-f]{2}:[0-9a-f]{2}\.[0-9a-f])(?=/)", path)
+    return functions[-1] if functions else None
+
+def get_link_info(dev_name, transport=""):
+    """
+    Returns the negotiated and maximum speed of a disk's link: PCIe for NVMe (sysfs), the SAS phy (sysfs),
+    or the SATA link (smartctl, which also knows what the drive itself supports).
+    Returns: {'current': 3.0, 'max': 6.0, 'unit': 'Gb/s', 'width': None, 'max_width': None, 'firmware': '80.00A80'}
+    """
+    info = {'current': None, 'max': None, 'unit': None, 'width': None, 'max_width': None, 'firmware': None}
+    if dev_name.startswith("nvme"):
+        # /sys/block/nvme0n1/device is the controller, its device/ the PCIe function
+        pci = f"/sys/block/{dev_name}/device/device"
+        info.update(unit="GT/s",
+                    current=parse_link_rate(read_sysfs_text(f"{pci}/current_link_speed")),
+                    max=parse_link_rate(read_sysfs_text(f"{pci}/max_link_speed")),
+                    width=read_sysfs_text(f"{pci}/current_link_width"),
+                    max_width=read_sysfs_text(f"{pci}/max_link_width"),
+                    firmware=read_sysfs_text(f"/sys/block/{dev_name}/device/firmware_rev"))
+        return info
+
+    if transport == "sas":
+        # .../host0/port-0:1/end_device-0:1/target0:0:1/... : the port directory holds the phy(s) of this link
+        path = os.path.realpath(f"/sys/block/{dev_name}/device")
+        match = re.search(r"^(.*/port-[\d:]+)/", path)
+        phys = [p for p in os.listdir(match.group(1)) if p.startswith("phy-")] if match and os.path.isdir(match.group(1)) else []
+        if phys:
+            phy = f"/sys/class/sas_phy/{phys[0]}"
+            info.update(unit="Gb/s",
+                        current=parse_link_rate(read_sysfs_text(f"{phy}/negotiated_linkrate")),
+                        max=parse_link_rate(read_sysfs_text(f"{phy}/maximum_linkrate")))
+            return info
+
+    if transport in ("sata", "sas", "ata"):
+        try:
+            result = subprocess.run(['smartctl', '-i', '-j', f"/dev/{dev_name}"], capture_output=True, text=True)
+            data = json.loads(result.stdout)
+        except Exception:
+            return info
+        speed = data.get('interface_speed', {})
+        info.update(unit="Gb/s",
+                    current=parse_link_rate(speed.get('current', {}).get('string')),
+                    max=parse_link_rate(speed.get('max', {}).get('string')),
+                    firmware=data.get('firmware_version'))
+    return info
+
+def link_downgraded(drive):
+    """
+    True when a drive negotiated a slower link than it supports, usually a bad cable, backplane or port.
+    """
+    link = drive.get('link') or {}
+    if link.get('current') and link.get('max') and link['current'] < link['max']:
+        return True
+    return bool(link.get('width') and link.get('max_width') and link['width'] != link['max_width'])
+
+def format_link(drive):
+    # e.g. '3.0/6.0 Gb/s' or '8.0/16.0 GT/s x2/x4', '' when unknown
+    link = drive.get('link') or {}
+    if not link.get('current'):
+        return ""
+    text = f"{link['current']:.1f}/{link['max']:.1f} {link['unit']}" if link.get('max') else f"{link['current']:.1f} {link['unit']}"
+    if link.get('width'):
+        text += f" x{link['width']}/x{link.get('max_width') or '?'}"
+    return text
+
+def predict_drive_conflicts(drive, selected_drives, preserve_partitions=False):
+    """
+    Returns consequence labels for including a drive in an array alongside the other selected drives,
+    so the GUI can warn while the user is still picking disks rather than at creation time.
+    """
+    labels = []
+    if preserve_partitions:
+        if drive.get('has_data'):
+            labels.append("existing partitions kept")
+    else:
+        if drive.get('has_data'):
+            labels.append("will be wiped")
+        if drive.get('has_esp'):
+            labels.append("contains ESP — boot risk")
+    if drive.get('transport') == 'usb' or drive.get('removable'):
+        labels.append("USB — not recommended")
+    if link_downgraded(drive):
+        labels.append(f"link below capability ({format_link(drive)}) — check cable")
+    health = (drive.get('smart') or {}).get('health')
+    if health == "failing":
+        labels.append("SMART failing — replace it")
+    elif health == "warning":
+        labels.append("SMART sector errors")
+
+    # Every member is truncated to the smallest one, flag it if it is noticeably smaller (>1%)
+    largest = max((d.get('size_bytes', 0) for d in selected_drives), default=0)
+    if len(selected_drives) > 1 and drive.get('size_bytes', 0) < largest * 0.99:
+        labels.append("smaller than others — limits capacity")
+    return labels
+
+# Minimum member count mdadm accepts for each level offered by the GUI
+MIN_DEVICES = {"0": 2, "1": 2, "5": 3, "6": 4, "10": 2}
+
+# mdadm --create tunables beyond the chunk size (None everywhere = mdadm's own default)
+METADATA_VERSIONS = ["0.90", "1.0", "1.1", "1.2"]
+BITMAP_MODES = ["internal", "none"]
+# RAID 10 layouts with two copies: near mirrors neighbouring members, far and offset spread the second copy
+# for faster sequential reads (far can't be reshaped later)
+RAID10_LAYOUTS = {'near': "n2", 'far': "f2", 'offset': "o2"}
+# 0.90 superblocks address members of up to 4 TiB and arrays of up to 28 members
+METADATA_090_MAX_BYTES = 4 * 1024**4
+METADATA_090_MAX_DEVICES = 28
+
+def check_md_options(level, metadata=None, bitmap=None, layout=None, name=None):
+    """
+    Checks the mdadm tunables of a new array against its level, without looking at the drives.
+    Returns: [('layout', 'Layouts near/far/offset are RAID 10 only ...')]
+    """
+    level = str(level)
+    errors = []
+    if metadata is not None and metadata not in METADATA_VERSIONS:
+        errors.append(('metadata', f"Unknown metadata version '{metadata}' (use {', '.join(METADATA_VERSIONS)})"))
+    if bitmap is not None and bitmap not in BITMAP_MODES:
+        errors.append(('bitmap', f"Unknown bitmap '{bitmap}' (use {', '.join(BITMAP_MODES)})"))
+    elif bitmap == "internal" and level == "0":
+        errors.append(('bitmap', "RAID 0 has no redundancy to resync, it can't have a write-intent bitmap."))
+    if layout is not None and layout not in RAID10_LAYOUTS:
+        errors.append(('layout', f"Unknown layout '{layout}' (use {', '.join(RAID10_LAYOUTS)})"))
+    elif layout is not None and level != "10":
+        errors.append(('layout', f"Layouts {'/'.join(RAID10_LAYOUTS)} are RAID 10 only, RAID {level} has none to choose."))
+    if name is not None and not re.fullmatch(r"[A-Za-z0-9_.-]{1,32}", name):
+        errors.append(('name', f"'{name}' is not a usable array name, use up to 32 letters, digits, '.', '_' or '-'."))
+    return errors
+
+def validate_array(level, drives, suppress=None, spares=None, strict=None, metadata=None):
+    """
+    Checks a proposed array before creation. Errors block creation, warnings are advisories
+    that carry a code so automation can silence the ones it has accepted (suppress_warnings in config.toml).
+    spares are drive dicts like drives, for the hot spares added next to the members, None leaves them
+    out of the check. strict turns a failing SMART health into an error (default: smart_strict in
+    config.toml). metadata is the superblock version, whose limits are checked against the members.
+    Returns: {'errors': ['...'], 'warnings': [{'code': 'mixed-media', 'message': '...'}]}
+    """
+    level = str(level)
+    suppressed = set(config.get_setting('suppress_warnings')) | set(suppress or [])
+    errors = []
+    warnings = []
+
+    def warn(code, message):
+        if code not in suppressed:
+            warnings.append({'code': code, 'message': message})
+
+    if level not in MIN_DEVICES:
+        errors.append(f"Unsupported RAID level: {level}")
+    elif len(drives) < MIN_DEVICES[level]:
+        errors.append(f"RAID {level} needs at least {MIN_DEVICES[level]} drives, {len(drives)} selected")
+
+    if level == "0":
+        warn("no-redundancy", "RAID 0 has no redundancy, losing any drive loses the whole array")
+    if len({d.get('rotational') for d in drives}) > 1:
+        warn("mixed-media", "Mixing SSDs and HDDs, the array runs at the speed of the slowest member")
+    sizes = [d.get('size_bytes', 0) for d in drives]
+    if sizes and min(sizes) < max(sizes) * 0.99:
+        unused = sum(size - min(sizes) for size in sizes)
+        warn("mixed-sizes", f"Drives differ in size, every member is truncated to the smallest one ({units.format_size(unused)} left unused)")
+    if level == "10" and len(drives) % 2:
+        warn("odd-raid10", f"RAID 10 on {len(drives)} drives spreads the copies over all members, there are no fixed mirror pairs")
+    elif level == "10":
+        # mdadm's near layout mirrors each member with the one after it, the order decides which disks pair up
+        for first, second in mirror_pairs(drives):
+            if min(first.get('size_bytes', 0), second.get('size_bytes', 0)) < max(first.get('size_bytes', 0), second.get('size_bytes', 0)) * 0.99:
+                warn("pair-size", f"Mirror pair {first['name']} + {second['name']} differs in size, pair disks of the same size")
+        same = [pair for pair in mirror_pairs(drives) if pair[0].get('controller') and pair[0]['controller'] == pair[1].get('controller')]
+        if same and len(controllers(drives)) > 1:
+            names = ", ".join(f"{a['name']} + {b['name']}" for a, b in same)
+            warn("pair-controller", f"Both halves of mirror pair(s) {names} are on the same controller, "
+                                    f"order the members as {' '.join(d['name'] for d in suggest_mirror_order(drives))} to mirror across controllers")
+        legs = {frozenset(bool(d.get('rotational')) for d in pair) for pair in mirror_pairs(drives)}
+        if len(legs) > 1:
+            warn("unbalanced-legs", "The mirror pairs mix SSD and HDD differently, every stripe runs at the speed of the slowest pair")
+    # How many members one controller may take down before the array is lost (RAID 10 pairs are checked above)
+    tolerance = {"1": len(drives) - 1, "5": 1, "6": 2}.get(level)
+    domains = controllers(drives)
+    if tolerance is not None and len(drives) > 1 and None not in domains:
+        crowded = {c: names for c, names in domains.items() if len(names) > tolerance}
+        if crowded and len(domains) == 1:
+            warn("single-controller", f"Every member is on controller {next(iter(domains))}, a controller or cable failure takes out all of them at once")
+        elif crowded:
+            for controller, names in crowded.items():
+                warn("controller-domain", f"{', '.join(names)} share controller {controller}, more than RAID {level} survives losing at once")
+    if level == "5" and any(size > 8 * 1000**4 for size in sizes):
+        warn("large-raid5", "RAID 5 on drives over 8 TB is risky, a second failure during the long rebuild loses the array (consider RAID 6)")
+    usb = [d['name'] for d in drives if d.get('transport') == 'usb' or d.get('removable')]
+    if usb:
+        warn("usb-member", f"USB/removable members ({', '.join(usb)}) tend to drop out of arrays")
+    for d in drives:
+        if link_downgraded(d):
+            warn("slow-link", f"{d['name']} negotiated {format_link(d)}, below what it supports. "
+                              "Check the cable, backplane slot or controller port before building on it")
+
+    # [] is an array planned without spares, None a check that doesn't look at them
+    spareless = spares == []
+    spares = spares or []
+    if spares and level == "0":
+        errors.append("RAID 0 has no redundancy to rebuild, it cannot use spares")
+    for s in spares:
+        if s['name'] in {d['name'] for d in drives}:
+            errors.append(f"{s['name']} is already an active member, it can't also be a spare")
+        elif sizes and s.get('size_bytes', 0) < min(sizes):
+            errors.append(f"{s['name']} is smaller than the members, it could never replace one")
+    if spares and len(spares) >= len(drives) and level in MIN_DEVICES:
+        warn("many-spares", f"{len(spares)} spares for {len(drives)} members, a larger RAID level would put them to use")
+    elif spareless and level in MIN_DEVICES and level != "0":
+        warn("no-spare", f"RAID {level} has no hot spare, a failed member is only rebuilt once someone replaces it")
+    if metadata == "0.90":
+        too_large = [d['name'] for d in list(drives) + spares if d.get('size_bytes', 0) > METADATA_090_MAX_BYTES]
+        if too_large:
+            errors.append(f"0.90 metadata addresses members of up to {units.format_size(METADATA_090_MAX_BYTES)}, {', '.join(too_large)} are larger. Use 1.0 or 1.2")
+        if len(drives) + len(spares) > METADATA_090_MAX_DEVICES:
+            errors.append(f"0.90 metadata holds at most {METADATA_090_MAX_DEVICES} devices, use 1.0 or 1.2")
+    for code, message in smart_findings(list(drives) + spares):
+        if code == "smart-failing" and smart_strict(strict):
+            errors.append(message)
+        else:
+            warn(code, message)
+
+    return {'errors': errors, 'warnings': warnings}
+
+# The pre-apply checklist: what each item checks and the validate_array() warning codes that fail it
+VALIDATION_CHECKS = [
+    ("Redundancy", ["no-redundancy", "large-raid5", "odd-raid10", "many-spares", "no-spare"]),
+    ("Drives of the same size", ["mixed-sizes", "pair-size"]),
+    ("One kind of media (SSD or HDD)", ["mixed-media", "unbalanced-legs"]),
+    ("No USB or removable members", ["usb-member"]),
+    ("Members spread over controllers", ["single-controller", "controller-domain", "pair-controller"]),
+    ("SMART health", ["smart-warning", "smart-failing"]),
+    ("Links at full speed", ["slow-link"]),
+]
+
+def validation_checklist(report):
+    """
+    A validation report (validate_array() or plan.validate_plan()) as the checklist shown before applying.
+    Errors come first, warnings no item covers (policy, tools, ...) are gathered under 'Other'.
+    Returns: [{'title': 'Drives of the same size', 'status': 'ok' | 'warn' | 'error', 'messages': ['...']}]
+    """
+    errors = [e['message'] if isinstance(e, dict) else e for e in report['errors']]
+    checklist = [{'title': "Plan is valid", 'status': "error" if errors else "ok", 'messages': errors}]
+    covered = set()
+    for title, codes in VALIDATION_CHECKS:
+        covered.update(codes)
+        messages = [w['message'] for w in report['warnings'] if w['code'] in codes]
+        checklist.append({'title': title, 'status': "warn" if messages else "ok", 'messages': messages})
+    other = [w['message'] for w in report['warnings'] if w['code'] not in covered]
+    if other:
+        checklist.append({'title': "Other", 'status': "warn", 'messages': other})
+    return checklist
+
+def controllers(drives):
+    """
+    Groups drives by the controller they hang off (see get_controller()).
+    Returns: {'0000:00:17.0': ['/dev/sdb', '/dev/sdc'], '0000:3d:00.0': ['/dev/sdd']} (None for unknown)
+    """
+    groups = {}
+    for d in drives:
+        groups.setdefault(d.get('controller'), []).append(d['name'])
+    return groups
+
+def suggest_mirror_order(drives):
+    """
+    Reorders RAID 10 members so that, as far as the controllers allow, the two halves of each mirror pair
+    sit on different controllers: each pair takes one drive from each of the two controllers with the most
+    drives left. Returns the drive dicts in the suggested order (unchanged with a single controller).
+    """
+    queues = {}
+    for d in drives:
+        queues.setdefault(d.get('controller'), []).append(d)
+    if len(queues) < 2:
+        return list(drives)
+    order = []
+    while any(queues.values()):
+        busiest = sorted((q for q in queues.values() if q), key=len, reverse=True)
+        order += [q.pop(0) for q in busiest[:2]]
+    return order
+
+def mirror_pairs(members):
+    """
+    The mirror pairs of a RAID 10 array (near layout, 2 copies) in member order: the members at slots 0+1, 2+3, ...
+    Returns: [['/dev/sdb', '/dev/sdc'], ['/dev/sdd', '/dev/sde']] (or pairs of drive dicts, whatever was passed)
+    """
+    return [list(members[i:i + 2]) for i in range(0, len(members) - 1, 2)]
+
+def estimate_array_capacity(level, drives):
+    """
+    Returns the usable size in bytes of a proposed array (members are truncated to the smallest one).
+    """
+    sizes = [d.get('size_bytes', 0) for d in drives]
+    if not sizes:
+        return 0
+    smallest, count = min(sizes), len(sizes)
+    return {"0": smallest * count, "1": smallest, "5": smallest * (count - 1), "6": smallest * (count - 2), "10": smallest * count // 2}.get(str(level), 0)
+
+def fault_tolerance(level, count):
+    """
+    How many failed members an array of count members survives.
+    Returns: (guaranteed, best_case), e.g. (1, 2) for RAID 10 on 4 drives: any one, two if they are in different mirror pairs.
+    """
+    level = str(level)
+    if count < MIN_DEVICES.get(level, 0):
+        return 0, 0
+    if level == "10":
+        # Near layout with 2 copies: each mirror pair may lose one half (odd counts have no fixed pairs)
+        return 1, count // 2 if count % 2 == 0 else 1
+    tolerance = {"0": 0, "1": count - 1, "5": 1, "6": 2}.get(level, 0)
+    return tolerance, tolerance
+
+def capacity_summary(level, drives, spares=()):
+    """
+    Usable capacity, what the smallest-disk rule leaves unused and how many failures the array survives.
+    Returns: {'capacity_bytes': 3000592982016, 'raw_bytes': 4000797868032, 'unused_bytes': 0,
+              'fault_tolerance': 1, 'fault_tolerance_max': 1, 'spares': 1}
+    """
+    sizes = [d.get('size_bytes', 0) for d in drives]
+    guaranteed, best = fault_tolerance(level, len(drives))
+    return {
+        'capacity_bytes': estimate_array_capacity(level, drives),
+        'raw_bytes': sum(sizes),
+        # Every member only contributes as much as the smallest one
+        'unused_bytes': sum(sizes) - min(sizes) * len(sizes) if sizes else 0,
+        'fault_tolerance': guaranteed,
+        'fault_tolerance_max': best,
+        'spares': len(spares),
+    }
+
+def describe_capacity(summary):
+    text = f"{units.format_size(summary['capacity_bytes'])} usable of {units.format_size(summary['raw_bytes'])} raw"
+    if summary['unused_bytes']:
+        text += f", {units.format_size(summary['unused_bytes'])} unused (members are cut to the smallest one)"
+    if summary['fault_tolerance'] == 0:
+        text += ", does not survive any failed drive"
+    else:
+        text += f", survives {summary['fault_tolerance']} failed drive{'s' if summary['fault_tolerance'] > 1 else ''}"
+        if summary['fault_tolerance_max'] > summary['fault_tolerance']:
+            text += f" (up to {summary['fault_tolerance_max']} if each is in a different mirror pair)"
+    if summary['spares']:
+        text += f", plus {summary['spares']} hot spare{'s' if summary['spares'] > 1 else ''} rebuilt onto automatically"
+    return text
+
+def level_eligibility(drive, selected_drives, suppress=None):
+    """
+    Checks a drive against every RAID level, as if it were added to the drives selected so far.
+    Returns: {'0': {'errors': [...], 'warnings': [...], 'capacity_bytes': 2000398934016}, '1': {...}, ...}
+    """
+    members = [d for d in selected_drives if d['name'] != drive['name']] + [drive]
+    eligibility = {}
+    for level in MIN_DEVICES:
+        report = validate_array(level, members, suppress)
+        eligibility[level] = dict(report, capacity_bytes=estimate_array_capacity(level, members))
+    return eligibility
+
+def get_signatures(device_path):
+    """
+    Lists the filesystem, RAID and partition table signatures on a device without erasing anything (wipefs --no-act).
+    Returns: [{'type': 'linux_raid_member', 'offset': '0x1000', 'label': 'nas:0', 'uuid': '...'}]
+    """
+    try:
+        result = subprocess.run(['wipefs', '--no-act', '--json', device_path], capture_output=True, text=True, check=True)
+        data = json.loads(result.stdout) if result.stdout.strip() else {}
+    except Exception:
         return []
+    return [{'type': s.get('type'), 'offset': s.get('offset'), 'label': s.get('label'), 'uuid': s.get('uuid')}
+            for s in data.get('signatures', [])]
+
+def get_holders(disk_name):
+    """
+    Returns the devices stacked on a disk or its partitions (md arrays, LVM, LUKS), e.g. ['md0', 'dm-2'].
+    """
+    base = f"/sys/block/{disk_name}"
+    try:
+        paths = [base] + [f"{base}/{p}" for p in sorted(os.listdir(base)) if os.path.exists(f"{base}/{p}/partition")]
+    except OSError:
+        return []
+    holders = []
+    for path in paths:
+        try:
+            holders.extend(h for h in sorted(os.listdir(f"{path}/holders")) if h not in holders)
+        except OSError:
+            continue
+    return holders
+
+# Filesystems of other operating systems, most likely someone's old data when a machine is being migrated
+FOREIGN_FILESYSTEMS = {'ntfs': "NTFS", 'exfat': "exFAT", 'hfsplus': "HFS+"}
+
+# lastMountedVersion in the HFS+ volume header (offset 1024 + 8)
+HFS_LAST_MOUNTED = {b'10.0': "macOS", b'HFSJ': "macOS", b'8.10': "Mac OS 8/9", b'H+Lx': "Linux", b'fsck': "fsck_hfs"}
+
+def guess_os(mount_point):
+    # What the directory layout of a mounted volume says about the system that used it
+    if os.path.isdir(os.path.join(mount_point, "Windows", "System32")):
+        return "Windows (system disk)"
+    if os.path.isdir(os.path.join(mount_point, "System", "Library", "CoreServices")):
+        return "macOS (system disk)"
+    return None
+
+def hfs_last_mounted(device_path):
+    try:
+        with open(device_path, 'rb') as f:
+            f.seek(1024 + 8)
+            return HFS_LAST_MOUNTED.get(f.read(4))
+    except OSError:
+        return None
+
+def foreign_data_summary(device_path):
+    """
+    Finds NTFS, exFAT and HFS+ filesystems on a disk and what they hold, so nobody wipes the only copy of the data
+    of the machine they are migrating from. Filesystems that aren't mounted are mounted read-only in a temporary
+    directory for a moment to read the used space (this happens in dry runs too, nothing is written).
+    Returns: [{'device': '/dev/sdb2', 'fstype': 'NTFS', 'label': 'Windows', 'size_bytes': 511101108224,
+               'used_bytes': 335007449088, 'last_os': 'Windows (system disk)'}]
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-b', '-p', '-o', 'NAME,FSTYPE,LABEL,SIZE,MOUNTPOINTS', device_path],
+                                capture_output=True, text=True, check=True)
+        nodes = json.loads(result.stdout).get('blockdevices', [])
+    except Exception:
+        return []
+    found = []
+    while nodes:
+        found += [n for n in nodes if n.get('fstype') in FOREIGN_FILESYSTEMS]
+        nodes = [child for n in nodes for child in n.get('children', [])]
+
+    summary = []
+    for fs in found:
+        entry = {'device': fs['name'], 'fstype': FOREIGN_FILESYSTEMS[fs['fstype']], 'label': fs.get('label'),
+                 'size_bytes': int(fs.get('size') or 0), 'used_bytes': None,
+                 'last_os': hfs_last_mounted(fs['name']) if fs['fstype'] == 'hfsplus' else None}
+        mounted = [m for m in fs.get('mountpoints') or [] if m]
+        mount_point = mounted[0] if mounted else None
+        if mount_point is None:
+            mount_point = tempfile.mkdtemp(prefix="liveraid-peek-")
+            if subprocess.run(['mount', '-o', 'ro', fs['name'], mount_point], capture_output=True).returncode != 0:
+                os.rmdir(mount_point)
+                summary.append(entry)
+                continue
+        try:
+            stat = os.statvfs(mount_point)
+            entry['used_bytes'] = (stat.f_blocks - stat.f_bfree) * stat.f_frsize
+            entry['last_os'] = guess_os(mount_point) or entry['last_os']
+        finally:
+            if not mounted:
+                if subprocess.run(['umount', mount_point], capture_output=True).returncode == 0:
+                    os.rmdir(mount_point)
+        summary.append(entry)
+    return summary
+
+def describe_foreign_data(entry):
+    text = f"{entry['device']}: {entry['fstype']}" + (f" '{entry['label']}'" if entry['label'] else "")
+    if entry['used_bytes'] is not None:
+        text += f", {units.format_size(entry['used_bytes'])} used of {units.format_size(entry['size_bytes'])}"
+    else:
+        text += f", {units.format_size(entry['size_bytes'])} (could not be mounted to check the used space)"
+    if entry['last_os']:
+        text += f", last used by {entry['last_os']}"
+    return text
+
+def measure_read_speed(device_path, size_bytes=1024**3):
+    """
+    Reads the first size_bytes of a device with O_DIRECT, bypassing the page cache (nothing is written).
+    Returns the sequential read throughput in bytes per second, or None if the read failed.
+    """
+    started = time.monotonic()
+    try:
+        subprocess.run(['dd', f"if={device_path}", "of=/dev/null", "bs=1M", f"count={size_bytes // 1024**2}", "iflag=direct"],
+                       capture_output=True, check=True)
+    except Exception as e:
+        log.error(f"Read test of {device_path} failed: {e}")
+        return None
+    return size_bytes / max(time.monotonic() - started, 0.001)
+
+def read_drive(device_path):
+    """
+    Returns the get_available_drives() entry of a disk even when it isn't available (mounted, in an array),
+    or None when the device isn't a disk.
+    """
+    name = os.path.basename(os.path.realpath(device_path))
+    if not os.path.isdir(f"/sys/block/{name}"):
+        return None
+    entry = read_sysfs_block_device(name)
+    if entry is None:
+        try:
+            entry = next(iter(discover_lsblk([f"/dev/{name}"])), None)
+        except Exception as e:
+            log.error(f"Failed to inspect /dev/{name}: {e}")
+            return None
+    return describe_drive(entry) if entry else None
+
+def parse_mdstat():
+    """
+    Reads /proc/mdstat once and returns every md device with its sync progress, so polling
+    many arrays doesn't re-read and re-parse the file for each of them.
+    Returns: {'/dev/md0': {'name': '/dev/md0', 'status': 'active', 'type': 'raid5', 'raid_disks': 3, 'working': 2,
+                           'sync': {'action': 'recovery', 'percent': 8.4, 'finish': '0.7min', 'speed': '22208K/sec', 'eta_seconds': 42}}}
+    """
+    try:
+        lines = read_mdstat().splitlines()
+    except Exception as e:
+        log.error(f"Failed to parse /proc/mdstat: {e}")
+        return {}
+
+    arrays = {}
+    current = None
+    for line in lines:
+        # Look for lines like: md0 : active raid1 sdb[1] sda[0]
+        if line.startswith("md") and ":" in line:
+            parts = line.split(":")
+            details = parts[1].strip().split()
+            current = None
+            if len(details) >= 2:
+                # 'active (auto-read-only) raid1 ...'; inactive arrays list their members without a level
+                words = [d for d in details[1:] if not d.startswith("(")]
+                raid_type = words[0] if words and "[" not in words[0] else None
+                current = {'name': f"/dev/{parts[0].strip()}", 'status': details[0], 'type': raid_type,
+                           'raid_disks': None, 'working': None, 'sync': None}
+                arrays[current['name']] = current
+            continue
+        if current is None:
+            continue
+        # e.g. 2095104 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/2] [UU_]
+        match = re.search(r'\[(\d+)/(\d+)\]', line)
+        if match:
+            current['raid_disks'], current['working'] = int(match.group(1)), int(match.group(2))
+        # e.g. [=>...................]  resync =  8.4% (88832/1046528) finish=0.7min speed=22208K/sec
+        match = re.search(r'(resync|recovery|reshape|check|repair)\s*=\s*([\d.]+)%.*?finish=(\S+)\s+speed=(\S+)', line)
+        if match:
+            # The kernel always reports the estimate in minutes
+            finish = re.match(r'([\d.]+)min', match.group(3))
+            current['sync'] = {'action': match.group(1), 'percent': float(match.group(2)), 'finish': match.group(3), 'speed': match.group(4),
+                               'eta_seconds': round(float(finish.group(1)) * 60) if finish else None}
+            continue
+        # Queued behind another array sharing the same disks
+        match = re.search(r'(resync|recovery|reshape|check|repair)\s*=\s*(DELAYED|PENDING)', line)
+        if match:
+            current['sync'] = {'action': match.group(1), 'percent': 0.0, 'finish': match.group(2).lower(), 'speed': '', 'eta_seconds': None}
+    return arrays
 
 def get_active_arrays():
     """
     Parses /proc/mdstat to return a list of currently running md devices.
     Returns: [{'name': '/dev/md0', 'type': 'raid1', 'status': 'active'}]
     """
-    arrays = []
+    return [{'name': arr['name'], 'status': arr['status'], 'type': arr['type']} for arr in parse_mdstat().values()]
+
+def get_drive_serial(device_path):
     try:
-        with open('/proc/mdstat', 'r') as f:
-            lines = f.readlines()
-            
-        for line in lines:
-            line = line.strip()
-            # Look for lines like: md0 : active raid1 sdb[1] sda[0]
-            if line.startswith("md") and ":" in line:
-                parts = line.split(":")
-                md_name = parts[0].strip()
-                details = parts[1].strip().split()
-                if len(details) >= 2:
-                    status = details[0] # active or inactive
-                    raid_type = details[1]
-                    arrays.append({
-                        'name': f"/dev/{md_name}",
-                        'status': status,
-                        'type': raid_type
-                    })
-        return arrays
+        result = subprocess.run(['lsblk', '-d', '-n', '-o', 'SERIAL', device_path], capture_output=True, text=True, check=True)
+        return result.stdout.strip() or None
+    except Exception:
+        return None
+
+def drive_identity(drive):
+    # What identifies the physical disk behind a /dev/sdX name, which can change across reboots or hotplug
+    return {'serial': drive.get('serial'), 'wwn': drive.get('wwn'), 'size_bytes': drive.get('size_bytes')}
+
+def get_device_identity(device_path):
+    """
+    Returns: {'serial': 'WD-XYZ', 'wwn': '0x50014ee2b1c2d3e4', 'size_bytes': 1000204886016}, or None if the device is gone.
+    Read the same way get_available_drives() found the disk, so the values compare equal.
+    """
+    if SIMULATED_DRIVES is not None:
+        return next((drive_identity(d) for d in SIMULATED_DRIVES if d['name'] == device_path), None)
+    name = os.path.basename(os.path.realpath(device_path))
+    dev = read_sysfs_block_device(name) if os.path.isdir(f"/sys/block/{name}") else None
+    if dev is not None:
+        return {'serial': (dev['serial'] or '').strip() or None, 'wwn': dev['wwn'] or None, 'size_bytes': dev['size']}
+    try:
+        result = subprocess.run(['lsblk', '-J', '-d', '-b', '-o', 'SERIAL,WWN,SIZE', device_path], capture_output=True, text=True, check=True)
+        dev = json.loads(result.stdout)['blockdevices'][0]
+    except Exception:
+        return None
+    return {'serial': (dev.get('serial') or '').strip() or None, 'wwn': dev.get('wwn') or None, 'size_bytes': int(dev.get('size') or 0)}
+
+def verify_device_identities(expected):
+    """
+    Re-checks, right before a destructive step, that each device path still points at the disk that
+    was selected: {'/dev/sdb': drive_identity(...), ...}. Returns a list of errors, empty when all match.
+    """
+    errors = []
+    for device, identity in expected.items():
+        current = get_device_identity(device)
+        if current is None:
+            errors.append(f"{device} is no longer present.")
+            continue
+        for key, label in (('serial', "serial"), ('wwn', "WWN")):
+            if identity.get(key) and current[key] != identity[key]:
+                errors.append(f"{device} now has {label} {current[key] or '(none)'}, but {identity[key]} was selected. "
+                              "The disk was swapped or the names were reshuffled, re-scan and select the disks again.")
+                break
+        else:
+            # Disks without serial/WWN (some VMs, USB bridges) can at least be told apart by size
+            if not identity.get('serial') and not identity.get('wwn') and identity.get('size_bytes') not in (None, current['size_bytes']):
+                errors.append(f"{device} changed size since it was selected, re-scan and select the disks again.")
+    return errors
+
+def parse_member_table(detail_out):
+    """
+    Parses the device table at the end of `mdadm --detail` into one entry per slot/member.
+    Slots without a device ('removed') are kept so callers can see which position is missing.
+    """
+    members = []
+    in_table = False
+    for line in detail_out.split('\n'):
+        parts = line.split()
+        if parts[:2] == ['Number', 'Major']:
+            in_table = True
+            continue
+        if not in_table or len(parts) < 5:
+            continue
+
+        # Number Major Minor RaidDevice State... [/dev/xxx]
+        number, raid_device = parts[0], parts[3]
+        device = parts[-1] if parts[-1].startswith('/dev/') else None
+        state = " ".join(parts[4:-1] if device else parts[4:])
+
+        if 'faulty' in state:
+            role = 'faulty'
+        elif 'rebuilding' in state:
+            role = 'rebuilding'
+        elif 'spare' in state:
+            role = 'spare'
+        elif 'removed' in state:
+            role = 'removed'
+        else:
+            role = 'active'
+
+        members.append({
+            'number': int(number) if number.isdigit() else None,
+            'slot': int(raid_device) if raid_device.isdigit() else None,
+            'device': device,
+            'state': state,
+            'role': role
+        })
+    return members
+
+def get_array_members(array_name, serials=None):
+    """
+    Returns which member occupies which slot of an array, including spare, faulty and empty slots.
+    `serials` ({'/dev/sda': 'WD-XYZ'} from get_drive_serials()) saves one lsblk call per member.
+    Returns: [{'slot': 0, 'number': 0, 'device': '/dev/sda', 'state': 'active sync', 'role': 'active', 'serial': 'WD-XYZ'}]
+    """
+    try:
+        detail = query(['mdadm', '--detail', array_name])
     except Exception as e:
-        print(f"Error parsing /proc/mdstat: {e}")
+        log.error(f"Failed to read details of {array_name}: {e}")
+        return []
+
+    members = parse_member_table(detail)
+    for member in members:
+        # The serial number is what identifies the physical disk to pull from the chassis
+        if not member['device']:
+            member['serial'] = None
+        elif serials is not None:
+            member['serial'] = serials.get(member['device'])
+        else:
+            member['serial'] = get_drive_serial(member['device'])
+    # Active slots in order first, then spares/faulty members which have no slot
+    return sorted(members, key=lambda m: (m['slot'] is None, m['slot'] if m['slot'] is not None else m['number'] or 0))
+
+def get_drive_serials():
+    """
+    Returns the serial number of every block device (disks and partitions) from a single lsblk call.
+    Returns: {'/dev/sda': 'WD-XYZ', '/dev/sda1': 'WD-XYZ'}
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,SERIAL'], capture_output=True, text=True, check=True)
+        nodes = json.loads(result.stdout).get('blockdevices', [])
+    except Exception as e:
+        log.error(f"Failed to read drive serials: {e}")
+        return {}
+    serials = {}
+
+    def walk(node, parent_serial):
+        # Partitions have no serial of their own, they belong to their disk's
+        serial = (node.get('serial') or '').strip() or parent_serial
+        serials[node['name']] = serial
+        for child in node.get('children', []):
+            walk(child, serial)
+
+    for node in nodes:
+        walk(node, None)
+    return serials
+
+def get_arrays_status(array_names=None):
+    """
+    Returns the state and members of many arrays at once: /proc/mdstat and the drive serials are read once,
+    the `mdadm --detail` calls run in parallel (at most STATUS_WORKERS at a time).
+    Returns: [{'name': '/dev/md0', 'status': 'active', 'type': 'raid5', 'raid_disks': 3, 'working': 2,
+               'sync': {...} or None, 'members': [...as get_array_members()...],
+               'health': 'rebuilding', 'failed': ['/dev/sdc'], 'rebuilding': ['/dev/sdd']}]
+    health is one of array_health()'s values.
+    """
+    mdstat = parse_mdstat()
+    names = list(array_names) if array_names else list(mdstat)
+    serials = get_drive_serials()
+    # Only running arrays have member details; unknown names are reported as missing
+    wanted = [name for name in names if name in mdstat]
+    members = {}
+    if wanted:
+        with concurrent.futures.ThreadPoolExecutor(max_workers=min(STATUS_WORKERS, len(wanted))) as pool:
+            for name, result in zip(wanted, pool.map(lambda n: get_array_members(n, serials), wanted)):
+                members[name] = result
+
+    statuses = []
+    for name in names:
+        if name in mdstat:
+            status = dict(mdstat[name], members=members[name])
+        else:
+            status = {'name': name, 'status': "missing", 'type': None, 'raid_disks': None, 'working': None,
+                      'sync': None, 'members': []}
+        status['failed'] = [m['device'] for m in status['members'] if m['role'] == 'faulty']
+        status['rebuilding'] = [m['device'] for m in status['members'] if m['role'] == 'rebuilding']
+        status['health'] = array_health(status)
+        statuses.append(status)
+    return statuses
+
+def array_health(status):
+    """
+    Sums up an array's state (parse_mdstat() entry) in one word:
+    'healthy', 'resyncing' (initial sync, check or reshape running), 'rebuilding' (redundancy being restored onto a member),
+    'degraded' (members missing, nothing rebuilding), 'inactive' (assembled but not running) or 'missing'.
+    """
+    if status['status'] == "missing":
+        return "missing"
+    if status['status'] != "active":
+        return "inactive"
+    sync = status['sync'] or {}
+    if status['raid_disks'] is not None and status['working'] < status['raid_disks']:
+        return "rebuilding" if sync.get('action') == "recovery" else "degraded"
+    return "resyncing" if sync else "healthy"
+
+def get_drive_temperature(device_path):
+    """
+    Returns the drive temperature in °C as reported by smartctl, or None if unavailable.
+    """
+    try:
+        # smartctl uses non-zero exit bits for health warnings, so don't treat them as failures
+        result = subprocess.run(['smartctl', '-A', '-j', device_path], capture_output=True, text=True)
+        return json.loads(result.stdout).get('temperature', {}).get('current')
+    except Exception:
+        return None
+
+def get_smart_report(device_path, wake=True):
+    """
+    Summarises the SMART state of a drive (ATA and NVMe). Without wake a drive in standby is left asleep
+    and reported with every value None.
+    Returns: {'passed': True, 'reallocated': 0, 'pending': 0, 'power_on_hours': 41230, 'self_test_running': False,
+              'last_self_test_passed': True}
+    """
+    report = {'passed': None, 'reallocated': None, 'pending': None, 'power_on_hours': None, 'self_test_running': False,
+              'last_self_test_passed': None}
+    try:
+        # A drive that doesn't answer (a hung USB bridge) must not stall discovery
+        result = subprocess.run(['smartctl', '-a', '-j'] + ([] if wake else ['-n', 'standby']) + [device_path],
+                                capture_output=True, text=True, timeout=SMART_TIMEOUT)
+        data = json.loads(result.stdout)
+    except Exception as e:
+        log.error(f"Failed to read SMART data of {device_path}: {e}")
+        return report
+
+    report['passed'] = data.get('smart_status', {}).get('passed')
+    report['power_on_hours'] = data.get('power_on_time', {}).get('hours')
+    for attr in data.get('ata_smart_attributes', {}).get('table', []):
+        if attr.get('id') == 5:
+            report['reallocated'] = attr.get('raw', {}).get('value')
+        elif attr.get('id') == 197:
+            report['pending'] = attr.get('raw', {}).get('value')
+
+    # ATA reports the running test under ata_smart_data, NVMe under its self-test log
+    report['self_test_running'] = 'remaining_percent' in data.get('ata_smart_data', {}).get('self_test', {}).get('status', {})
+    tests = data.get('ata_smart_self_test_log', {}).get('standard', {}).get('table', [])
+    if tests:
+        report['last_self_test_passed'] = tests[0].get('status', {}).get('passed')
+    nvme_log = data.get('nvme_self_test_log', {})
+    if nvme_log:
+        report['self_test_running'] = nvme_log.get('current_self_test_operation', {}).get('value', 0) != 0
+        results = nvme_log.get('table', [])
+        if results:
+            report['last_self_test_passed'] = results[0].get('self_test_result', {}).get('value') == 0
+    return report
+
+def smart_health(report):
+    """
+    Returns: 'failing' (the drive's own assessment or its last self-test failed), 'warning' (smart_sector_warn or
+    more reallocated + pending sectors), 'healthy', or None when SMART is unavailable.
+    """
+    if report['passed'] is False or report['last_self_test_passed'] is False:
+        return "failing"
+    if report['passed'] is None:
+        return None
+    if (report['reallocated'] or 0) + (report['pending'] or 0) >= config.get_setting('smart_sector_warn'):
+        return "warning"
+    return "healthy"
+
+def get_drive_health(device_path):
+    """
+    The SMART summary discovery attaches to every drive ('smart'), all None when smart_discovery is off
+    or smartctl is missing.
+    Returns: {'health': 'warning', 'reallocated': 8, 'pending': 0, 'power_on_hours': 41230}
+    """
+    if not config.get_setting('smart_discovery') or shutil.which("smartctl") is None:
+        return dict(NO_SMART)
+    # Listing drives must not spin up the ones that sleep, they report no health until they are awake
+    report = get_smart_report(device_path, wake=False)
+    return {'health': smart_health(report), 'reallocated': report['reallocated'], 'pending': report['pending'],
+            'power_on_hours': report['power_on_hours']}
+
+def smart_strict(strict=None):
+    return config.get_setting('smart_strict') if strict is None else strict
+
+def smart_findings(drives):
+    """
+    The SMART problems of drives about to join an array.
+    Returns: [('smart-failing', '/dev/sdb reports failing SMART health ...'), ('smart-warning', '...')]
+    """
+    findings = []
+    for d in drives:
+        smart = d.get('smart') or {}
+        if smart.get('health') == "failing":
+            findings.append(("smart-failing", f"{d['name']} reports failing SMART health, it is likely to drop out of the array soon. Use another disk"))
+        elif smart.get('health') == "warning":
+            sectors = (smart.get('reallocated') or 0) + (smart.get('pending') or 0)
+            findings.append(("smart-warning", f"{d['name']} has {sectors} reallocated or pending sectors, watch it closely"))
+    return findings
+
+def start_smart_test(device_path, test="short"):
+    return run_command(["smartctl", "-t", test, device_path])
+
+def get_parent_disk(device_path):
+    """
+    Returns the disk a partition belongs to ('/dev/sda3' -> '/dev/sda'), or the device itself if it is a whole disk.
+    """
+    name = os.path.basename(os.path.realpath(device_path))
+    sys_path = f"/sys/class/block/{name}"
+    if os.path.exists(f"{sys_path}/partition"):
+        return "/dev/" + os.path.basename(os.path.dirname(os.path.realpath(sys_path)))
+    return device_path
+
+# Mount points that tie a disk to the running system: the installed root and its boot partitions, or the live medium
+BOOT_MOUNT_POINTS = ("/", "/boot", "/boot/efi", "/efi", "/run/live/medium", "/run/initramfs/live", "/run/archiso/bootmnt", "/cdrom")
+
+def get_boot_disks():
+    """
+    Returns the disks holding the root filesystem, /boot or the live medium, e.g. ['/dev/sda'].
+    Discovery only skips disks mounted as a whole, these usually carry partitions instead.
+    """
+    disks = set()
+    for major_minor, mount_points in get_block_mountpoints().items():
+        if not any(m in BOOT_MOUNT_POINTS for m in mount_points):
+            continue
+        try:
+            name = os.path.basename(os.path.realpath(f"/sys/dev/block/{major_minor}"))
+        except OSError:
+            continue
+        # / on an array or LUKS mapping: the disks underneath are in use and never offered anyway
+        if name.startswith(("md", "dm-")):
+            continue
+        disks.add(get_parent_disk(f"/dev/{name}"))
+    return sorted(disks)
+
+def read_md_attribute(array_name, attribute):
+    """
+    Reads a value below /sys/block/mdX/md/, e.g. 'degraded' or 'dev-sda/errors'. Returns None if missing.
+    """
+    try:
+        with open(f"{md_sysfs_dir(array_name)}/{attribute}", 'r') as f:
+            return f.read().strip()
+    except Exception:
+        return None
+
+def get_sync_progress(array_name):
+    """
+    Returns the resync/recovery/reshape/check currently running on an array, or None when it is idle.
+    Returns: {'action': 'resync', 'percent': 8.4, 'finish': '0.7min', 'speed': '22208K/sec'}
+    """
+    md_name = os.path.basename(os.path.realpath(array_name))
+    return parse_mdstat().get(f"/dev/{md_name}", {}).get('sync')
+
+def parse_duration(text):
+    """
+    Parses '90', '90s', '30m' or '6h' into seconds.
+    """
+    units = {'s': 1, 'm': 60, 'h': 3600, 'd': 86400}
+    text = str(text).strip().lower()
+    if text and text[-1] in units:
+        return float(text[:-1]) * units[text[-1]]
+    return float(text)
+
+def wait_for_sync(array_name, timeout=None, on_progress=None, poll_interval=5, cancelled=None):
+    """
+    Blocks until the array has no resync/recovery running, calling on_progress(progress) on every poll.
+    Returns (False, ...) if the timeout (in seconds) expires or cancelled() (or the thread's runner.cancellation())
+    returns True first; the sync keeps running in that case.
+    """
+    if DRY_RUN:
+        script.record_command(["mdadm", "--wait", array_name])
+        return True, f"[DRY RUN] Would wait for {array_name} to finish syncing\n"
+
+    deadline = time.monotonic() + timeout if timeout else None
+    while True:
+        progress = get_sync_progress(array_name)
+        if progress is None:
+            return True, f"{array_name} is fully synchronised.\n"
+        if on_progress:
+            on_progress(progress)
+        if deadline and time.monotonic() >= deadline:
+            return False, f"Timed out waiting for {array_name}: {progress['action']} at {progress['percent']}%\n"
+        if (cancelled and cancelled()) or runner.cancel_requested():
+            return False, f"Stopped waiting for {array_name}: {progress['action']} at {progress['percent']}%\n"
+        time.sleep(poll_interval)
+
+def pause_sync(array_name):
+    """
+    Freezes an array's resync/recovery/check where it is (sync_action 'frozen'), resume_sync() lets it go on from there.
+    """
+    return write_sysfs(f"{md_sysfs_dir(array_name)}/sync_action", "frozen")
+
+def sync_checkpoint(array_name):
+    """
+    How far the running sync pass got, in sectors (sync_completed), for resuming a check or repair after a freeze.
+    Returns: 8388608, or None when no pass runs
+    """
+    value = read_md_attribute(array_name, "sync_completed") or ""
+    done = value.split("/")[0].strip()
+    return int(done) if done.isdigit() else None
+
+def resume_sync(array_name, action=None, position=None):
+    """
+    Lets a frozen pass go on. 'idle' lifts the freeze and md restarts an interrupted resync, recovery or reshape
+    from its checkpoint; a check or repair (action) would just end there, so it is started again from position
+    (sectors, see sync_checkpoint()).
+    """
+    sysfs_dir = md_sysfs_dir(array_name)
+    if action not in ("check", "repair"):
+        return write_sysfs(f"{sysfs_dir}/sync_action", "idle")
+    logs = []
+    if position:
+        # md only takes a sync_min on a chunk boundary
+        chunk = read_md_attribute(array_name, "chunk_size")
+        sectors = int(chunk) // 512 if chunk and chunk.isdigit() and int(chunk) else 1
+        success, out = write_sysfs(f"{sysfs_dir}/sync_min", str(position - position % sectors))
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+    success, out = write_sysfs(f"{sysfs_dir}/sync_action", action)
+    logs.append(out)
+    return success, "".join(logs)
+
+def get_member_errors(array_name):
+    """
+    Returns the read errors md has corrected per member, e.g. {'/dev/sda': 0, '/dev/sdb': 12}.
+    """
+    errors = {}
+    for member in get_array_members(array_name):
+        if member['device']:
+            value = read_md_attribute(array_name, f"dev-{os.path.basename(os.path.realpath(member['device']))}/errors")
+            errors[member['device']] = int(value) if value and value.isdigit() else 0
+    return errors
+
+def get_array_uuid(array_name):
+    """
+    Returns the md UUID of an array (colon separated, as used in mdadm.conf), or None.
+    """
+    try:
+        detail = query(['mdadm', '--detail', '--export', array_name])
+    except Exception:
+        return None
+    for line in detail.split('\n'):
+        if line.startswith('MD_UUID='):
+            return line.split('=', 1)[1].strip()
+    return None
+
+def get_mountpoints(device_path):
+    """
+    Returns every mountpoint of a device and of the partitions on it.
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,MOUNTPOINTS', device_path], capture_output=True, text=True, check=True)
+        data = json.loads(result.stdout)
+    except Exception:
         return []
 
-def delete_raid(array_name):
+    mountpoints = []
+    def visit(node):
+        mountpoints.extend(m for m in node.get('mountpoints', []) if m is not None)
+        for child in node.get('children', []):
+            visit(child)
+    for block_device in data.get('blockdevices', []):
+        visit(block_device)
+    return mountpoints
+
+def destroy_array(array_name, zero_superblocks=True, wipe_members=True):
     """
-    Stops a running RAID array and zeroes the superblocks of its constituent devices.
+    Tears down exactly one array: unmounts it, stops it and (optionally) clears the md superblocks
+    and signatures of its own members. Other arrays on the host are never touched.
+    array_name may be /dev/md0, md0 or /dev/md/<name>. A superblock that can't be cleared fails the call.
     """
+    # /proc/mdstat lists the kernel name, /dev/md/<name> links to it
+    device = "/dev/" + os.path.basename(os.path.realpath(array_name))
+    if not any(arr['name'] == device for arr in get_active_arrays()):
+        return False, f"{array_name} is not a running md array.\n"
+
     logs = []
     
     # 1. Identify constituent devices before stopping
-    success, detail_out = run_command(["mdadm", "--detail", array_name])
-    if not success:
-        return False, f"Failed to get details for {array_name}.\n{detail_out}\n"
-    
-    devices_to_zero = []
-    for line in detail_out.split('\n'):
-        if "/dev/sd" in line or "/dev/nvme" in line or "/dev/hd" in line:
-            parts = line.split()
-            device_path = parts[-1]
-            if device_path.startswith("/dev/"):
-                devices_to_zero.append(device_path)
+    devices_to_zero = [m['device'] for m in get_array_members(device) if m['device']]
+    if not devices_to_zero:
+        return False, f"Failed to get details for {array_name}.\n"
                 
-    # 2. Unmount just in case (we ignore errors if it wasn't mounted)
-    run_command(["umount", "-f", array_name], dry_run=False) # Always attempt real umount if needed, but not critical to logs usually
+    # 2. Unmount the array and any partition on it
+    for mp in get_mountpoints(device):
+        success, out = run_command(["umount", mp])
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
     
     # 3. Stop the array
-    success, stop_out = run_command(["mdadm", "--stop", array_name])
+    success, stop_out = run_command(["mdadm", "--stop", device])
     logs.append(stop_out)
     if not success:
         return False, "".join(logs)
         
     # 4. Zero the superblocks of the raw drives so they appear "blank" again
+    failed = []
     for dev in devices_to_zero:
-        success, zero_out = run_command(["mdadm", "--zero-superblock", dev])
-        logs.append(zero_out)
-        # Attempt to wipe thoroughly so lsblk updates instantly
-        run_command(["wipefs", "-a", dev])
+        if zero_superblocks:
+            success, zero_out = run_command(["mdadm", "--zero-superblock", dev])
+            logs.append(zero_out)
+            if not success:
+                # The array would be assembled again from this member at the next boot, the others are still cleared
+                failed.append(dev)
+                continue
+        if wipe_members:
+            # Attempt to wipe thoroughly so lsblk updates instantly
+            run_command(["wipefs", "-a", dev])
         
     # Flush udev so lsblk reflects the changes immediately
     run_command(["udevadm", "settle", "--timeout=2"])
-        
+
+    if failed:
+        logs.append(f"ERROR: {array_name} is stopped, but the md superblock of {', '.join(failed)} could not be cleared. "
+                    f"Run 'mdadm --zero-superblock {' '.join(failed)}' before the disks are reused.\n")
+        return False, "".join(logs)
     return True, "".join(logs)
     
-def create_raid(level, device_paths, array_name="/dev/md0", chunk_size="Default", ssd_mode=False):
-    num_devices = len(device_paths)
-    if num_devices == 0:
-        return False, "No devices selected for RAID.\n"
-    
+def get_volume_group(pv_path):
+    """
+    Returns the LVM volume group name a physical volume belongs to, or None.
+    """
+    try:
+        result = subprocess.run(['pvs', '--noheadings', '-o', 'vg_name', pv_path], capture_output=True, text=True, check=True)
+        vg_name = result.stdout.strip()
+        return vg_name or None
+    except Exception:
+        return None
+
+def plan_teardown(disk):
+    """
+    Walks everything layered on top of a disk (partitions, md arrays, LUKS mappings, LVM volumes)
+    and returns the ordered list of commands needed to release it, deepest layer first.
+    Returns: [['umount', '/mnt/data'], ['mdadm', '--stop', '/dev/md0'], ..., ['wipefs', '-a', '/dev/sdb']]
+    """
+    result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,TYPE,FSTYPE,MOUNTPOINTS', disk], capture_output=True, text=True, check=True)
+    data = json.loads(result.stdout)
+
+    steps = []
+    seen = set()  # md arrays show up once under every member, only tear them down once
+    volume_groups = []
+    wipe_targets = []
+
+    def visit(node):
+        name = node.get('name')
+        if name in seen:
+            return
+        seen.add(name)
+
+        # Children (things built on top of this device) must be released first
+        for child in node.get('children', []):
+            visit(child)
+
+        dev_type = node.get('type') or ''
+        fstype = node.get('fstype') or ''
+        mountpoints = [m for m in node.get('mountpoints', []) if m is not None]
+
+        for mp in mountpoints:
+            if mp == '[SWAP]':
+                steps.append(["swapoff", name])
+            else:
+                steps.append(["umount", mp])
+
+        if dev_type == 'crypt':
+            steps.append(["cryptsetup", "close", name.split('/')[-1]])
+        elif dev_type == 'lvm':
+            steps.append(["lvchange", "-an", name])
+        elif dev_type.startswith('raid'):
+            steps.append(["mdadm", "--stop", name])
+        elif dev_type in ('disk', 'part'):
+            if fstype == 'LVM2_member':
+                vg_name = get_volume_group(name)
+                if vg_name and vg_name not in volume_groups:
+                    volume_groups.append(vg_name)
+                    steps.append(["vgchange", "-an", vg_name])
+            if fstype == 'linux_raid_member':
+                steps.append(["mdadm", "--zero-superblock", name])
+            wipe_targets.append(name)
+
+    for block_device in data.get('blockdevices', []):
+        visit(block_device)
+
+    # Wipe signatures last: partitions before the disk that carries their table
+    for target in wipe_targets:
+        steps.append(["wipefs", "-a", target])
+
+    return steps
+
+def teardown_disk(disk, dry_run=None):
+    """
+    Deactivates every layer stacked on a disk (unmount, swapoff, LVM, LUKS, md) in the correct order
+    and wipes its signatures so it can be reused in a new array.
+    """
+    try:
+        steps = plan_teardown(disk)
+    except Exception as e:
+        return False, f"Failed to inspect {disk}: {e}\n"
+
+    logs = []
+    for cmd in steps:
+        success, out = run_command(cmd, dry_run=dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+
+    # Flush udev so lsblk reflects the changes immediately
+    run_command(["udevadm", "settle", "--timeout=2"], dry_run=dry_run)
+
+    return True, "".join(logs)
+
+def examine_member(device_path):
+    """
+    Reads the md superblock on a device, if any.
+    Returns: {'uuid': '...', 'level': 'raid1', 'name': 'host:0', 'devices': 2} or None
+    """
+    try:
+        examined = query(['mdadm', '--examine', '--export', device_path])
+    except Exception:
+        return None
+
+    info = {}
+    for line in examined.split('\n'):
+        if '=' in line:
+            key, value = line.split('=', 1)
+            info[key] = value.strip()
+    if 'MD_UUID' not in info:
+        return None
+    return {
+        'uuid': info['MD_UUID'],
+        'level': info.get('MD_LEVEL', 'unknown'),
+        'name': info.get('MD_NAME', ''),
+        'devices': int(info['MD_DEVICES']) if info.get('MD_DEVICES', '').isdigit() else None
+    }
+
+def find_old_arrays(disks):
+    """
+    Groups leftover md superblocks found on the given disks (or their partitions) by array.
+    Returns: [{'uuid': '...', 'level': 'raid1', 'name': 'host:0', 'devices': 2, 'members': ['/dev/sdb1'], 'disks': ['/dev/sdb']}]
+    """
+    arrays = {}
+    for disk in disks:
+        try:
+            result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,FSTYPE', disk], capture_output=True, text=True, check=True)
+            data = json.loads(result.stdout)
+        except Exception:
+            continue
+
+        candidates = []
+        for block_device in data.get('blockdevices', []):
+            candidates.append(block_device)
+            candidates.extend(block_device.get('children', []))
+
+        for dev in candidates:
+            if dev.get('fstype') != 'linux_raid_member':
+                continue
+            info = examine_member(dev['name'])
+            if info is None:
+                continue
+            entry = arrays.setdefault(info['uuid'], dict(info, members=[], disks=[]))
+            entry['members'].append(dev['name'])
+            if disk not in entry['disks']:
+                entry['disks'].append(disk)
+    return list(arrays.values())
+
+def teardown_old_array(old, dry_run=None):
+    """
+    Stops an array found by find_old_arrays() if it is assembled and clears the md superblocks of its members.
+    Other partitions, filesystems and signatures on the disks are left alone.
+    Returns: (success, log_str)
+    """
+    logs = []
+    for arr in get_active_arrays():
+        if get_array_uuid(arr['name']) != old['uuid']:
+            continue
+        success, out = run_command(["mdadm", "--stop", arr['name']], dry_run=dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+    for member in old['members']:
+        success, out = run_command(["mdadm", "--zero-superblock", member], dry_run=dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+
+    # Flush udev so lsblk reflects the changes immediately
+    run_command(["udevadm", "settle", "--timeout=2"], dry_run=dry_run)
+    return True, "".join(logs)
+
+def get_device_contents(device_path):
+    """
+    Reports what already lives on a device so a pre-created array can be reused safely.
+    Returns a list of human readable findings, empty when the device carries no partition table,
+    filesystem or partitions. Returns None if the device could not be inspected.
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,TYPE,FSTYPE,PTTYPE', device_path], capture_output=True, text=True, check=True)
+        data = json.loads(result.stdout)
+    except Exception as e:
+        log.error(f"Failed to inspect {device_path}: {e}")
+        return None
+
+    findings = []
+    for block_device in data.get('blockdevices', []):
+        if block_device.get('pttype'):
+            findings.append(f"{block_device.get('pttype')} partition table")
+        if block_device.get('fstype'):
+            findings.append(f"{block_device.get('fstype')} filesystem")
+        for child in block_device.get('children', []):
+            desc = child.get('fstype') or "unformatted"
+            findings.append(f"partition {child.get('name')} ({desc})")
+    return findings
+
+def get_used_raid_drives_of(array_name):
+    # Members as listed on the array's /proc/mdstat line, e.g. {'sdg'}
+    md_name = os.path.basename(array_name)
+    try:
+        for line in read_mdstat().splitlines():
+            if line.split(":")[0].strip() == md_name:
+                return {p.split('[')[0] for p in line.split()[3:] if '[' in p}
+    except Exception:
+        pass
+    return set()
+
+def find_stale_md_devices():
+    """
+    Finds md leftovers of earlier failed or interrupted runs, which make `mdadm --create` fail with
+    "already in use" or silently hold on to disks:
+      inactive      - an array md assembled partially (e.g. from old superblocks) but never started
+      stale-node    - a /dev/mdN node without a kernel md device behind it
+      dangling-link - a /dev/md/<name> link pointing at a device that is gone
+    Returns: [{'path': '/dev/md127', 'kind': 'inactive', 'detail': 'holds sdg'}]
+    """
+    stale = []
+    mdstat = parse_mdstat()
+    for name, arr in mdstat.items():
+        if arr['status'] == "inactive":
+            members = sorted(d for d in get_used_raid_drives_of(name))
+            stale.append({'path': name, 'kind': "inactive", 'detail': f"holds {', '.join(members) or 'no members'}"})
+    try:
+        nodes = sorted(n for n in os.listdir("/dev") if re.fullmatch(r"md\d+", n))
+    except Exception:
+        nodes = []
+    for node in nodes:
+        if f"/dev/{node}" not in mdstat and not os.path.exists(f"/sys/block/{node}/md"):
+            stale.append({'path': f"/dev/{node}", 'kind': "stale-node", 'detail': "no md device behind it"})
+    try:
+        links = sorted(os.listdir("/dev/md"))
+    except Exception:
+        links = []
+    for link in links:
+        path = f"/dev/md/{link}"
+        if os.path.islink(path) and not os.path.exists(path):
+            stale.append({'path': path, 'kind': "dangling-link", 'detail': f"points at the missing {os.readlink(path)}"})
+    return stale
+
+def cleanup_stale_md(entries, dry_run=None):
+    """
+    Removes what find_stale_md_devices() reported: inactive arrays are stopped (their superblocks and data
+    stay untouched), stale nodes and dangling links are deleted.
+    Returns: (success, log_str)
+    """
+    logs = []
+    success = True
+    for entry in entries:
+        if entry['kind'] == "inactive":
+            ok, out = run_command(["mdadm", "--stop", entry['path']], dry_run)
+        else:
+            ok, out = run_command(["rm", "-f", entry['path']], dry_run)
+        logs.append(out)
+        success = success and ok
+    return success, "".join(logs)
+
+def is_array_active(array_name):
+    return any(arr['name'] == array_name and arr['status'] == 'active' for arr in get_active_arrays())
+
+def build_create_command(level, device_paths, array_name, chunk_size="Default", ssd_mode=False,
+                         write_mostly=None, write_behind=0, spares=None, metadata=None, bitmap=None, layout=None, name=None):
+    """
+    Returns the mdadm --create command for an array. Spares are listed after the active members.
+    metadata, bitmap, layout (RAID 10, a RAID10_LAYOUTS key) and name are left to mdadm when None.
+    """
+    write_mostly = [d for d in (write_mostly or []) if d in device_paths]
+    spares = spares or []
     cmd = [
         "mdadm", "--create", "--verbose", "--run", array_name,
-        f"--level={level}", f"--raid-devices={num_devices}"
+        f"--level={level}", f"--raid-devices={len(device_paths)}"
     ]
+    if spares:
+        cmd.append(f"--spare-devices={len(spares)}")
+    if metadata:
+        cmd.append(f"--metadata={metadata}")
+    if name:
+        cmd.append(f"--name={name}")
+    if layout:
+        cmd.append(f"--layout={RAID10_LAYOUTS[layout]}")
     
     if chunk_size != "Default":
         # Parse '64K' into '64'
@@ -160,37 +1722,148 @@ def create_raid(level, device_paths, array_name="/dev/md0", chunk_size="Default"
         
     if ssd_mode:
         cmd.append("--assume-clean")
+
+    if write_behind:
+        # Write-behind is tracked through the write-intent bitmap
+        cmd.extend(["--bitmap=internal", f"--write-behind={write_behind}"])
+    elif bitmap:
+        cmd.append(f"--bitmap={bitmap}")
         
-    cmd.extend(device_paths)
-    
-    return run_command(cmd)
+    cmd.extend([d for d in device_paths if d not in write_mostly])
+    if write_mostly:
+        # mdadm flags every device listed after --write-mostly
+        cmd.append("--write-mostly")
+        cmd.extend(write_mostly)
+    cmd.extend(spares)
+    return cmd
+
+def create_raid(level, device_paths, array_name, chunk_size="Default", ssd_mode=False,
+                write_mostly=None, write_behind=0, auto_read_only=False, spares=None, metadata=None, bitmap=None, layout=None,
+                name=None):
+    if not device_paths:
+        return False, "No devices selected for RAID.\n"
+
+    write_mostly = [d for d in (write_mostly or []) if d in device_paths]
+    if write_mostly and str(level) != "1":
+        return False, "Write-mostly members are only supported on RAID 1.\n"
+    if write_behind and not write_mostly:
+        return False, "Write-behind requires at least one write-mostly member.\n"
+    if spares and str(level) == "0":
+        return False, "RAID 0 cannot have spare devices.\n"
+    if write_behind and bitmap == "none":
+        return False, "Write-behind needs the write-intent bitmap, it can't be combined with bitmap none.\n"
+    errors = check_md_options(level, metadata, bitmap, layout, name)
+    if errors:
+        return False, "".join(f"{message}\n" for _, message in errors)
 
-def format_device(device_path, fs_type="ext4", boot_flag=False, trim_discard=False):
     logs = []
-    
-    # 1. Create a fresh GPT partition table
-    success, out = run_command(["parted", "-s", device_path, "mklabel", "gpt"])
-    logs.append(out)
-    if not success: return False, "".join(logs)
-    
-    # 2. Create the primary partition using 100% of space
-    success, out = run_command(["parted", "-s", device_path, "mkpart", "primary", "0%", "100%"])
+    if config.get_setting('wipe_policy') != "none":
+        # Stale filesystem/RAID signatures make mdadm prompt or assemble old metadata. The slower
+        # wipe methods only run as the wipe stage of a plan (wipe.py), here the signatures are enough.
+        for dev in list(device_paths) + list(spares or []):
+            success, out = run_command(["wipefs", "-a", dev])
+            logs.append(out)
+            if not success:
+                return False, "".join(logs)
+
+    cmd = build_create_command(level, device_paths, array_name, chunk_size, ssd_mode, write_mostly, write_behind, spares,
+                               metadata, bitmap, layout, name)
+    success, out = run_command(cmd)
     logs.append(out)
-    if not success: return False, "".join(logs)
-    
-    # Give the system a second to register the partition table before tagging or formatting
-    if not DRY_RUN:
-        time.sleep(1)
-        
-    partition_path = f"{device_path}p1"
-    
-    # 3. Apply boot flag if requested
-    if boot_flag:
-        success, out = run_command(["parted", "-s", device_path, "set", "1", "boot", "on"])
+    if success and auto_read_only:
+        success, out = set_array_mode(array_name, "read-auto")
         logs.append(out)
-        if not success: return False, "".join(logs)
-        
-    # 4. Format the partition
+    return success, "".join(logs)
+
+def set_array_mode(array_name, mode):
+    """
+    Switches an array between 'readwrite', 'readonly' and 'read-auto'.
+    read-auto keeps the array read-only (no resync, no superblock updates) until the first write arrives.
+    """
+    if mode == "readonly":
+        return run_command(["mdadm", "--readonly", array_name])
+    if mode == "readwrite":
+        return run_command(["mdadm", "--readwrite", array_name])
+    if mode == "read-auto":
+        return write_sysfs(f"{md_sysfs_dir(array_name)}/array_state", "read-auto")
+    return False, f"Unknown array mode: {mode}\n"
+
+def set_member_write_mostly(array_name, member, enabled=True):
+    """
+    Toggles the write-mostly flag on an existing member, reads are then steered to the other mirrors.
+    """
+    dev_name = os.path.basename(os.path.realpath(member))
+    value = "writemostly" if enabled else "-writemostly"
+    return write_sysfs(f"{md_sysfs_dir(array_name)}/dev-{dev_name}/state", value)
+
+# md's limit for the bitmap backlog (--write-behind)
+MAX_WRITE_BEHIND = 16383
+
+def set_write_behind(array_name, max_writes):
+    """
+    Sets how many writes to write-mostly members may be outstanding. Requires a write-intent bitmap.
+    """
+    return write_sysfs(f"{md_sysfs_dir(array_name)}/bitmap/backlog", str(max_writes))
+
+# Room a new member needs on top of the array's component size (1.2 superblock + data offset)
+REPLACEMENT_HEADROOM = 256 * 1024**2
+
+def needs_replacement(array_name):
+    """
+    True when an array is missing members and no recovery is running yet to fill the gap.
+    """
+    degraded = read_md_attribute(array_name, "degraded")
+    if not degraded or degraded == "0":
+        return False
+    return read_md_attribute(array_name, "sync_action") in (None, "idle")
+
+def find_replacement_candidates(array_name, known=None):
+    """
+    Returns the blank drives large enough to stand in for a failed member of an array.
+    Drives listed in `known` (e.g. everything that was attached when watching started) are skipped,
+    so only newly plugged-in disks are offered.
+    """
+    component_kib = read_md_attribute(array_name, "component_size")
+    if not component_kib or not component_kib.isdigit():
+        return []
+    needed = int(component_kib) * 1024 + REPLACEMENT_HEADROOM
+    known = known or set()
+    return [d for d in get_available_drives(smart=False)
+            if d['name'] not in known and not d['has_data'] and d['size_bytes'] >= needed]
+
+def add_member(array_name, device_path):
+    """
+    Hot-adds a disk to an array. md starts rebuilding onto it right away if the array is degraded.
+    """
+    return run_command(["mdadm", "--manage", array_name, "--add", device_path])
+
+def replace_member(array_name, old_device, new_device):
+    """
+    Copies a member onto a spare that was added before, keeping full redundancy while it runs.
+    md marks the old member faulty once the copy is complete.
+    """
+    return run_command(["mdadm", "--manage", array_name, "--replace", old_device, "--with", new_device])
+
+def remove_member(array_name, device_path):
+    return run_command(["mdadm", "--manage", array_name, "--remove", device_path])
+
+def grow_to_max_size(array_name):
+    # Members were all swapped for larger disks: use all of their space
+    return run_command(["mdadm", "--grow", array_name, "--size=max"])
+
+def grow_raid_devices(array_name, count, backup_file=None):
+    # Reshapes onto count members; the spares added before become active as the reshape reaches them
+    return run_command(["mdadm", "--grow", array_name, f"--raid-devices={count}"] + ([f"--backup-file={backup_file}"] if backup_file else []))
+
+# Volume label flag and maximum label length of each mkfs
+LABEL_FLAGS = {'ext4': "-L", 'btrfs': "-L", 'xfs': "-L", 'f2fs': "-l", 'exfat': "-n", 'ntfs': "-L", 'vfat': "-n"}
+LABEL_MAX_LENGTH = {'ext4': 16, 'btrfs': 255, 'xfs': 12, 'f2fs': 512, 'exfat': 15, 'ntfs': 128, 'vfat': 11}
+
+def build_mkfs_command(fs_type, partition_path, trim_discard=False, extra_options=None, label=None):
+    """
+    Returns the full mkfs command for a filesystem: built-in flags, then the site defaults from
+    config.toml ([mkfs.<fs>] options), then per-run overrides. Returns None for unsupported filesystems.
+    """
     mkfs_cmd = ["mkfs.ext4"] # Default
     
     # Discard/TRIM flags differ by filesystem. e2fsprogs enables it by default usually, but we can force it.
@@ -215,16 +1888,152 @@ def format_device(device_path, fs_type="ext4", boot_flag=False, trim_discard=Fal
              mkfs_cmd.append("-K")
     elif fs_type == "zfs":
         # ZFS is not created via mkfs.*, it's a zpool creation command which would replace mdadm entirely.
-        return False, "ERROR: ZFS unsupported in this GUI design as it handles its own RAID (RAID-Z).\n"
+        return None
     elif fs_type == "f2fs":
         mkfs_cmd = ["mkfs.f2fs", "-f"]
     elif fs_type == "exfat":
         mkfs_cmd = ["mkfs.exfat"]
     elif fs_type == "ntfs":
         mkfs_cmd = ["mkfs.ntfs", "-Q"]
-    
+
+    if label and fs_type in LABEL_FLAGS:
+        mkfs_cmd.extend([LABEL_FLAGS[fs_type], label])
+    mkfs_cmd.extend(config.get_mkfs_defaults(fs_type))
+    mkfs_cmd.extend(extra_options or [])
     mkfs_cmd.append(partition_path)
-    success, out = run_command(mkfs_cmd)
+    return mkfs_cmd
+
+# Sizes outside which mkfs refuses to work (xfsprogs >= 5.19 rejects filesystems under 300 MiB)
+FS_MIN_BYTES = {'xfs': 300 * 1024**2, 'btrfs': 109 * 1024**2}
+FS_MAX_BYTES = {'f2fs': 16 * 1024**4}
+
+def read_block_attribute(device_path, attribute):
+    try:
+        with open(f"/sys/class/block/{os.path.basename(os.path.realpath(device_path))}/{attribute}", 'r') as f:
+            return int(f.read().strip())
+    except Exception:
+        return None
+
+def check_filesystem_limits(fs_type, device_path, mkfs_cmd=None, size_bytes=None):
+    """
+    Checks a device against the size limits and required features of a filesystem before
+    anything is partitioned, so a doomed format fails up front instead of halfway through mkfs.
+    size_bytes stands in for arrays that don't exist yet (plans).
+    Returns a list of errors, empty when the filesystem fits (or the size can't be read, e.g. dry run).
+    """
+    size = size_bytes or (read_block_attribute(device_path, "size") or 0) * 512
+    if not size:
+        return []
+    size_str = units.format_size(size)
+    options = " ".join(mkfs_cmd or [])
+    errors = []
+
+    if fs_type in FS_MIN_BYTES and size < FS_MIN_BYTES[fs_type]:
+        errors.append(f"{fs_type} needs at least {units.format_size(FS_MIN_BYTES[fs_type])}, {device_path} is only {size_str}. Use ext4 or vfat for small devices.")
+    if fs_type in FS_MAX_BYTES and size > FS_MAX_BYTES[fs_type]:
+        errors.append(f"{fs_type} supports at most {units.format_size(FS_MAX_BYTES[fs_type])}, {device_path} is {size_str}.")
+
+    if fs_type == "ext4" and size > 16 * 1024**4 and "^64bit" in options:
+        errors.append(f"ext4 over {units.format_size(16 * 1024**4)} requires the 64bit feature, remove '^64bit' from the mkfs options ({device_path} is {size_str}).")
+
+    if fs_type == "vfat":
+        # FAT32 counts sectors in 32 bits: 2 TiB with 512-byte sectors, 16 TiB on 4Kn devices
+        sector_size = read_block_attribute(device_path, "queue/logical_block_size") or 512
+        limit = 2**32 * sector_size
+        if size > limit:
+            errors.append(f"FAT32 is limited to {units.format_size(limit)} with {sector_size}-byte sectors, {device_path} is {size_str}. Use exfat instead.")
+    return errors
+
+# raid4/5/6 drop discards unless told the members reliably return zeroes for discarded blocks
+RAID456_DISCARD_PARAM = "/sys/module/raid456/parameters/devices_handle_discard_safely"
+
+def device_supports_discard(device_path):
+    # Partitions have no queue/ of their own, theirs is the parent disk's
+    max_bytes = read_block_attribute(device_path, "queue/discard_max_bytes")
+    if max_bytes is None:
+        max_bytes = read_block_attribute(device_path, "../queue/discard_max_bytes")
+    return bool(max_bytes)
+
+def check_discard(array_name):
+    """
+    Reports whether discard (TRIM) requests issued on an array actually reach its member SSDs.
+    Returns: {'array': True, 'members': {'/dev/sda': True, ...}, 'raid456_safe': None, 'warnings': ["..."]}
+    """
+    members = {m['device']: device_supports_discard(m['device']) for m in get_array_members(array_name)
+               if m['device'] and m['role'] in ('active', 'rebuilding', 'spare')}
+    level = read_md_attribute(array_name, "level") or ""
+    report = {'array': device_supports_discard(array_name), 'members': members, 'raid456_safe': None, 'warnings': []}
+
+    for device, supported in members.items():
+        if not supported:
+            report['warnings'].append(f"{device} does not accept discards (HDD, USB bridge or controller), TRIM never reaches it.")
+
+    if level in ("raid4", "raid5", "raid6"):
+        try:
+            with open(RAID456_DISCARD_PARAM, 'r') as f:
+                report['raid456_safe'] = f.read().strip() == "Y"
+        except Exception:
+            report['raid456_safe'] = False
+        if not report['raid456_safe']:
+            report['warnings'].append(f"{level} ignores discards unless raid456 devices_handle_discard_safely=Y. "
+                                      "Only enable it if every member returns zeroes for discarded blocks.")
+
+    if members and all(members.values()) and not report['array'] and report['raid456_safe'] is not False:
+        report['warnings'].append(f"All members support discard but {array_name} does not pass it on.")
+    return report
+
+def format_discard_report(report):
+    lines = [f"Discard on array: {'yes' if report['array'] else 'no'}"]
+    for device, supported in report['members'].items():
+        lines.append(f"  {device}: {'discard supported' if supported else 'no discard'}")
+    if report['raid456_safe'] is not None:
+        lines.append(f"  raid456 devices_handle_discard_safely: {'Y' if report['raid456_safe'] else 'N'}")
+    lines.extend(f"WARNING: {w}" for w in report['warnings'])
+    return "\n".join(lines) + "\n"
+
+def partition_array(device_path, boot_flag=False):
+    """
+    Puts a GPT label and one partition spanning the whole array on it ({device_path}p1), optionally flagged bootable.
+    Returns: (success, log_str)
+    """
+    logs = []
+
+    # 1. Create a fresh GPT partition table
+    success, out = run_command(["parted", "-s", device_path, "mklabel", "gpt"])
     logs.append(out)
+    if not success: return False, "".join(logs)
     
-    return success, "".join(logs)
+    # 2. Create the primary partition using 100% of space
+    success, out = run_command(["parted", "-s", device_path, "mkpart", "primary", "0%", "100%"])
+    logs.append(out)
+    if not success: return False, "".join(logs)
+    
+    # Give the system a second to register the partition table before tagging or formatting
+    if not DRY_RUN:
+        time.sleep(1)
+    
+    # 3. Apply boot flag if requested
+    if boot_flag:
+        success, out = run_command(["parted", "-s", device_path, "set", "1", "boot", "on"])
+        logs.append(out)
+        if not success: return False, "".join(logs)
+    return True, "".join(logs)
+
+def partition_device(array_name, number=1):
+    # The kernel names the partitions of /dev/md0 md0p1; udev links those of /dev/md/<name> as /dev/md/<name>1,
+    # with the same 'p' only when the name ends in a digit (/dev/md/liveraid0p1)
+    return f"{array_name}{'p' if array_name[-1:].isdigit() else ''}{number}"
+
+def format_device(device_path, fs_type="ext4", boot_flag=False, trim_discard=False, mkfs_options=None, label=None):
+    partition_path = partition_device(device_path)
+    mkfs_cmd = build_mkfs_command(fs_type, partition_path, trim_discard, mkfs_options, label)
+    if mkfs_cmd is None:
+        return False, "ERROR: ZFS unsupported in this GUI design as it handles its own RAID (RAID-Z).\n"
+
+    success, out = partition_array(device_path, boot_flag)
+    if not success:
+        return False, out
+
+    # 4. Format the partition
+    success, mkfs_out = run_command(mkfs_cmd)
+    return success, out + mkfs_out
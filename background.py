@@ -0,0 +1,88 @@
+import logging
+import threading
+import executor
+import runner
+
+log = logging.getLogger("liveraid")
+
+# Long operations started by a frontend that has to stay responsive meanwhile: the GUI's main loop, the daemon
+# answering its other clients. An Operation runs one call on a thread of its own, cancel() stops the command that
+# call is running (mkfs, dd, mdadm --wait, ...) through runner.cancellation(), and an executor.Executor rolls back
+# the steps it already completed. pause() and resume() go to the executor.JobHandle the call was given.
+# RaidService.apply_plan_async() and inventory_async() return one.
+
+class Operation:
+    """
+    operation = Operation(service.apply_plan, proposal, identities, on_done=...).start()
+    operation.cancel(); operation.wait() -> (False, None)
+    on_done(operation) is called on the operation's thread when it ends, with result set to what the call returned
+    (or error to the exception it raised). GUI callbacks hand it on with GLib.idle_add.
+    handle is the executor.JobHandle cancel(), pause() and resume() act on; calls that run an Executor pass the same
+    one to it, see RaidService.apply_plan_async().
+    """
+
+    def __init__(self, target, *args, on_done=None, handle=None, **kwargs):
+        self.target = target
+        self.args = args
+        self.kwargs = kwargs
+        self.on_done = on_done
+        self.handle = handle or executor.JobHandle()
+        self.result = None
+        self.error = None
+        self.finished = threading.Event()
+        self.thread = threading.Thread(target=self._run, daemon=True)
+
+    def start(self):
+        self.thread.start()
+        return self
+
+    def _run(self):
+        try:
+            with runner.cancellation(lambda: self.handle.cancelled):
+                self.result = self.target(*self.args, **self.kwargs)
+        except Exception as e:
+            log.exception("Background operation failed")
+            self.error = e
+        finally:
+            # Paused during its last step, or a call without an Executor that ended while paused
+            self.handle.finish()
+            self.finished.set()
+            if self.on_done:
+                self.on_done(self)
+
+    def cancel(self):
+        """
+        Asks the operation to stop at its next cancellation point, it keeps running until it did (see wait()).
+        """
+        self.handle.cancel()
+
+    def pause(self):
+        """
+        Returns: (success, log_str) of freezing the resyncs, see executor.JobHandle.pause()
+        """
+        return self.handle.pause()
+
+    def resume(self):
+        return self.handle.resume()
+
+    @property
+    def cancelled(self):
+        return self.handle.cancelled
+
+    @property
+    def paused(self):
+        return self.handle.paused
+
+    def done(self):
+        return self.finished.is_set()
+
+    def wait(self, timeout=None):
+        """
+        Blocks until the operation ended. Returns what the call returned, raises what it raised
+        and TimeoutError when it is still running after timeout seconds.
+        """
+        if not self.finished.wait(timeout):
+            raise TimeoutError("The operation is still running")
+        if self.error is not None:
+            raise self.error
+        return self.result
@@ -0,0 +1,162 @@
+import json
+import mmap
+import os
+import random
+import shutil
+import subprocess
+import time
+import config
+import fsoptions
+import units
+
+# Benchmark of a provisioned array (`cli.py bench`): sequential and random reads and writes on a test file in
+# its mounted filesystem, reported as throughput, IOPS and latency. fio runs the workloads where it is installed,
+# otherwise a built-in tester does (one request at a time, so its random numbers are lower than fio's).
+# The baseline repeats the read workloads on one member disk, read-only, so the array can be compared with
+# what a single disk manages; writing to a member would destroy the array, so writes have no baseline.
+
+# Workload name -> fio's rw mode, block size and queue depth
+WORKLOADS = {
+    'seq-write': {'rw': "write", 'bs': 1024**2, 'iodepth': 4},
+    'seq-read': {'rw': "read", 'bs': 1024**2, 'iodepth': 4},
+    'rand-write': {'rw': "randwrite", 'bs': 4096, 'iodepth': 32},
+    'rand-read': {'rw': "randread", 'bs': 4096, 'iodepth': 32},
+}
+
+READ_WORKLOADS = ("seq-read", "rand-read")
+
+ENGINES = ("auto", "fio", "builtin")
+
+TEST_FILE = ".liveraid-bench"
+
+def resolve_engine(engine=None):
+    """
+    Returns: 'fio' or 'builtin' for an engine setting (bench_engine in config.toml), None when fio was asked for but isn't installed.
+    """
+    engine = engine or config.get_setting('bench_engine')
+    has_fio = shutil.which("fio") is not None
+    if engine == "fio":
+        return "fio" if has_fio else None
+    if engine == "builtin":
+        return "builtin"
+    return "fio" if has_fio else "builtin"
+
+def result(workload, engine, bandwidth, iops, mean_ms, p99_ms):
+    return {'workload': workload, 'engine': engine, 'bandwidth': bandwidth, 'iops': iops,
+            'latency_ms': {'mean': mean_ms, 'p99': p99_ms}}
+
+def run_fio(path, workload, size_bytes, runtime, readonly=False):
+    """
+    Runs one workload with fio. Returns: {'workload': 'seq-read', 'engine': 'fio', 'bandwidth': 512000000.0,
+    'iops': 488.3, 'latency_ms': {'mean': 8.1, 'p99': 12.4}}, or None when fio failed.
+    """
+    spec = WORKLOADS[workload]
+    cmd = ["fio", f"--name={workload}", f"--filename={path}", f"--rw={spec['rw']}", f"--bs={spec['bs']}",
+           f"--size={size_bytes}", f"--runtime={runtime}", f"--iodepth={spec['iodepth']}", "--ioengine=libaio",
+           "--direct=1", "--output-format=json"] + (["--readonly"] if readonly else [])
+    proc = subprocess.run(cmd, capture_output=True, text=True)
+    if proc.returncode != 0:
+        return None
+    try:
+        job = json.loads(proc.stdout[proc.stdout.index("{"):])['jobs'][0]
+    except (ValueError, KeyError, IndexError):
+        return None
+    side = job['read' if "read" in spec['rw'] else 'write']
+    latency = side.get('clat_ns') or side.get('lat_ns') or {}
+    return result(workload, "fio", float(side['bw_bytes']), float(side['iops']), latency.get('mean', 0) / 1e6,
+                  latency.get('percentile', {}).get('99.000000', 0) / 1e6)
+
+def _open(path, flags):
+    # O_DIRECT keeps the page cache out of the numbers; tmpfs and a few others refuse it, they are measured through the cache
+    try:
+        return os.open(path, flags | getattr(os, "O_DIRECT", 0))
+    except OSError:
+        return os.open(path, flags)
+
+def run_builtin(path, workload, size_bytes, runtime, readonly=False):
+    """
+    Runs one workload with plain reads and writes, one request at a time. Sequential workloads stop after one pass
+    over size_bytes, random ones after as many requests as fit in it; both stop after runtime seconds.
+    Returns the same as run_fio().
+    """
+    spec = WORKLOADS[workload]
+    reading = "read" in spec['rw']
+    block = spec['bs']
+    blocks = max(size_bytes // block, 1)
+    if reading and not readonly and os.path.exists(path):
+        blocks = max(min(blocks, os.path.getsize(path) // block), 1)
+    # Anonymous maps are page aligned, which O_DIRECT needs; random data so a compressing filesystem can't cheat
+    buffer = mmap.mmap(-1, block)
+    buffer.write(os.urandom(block))
+    latencies = []
+    fd = _open(path, os.O_RDONLY if reading else os.O_WRONLY | os.O_CREAT)
+    started = time.monotonic()
+    try:
+        for i in range(blocks):
+            offset = (i if spec['rw'] in ("read", "write") else random.randrange(blocks)) * block
+            begin = time.perf_counter()
+            done = os.preadv(fd, [buffer], offset) if reading else os.pwritev(fd, [buffer], offset)
+            latencies.append(time.perf_counter() - begin)
+            if done != block or time.monotonic() - started >= runtime:
+                break
+        if not reading:
+            os.fsync(fd)
+    except OSError:
+        return None
+    finally:
+        os.close(fd)
+        buffer.close()
+    elapsed = max(time.monotonic() - started, 0.001)
+    latencies.sort()
+    return result(workload, "builtin", len(latencies) * block / elapsed, len(latencies) / elapsed,
+                  1000 * sum(latencies) / len(latencies), 1000 * latencies[min(int(len(latencies) * 0.99), len(latencies) - 1)])
+
+def run(path, workloads, size_bytes, runtime, engine, readonly=False):
+    """
+    Runs the workloads in order against path (a test file, or a member disk when readonly).
+    Returns one result per workload, None for those that failed.
+    """
+    runner = run_fio if engine == "fio" else run_builtin
+    return [runner(path, workload, size_bytes, runtime, readonly) for workload in workloads]
+
+def prepare(path, size_bytes):
+    """
+    Writes the test file the read workloads read, when no write workload did so already.
+    """
+    if os.path.exists(path) and os.path.getsize(path) >= size_bytes:
+        return True
+    return run_builtin(path, "seq-write", size_bytes, float("inf")) is not None
+
+def expected_ratios(level, count, engine="fio"):
+    """
+    How many times a single member's read rate the array should reach: a single sequential reader gets every
+    data disk of a striped level (fsoptions.data_disks(), the model acceptance.py estimates with) but only one disk
+    of a mirror, random reads spread over as many members as there are requests in flight, which is one for the
+    built-in tester.
+    Returns: {'seq-read': 3, 'rand-read': 4}
+    """
+    sequential = fsoptions.data_disks(level, count) or 1
+    depth = WORKLOADS['rand-read']['iodepth'] if engine == "fio" else 1
+    return {'seq-read': max(sequential, 1), 'rand-read': max(min(count, depth), 1)}
+
+def compare(results, baseline, level, count, engine="fio", min_ratio=None):
+    """
+    Compares the read results of the array with those of one member (throughput_min_ratio of the expected ratio passes).
+    Returns: [{'workload': 'seq-read', 'ratio': 2.8, 'expected': 3, 'passed': True}]
+    """
+    min_ratio = config.get_setting('throughput_min_ratio') if min_ratio is None else min_ratio
+    expected = expected_ratios(level, count, engine)
+    member = {r['workload']: r for r in baseline if r}
+    comparison = []
+    for r in results:
+        if r and r['workload'] in member and member[r['workload']]['bandwidth']:
+            ratio = r['bandwidth'] / member[r['workload']]['bandwidth']
+            comparison.append({'workload': r['workload'], 'ratio': round(ratio, 2), 'expected': expected[r['workload']],
+                               'passed': ratio >= expected[r['workload']] * min_ratio})
+    return comparison
+
+def format_result(workload, result):
+    if result is None:
+        return f"{workload:<11} failed"
+    return (f"{workload:<11} {units.format_size(result['bandwidth']) + '/s':>13} {result['iops']:>10.0f} IOPS"
+            f"  mean {result['latency_ms']['mean']:.2f} ms  p99 {result['latency_ms']['p99']:.2f} ms")
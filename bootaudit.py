@@ -0,0 +1,332 @@
+import hashlib
+import json
+import os
+import re
+import subprocess
+import tempfile
+import backend
+import files
+import fstab
+
+# 'cli.py verify-boot': would this system still boot if one member of the array holding / or /boot died?
+# A mirrored root alone doesn't answer that: the firmware needs a bootloader on a surviving disk, the
+# initramfs has to assemble the array with a member missing, and an fstab entry for a partition on the dead
+# disk (usually the ESP) drops boot into emergency mode. Every check names the disk it depends on.
+
+BOOT_PATHS = ("/", "/boot", "/boot/efi", "/efi")
+
+# Firmware falls back to this loader when no boot entry points at a disk
+FALLBACK_LOADER = "\\EFI\\BOOT\\BOOTX64.EFI"
+
+# Initramfs listing tools, first match wins: (tool, image path patterns, regenerate command)
+INITRAMFS_TOOLS = [
+    ("lsinitramfs", ["/boot/initrd.img-{release}"], ["update-initramfs", "-u", "-k", "{release}"]),
+    ("lsinitrd", ["/boot/initramfs-{release}.img", "/boot/initrd-{release}"], ["dracut", "-f", "--kver", "{release}"]),
+]
+
+# initramfs-tools refuses to start a degraded array unless this says otherwise (or bootdegraded=true is on the command line)
+INITRAMFS_TOOLS_MDADM_CONF = "/etc/initramfs-tools/conf.d/mdadm"
+
+# Levels that survive losing one member
+REDUNDANT_LEVELS = ("raid1", "raid4", "raid5", "raid6", "raid10")
+
+def boot_mounts():
+    """
+    Returns: {'/': '/dev/md0', '/boot/efi': '/dev/sda1'}
+    """
+    mounts = {}
+    try:
+        with open('/proc/mounts', 'r') as f:
+            for line in f:
+                fields = line.split()
+                if len(fields) >= 2 and fields[1] in BOOT_PATHS and fields[0].startswith("/dev/"):
+                    mounts[fields[1]] = fields[0]
+    except OSError:
+        pass
+    return mounts
+
+def array_below(device_path):
+    """
+    The md array a device sits on, through LUKS and LVM. Returns: '/dev/md0' or None
+    """
+    try:
+        result = subprocess.run(['lsblk', '-s', '-l', '-n', '-p', '-o', 'NAME,TYPE', device_path], capture_output=True, text=True, check=True)
+    except Exception:
+        return None
+    for line in result.stdout.splitlines():
+        fields = line.split()
+        if len(fields) == 2 and fields[1].startswith("raid"):
+            return fields[0]
+    return None
+
+def resolve_spec(spec):
+    """
+    Turns an fstab spec (UUID=, PARTUUID=, LABEL= or a path) into its device. Returns: '/dev/sda1' or None
+    """
+    for key, directory in (("UUID=", "by-uuid"), ("PARTUUID=", "by-partuuid"), ("LABEL=", "by-label")):
+        if spec.startswith(key):
+            path = f"/dev/disk/{directory}/{spec[len(key):]}"
+            return os.path.realpath(path) if os.path.exists(path) else None
+    return os.path.realpath(spec) if spec.startswith("/dev/") else None
+
+def partitions(disk):
+    """
+    Returns: [{'name': '/dev/sda1', 'parttype': 'c12a7328-...', 'partuuid': '...', 'number': 1}]
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,TYPE,PARTTYPE,PARTUUID', disk], capture_output=True, text=True, check=True)
+        nodes = json.loads(result.stdout).get('blockdevices', [])
+    except Exception:
+        return []
+    found = []
+    for node in nodes:
+        for child in node.get('children') or []:
+            if child.get('type') != "part":
+                continue
+            number = backend.read_sysfs_text(f"/sys/class/block/{os.path.basename(child['name'])}/partition")
+            found.append({'name': child['name'], 'parttype': (child.get('parttype') or "").lower(),
+                          'partuuid': (child.get('partuuid') or "").lower(), 'number': int(number) if number and number.isdigit() else None})
+    return found
+
+def efi_entries():
+    """
+    Returns the boot entries of `efibootmgr -v`: [{'label': 'ubuntu', 'partuuid': '...', 'loader': '\\EFI\\ubuntu\\shimx64.efi'}]
+    """
+    try:
+        result = subprocess.run(['efibootmgr', '-v'], capture_output=True, text=True, check=True)
+    except Exception:
+        return []
+    entries = []
+    for line in result.stdout.splitlines():
+        match = re.match(r"Boot[0-9A-Fa-f]{4}\*?\s+(.*?)\s+HD\(\d+,GPT,([0-9a-fA-F-]+),.*?File\(([^)]+)\)", line)
+        if match:
+            entries.append({'label': match.group(1), 'partuuid': match.group(2).lower(), 'loader': match.group(3)})
+    return entries
+
+def loader_digest(root, loader):
+    """
+    The SHA-256 of a loader ('\\EFI\\ubuntu\\shimx64.efi') below the root of a mounted ESP, None when it is missing.
+    """
+    path = os.path.join(root, *[part for part in loader.split("\\") if part])
+    try:
+        with open(path, 'rb') as f:
+            return hashlib.sha256(f.read()).hexdigest()
+    except OSError:
+        return None
+
+def esp_loader_digest(esp, loader, mounts):
+    """
+    Reads the loader from an ESP, mounting it read-only for the look when it isn't mounted.
+    Returns: (digest or None when the file is missing, mounted: False when the ESP could not be mounted)
+    """
+    mounted = [point for point, source in mounts.items() if os.path.realpath(source) == esp]
+    if mounted:
+        return loader_digest(mounted[0], loader), True
+    root = tempfile.mkdtemp(prefix="liveraid-esp-")
+    if subprocess.run(['mount', '-o', 'ro', esp, root], capture_output=True).returncode != 0:
+        os.rmdir(root)
+        return None, False
+    try:
+        return loader_digest(root, loader), True
+    finally:
+        if subprocess.run(['umount', root], capture_output=True).returncode == 0:
+            os.rmdir(root)
+
+def has_grub_boot_code(disk):
+    # GRUB's boot.img carries its name in the first sector
+    try:
+        with open(disk, 'rb') as f:
+            return b"GRUB" in f.read(512)
+    except OSError:
+        return None
+
+def initramfs_listing(release):
+    """
+    Returns: ('/boot/initrd.img-6.8.0-31-generic', ['usr/sbin/mdadm', 'etc/mdadm/mdadm.conf', ...], ['update-initramfs', '-u', ...])
+    or (None, None, None) when no image or listing tool was found.
+    """
+    for tool, patterns, regenerate in INITRAMFS_TOOLS:
+        images = [p.format(release=release) for p in patterns if os.path.exists(p.format(release=release))]
+        if not images:
+            continue
+        regenerate = [part.format(release=release) for part in regenerate]
+        try:
+            result = subprocess.run([tool, images[0]], capture_output=True, text=True, check=True)
+        except Exception:
+            return images[0], None, regenerate
+        return images[0], [line.strip().lstrip("./") for line in result.stdout.splitlines()], regenerate
+    return None, None, None
+
+def kernel_cmdline():
+    try:
+        with open('/proc/cmdline', 'r') as f:
+            return f.read().split()
+    except OSError:
+        return []
+
+def check_loader(add, disk, esp, entries, mounts):
+    # A boot entry or the fallback path is no use when the file it names isn't on the ESP, or is an older build than the one booted
+    entry = next((e for e in entries if e['partuuid'] == esp['partuuid']), None)
+    loader = entry['loader'] if entry else FALLBACK_LOADER
+    digest, readable = esp_loader_digest(esp['name'], loader, mounts)
+    esp_mount = next((point for point in ("/boot/efi", "/efi") if point in mounts), None)
+    reference = loader_digest(esp_mount, loader) if esp_mount else None
+    sync = {'description': f"Copy the mounted ESP onto {esp['name']}", 'commands': []}
+    if not readable:
+        add(f"Loader {disk}", "warn", f"could not mount {esp['name']} to look for {loader}")
+    elif digest is None:
+        add(f"Loader {disk}", "error", f"{loader} is missing on {esp['name']}, the firmware has nothing to start from {disk}", sync)
+    elif reference and os.path.realpath(mounts[esp_mount]) != esp['name'] and digest != reference:
+        add(f"Loader {disk}", "warn", f"{loader} on {esp['name']} differs from the mounted ESP's, it may start an old bootloader", sync)
+    else:
+        add(f"Loader {disk}", "ok", f"{loader} is on {esp['name']}")
+
+def audit(member=None):
+    """
+    Checks whether the system boots with one member of its boot arrays missing, member (e.g. '/dev/sdb')
+    or by default every member in turn. Fixes carry the commands to run where there is a safe one.
+    Returns: {'arrays': ['/dev/md0'], 'disks': ['/dev/sda', '/dev/sdb'], 'member': '/dev/sdb', 'firmware': 'uefi',
+              'checks': [{'name': 'ESP /dev/sdb', 'status': 'ok' | 'warn' | 'error', 'detail': '...',
+                          'fix': {'description': '...', 'commands': [[...]]} or None}]}
+    """
+    checks = []
+
+    def add(name, status, detail, fix=None):
+        checks.append({'name': name, 'status': status, 'detail': detail, 'fix': fix})
+
+    firmware = "uefi" if os.path.isdir("/sys/firmware/efi") else "bios"
+    mounts = boot_mounts()
+    arrays = {}
+    for mount_point, source in mounts.items():
+        array = array_below(source)
+        if array:
+            arrays.setdefault(array, []).append(mount_point)
+    report = {'arrays': sorted(arrays), 'disks': [], 'member': member, 'firmware': firmware, 'checks': checks}
+    if not arrays:
+        add("Boot arrays", "ok", f"{', '.join(sorted(mounts)) or '/'} not on an md array, no member to lose")
+        return report
+
+    disks = []
+    mdstat = backend.parse_mdstat()
+    for array, mount_points in sorted(arrays.items()):
+        array_disks = [backend.get_parent_disk(m['device']) for m in backend.get_array_members(array) if m['device'] and m['role'] == "active"]
+        disks.extend(d for d in array_disks if d not in disks)
+        state = mdstat.get(f"/dev/{os.path.basename(os.path.realpath(array))}", {})
+        holds = f"{array} ({', '.join(mount_points)})"
+        if state.get('type') not in REDUNDANT_LEVELS:
+            add(f"Redundancy {array}", "error", f"{holds} is {state.get('type') or 'not a redundant level'}, losing any member loses the array")
+        elif state.get('working') is not None and state['working'] < state['raid_disks']:
+            add(f"Redundancy {array}", "error", f"{holds} is already degraded ({state['working']}/{state['raid_disks']}), "
+                "replace the failed member before losing another")
+        else:
+            add(f"Redundancy {array}", "ok", f"{holds} is {state['type']} on {', '.join(array_disks)}")
+    report['disks'] = disks
+    if member and backend.get_parent_disk(member) not in disks:
+        add("Member", "warn", f"{member} is not a member of {', '.join(sorted(arrays))}, checking every member instead")
+        member = None
+    lost = [backend.get_parent_disk(member)] if member else disks
+    survivors = [d for d in disks if d not in lost] if member else disks
+
+    # The bootloader: every disk that may be left over must be able to start the system on its own
+    if firmware == "uefi":
+        entries = efi_entries()
+        loader = entries[0]['loader'] if entries else FALLBACK_LOADER
+        esp_array = array_below(mounts.get("/boot/efi") or mounts.get("/efi") or "")
+        for disk in survivors:
+            esps = [p for p in partitions(disk) if p['parttype'] == backend.ESP_PARTTYPE]
+            if esp_array:
+                add(f"ESP {disk}", "ok", f"the ESP is mirrored on {esp_array}")
+            elif not esps:
+                add(f"ESP {disk}", "error", f"{disk} has no EFI system partition, the firmware can't start anything from it",
+                    {'description': f"Create an ESP on {disk} and copy the contents of the mounted one onto it", 'commands': []})
+            elif not any(e['partuuid'] == esp['partuuid'] for esp in esps for e in entries):
+                esp = esps[0]
+                add(f"ESP {disk}", "warn", f"{esp['name']} has no UEFI boot entry, the firmware only finds it through {FALLBACK_LOADER}",
+                    {'description': f"Add a boot entry for {esp['name']}",
+                     'commands': [["efibootmgr", "--create", "--disk", disk, "--part", str(esp['number'] or 1),
+                                   "--label", f"LiveRAID {os.path.basename(disk)}", "--loader", loader]]})
+            else:
+                add(f"ESP {disk}", "ok", f"{esps[0]['name']} has a UEFI boot entry")
+            if esps and not esp_array:
+                check_loader(add, disk, esps[0], entries, mounts)
+    else:
+        for disk in survivors:
+            found = has_grub_boot_code(disk)
+            if found is None:
+                add(f"GRUB {disk}", "warn", f"could not read the boot sector of {disk}")
+            elif not found:
+                add(f"GRUB {disk}", "error", f"no GRUB boot code in the MBR of {disk}, the BIOS can't boot from it",
+                    {'description': f"Install GRUB to {disk}", 'commands': [["grub-install", disk]]})
+            else:
+                add(f"GRUB {disk}", "ok", f"GRUB installed to {disk}")
+
+    # The initramfs assembles the array: it needs mdadm and an mdadm.conf that knows the array
+    conf_path = files.get_mdadm_conf_path()
+    conf_text = files.read_file(conf_path)
+    release = os.uname().release
+    image, listing, regenerate = initramfs_listing(release)
+    if image is None:
+        add("Initramfs", "warn", f"no initramfs found for {release}, or no lsinitramfs/lsinitrd to read it")
+    elif listing is None:
+        add("Initramfs", "warn", f"could not list {image}")
+    else:
+        missing = [name for name, present in (("mdadm", any(p.endswith("sbin/mdadm") for p in listing)),
+                                              ("mdadm.conf", any(p.endswith("mdadm.conf") for p in listing))) if not present]
+        fix = {'description': f"Rebuild {image}", 'commands': [regenerate]}
+        if missing:
+            add("Initramfs", "error", f"{image} lacks {' and '.join(missing)}, the array can't be assembled at boot", fix)
+        elif os.path.exists(conf_path) and os.path.getmtime(conf_path) > os.path.getmtime(image):
+            add("Initramfs", "warn", f"{conf_path} changed after {image} was built, the initramfs has an old copy", fix)
+        else:
+            add("Initramfs", "ok", f"{image} contains mdadm and mdadm.conf")
+    for array in sorted(arrays):
+        uuid = backend.get_array_uuid(array)
+        if uuid and uuid not in conf_text:
+            add(f"mdadm.conf {array}", "error", f"{conf_path} has no ARRAY line for {array} (UUID {uuid})",
+                {'description': f"Add {array} to {conf_path}, then rebuild the initramfs",
+                 'commands': [["sh", "-c", f"mdadm --detail --brief {array} >> {conf_path}"]] + ([regenerate] if regenerate else [])})
+        elif uuid:
+            add(f"mdadm.conf {array}", "ok", f"ARRAY line for UUID {uuid}")
+
+    # Degraded-boot policy of the initramfs
+    cmdline = kernel_cmdline()
+    if "rd.md=0" in cmdline:
+        add("Degraded boot", "error", "rd.md=0 on the kernel command line keeps dracut from assembling any array")
+    elif regenerate and regenerate[0] == "update-initramfs":
+        setting = re.search(r"^\s*BOOT_DEGRADED=(\S+)", files.read_file(INITRAMFS_TOOLS_MDADM_CONF), re.M)
+        if "bootdegraded=true" in cmdline or (setting and setting.group(1).strip("\"'") == "true"):
+            add("Degraded boot", "ok", "the initramfs starts degraded arrays")
+        else:
+            add("Degraded boot", "warn", "initramfs-tools may wait for the missing member and drop to a shell",
+                {'description': f"Set BOOT_DEGRADED=true in {INITRAMFS_TOOLS_MDADM_CONF} and rebuild the initramfs",
+                 'commands': [["sh", "-c", f"echo BOOT_DEGRADED=true >> {INITRAMFS_TOOLS_MDADM_CONF}"], regenerate]})
+    else:
+        wanted = [arg.split("=", 1)[1] for arg in cmdline if arg.startswith("rd.md.uuid=")]
+        uuids = [backend.get_array_uuid(array) for array in sorted(arrays)]
+        unlisted = [u for u in uuids if u and wanted and u not in wanted]
+        if unlisted:
+            add("Degraded boot", "error", f"rd.md.uuid= on the kernel command line leaves out {', '.join(unlisted)}")
+        else:
+            add("Degraded boot", "ok", "dracut starts degraded arrays after its timeout")
+
+    # Mounts on the lost disk alone: without nofail systemd waits for them and ends in emergency mode
+    entries = fstab.FstabEditor(files.read_file(files.FSTAB_PATH)).entries()
+    for entry in entries:
+        if entry['file'] in ("/", "none", "swap") or entry['vfstype'] == "swap":
+            continue
+        device = resolve_spec(entry['spec'])
+        if device is None or not os.path.basename(device).startswith(("sd", "nvme", "vd", "hd", "mmcblk", "xvd")):
+            continue
+        disk = backend.get_parent_disk(device)
+        if disk not in lost:
+            continue
+        options = entry['mntops'].split(",")
+        if "nofail" in options:
+            add(f"fstab {entry['file']}", "ok", f"{device} is on {disk} only, mounted with nofail")
+        else:
+            add(f"fstab {entry['file']}", "error", f"{device} is on {disk} only and has no nofail, boot stops in emergency mode without it",
+                {'description': f"Add nofail to the options of {entry['file']} in {files.FSTAB_PATH}", 'commands': []})
+    return report
+
+def passed(report):
+    return not any(c['status'] == "error" for c in report['checks'])
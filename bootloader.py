@@ -0,0 +1,347 @@
+import glob
+import os
+import re
+import subprocess
+import backend
+import bootaudit
+import config
+import files
+import grub
+
+# Boot arrays need the initramfs told which arrays to assemble (rd.md.uuid=), and where that goes depends on
+# what boots the kernel: GRUB's /etc/default/grub (grub.py), systemd-boot's loader entries, or the firmware
+# itself starting the kernel as an EFI stub, with the command line kept in its boot entry. 'bootloader' in
+# config.toml picks one; "auto" detects it from the running system.
+
+# Where systemd-boot's Type 1 loader entries live, on the ESP or XBOOTLDR partition
+ENTRY_DIRS = ["/boot/efi/loader/entries", "/efi/loader/entries", "/boot/loader/entries"]
+
+# kernel-install writes the options of new kernels' entries from this file, so they keep the arguments too
+KERNEL_CMDLINE_PATH = "/etc/kernel/cmdline"
+
+# BootCurrent's loader is the kernel itself: direct EFI stub boot
+STUB_LOADER = re.compile(r"vmlinu[xz]|bzimage", re.IGNORECASE)
+
+# efibootmgr before version 18 prints the loader as File(\vmlinuz-linux), later ones as a plain path: HD(...)/\vmlinuz-linux
+EFI_ENTRY = re.compile(r"Boot([0-9A-Fa-f]{4})\*?\s+(.*?)\s+HD\((\d+),GPT,([0-9a-fA-F-]+),[^)]*\)/(?:File\(([^)]+)\)|(\\\S+))(.*)$")
+
+def current_efi_entry():
+    """
+    The boot entry the firmware started this boot from (BootCurrent of `efibootmgr -v`).
+    Returns: {'num': '0001', 'label': 'Arch', 'part': 1, 'partuuid': '...', 'loader': '\\vmlinuz-linux', 'args': ['root=...']} or None
+    """
+    if not os.path.isdir("/sys/firmware/efi"):
+        return None
+    try:
+        result = subprocess.run(['efibootmgr', '-v'], capture_output=True, text=True, check=True)
+    except Exception:
+        return None
+    current = re.search(r"^BootCurrent:\s*([0-9A-Fa-f]{4})", result.stdout, re.MULTILINE)
+    for line in result.stdout.splitlines():
+        match = EFI_ENTRY.match(line)
+        if match and current and match.group(1).upper() == current.group(1).upper():
+            args = match.group(7).strip()
+            # Older efibootmgr prints the UCS-2 optional data as 'r.o.o.t.=...'
+            if re.fullmatch(r"(?:[^.]\.)+", args):
+                args = args[::2]
+            return {'num': match.group(1).upper(), 'label': match.group(2), 'part': int(match.group(3)),
+                    'partuuid': match.group(4).lower(), 'loader': match.group(5) or match.group(6), 'args': args.split()}
+    return None
+
+def efi_boot_entries():
+    """
+    The boot entry numbers and BootOrder as `efibootmgr` lists them.
+    Returns: (['0000', '0001'], ['0001', '0000']), or None when efibootmgr can't be run
+    """
+    try:
+        result = subprocess.run(['efibootmgr'], capture_output=True, text=True, check=True)
+    except Exception:
+        return None
+    entries = [num.upper() for num in re.findall(r"^Boot([0-9A-Fa-f]{4})", result.stdout, re.MULTILINE)]
+    order = re.search(r"^BootOrder:\s*(\S+)", result.stdout, re.MULTILINE)
+    return entries, (order.group(1).upper().split(",") if order else [])
+
+def root_argument(array_name):
+    """
+    root=UUID= of the root filesystem when it lives on the array (directly, on a partition, or through LUKS and LVM).
+    Returns: 'root=UUID=5f3c...' or None
+    """
+    device = bootaudit.boot_mounts().get("/")
+    if not device or array_name not in (device, bootaudit.array_below(device)):
+        return None
+    uuid = files.get_filesystem_uuid(device)
+    return f"root=UUID={uuid}" if uuid else None
+
+def edit_args(args, arrays, root=None):
+    """
+    A kernel command line with one rd.md.uuid= per array and, when given, root= replaced. Other arguments keep their order.
+    Returns: ['quiet', 'root=UUID=...', 'rd.md.uuid=...']
+    """
+    kept = [arg for arg in args if not arg.startswith("rd.md.uuid=")]
+    if root:
+        kept = [root if arg.startswith("root=") else arg for arg in kept]
+        if root not in kept:
+            kept.append(root)
+    return kept + [f"rd.md.uuid={arr['uuid']}" for arr in arrays]
+
+def md_uuids(args):
+    return [arg.split("=", 1)[1] for arg in args if arg.startswith("rd.md.uuid=")]
+
+class Bootloader:
+    name = None
+    title = None
+
+    def detected(self):
+        """
+        Whether the running system was booted by this loader.
+        """
+        return False
+
+    def arrays(self):
+        """
+        The arrays the loader has assembled at boot. Returns: [{'name': '/dev/md0' or None, 'uuid': '...'}]
+        """
+        raise NotImplementedError
+
+    def diff(self, arrays, root=None, color=False):
+        """
+        What write() would change. Returns: unified diff, '' when nothing changes
+        """
+        raise NotImplementedError
+
+    def write(self, arrays, root=None, dry_run=None):
+        """
+        Makes the loader assemble exactly these arrays, root is a root= argument to set (see root_argument()).
+        Returns: (success, log_str)
+        """
+        raise NotImplementedError
+
+    def locations(self):
+        """
+        What the loader's settings are kept in, for 'bootloader show'. Returns: ['/etc/default/grub']
+        """
+        return []
+
+class GrubBootloader(Bootloader):
+    # grub-mkconfig finds root itself, only the arrays are written (see grub.py)
+    name = "grub"
+    title = "GRUB"
+
+    def detected(self):
+        return os.path.isfile(grub.GRUB_DEFAULT_PATH)
+
+    def arrays(self):
+        return grub.parse(files.read_file(grub.GRUB_DEFAULT_PATH))['arrays']
+
+    def diff(self, arrays, root=None, color=False):
+        current = files.read_file(grub.GRUB_DEFAULT_PATH)
+        return files.render_diff(grub.GRUB_DEFAULT_PATH, current, grub.render_grub(arrays, current), color)
+
+    def write(self, arrays, root=None, dry_run=None):
+        return grub.write_arrays(arrays, dry_run)
+
+    def locations(self):
+        return [grub.GRUB_DEFAULT_PATH]
+
+class SystemdBootBootloader(Bootloader):
+    # The options lines of every Linux loader entry, and /etc/kernel/cmdline for the entries of future kernels
+    name = "systemd-boot"
+    title = "systemd-boot"
+
+    def entries(self):
+        paths = []
+        for directory in ENTRY_DIRS:
+            for path in sorted(glob.glob(os.path.join(directory, "*.conf"))):
+                # Chainloaded entries (efi ...) have no kernel command line
+                if re.search(r"^\s*linux\s", files.read_file(path), re.MULTILINE) and os.path.realpath(path) not in map(os.path.realpath, paths):
+                    paths.append(path)
+        return paths
+
+    def detected(self):
+        current = current_efi_entry()
+        if current and "systemd" in current['loader'].lower():
+            return True
+        return bool(self.entries()) and not os.path.isfile(grub.GRUB_DEFAULT_PATH)
+
+    def render_entry(self, text, arrays, root):
+        lines = text.splitlines()
+        options = [i for i, line in enumerate(lines) if re.match(r"^\s*options(\s|$)", line)]
+        if not options:
+            lines.append("options")
+            options = [len(lines) - 1]
+        for i in options:
+            args = [arg for arg in lines[i].split()[1:] if not arg.startswith("rd.md.uuid=")]
+            if root:
+                args = [root if arg.startswith("root=") else arg for arg in args]
+            lines[i] = " ".join(["options"] + args)
+        # The arrays (and root when no options line had one) go on the last options line
+        last = lines[options[-1]].split()
+        if root and root not in " ".join(lines[i] for i in options).split():
+            last.append(root)
+        lines[options[-1]] = " ".join(last + [f"rd.md.uuid={arr['uuid']}" for arr in arrays])
+        return "\n".join(lines) + "\n"
+
+    def proposed(self, arrays, root):
+        # {path: (current text, new text)}
+        changes = {}
+        for path in self.entries():
+            current = files.read_file(path)
+            changes[path] = (current, self.render_entry(current, arrays, root))
+        if os.path.isfile(KERNEL_CMDLINE_PATH):
+            current = files.read_file(KERNEL_CMDLINE_PATH)
+            changes[KERNEL_CMDLINE_PATH] = (current, " ".join(edit_args(current.split(), arrays, root)) + "\n")
+        return changes
+
+    def arrays(self):
+        uuids = []
+        for path in self.entries():
+            for line in files.read_file(path).splitlines():
+                if re.match(r"^\s*options\s", line):
+                    uuids += [uuid for uuid in md_uuids(line.split()) if uuid not in uuids]
+        return [{'name': None, 'uuid': uuid} for uuid in uuids]
+
+    def diff(self, arrays, root=None, color=False):
+        return "".join(files.render_diff(path, current, new, color) for path, (current, new) in self.proposed(arrays, root).items())
+
+    def write(self, arrays, root=None, dry_run=None):
+        changes = self.proposed(arrays, root)
+        if not changes:
+            return False, f"ERROR: No systemd-boot loader entries found in {', '.join(ENTRY_DIRS)}.\n"
+        logs = []
+        for path, (_, new) in changes.items():
+            success, out = files.write_file(path, new, dry_run)
+            logs.append(out)
+            if not success:
+                return False, "".join(logs)
+        return True, "".join(logs)
+
+    def locations(self):
+        return self.entries() + ([KERNEL_CMDLINE_PATH] if os.path.isfile(KERNEL_CMDLINE_PATH) else [])
+
+class EfiStubBootloader(Bootloader):
+    # The firmware starts the kernel directly, its command line is the boot entry's optional data. efibootmgr
+    # can't edit that in place, so a new entry is created, takes the old one's place in BootOrder, and only
+    # then the old entry is deleted: a failure on the way never leaves the system without an entry to boot.
+    name = "efistub"
+    title = "EFI stub (efibootmgr)"
+
+    def detected(self):
+        current = current_efi_entry()
+        return bool(current and STUB_LOADER.search(current['loader']))
+
+    def arrays(self):
+        current = current_efi_entry()
+        return [{'name': None, 'uuid': uuid} for uuid in md_uuids(current['args'])] if current else []
+
+    def diff(self, arrays, root=None, color=False):
+        current = current_efi_entry()
+        if current is None:
+            return ""
+        where = f"EFI boot entry Boot{current['num']} ({current['label']})"
+        return files.render_diff(where, " ".join(current['args']) + "\n", " ".join(edit_args(current['args'], arrays, root)) + "\n", color)
+
+    def write(self, arrays, root=None, dry_run=None):
+        current = current_efi_entry()
+        if current is None:
+            return False, "ERROR: The boot entry this system started from isn't readable, is efibootmgr installed and the system booted via UEFI?\n"
+        args = edit_args(current['args'], arrays, root)
+        if args == current['args']:
+            return True, f"Boot{current['num']} already up to date.\n"
+        partition = os.path.realpath(f"/dev/disk/by-partuuid/{current['partuuid']}")
+        if not os.path.exists(partition):
+            return False, f"ERROR: The partition of Boot{current['num']} (PARTUUID {current['partuuid']}) is not present.\n"
+        before = efi_boot_entries()
+        if before is None:
+            return False, "ERROR: efibootmgr can't list the boot entries.\n"
+        logs = [f"Old command line of Boot{current['num']}: {' '.join(current['args'])}\n"]
+        success, out = backend.run_command(["efibootmgr", "--create", "--disk", backend.get_parent_disk(partition), "--part", str(current['part']),
+                                            "--label", current['label'], "--loader", current['loader'], "--unicode", " ".join(args)], dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+        # efibootmgr picks the number of the new entry, it is the one that wasn't there before
+        if dry_run is None:
+            dry_run = backend.DRY_RUN
+        after = ([*before[0], "NNNN"], before[1]) if dry_run else efi_boot_entries()
+        new = next((num for num in (after or ([], []))[0] if num not in before[0]), None)
+        if new is None:
+            logs.append(f"ERROR: The new boot entry wasn't found, Boot{current['num']} was kept. Check 'efibootmgr -v'.\n")
+            return False, "".join(logs)
+        order = [new if num == current['num'] else num for num in before[1]]
+        if new not in order:
+            order.insert(0, new)
+        for cmd in (["efibootmgr", "--bootorder", ",".join(order)], ["efibootmgr", "--bootnum", current['num'], "--delete-bootnum"]):
+            success, out = backend.run_command(cmd, dry_run)
+            logs.append(out)
+            if not success:
+                logs.append(f"ERROR: Boot{new} was created with the new command line, but Boot{current['num']} is still there. "
+                            f"Fix the boot order with 'efibootmgr --bootorder' and delete the old entry by hand.\n")
+                return False, "".join(logs)
+        return True, "".join(logs)
+
+    def locations(self):
+        current = current_efi_entry()
+        return [f"EFI boot entry Boot{current['num']} ({current['label']})"] if current else []
+
+BOOTLOADERS = {b.name: b for b in (GrubBootloader(), SystemdBootBootloader(), EfiStubBootloader())}
+
+def detect():
+    """
+    The loader that booted the running system, GRUB when nothing else is recognized. Returns: 'systemd-boot'
+    """
+    for name in ("efistub", "systemd-boot"):
+        if BOOTLOADERS[name].detected():
+            return name
+    return "grub"
+
+def get_bootloader(name=None):
+    """
+    The bootloader by name, default: 'bootloader' in config.toml, where "auto" detects it.
+    Raises ValueError for unknown names.
+    """
+    name = name or config.get_setting('bootloader')
+    if name == "auto":
+        name = detect()
+    if name not in BOOTLOADERS:
+        raise ValueError(f"Unknown bootloader '{name}', choose one of: {', '.join(BOOTLOADERS)}")
+    return BOOTLOADERS[name]
+
+def changed_arrays(loader, action, array_name):
+    """
+    The arrays the loader assembles after add-raid (the array joins, or its UUID is refreshed after a re-create)
+    or remove-raid (by device or UUID). The UUID is the array's real one, read from mdadm.
+    Returns: ([{'name': '/dev/md0', 'uuid': '...'}], error message or None)
+    """
+    arrays = loader.arrays()
+    uuid = backend.get_array_uuid(array_name)
+    if action == "remove-raid":
+        kept = [arr for arr in arrays if array_name not in (arr['name'], arr['uuid']) and arr['uuid'] != uuid]
+        if len(kept) == len(arrays):
+            return None, f"{array_name} is not among the arrays {loader.title} assembles at boot."
+        return kept, None
+    if uuid is None:
+        return None, f"Could not read the UUID of {array_name}, is it running?"
+    return [arr for arr in arrays if arr['name'] != array_name and arr['uuid'] != uuid] + [{'name': array_name, 'uuid': uuid}], None
+
+def diff(action, array_name, name=None, color=False):
+    """
+    What add-raid or remove-raid would change, without writing anything.
+    Returns: (unified diff, '' when nothing changes, error message or None)
+    """
+    loader = get_bootloader(name)
+    arrays, error = changed_arrays(loader, action, array_name)
+    if error:
+        return "", error
+    return loader.diff(arrays, root_argument(array_name) if action == "add-raid" else None, color), None
+
+def update(action, array_name, name=None, dry_run=None):
+    """
+    Carries out add-raid or remove-raid with the configured (or named) bootloader. Added arrays holding the root
+    filesystem also get root=UUID= where the loader takes root from its command line.
+    Returns: (success, log_str)
+    """
+    loader = get_bootloader(name)
+    arrays, error = changed_arrays(loader, action, array_name)
+    if error:
+        return False, f"ERROR: {error}\n"
+    return loader.write(arrays, root_argument(array_name) if action == "add-raid" else None, dry_run)
@@ -18,16 +18,16 @@ if [ -x "$(command -v apt-get)" ]; then
     apt-get update -yq
     echo "Installing requirements..."
     export DEBIAN_FRONTEND=noninteractive
-    apt-get install -yq python3-gi python3-gi-cairo gir1.2-gtk-3.0 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs curl wget
+    apt-get install -yq python3-gi python3-gi-cairo gir1.2-gtk-3.0 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs rsync gddrescue curl wget
 elif [ -x "$(command -v dnf)" ]; then
     echo "Detected DNF (Fedora/RHEL). Installing requirements..."
-    dnf install -y python3-gobject gtk3 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs curl wget
+    dnf install -y python3-gobject gtk3 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs rsync ddrescue curl wget
 elif [ -x "$(command -v pacman)" ]; then
     echo "Detected Pacman (Arch). Installing requirements..."
-    pacman -Sy --noconfirm python-gobject gtk3 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs curl wget
+    pacman -Sy --noconfirm python-gobject gtk3 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs rsync ddrescue curl wget
 elif [ -x "$(command -v zypper)" ]; then
     echo "Detected Zypper (SUSE). Installing requirements..."
-    zypper install -y python3-gobject gtk3 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs curl wget
+    zypper install -y python3-gobject gtk3 mdadm parted e2fsprogs btrfs-progs dosfstools xfsprogs rsync gddrescue curl wget
 else
     echo "Could not detect a supported package manager (apt, dnf, pacman, zypper)."
     echo "Please install dependencies manually: python3-gobject, gtk3, mdadm, parted, and mkfs tools."
@@ -46,16 +46,14 @@ PROJECT_SRC="/home/user/Desktop/Vibe_Projects/LiveRAID"
 
 if [ -d "$PROJECT_SRC" ]; then
     echo "Copying source from $PROJECT_SRC to $LIVERAID_DIR for execution..."
-    cp "$PROJECT_SRC"/main.py "$LIVERAID_DIR/"
-    cp "$PROJECT_SRC"/backend.py "$LIVERAID_DIR/"
-    cp "$PROJECT_SRC"/ui.py "$LIVERAID_DIR/"
+    cp "$PROJECT_SRC"/*.py "$LIVERAID_DIR/"
 else
     echo "This is where we would download the scripts from GitHub..."
     # Example: wget https://raw.githubusercontent.com/user/liveraid/main/main.py -O $LIVERAID_DIR/main.py
     # Example: wget https://raw.githubusercontent.com/user/liveraid/main/backend.py -O $LIVERAID_DIR/backend.py
     # Example: wget https://raw.githubusercontent.com/user/liveraid/main/ui.py -O $LIVERAID_DIR/ui.py
     # But since we are generating them locally, we stop if we can't find them.
-    echo "Source files not found! Ensure main.py, backend.py, and ui.py exist in $PROJECT_SRC"
+    echo "Source files not found! Ensure main.py, backend.py, ui.py, and the other modules exist in $PROJECT_SRC"
     exit 1
 fi
 
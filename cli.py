@@ -0,0 +1,2174 @@
+import argparse
+import contextlib
+import csv
+import getpass
+import os
+import shlex
+import shutil
+import signal
+import sys
+import time
+import acceptance
+import arraynames
+import background
+import backend
+import bench
+import bootaudit
+import bootloader
+import config
+import daemon
+import devicefilter
+import doctor
+import events
+import executor
+import expand
+import files
+import fsoptions
+import fstab
+import geometry
+import grub
+import health
+import hooks
+import jobs
+import luks
+import metrics
+import migrate
+import notifications
+import output
+import plan
+import provisioning
+import raidbackend
+import script
+import scrub
+import simulate
+import snapshot
+import support
+import units
+import wipe
+from service import READ_ONLY_ROOT, RaidService
+
+def confirm(prompt):
+    try:
+        return input(f"{prompt} [y/N] ").strip().lower() in ("y", "yes")
+    except EOFError:
+        return False
+
+def print_foreign_data(args, devices):
+    # Old Windows/macOS volumes on the disks to be wiped, probably data from the machine being replaced
+    foreign = args.service.foreign_data(devices)
+    if foreign:
+        print("WARNING [foreign-data]: These disks still hold data of another operating system:")
+        for entries in foreign.values():
+            for entry in entries:
+                print(f"  {backend.describe_foreign_data(entry)}")
+        print("  Copy it off first if it is still needed ('mount-ro <disk>' shows it read-only).")
+
+def print_checklist(checklist):
+    # The pre-apply checklist of plan.validate_plan(), the WARNING lines above it say what failed
+    symbols = {'ok': 'PASS', 'warn': 'WARN', 'error': 'FAIL'}
+    print("Checklist:")
+    for item in checklist:
+        print(f"  [{symbols[item['status']]}] {item['title']}")
+
+def confirm_action(args, prompt, destructive):
+    # --yes always wins, otherwise config.toml's confirm_policy decides whether to ask
+    if getattr(args, "yes", False) or not config.should_confirm(destructive):
+        return True
+    return confirm(prompt)
+
+def size_arg(text):
+    try:
+        return units.parse_size(text)
+    except ValueError as e:
+        raise argparse.ArgumentTypeError(str(e))
+
+def filter_arg(text):
+    try:
+        return devicefilter.parse(text)
+    except ValueError as e:
+        raise argparse.ArgumentTypeError(str(e))
+
+def fs_opt_arg(text):
+    try:
+        return fsoptions.parse_assignment(text)
+    except ValueError as e:
+        raise argparse.ArgumentTypeError(str(e))
+
+def drive_filter_arg(args):
+    # The discovery flags narrow down whatever --filter expression was given
+    drive_filter = args.filter or devicefilter.DeviceFilter()
+    if args.min_size is not None:
+        drive_filter.min_size(args.min_size)
+    if args.max_size is not None:
+        drive_filter.max_size(args.max_size)
+    if args.media:
+        getattr(drive_filter, args.media)()
+    drive_filter.transport(*args.transport)
+    for pattern in args.model:
+        drive_filter.model(pattern)
+    if args.blank:
+        drive_filter.blank()
+    if args.exclude_boot_disk:
+        drive_filter.exclude_boot_disk()
+    if args.exclude_removable:
+        drive_filter.exclude_removable()
+    return drive_filter
+
+def partition_arg(text):
+    # DEVICE, DEVICE=SIZE or DEVICE=SIZE@OFFSET, e.g. /dev/sdb=500G@1M
+    device, _, placement = text.partition("=")
+    size, _, start = placement.partition("@")
+    if not device.startswith("/dev/"):
+        raise argparse.ArgumentTypeError(f"'{text}' does not start with a device, use e.g. /dev/sdb=500G")
+    return device, {'size': size_arg(size) if size else None, 'start': size_arg(start) if start else None}
+
+def print_probe(args):
+    info = args.service.probe(args.probe)
+    if info is None:
+        print(f"ERROR: {args.probe} is not a disk.")
+        return 1
+    if args.format != "table":
+        return output.emit(args.format, "probe", {'probe': info, 'eligibility': backend.level_eligibility(info['drive'], [])})
+    d = info['drive']
+    print(f"{d['name']}: {d['model'] or 'unknown model'}, serial {d['serial'] or '-'}, WWN {d['wwn'] or '-'}, firmware {d['firmware'] or '-'}")
+    neighbours = [n for n in backend.controllers(args.service.inventory().values()).get(d['controller'], []) if n != d['name']]
+    print(f"  Controller:  {d['controller'] or 'unknown'}" + (f", shared with {', '.join(neighbours)}" if d['controller'] and neighbours else ""))
+    print(f"  Size:        {units.format_size(d['size_bytes'])} ({d['size_bytes']} bytes), "
+          f"{'SSD' if not d['rotational'] else 'HDD'}, {d['transport'] or 'unknown transport'}"
+          + (f", link {backend.format_link(d)}" if backend.format_link(d) else ""))
+
+    in_use = [f"mounted at {', '.join(info['mountpoints'])}"] if info['mountpoints'] else []
+    if info['raid_member']:
+        in_use.append("member of an md array")
+    if info['holders']:
+        in_use.append(f"held by {', '.join(info['holders'])}")
+    print(f"  Usage:       {'available' if info['available'] else 'in use'}" + (f" ({'; '.join(in_use)})" if in_use else ""))
+
+    smart = info['smart']
+    if smart['passed'] is None:
+        print("  SMART:       unavailable")
+    else:
+        print(f"  SMART:       {'PASSED' if smart['passed'] else 'FAILED'}, reallocated {smart['reallocated'] if smart['reallocated'] is not None else '-'}, "
+              f"pending {smart['pending'] if smart['pending'] is not None else '-'}"
+              + (f", {smart['power_on_hours']} power-on hours" if smart['power_on_hours'] is not None else "")
+              + (f", {info['temperature']} °C" if info['temperature'] is not None else ""))
+
+    table = info['partitions']
+    if table is None:
+        print("  Partitions:  unreadable")
+    else:
+        print(f"  Partitions:  {table['label'] or 'no partition table'}")
+        for p in table['partitions']:
+            print(f"    {p['number']:>3}  {units.format_size(p['size']):>10}  {p['fstype'] or '-':<10} {p['name'] or ''}"
+                  + (f" [{', '.join(p['flags'])}]" if p['flags'] else ""))
+        free = sum(r['size'] for r in table['free'])
+        if table['label'] and free:
+            print(f"         {units.format_size(free):>10}  free")
+    for sig in info['signatures']:
+        print(f"  Signature:   {sig['type']} at {sig['offset']}" + (f", label {sig['label']}" if sig['label'] else "")
+              + (f", UUID {sig['uuid']}" if sig['uuid'] else ""))
+
+    if args.speed_test:
+        speed = backend.measure_read_speed(d['name'])
+        print(f"  Read speed:  {units.format_size(speed)}/s (sequential, first 1 GiB)" if speed else "  Read speed:  test failed")
+    else:
+        print("  Read speed:  not measured (--speed-test reads the first 1 GiB)")
+
+    inventory = args.service.inventory()
+    selected = []
+    for device in args.with_devices:
+        if device not in inventory:
+            print(f"WARNING: {device} is not an available drive, left out of the eligibility check.")
+            continue
+        selected.append(inventory[device])
+    flags = backend.predict_drive_conflicts(d, selected + [d])
+    if flags:
+        print(f"  Flags:       {', '.join(flags)}")
+
+    print("  Eligibility" + (f" alongside {', '.join(s['name'] for s in selected)}:" if selected else " (on its own, add the current selection with --with):"))
+    for level, report in backend.level_eligibility(d, selected).items():
+        verdict = "no" if report['errors'] else "yes"
+        detail = report['errors'] + [f"[{w['code']}] {w['message']}" for w in report['warnings']]
+        print(f"    RAID {level:<3} {verdict:<4} {units.format_size(report['capacity_bytes']) if not report['errors'] else '':>10}  {'; '.join(detail)}")
+    return 0
+
+def cmd_drives(args):
+    if args.probe:
+        return print_probe(args)
+    if args.benchmark:
+        timings = []
+        for _ in range(args.benchmark):
+            started = time.monotonic()
+            count = len(backend.get_available_drives())
+            timings.append((time.monotonic() - started) * 1000)
+        print(f"Discovered {count} drives in {min(timings):.0f} ms best, {sum(timings) / len(timings):.0f} ms average over {len(timings)} runs")
+        return 0
+    drive_filter = drive_filter_arg(args)
+    drives = drive_filter.apply(backend.get_available_drives())
+    if args.format != "table":
+        return output.emit(args.format, "discover", {'filter': None if drive_filter.is_empty() else drive_filter.describe(),
+                                                     'drives': [dict(d, flags=backend.predict_drive_conflicts(d, [])) for d in drives]})
+    if not drives:
+        print("No available drives" + ("." if drive_filter.is_empty() else f" matching {drive_filter.describe()}."))
+    for d in drives:
+        kind = "SSD" if not d['rotational'] else "HDD"
+        flags = ", ".join(backend.predict_drive_conflicts(d, []))
+        health = d['smart']['health'] or "-"
+        print(f"{d['name']:<14} {units.format_size(d['size_bytes']):>10}  {kind}  {health:<8} {d['transport'] or '-':<5} "
+              f"{d['firmware'] or '-':<10} {backend.format_link(d) or '-':<16} {flags}")
+    return 0
+
+def cmd_teardown_stack(args):
+    # Pin the physical disk now, the plan is only valid for the disk it was computed on
+    identity = backend.get_device_identity(args.disk)
+    try:
+        steps = backend.plan_teardown(args.disk)
+    except Exception as e:
+        print(f"ERROR: Failed to inspect {args.disk}: {e}")
+        return 1
+
+    print(f"Teardown plan for {args.disk}:")
+    for i, cmd in enumerate(steps, 1):
+        print(f"  {i}. {' '.join(cmd)}")
+
+    print_foreign_data(args, [args.disk])
+    if args.dry_run:
+        return 0
+    if not confirm_action(args, f"This will release and wipe {args.disk}. Continue?", destructive=True):
+        print("Aborted.")
+        return 1
+
+    if not args.service.teardown_disk(args.disk, identity):
+        print(f"ERROR: Teardown of {args.disk} failed.")
+        return 1
+    print(f"SUCCESS: {args.disk} is ready to be used in a new array.")
+    return 0
+
+def print_step_progress(event):
+    if output.progress_proto:
+        # Each step is a phase of its own, PERCENT is how far that step got; a failure and the rollback end in RESULT
+        if event['state'] in ("running", "done"):
+            output.progress(f"step-{event['step']}", event['name'], event.get('step_percent', 100.0 if event['state'] == "done" else 0.0))
+        return
+    # One bar line per step event, the log of each step prints in between
+    filled = int(event['percent'] / 5)
+    within = f" ({event['step_percent']:.1f}%)" if 'step_percent' in event else ""
+    state = event.get('outcome') or event['state']
+    print(f"[{'#' * filled}{'.' * (20 - filled)}] {event['percent']:5.1f}%  step {event['step']}/{event['steps']} "
+          f"{state}{within}: {event['name']}", flush=True)
+
+def print_progress(progress):
+    if output.progress_proto:
+        output.progress(progress['action'], progress['action'], progress['percent'])
+        return
+    mismatches = f"  mismatches={progress['mismatches']}" if 'mismatches' in progress else ""
+    print(f"  {progress['action']} {progress['percent']:5.1f}%  finish={progress['finish']} speed={progress['speed']}{mismatches}", flush=True)
+
+def clean_stale_md(args):
+    # Leftovers of earlier runs hold on to disks and md names, offer to clear them before creating
+    stale = args.service.stale_md_devices()
+    if not stale:
+        return True
+    print("Leftovers of earlier md runs found:")
+    for entry in stale:
+        print(f"  {entry['path']:<16} {entry['kind']:<14} {entry['detail']}")
+    if not confirm_action(args, "Stop/remove them before continuing?", destructive=True):
+        print("Leaving them in place, a colliding name or held disk can make the next step fail.")
+        return True
+    return args.service.cleanup_stale_md(stale)
+
+def cmd_cleanup_md(args):
+    if not args.service.stale_md_devices():
+        print("No stale md devices found.")
+        return 0
+    return 0 if clean_stale_md(args) else 1
+
+def md_options(args):
+    return {'metadata': args.metadata, 'bitmap': args.bitmap, 'layout': args.layout, 'name': args.name}
+
+def cmd_create(args):
+    if not clean_stale_md(args):
+        print("ERROR: Cleaning up the stale md devices failed.")
+        return 1
+    args.array = args.array or arraynames.next_array_name()
+    drives = args.service.inventory()
+    report = args.service.validate(args.level, args.devices, args.suppress, args.preserve_partitions, args.spare, args.strict or None,
+                                   **md_options(args))
+    for error in report['errors']:
+        print(f"ERROR: {error}")
+    if report['errors']:
+        return 1
+    for warning in report['warnings']:
+        print(f"WARNING [{warning['code']}]: {warning['message']}")
+
+    # Existing partitions survive when only free space is used
+    # Spares are always whole disks, their partitions go even with --preserve-partitions
+    has_data = [dev for dev in ([] if args.preserve_partitions else args.devices) + args.spare if drives.get(dev, {}).get('has_data')]
+    prompt = f"Create {args.array} from {', '.join(args.devices)}" + (f" with spare {', '.join(args.spare)}?" if args.spare else "?")
+    if has_data:
+        print_foreign_data(args, has_data)
+        prompt = f"{', '.join(has_data)} still hold data that will be destroyed. " + prompt
+    if not confirm_action(args, prompt, destructive=bool(has_data)):
+        print("Aborted.")
+        return 1
+
+    identities = {dev: backend.drive_identity(drives[dev]) for dev in args.devices + args.spare}
+    success, job = args.service.create_array(args.level, args.devices, args.array, args.chunk, args.assume_clean, identities,
+                                             args.preserve_partitions, spares=args.spare, **md_options(args))
+    if not success:
+        print("ERROR: RAID creation failed.")
+        return 1
+    print(f"SUCCESS: {args.array} created.")
+
+    if job is None:
+        return 0
+    if not args.wait:
+        print(f"Initial sync continues in the background as job {job['id']} (see 'jobs show {job['id']}').")
+        return 0
+
+    return 0 if wait_for_initial_sync(args, args.array, job) else 2
+
+def wait_for_initial_sync(args, array_name, job):
+    # --wait of create and apply, False when --timeout ran out first (exit code 2)
+    timeout = backend.parse_duration(args.timeout) if args.timeout else None
+    print(f"Waiting for {array_name} to finish its initial sync (job {job['id']})...")
+    return args.service.wait_for_sync(array_name, job, timeout, print_progress)
+
+# Command line flag of each plan field, so validation errors point at what to change
+PLAN_FLAGS = {'level': "level", 'devices': "devices", 'array': "--array", 'filesystem': "--filesystem",
+              'mount_point': "--mount-point", 'label': "--label", 'chunk': "--chunk", 'spares': "--spare",
+              'mount_options': "--mount-option", 'scrub': "--scrub", 'preserve_partitions': "--preserve-partitions",
+              'backend': "--backend", 'smart_short': "--smart-short", 'smart_long': "--smart-long", 'encryption': "--encrypt",
+              'partitions': "--partition", 'wipe': "--wipe", 'metadata': "--metadata", 'bitmap': "--bitmap", 'layout': "--layout",
+              'name': "--name", 'fs_options': "--fs-opt", 'write_mostly': "--write-mostly", 'write_behind': "--write-behind",
+              'auto_read_only': "--auto-read-only", 'existing': "--existing"}
+
+def encryption_arg(args):
+    return luks.new_encryption(args.cipher, args.key_size, args.pbkdf, args.keyfile) if args.encrypt else None
+
+def ask_passphrase(encryption):
+    """
+    Returns the passphrase for a new LUKS container ('' when a keyfile or a dry run needs none), or None if it wasn't given.
+    """
+    if encryption['keyfile'] or backend.DRY_RUN:
+        return ""
+    try:
+        passphrase = getpass.getpass("Passphrase for the encrypted array: ")
+        again = getpass.getpass("Repeat the passphrase: ")
+    except EOFError:
+        return None
+    if passphrase != again:
+        print("ERROR: The passphrases don't match.")
+        return None
+    if len(passphrase) < luks.MIN_PASSPHRASE_LENGTH:
+        print(f"ERROR: The passphrase must be at least {luks.MIN_PASSPHRASE_LENGTH} characters long.")
+        return None
+    return passphrase
+
+def cmd_presets(args):
+    for name, preset in plan.PRESETS.items():
+        options = f"RAID {preset['level']}, {preset['filesystem']}"
+        if preset.get('chunk'):
+            options += f", chunk {preset['chunk']}"
+        if preset.get('mount_options'):
+            options += f", mounted {','.join(preset['mount_options'])}"
+        if preset.get('scrub'):
+            options += f", {preset['scrub']} scrub"
+        if preset.get('smart_short') or preset.get('smart_long'):
+            options += ", SMART self-tests"
+        print(f"{name:<10} {preset['title']} ({options})")
+        print(f"{'':<10} {preset['description']}")
+    return 0
+
+def plan_fields(args):
+    """
+    The plan.new_plan() arguments given on the command line, also what 'plan --remote' sends to the daemon.
+    """
+    # With --preset the level may be left out: 'plan --preset backup /dev/sdb /dev/sdc'
+    if args.level and args.level.startswith("/"):
+        args.devices.insert(0, args.level)
+        args.level = None
+    if args.existing and args.array and not args.devices:
+        # Level and members as the array runs, nothing of it is recreated
+        for arr in backend.get_arrays_status([args.array]):
+            if arr['status'] == "active":
+                args.level = args.level or (arr['type'] or "").replace("raid", "") or None
+                args.devices = [m['device'] for m in arr['members'] if m['device'] and m['role'] in ("active", "rebuilding")]
+                args.spare = args.spare or [m['device'] for m in arr['members'] if m['device'] and m['role'] == "spare"]
+    if args.select:
+        selected = plan.select_devices(args.select, list(args.service.inventory().values()), args.devices + args.spare,
+                                       args.include_boot_disk)
+        if not selected:
+            print(f"WARNING: No available drive matches {args.select.describe()}.")
+        args.devices += selected
+    return {'level': args.level, 'devices': args.devices, 'array_name': args.array, 'filesystem': args.filesystem,
+            'mount_point': args.mount_point, 'label': args.label, 'chunk': args.chunk, 'spares': args.spare,
+            'mount_options': args.mount_option or None, 'scrub': args.scrub, 'preset': args.preset,
+            'preserve_partitions': args.preserve_partitions, 'backend': args.backend, 'smart_short': args.smart_short,
+            'smart_long': args.smart_long, 'encryption': encryption_arg(args), 'partitions': dict(args.partition),
+            'wipe': {'method': args.wipe, 'block_size': args.wipe_block_size} if args.wipe or args.wipe_block_size else None,
+            'metadata': args.metadata, 'bitmap': args.bitmap, 'layout': args.layout, 'name': args.name,
+            'fs_options': dict(args.fs_opt) or None, 'write_mostly': args.write_mostly, 'write_behind': args.write_behind,
+            'auto_read_only': args.auto_read_only, 'existing': args.existing}
+
+def cmd_plan(args):
+    # Plans made earlier in the same session and not applied yet, their arrays and drives are taken
+    try:
+        others = [plan.load_plan(path) for path in args.alongside]
+    except (OSError, ValueError) as e:
+        print(f"ERROR: {e}")
+        return 1
+    planned = [other['array'] for other in others]
+    proposal = plan.new_plan(**plan_fields(args), planned=planned)
+    drives = args.service.inventory()
+    report = plan.validate_plan(proposal, drives, args.suppress, args.strict or None, others)
+    # Checked again by 'apply', a disk swapped in the meantime is refused
+    plan.record_identities(proposal, drives)
+    # How the array's name was picked when none was given, so it's clear why it isn't /dev/md0
+    allocation = None
+    if not args.array and proposal['backend'] == "mdadm":
+        allocated, skipped = arraynames.allocate(proposal['name'], planned)
+        allocation = {'array': allocated, 'naming': config.get_setting('md_naming'), 'skipped': skipped}
+    if args.format != "table":
+        valid = not report['errors']
+        if valid and args.output:
+            plan.save_plan(proposal, args.output)
+        return output.emit(args.format, "plan", {'valid': valid, 'errors': report['errors'], 'warnings': report['warnings'],
+                                                 'capacity_bytes': report['capacity_bytes'], 'summary': report['summary'],
+                                                 'checklist': report['checklist'], 'plan': proposal,
+                                                 'steps': plan.plan_steps(proposal), 'allocation': allocation,
+                                                 'saved_to': args.output if valid else None},
+                           0 if valid else 1)
+    for error in report['errors']:
+        print(f"ERROR ({PLAN_FLAGS[error['field']]}): {error['message']}")
+    if report['errors']:
+        return 1
+    for warning in report['warnings']:
+        print(f"WARNING [{warning['code']}]: {warning['message']}")
+
+    if proposal['preset']:
+        print(f"Preset: {plan.PRESETS[proposal['preset']]['title']}")
+    print(f"Plan for {proposal['array']}: RAID {proposal['level']} on {', '.join(proposal['devices'])}"
+          + (f" + spare {', '.join(proposal['spares'])}" if proposal['spares'] else ""))
+    print(f"  Backend: {raidbackend.get_backend(proposal['backend']).title}")
+    if proposal['existing']:
+        print(f"  Existing array: {proposal['array']} runs already and is reused as it is, not created")
+    if allocation:
+        print(f"  Array name: {arraynames.describe(proposal['array'], allocation['skipped'])}")
+    tunables = [f"{field} {proposal[field]}" for field in ('metadata', 'bitmap', 'layout', 'name') if proposal[field]]
+    tunables += [f"write-mostly {dev}" for dev in proposal['write_mostly']]
+    if proposal['write_behind']:
+        tunables.append(f"write-behind {proposal['write_behind']}")
+    if proposal['auto_read_only']:
+        tunables.append("read-only until the first write")
+    if tunables:
+        print(f"  mdadm options: {', '.join(tunables)}")
+    if proposal['level'] == "10" and len(proposal['devices']) % 2 == 0 and proposal['layout'] in (None, "near"):
+        print(f"  Mirror pairs: {', '.join(' + '.join(pair) for pair in backend.mirror_pairs(proposal['devices']))} (in the order given)")
+    print(f"  Capacity: {backend.describe_capacity(report['summary'])}")
+    if proposal['wipe']['method'] != "none":
+        print(f"  Wipe first: {wipe.describe(proposal['wipe'])} of every member and spare")
+    if proposal['partitions']:
+        print(f"  Partitions: {plan.format_value(proposal['partitions'])}, other members whole disks")
+    if proposal['encryption']:
+        print(f"  Encryption: {luks.describe(proposal['encryption'])}")
+    print(f"  Filesystem: {proposal['filesystem']}" + (f" labeled '{proposal['label']}'" if proposal['label'] else ""))
+    tuning = fsoptions.resolve(proposal['filesystem'], proposal['fs_options'], proposal['level'], len(proposal['devices']), proposal['chunk'])
+    if tuning:
+        derived = [key for key in tuning if key not in proposal['fs_options']]
+        print(f"  Filesystem tuning: {fsoptions.describe(proposal['filesystem'], tuning)}"
+              + (f" ({', '.join(derived)} derived from the chunk size and data disks)" if derived else ""))
+    if proposal['mount_point']:
+        print(f"  Mount point: {proposal['mount_point']} ({plan.mount_options(proposal)})")
+    else:
+        print("  Mount point: (not mounted)")
+    if proposal['scrub']:
+        print(f"  Scrub: {proposal['scrub']}")
+    if proposal['smart_short'] or proposal['smart_long']:
+        print(f"  SMART self-tests: short {proposal['smart_short'] or 'never'}, long {proposal['smart_long'] or 'never'}")
+    print_checklist(report['checklist'])
+    print("Steps:")
+    for i, step in enumerate(plan.plan_steps(proposal), 1):
+        print(f"  {i}. {step}")
+    if args.output:
+        plan.save_plan(proposal, args.output)
+        print(f"Plan saved to {args.output}, run it with 'apply {args.output}'.")
+    return 0
+
+def apply_result(args, proposal, report, success, job=None, recorded=None):
+    # The --format json|yaml document of 'apply', whichever way it ends, with the script of a dry run
+    applied = args.service.last_apply_job
+    steps = applied['steps'] if applied and 'steps' in applied else \
+        [{'name': step, 'state': 'pending'} for step in (plan.plan_steps(proposal) if proposal else [])]
+    return output.emit(args.format, "apply", {'plan_file': args.plan_file, 'array': proposal['array'] if proposal else None,
+                                              'success': success, 'errors': report['errors'], 'warnings': report['warnings'],
+                                              'capacity_bytes': report.get('capacity_bytes'), 'checklist': report.get('checklist'), 'steps': steps,
+                                              'apply_job': applied['id'] if applied else None, 'sync_job': job['id'] if job else None,
+                                              'uuids': applied.get('uuids') if applied else None,
+                                              'script': recorded.entries if recorded else None},
+                       0 if success else 1)
+
+def print_script(args, recorded):
+    # What a dry run of 'apply' skipped, as a list or (--script) a bash script
+    if args.script:
+        text = recorded.to_bash()
+        if args.script == "-":
+            print(text, end="")
+            return
+        with open(args.script, 'w') as f:
+            f.write(text)
+        os.chmod(args.script, 0o755)
+        print(f"Script saved to {args.script}, {len(recorded.entries)} commands and file edits.")
+        return
+    print("A real run would execute:")
+    for i, entry in enumerate(recorded.entries, 1):
+        if entry['kind'] == "command":
+            print(f"  {i}. {shlex.join(entry['argv'])}")
+        elif entry['kind'] == "sysfs":
+            print(f"  {i}. echo {entry['value']} > {entry['path']}")
+        else:
+            print(f"  {i}. write {entry['path']}")
+    print("Save it as a bash script with --script FILE.")
+
+def cmd_apply(args):
+    # Whatever way the apply ends, a provisioning pipeline waiting on --notify-url hears about it
+    outcome = {'proposal': None, 'report': {'errors': [], 'warnings': []}, 'success': False, 'job': None, 'throughput': None}
+    try:
+        return apply_plan_file(args, outcome)
+    finally:
+        notify_provisioning(args, **outcome)
+
+def apply_plan_file(args, outcome):
+    """
+    cmd_apply() without the provisioning report, it fills in outcome for that as it goes.
+    """
+    structured = args.format != "table"
+    if args.script and not backend.DRY_RUN:
+        print("ERROR: --script saves what a dry run would do, add --dry-run.")
+        return 1
+    try:
+        proposal = plan.load_plan(args.plan_file)
+    except (OSError, ValueError) as e:
+        print(f"ERROR: {e}")
+        outcome['report'] = {'errors': [{'field': None, 'message': str(e)}], 'warnings': []}
+        if structured:
+            return apply_result(args, None, outcome['report'], False)
+        return 1
+    outcome['proposal'] = proposal
+    if proposal['backend'] == "mdadm" and not clean_stale_md(args):
+        print("ERROR: Cleaning up the stale md devices failed.")
+        outcome['report'] = {'errors': [{'field': None, 'message': "Cleaning up the stale md devices failed."}], 'warnings': []}
+        if structured:
+            return apply_result(args, proposal, outcome['report'], False)
+        return 1
+    # The disks may have changed since the plan was written, so it is validated again now
+    drives = args.service.inventory()
+    report = outcome['report'] = plan.validate_plan(proposal, drives, args.suppress, args.strict or None)
+    for error in report['errors']:
+        print(f"ERROR ({PLAN_FLAGS[error['field']]}): {error['message']}")
+    if report['errors']:
+        return apply_result(args, proposal, report, False) if structured else 1
+    for warning in report['warnings']:
+        print(f"WARNING [{warning['code']}]: {warning['message']}")
+
+    print(f"Applying {args.plan_file}: RAID {proposal['level']} as {proposal['array']}, "
+          f"{units.format_size(report['capacity_bytes'])} usable")
+    for i, step in enumerate(plan.plan_steps(proposal), 1):
+        print(f"  {i}. {step}")
+    print_checklist(report['checklist'])
+    members = proposal['devices'] + proposal['spares']
+    has_data = [] if proposal['preserve_partitions'] else [dev for dev in members if drives.get(dev, {}).get('has_data')]
+    prompt = f"Apply this plan to {', '.join(members)}?"
+    if report['warnings']:
+        prompt = f"Apply this plan to {', '.join(members)} anyway, despite {len(report['warnings'])} warning(s)?"
+    if has_data:
+        print_foreign_data(args, has_data)
+        prompt = f"{', '.join(has_data)} still hold data that will be destroyed. " + prompt
+    if not confirm_action(args, prompt, destructive=bool(has_data)):
+        print("Aborted.")
+        return apply_result(args, proposal, report, False) if structured else 1
+
+    passphrase = None
+    if proposal['encryption']:
+        passphrase = ask_passphrase(proposal['encryption'])
+        if passphrase is None:
+            print("Aborted.")
+            return apply_result(args, proposal, report, False) if structured else 1
+
+    identities = plan.identities(proposal, drives)
+    handle = executor.JobHandle()
+    # The initial sync is followed as the last step, the throughput test would compete with it
+    testing = args.throughput_test and proposal['mount_point'] and not structured
+    if testing and not args.wait:
+        print("The throughput test waits for the initial sync first, it would compete with the test.")
+    timeout = backend.parse_duration(args.timeout) if args.timeout else None
+    with script.recording() if backend.DRY_RUN else contextlib.nullcontext() as recorded, cancel_on_interrupt(handle):
+        success, job = args.service.apply_plan(proposal, identities, passphrase, on_progress=print_step_progress, handle=handle,
+                                               wait=bool(args.wait or testing), timeout=timeout)
+    outcome.update(success=success, job=job)
+    # --timeout ran out before the sync finished (exit code 2)
+    synced = not success or args.service.last_apply_job.get('synced', True)
+    if structured:
+        status = apply_result(args, proposal, report, success, job, recorded)
+        return status if synced else 2
+    if recorded:
+        print_script(args, recorded)
+    if not success:
+        print("ERROR: Applying the plan was cancelled." if handle.cancelled else "ERROR: Applying the plan failed.")
+        return 1
+    steps = args.service.last_apply_job.get('steps') or []
+    print_outcomes(steps)
+    if all(step.get('outcome') == executor.UNCHANGED for step in steps):
+        print(f"SUCCESS: {proposal['array']} is already as planned, nothing was changed.")
+    else:
+        print(f"SUCCESS: {proposal['array']} created" + (f" and mounted on {proposal['mount_point']}." if proposal['mount_point'] else "."))
+    uuids = args.service.last_apply_job.get('uuids') or {}
+    found = [f"{kind} UUID {uuid}" for kind, uuid in (("Array", uuids.get('array')), ("filesystem", uuids.get('filesystem'))) if uuid]
+    if found:
+        print(f"{', '.join(found)}.")
+    if not synced:
+        print(f"WARNING: Stopped waiting for the initial sync of {proposal['array']}, it continues in the background as job {job['id']}.")
+        return 2
+    if job and not (args.wait or testing):
+        print(f"Initial sync continues in the background as job {job['id']} (see 'jobs show {job['id']}').")
+    if testing:
+        throughput = outcome['throughput'] = args.service.throughput_test(proposal['array'], proposal['mount_point'], args.throughput_test)
+        if throughput is not None:
+            print_throughput(throughput)
+        if throughput is None:
+            return 1
+        return 0 if throughput['passed'] else 3
+    return 0
+
+def print_outcomes(steps):
+    # What each step did, like a configuration-management run: Created, Updated or Unchanged
+    for step in steps:
+        if step.get('outcome'):
+            print(f"  {step['outcome'].capitalize():<10} {step['name']}")
+
+@contextlib.contextmanager
+def cancel_on_interrupt(handle):
+    """
+    Turns Ctrl-C into handle.cancel() for the block: the running step stops and the completed ones are rolled back
+    instead of the apply dying halfway. Further Ctrl-Cs are ignored until the rollback finished.
+    """
+    def on_interrupt(signum, frame):
+        if not handle.cancelled:
+            print("\nCancelling: the running step is stopped and the completed steps rolled back, wait for it to finish...", flush=True)
+        handle.cancel()
+
+    previous = signal.signal(signal.SIGINT, on_interrupt)
+    try:
+        yield handle
+    finally:
+        signal.signal(signal.SIGINT, previous)
+
+def notify_provisioning(args, proposal, report, success, job, throughput=None):
+    # Undelivered reports are only warned about, the array is built either way
+    if not provisioning.has_targets(args.notify_url):
+        return
+    document = provisioning.build_report(proposal, report, success, args.service.last_apply_job, job, throughput)
+    print(provisioning.notify(document, args.notify_url)[1], end="")
+
+def print_throughput(report):
+    print(f"Throughput of {report['array']} (RAID {report['level']}):")
+    for kind in ("write", "read"):
+        print(f"  {acceptance.format_result(kind, report[kind])}")
+    for cause in report['causes']:
+        print(f"  Possible cause: {cause}")
+    if report['passed']:
+        print(f"SUCCESS: {report['array']} reaches at least {config.get_setting('throughput_min_ratio'):.0%} of its estimated throughput.")
+    else:
+        print(f"ERROR: {report['array']} falls short of its estimated throughput (threshold {config.get_setting('throughput_min_ratio'):.0%}).")
+
+def cmd_throughput_test(args):
+    mount_point = args.mount_point or plan.plan_from_array(args.array)['mount_point']
+    if not mount_point:
+        print(f"ERROR: {args.array} is not mounted, pass --mount-point.")
+        return 1
+    report = args.service.throughput_test(args.array, mount_point, args.size)
+    if report is None:
+        return 1
+    if args.format != "table":
+        return output.emit(args.format, "throughput-test", report, 0 if report['passed'] else 1)
+    print_throughput(report)
+    return 0 if report['passed'] else 1
+
+def cmd_bench(args):
+    mount_point = args.mount_point or plan.plan_from_array(args.array)['mount_point']
+    if not mount_point:
+        print(f"ERROR: {args.array} is not mounted, pass --mount-point.")
+        return 1
+    report = args.service.benchmark(args.array, mount_point, args.workload, args.size, args.runtime, args.baseline, args.engine)
+    if report is None:
+        return 1
+    if args.format != "table":
+        return output.emit(args.format, "bench", report, 0 if report['passed'] else 1)
+    if not report['results']:
+        return 0
+    print(f"Benchmark of {report['array']} (RAID {report['level']}, {report['members']} members, {report['engine']}):")
+    for result, workload in zip(report['results'], args.workload or bench.WORKLOADS):
+        print(f"  {bench.format_result(workload, result)}")
+    if report['baseline']:
+        print(f"Baseline, {report['baseline']['device']} alone:")
+        for result in report['baseline']['results']:
+            if result:
+                print(f"  {bench.format_result(result['workload'], result)}")
+        for c in report['comparison']:
+            verdict = "PASS" if c['passed'] else "FAIL"
+            print(f"  {c['workload']:<11} {c['ratio']:.1f}x one member (RAID {report['level']} should reach about {c['expected']}x)  {verdict}")
+    if not report['passed']:
+        print(f"ERROR: {report['array']} " + ("falls short of the expected speed-up over one member." if all(report['results'])
+                                             else "could not run every workload."))
+        return 1
+    print(f"SUCCESS: {report['array']} benchmarked.")
+    return 0
+
+def cmd_format(args):
+    # Reuses an array that was created outside LiveRAID (e.g. with custom mdadm flags)
+    if not backend.is_array_active(args.array):
+        print(f"ERROR: {args.array} is not an active md array. Create or assemble it first.")
+        return 1
+
+    contents = backend.get_device_contents(args.array)
+    if contents is None:
+        print(f"ERROR: Could not inspect {args.array}.")
+        return 1
+    if contents:
+        print(f"{args.array} is not empty, it contains:")
+        for item in contents:
+            print(f"  - {item}")
+        if not args.force:
+            print("Refusing to format a non-empty array without --force.")
+            return 1
+
+    # Stripe geometry from the running array, explicit --fs-opt values on top
+    fs_options = dict(args.fs_opt)
+    level = (backend.read_md_attribute(args.array, "level") or "").replace("raid", "")
+    raid_disks = backend.read_md_attribute(args.array, "raid_disks")
+    chunk_bytes = backend.read_md_attribute(args.array, "chunk_size")
+    chunk = f"{int(chunk_bytes) // 1024}K" if chunk_bytes and chunk_bytes.isdigit() and int(chunk_bytes) else None
+    tuning = fsoptions.resolve(args.filesystem, fs_options, level, int(raid_disks) if raid_disks and raid_disks.isdigit() else 0, chunk)
+    mkfs_options = fsoptions.mkfs_arguments(args.filesystem, tuning) + shlex.split(" ".join(args.mkfs_opt))
+    encryption = encryption_arg(args)
+    mkfs_cmd, errors = args.service.validate_format(args.array, args.filesystem, args.trim, mkfs_options, encryption)
+    errors += fsoptions.check(args.filesystem, fs_options)
+    if encryption:
+        errors += luks.check(encryption)
+        if not backend.DRY_RUN and shutil.which("cryptsetup") is None:
+            errors.append(f"Encryption needs cryptsetup: {doctor.install_hint('cryptsetup')}")
+    for error in errors:
+        print(f"ERROR: {error}")
+    if errors:
+        return 1
+    if encryption:
+        print(f"Encryption: {luks.describe(encryption)}, filesystem in {luks.mapper_device(args.array)}")
+    print(f"mkfs command: {' '.join(mkfs_cmd)}")
+
+    if not confirm_action(args, f"Partition and format {args.array}?", destructive=bool(contents)):
+        print("Aborted.")
+        return 1
+    passphrase = ask_passphrase(encryption) if encryption else None
+    if encryption and passphrase is None:
+        print("Aborted.")
+        return 1
+
+    if not args.service.format_array(args.array, args.filesystem, args.boot, args.trim, mkfs_options,
+                                     encryption=encryption, passphrase=passphrase):
+        print(f"ERROR: Failed to format {args.array}.")
+        return 1
+    print(f"SUCCESS: {args.array} formatted as {args.filesystem}.")
+    return 0
+
+def cmd_migrate_hwraid(args):
+    volumes = migrate.detect_hardware_raid_volumes()
+    if not args.source:
+        if not volumes:
+            print("No hardware RAID volumes detected.")
+        for vol in volumes:
+            fs = ", ".join(f"{f['device']} ({f['fstype']})" for f in vol['filesystems']) or "no filesystems"
+            print(f"{vol['name']}  {units.format_size(vol['size_bytes'])}  {vol['model']} [{vol['driver']}]  {fs}")
+        return 0
+
+    if not args.level or not args.devices:
+        print("ERROR: Give the RAID level and the disks for the new array, e.g. 'migrate-hwraid /dev/sda1 1 /dev/sdc /dev/sdd'.")
+        return 1
+    report = args.service.validate(args.level, args.devices, args.suppress)
+    for error in report['errors']:
+        print(f"ERROR: {error}")
+    if report['errors']:
+        return 1
+    for warning in report['warnings']:
+        print(f"WARNING [{warning['code']}]: {warning['message']}")
+
+    drives = args.service.inventory()
+    identities = {dev: backend.drive_identity(drives[dev]) for dev in args.devices}
+
+    # Wiping the old volume afterwards needs the whole virtual disk, not the partition that was copied
+    old_volume = None
+    if args.repurpose:
+        old_volume = next((v['name'] for v in volumes if any(f['device'] == args.source for f in v['filesystems'])), None)
+        if old_volume is None:
+            print(f"ERROR: {args.source} is not on a detected hardware RAID volume, refusing to wipe it afterwards.")
+            return 1
+
+    array_name = args.array or arraynames.next_array_name()
+    steps = [f"mount {args.source} read-only", f"create RAID {args.level} {array_name} from {', '.join(args.devices)}",
+             f"format {array_name} as {args.filesystem}", "copy all files with rsync"]
+    if old_volume:
+        steps.append(f"wipe {old_volume} so its disks can be reused")
+    print("Migration plan:")
+    for i, step in enumerate(steps, 1):
+        print(f"  {i}. {step}")
+    if not confirm_action(args, f"{', '.join(args.devices)} will be wiped. Start the migration?", destructive=True):
+        print("Aborted.")
+        return 1
+
+    if not args.service.migrate_hardware_raid(args.source, args.level, args.devices, args.filesystem, array_name, old_volume, identities):
+        print(f"ERROR: Migration of {args.source} failed.")
+        return 1
+    print(f"SUCCESS: {args.source} migrated to {array_name}.")
+    return 0
+
+def cmd_migrate_data(args):
+    mode = args.mode
+    if mode == "auto":
+        mode = "block" if migrate.is_block_device(args.source) and migrate.is_block_device(args.target) else "files"
+    action = "overwrite every block of" if mode == "block" else "copy files into"
+    print(f"{'Block' if mode == 'block' else 'File'} copy: {args.source} -> {args.target}" + (f", limited to {units.format_size(args.rate)}/s" if args.rate else ""))
+    if not confirm_action(args, f"This will {action} {args.target}. Continue?", destructive=mode == "block"):
+        print("Aborted.")
+        return 1
+
+    def on_progress(event):
+        if output.progress_proto:
+            output.progress(event['phase'], f"{event['phase']} {args.source} -> {args.target}", event['percent'])
+            return
+        end = "\n" if not sys.stdout.isatty() else "\r"
+        print(f"  {event['phase']} {event['percent']:5.1f}%  {event['speed']}".ljust(40), end=end, flush=True)
+
+    success = args.service.migrate_data(args.source, args.target, mode, args.rate, not args.no_verify, on_progress)
+    if not success:
+        print(f"ERROR: Copy to {args.target} failed. Run the same command again to resume.")
+        return 1
+    print(f"SUCCESS: {args.source} copied to {args.target}" + ("." if args.no_verify else " and verified."))
+    return 0
+
+def cmd_tune(args):
+    results = []
+    for member in args.write_mostly:
+        results.append(backend.set_member_write_mostly(args.array, member, True))
+    for member in args.no_write_mostly:
+        results.append(backend.set_member_write_mostly(args.array, member, False))
+    if args.write_behind is not None:
+        results.append(backend.set_write_behind(args.array, args.write_behind))
+    if args.mode:
+        results.append(backend.set_array_mode(args.array, args.mode))
+    smart = {test: schedule for test, schedule in (("short", args.smart_short), ("long", args.smart_long)) if schedule}
+    if smart:
+        results.append((args.service.schedule_smart_tests(args.array, smart, args.smart_device), ""))
+
+    if not results:
+        print("Nothing to change. See 'tune --help' for the available settings.")
+        return 1
+
+    ok = True
+    for success, out in results:
+        print(out, end="")
+        ok = ok and success
+    return 0 if ok else 1
+
+def cmd_destroy(args):
+    members = [m['device'] for m in backend.get_array_members(args.array) if m['device']]
+    print(f"{args.array} members: {', '.join(members) or 'unknown'}")
+    if not confirm_action(args, f"Stop {args.array} and erase the RAID metadata on its members?", destructive=True):
+        print("Aborted.")
+        return 1
+
+    if not args.service.destroy_array(args.array, args.keep_superblocks):
+        print(f"ERROR: Failed to cleanly destroy {args.array}.")
+        return 1
+    print(f"SUCCESS: {args.array} stopped" + (" (superblocks kept)." if args.keep_superblocks else " and metadata cleared."))
+    return 0
+
+def cmd_discard(args):
+    if args.enable_raid456:
+        if not confirm_action(args, "Only safe if every member returns zeroes for discarded blocks. Enable raid456 discard?", destructive=False):
+            print("Aborted.")
+            return 1
+        if not args.service.enable_raid456_discard():
+            print("ERROR: Failed to enable raid456 discard.")
+            return 1
+        print(f"SUCCESS: raid456 discard enabled, re-assemble {args.array} for it to take effect.")
+
+    report = backend.check_discard(args.array)
+    print(backend.format_discard_report(report), end="")
+    return 1 if report['warnings'] else 0
+
+SCRUB_ACTIONS = ("start", "status", "schedule")
+
+def cmd_scrub(args):
+    if args.action not in SCRUB_ACTIONS and args.array is None:
+        args.action, args.array = "start", args.action
+    if args.action not in SCRUB_ACTIONS:
+        print(f"ERROR: Unknown scrub action '{args.action}', use {', '.join(SCRUB_ACTIONS)}.")
+        return 1
+    if args.array is None:
+        print(f"ERROR: 'scrub {args.action}' needs an array, e.g. /dev/md0.")
+        return 1
+    if args.action == "status":
+        return print_scrub_status(args)
+    if args.action == "schedule":
+        if not args.schedule:
+            print(f"ERROR: 'scrub schedule' needs a schedule: {', '.join(plan.SCRUB_SCHEDULES)} or off.")
+            return 1
+        if not args.service.schedule_scrub(args.array, args.schedule):
+            return 1
+        print(f"SUCCESS: {args.array} is scrubbed {args.schedule}." if args.schedule != "off" else f"SUCCESS: The scrub timer of {args.array} is removed.")
+        return 0
+
+    action = "repair" if args.repair else "check"
+    print(f"Running {action} on {args.array} (this reads every sector and can take hours)...", flush=True)
+    success, mismatches, errors = args.service.scrub(args.array, action, print_progress, record=True)
+    if not success:
+        print(f"ERROR: The {action} of {args.array} did not complete.")
+        return 1
+
+    print(f"{args.array}: {mismatches} mismatched sectors")
+    for dev, count in errors.items():
+        print(f"  {dev}: {count} read errors corrected")
+    if not mismatches and not any(errors.values()):
+        print(f"SUCCESS: {args.array} is consistent.")
+        return 0
+
+    if not args.yes and not confirm("Problems found. Run the remediation sequence (re-check, repair, SMART re-test)?"):
+        print(f"Run 'remediate {args.array}' later to follow up.")
+        return 2
+    args.service.remediate_scrub(args.array, print_progress)
+    return 2
+
+def print_scrub_status(args):
+    status = scrub.status(args.array)
+    if args.format != "table":
+        return output.emit(args.format, "scrub", status)
+    if status['running']:
+        print(f"{args.array}: {status['action']} running, {status['percent']:.1f}% (finish={status['finish']} speed={status['speed']}), "
+              f"{status['mismatches']} mismatched sectors so far")
+    else:
+        print(f"{args.array}: no scrub running" + (f" (sync_action {status['action']})" if status['action'] not in (None, "idle") else ""))
+    last = status['last']
+    print(f"  Last scrub: {time.strftime('%Y-%m-%d %H:%M', time.localtime(last['timestamp'])) + ', ' + last['summary'] if last else 'never'}")
+    print(f"  Schedule:   {status['schedule'] or 'none'}")
+    return 0
+
+def cmd_smart_test(args):
+    print(f"Running a {args.type} SMART self-test on the members of {args.array}...", flush=True)
+    success, reports = args.service.smart_test(args.array, args.type, args.device)
+    for disk, report in reports.items():
+        result = {True: "passed", False: "FAILED", None: "no result"}[report['last_self_test_passed']]
+        print(f"  {disk}: self-test {result}, SMART status {'FAILED' if report['passed'] is False else 'ok'}")
+    return 0 if success else 1
+
+def cmd_remediate(args):
+    args.service.remediate_scrub(args.array, print_progress)
+    return 0
+
+def cmd_health(args):
+    events = health.list_events(args.array)
+    if not events:
+        print("No health events recorded.")
+    for event in events:
+        stamp = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(event['timestamp']))
+        print(f"{stamp}  {event['array']:<10} {event['kind']:<18} {event['summary']}")
+    return 0
+
+def cmd_healthcheck(args):
+    # One line and a Nagios exit code, for cron jobs and check frameworks
+    status, problems = health.evaluate(args.arrays)
+    if status == health.OK:
+        print(f"RAID OK - {len(args.arrays) if args.arrays else len(backend.get_active_arrays())} arrays healthy")
+    else:
+        print(f"RAID {health.STATUS_NAMES[status]} - {'; '.join(problems)}")
+    return status
+
+def print_status(statuses):
+    if not statuses:
+        print("No md arrays found.")
+    for arr in statuses:
+        line = f"{arr['name']:<12} {arr['type'] or '-':<7} {arr['health'].upper()} ({arr['status']}"
+        if arr['raid_disks'] is not None:
+            line += f", {arr['working']}/{arr['raid_disks']} members"
+        line += ")"
+        if arr['sync']:
+            eta = arr['sync']['eta_seconds']
+            line += f", {arr['sync']['action']} {arr['sync']['percent']:.1f}%" + \
+                    (f", about {units.format_duration(eta)} left at {arr['sync']['speed']}" if eta is not None else f" ({arr['sync']['finish']})")
+        if arr['failed']:
+            line += f", failed: {', '.join(arr['failed'])}"
+        print(line)
+        for m in arr['members']:
+            slot = str(m['slot']) if m['slot'] is not None else "-"
+            print(f"  slot {slot:>2}  {m['role']:<10} {m['device'] or '(empty)':<14} {m['serial'] or '':<20} {m['state']}")
+
+def cmd_status(args):
+    if not args.watch:
+        statuses = args.service.status(args.arrays)
+        healthy = all(arr['status'] == "active" for arr in statuses)
+        if args.format != "table":
+            return output.emit(args.format, "status", {'arrays': statuses}, 0 if healthy else 1)
+        print_status(statuses)
+        return 0 if healthy else 1
+    if args.format != "table":
+        print("ERROR: --watch redraws a table, poll 'status --format json' instead.")
+        return 1
+
+    interval = backend.parse_duration(args.watch)
+    try:
+        while True:
+            # Redraw in place like watch(1)
+            print("\033[H\033[2J" + time.strftime("%H:%M:%S"), flush=True)
+            print_status(args.service.status(args.arrays))
+            time.sleep(interval)
+    except KeyboardInterrupt:
+        return 0
+
+def cmd_notify(args):
+    if args.action == "mdadm":
+        # mdadm --monitor runs its PROGRAM with EVENT MD-DEVICE [COMPONENT], events that aren't notified are fine too
+        if len(args.arguments) not in (2, 3):
+            print("ERROR: 'notify mdadm' takes what mdadm --monitor passes, e.g. notify mdadm Fail /dev/md0 /dev/sdb1.")
+            return 1
+        event = notifications.from_mdadm(*args.arguments)
+        if event is None:
+            return 0
+    else:
+        event = notifications.make_event("test", args.arguments[0] if args.arguments else "/dev/md0")
+    if not notifications.has_sinks():
+        print("WARNING: No [notification] sinks are configured in config.toml.")
+        return 0 if args.action == "mdadm" else 1
+    success, out = notifications.dispatch(event)
+    print(out, end="")
+    print(f"SUCCESS: Sent the {event['event']} notification of {event['array']}." if success
+          else f"ERROR: Not every sink took the {event['event']} notification of {event['array']}.")
+    return 0 if success else 1
+
+def cmd_members(args):
+    members = backend.get_array_members(args.array)
+    if not members:
+        print(f"ERROR: Could not read the members of {args.array}.")
+        return 1
+    for m in members:
+        slot = str(m['slot']) if m['slot'] is not None else "-"
+        print(f"slot {slot:>2}  {m['role']:<10} {m['device'] or '(empty)':<14} {m['serial'] or '':<20} {m['state']}")
+    return 0
+
+def cmd_watch(args):
+    policy = "auto" if args.auto_add else config.get_setting('hot_add_policy')
+    # Disks attached before watching started are never offered, only new arrivals
+    known = set(args.service.inventory())
+    detector = hooks.EventDetector()
+    interval = backend.parse_duration(args.interval)
+    print(f"Watching {', '.join(args.arrays) or 'all arrays'} every {args.interval} (hot-add policy: {policy}). Press Ctrl+C to stop.", flush=True)
+    verifications = []
+
+    def print_verification(operation):
+        if operation.error is not None:
+            print(f"ERROR: Verifying the rebuild failed: {operation.error}", flush=True)
+        else:
+            verified, summary = operation.result
+            print(f"{'SUCCESS' if verified else 'ERROR'}: {summary}", flush=True)
+
+    try:
+        while True:
+            arrays = args.arrays or [arr['name'] for arr in args.service.arrays()]
+            # State changes go to the [hooks] commands and [notification] sinks from config.toml
+            for event in detector.poll(arrays):
+                print(f"EVENT {event['event']}: {hooks.describe(event)}", flush=True)
+                print(hooks.run_hooks(event)[1], end="", flush=True)
+                print(notifications.dispatch(event)[1], end="", flush=True)
+            for array in arrays:
+                if not backend.needs_replacement(array):
+                    continue
+                for drive in backend.find_replacement_candidates(array, known):
+                    known.add(drive['name'])
+                    print(f"{array} is degraded and blank disk {drive['name']} ({units.format_size(drive['size_bytes'])}) was attached.", flush=True)
+                    if policy == "never":
+                        continue
+                    if policy == "prompt" and not confirm(f"Add {drive['name']} to {array} as a replacement?"):
+                        continue
+                    success, job = args.service.add_replacement(array, drive['name'], backend.drive_identity(drive))
+                    if success and (args.verify or config.get_setting('verify_rebuilds')):
+                        print(f"{drive['name']} added to {array}, rebuild tracked as job {job['id']}. Verifying once it is done...", flush=True)
+                        # The rebuild takes hours, events and other arrays keep being watched meanwhile
+                        verifications.append(background.Operation(args.service.verify_rebuild, array, drive['name'],
+                                                                  on_done=print_verification).start())
+                        break
+                    if success:
+                        print(f"SUCCESS: {drive['name']} added to {array}, rebuild tracked as job {job['id']}.", flush=True)
+                        break
+                    print(f"ERROR: Failed to add {drive['name']} to {array}.", flush=True)
+            time.sleep(interval)
+    except KeyboardInterrupt:
+        running = [operation for operation in verifications if not operation.done()]
+        for operation in running:
+            operation.cancel()
+        if running:
+            print(f"WARNING: {len(running)} rebuild verification(s) stopped before finishing, run verify-rebuild to repeat them.")
+        return 0
+
+def cmd_expand_plan(args):
+    if not backend.is_array_active(args.array):
+        print(f"ERROR: {args.array} is not an active md array.")
+        return 1
+    drives = args.service.inventory()
+    unknown = [dev for dev in args.devices if dev not in drives]
+    if unknown:
+        print(f"ERROR: {', '.join(unknown)} not available (mounted, in use by an array, or missing).")
+        return 1
+    candidates = [drives[dev] for dev in args.devices] if args.devices else list(drives.values())
+    layout = expand.current_layout(args.array)
+    print(f"{args.array}: RAID {layout['level']}, {len(layout['members'])} x {units.format_size(layout['component_bytes'])}, "
+          f"{units.format_size(layout['capacity_bytes'])} usable"
+          + (f", {units.format_size(layout['used_bytes'])} in use" if layout['used_bytes'] is not None else ""))
+    listed = [f"{d['name']} ({units.format_size(d['size_bytes'])})" for d in candidates]
+    print(f"Candidates: {', '.join(listed) or 'none'}")
+    for option in expand.expansion_paths(args.array, candidates):
+        print(f"\n[{option['path']}] {option['title']}")
+        if not option['possible']:
+            print(f"  Not possible: {option['reason']}")
+            continue
+        print(f"  Capacity: {units.format_size(option['capacity_bytes'])} (+{units.format_size(option['gain_bytes'])}), "
+              f"about {units.format_duration(option['duration_seconds'])} at {expand.ESTIMATED_RATE // 1000**2} MB/s")
+        for i, step in enumerate(option['steps'], 1):
+            print(f"  {i}. {step}")
+        for risk in option['risks']:
+            print(f"  ! {risk}")
+    return 0
+
+def cmd_upgrade(args):
+    existing = expand.load_upgrade(args.array)
+    if args.abandon:
+        expand.clear_upgrade(args.array)
+        print(f"Forgot the upgrade of {args.array}, the array itself is left as it is." if existing else f"No upgrade of {args.array} in progress.")
+        return 0
+    if not args.devices:
+        if existing is None:
+            print(f"ERROR: No upgrade of {args.array} in progress. Give the larger disks to start one.")
+            return 1
+        done = sum(1 for pair in existing['pairs'] if pair['state'] == "done")
+        print(f"Resuming the upgrade of {args.array}: {done}/{len(existing['pairs'])} members replaced, phase {existing['phase']}.", flush=True)
+        return 0 if args.service.upgrade_members(existing, print_progress) else 1
+    if existing is not None:
+        print(f"ERROR: An upgrade of {args.array} is already in progress. Run 'upgrade {args.array}' to resume it, or --abandon it.")
+        return 1
+
+    if not backend.is_array_active(args.array):
+        print(f"ERROR: {args.array} is not an active md array.")
+        return 1
+    layout = expand.current_layout(args.array)
+    drives = args.service.inventory()
+    errors = []
+    if layout['level'] == "0":
+        errors.append("RAID 0 has no redundancy to copy a member from, migrate it instead (see expand-plan).")
+    if len(args.devices) != len(layout['members']):
+        errors.append(f"{args.array} has {len(layout['members'])} members, give exactly one new disk for each.")
+    for dev in args.devices:
+        if dev not in drives:
+            errors.append(f"{dev} not available (mounted, in use by an array, or missing).")
+        elif drives[dev]['size_bytes'] <= layout['component_bytes']:
+            errors.append(f"{dev} ({units.format_size(drives[dev]['size_bytes'])}) is not larger than the current members.")
+    for error in errors:
+        print(f"ERROR: {error}")
+    if errors:
+        return 1
+
+    upgrade = expand.new_upgrade(args.array, args.devices, {dev: backend.drive_identity(drives[dev]) for dev in args.devices})
+    print(f"Upgrade plan for {args.array}:")
+    for i, pair in enumerate(upgrade['pairs'], 1):
+        print(f"  {i}. copy {pair['old']} onto {pair['new']} (mdadm --replace), then remove {pair['old']}")
+    print(f"  {len(upgrade['pairs']) + 1}. grow {args.array} to the size of its new members, then its filesystem")
+    if not confirm_action(args, "Start the upgrade? It can be resumed if interrupted.", destructive=True):
+        print("Aborted.")
+        return 1
+    return 0 if args.service.upgrade_members(upgrade, print_progress) else 1
+
+def cmd_grow(args):
+    existing = expand.load_grow(args.array)
+    if args.abandon:
+        expand.clear_grow(args.array)
+        print(f"Forgot the grow of {args.array}, a running reshape carries on in md." if existing else f"No grow of {args.array} in progress.")
+        return 0
+    if not args.devices:
+        if existing is None:
+            print(f"ERROR: No grow of {args.array} in progress. Give the disks to add to start one.")
+            return 1
+        print(f"Resuming the grow of {args.array} onto {existing['raid_devices']} members, phase {existing['phase']}.", flush=True)
+        return 0 if args.service.grow_array(existing, print_progress) else 1
+    if existing is not None:
+        print(f"ERROR: A grow of {args.array} is already in progress. Run 'grow {args.array}' to resume it, or --abandon it.")
+        return 1
+
+    if not backend.is_array_active(args.array):
+        print(f"ERROR: {args.array} is not an active md array.")
+        return 1
+    drives = args.service.inventory()
+    grow = expand.new_grow(args.array, args.devices, args.backup_file,
+                           {dev: backend.drive_identity(drives[dev]) for dev in args.devices if dev in drives})
+    report = expand.validate_grow(grow, drives)
+    for warning in report['warnings']:
+        print(f"WARNING: {warning}")
+    for error in report['errors']:
+        print(f"ERROR: {error}")
+    if report['errors']:
+        return 1
+
+    print(f"Grow plan for {args.array} (RAID {grow['level']}, {len(grow['members'])} -> {grow['raid_devices']} members, "
+          f"{units.format_size(grow['capacity_bytes'])} -> {units.format_size(grow['new_capacity_bytes'])}):")
+    for i, step in enumerate(expand.grow_steps(grow), 1):
+        print(f"  {i}. {step}")
+    hours = grow['new_capacity_bytes'] / expand.ESTIMATED_RATE / 3600
+    print(f"The reshape rewrites every stripe, roughly {hours:.1f}h; the array stays usable but slower meanwhile.")
+    if not confirm_action(args, "Start the grow? It can be resumed if interrupted.", destructive=True):
+        print("Aborted.")
+        return 1
+    return 0 if args.service.grow_array(grow, print_progress) else 1
+
+def cmd_verify_rebuild(args):
+    success, summary = args.service.verify_rebuild(args.array, args.device, print_progress)
+    print(f"{'SUCCESS' if success else 'ERROR'}: {summary}")
+    return 0 if success else 1
+
+def cmd_support_bundle(args):
+    success, out, _ = support.create_bundle(args.output)
+    print(out, end="")
+    return 0 if success else 1
+
+def cmd_metrics(args):
+    if args.action == "export":
+        since = backend.parse_duration(args.since) if args.since else None
+        writer = csv.DictWriter(sys.stdout, fieldnames=metrics.FIELDS)
+        writer.writeheader()
+        writer.writerows(metrics.export_metrics(since))
+        return 0
+
+    # record: one sample (for cron), or keep sampling every --interval
+    while True:
+        try:
+            count = metrics.record_sample()
+        except Exception as e:
+            print(f"ERROR: Failed to record metrics: {e}")
+            return 1
+        if not args.interval:
+            print(f"Recorded {count} values to {metrics.METRICS_FILE}")
+            return 0
+        time.sleep(backend.parse_duration(args.interval))
+
+def cmd_doctor(args):
+    checks = doctor.run_checks()
+    symbols = {'ok': 'OK  ', 'warn': 'WARN', 'error': 'FAIL'}
+    for check in checks:
+        print(f"[{symbols[check['status']]}] {check['name']}: {check['detail']}")
+        if check['fix'] and not args.fix:
+            print(f"       fix: {check['fix']['description']} (doctor --fix)")
+    if not args.fix:
+        return 1 if doctor.has_blocking_errors(checks) else 0
+    if not args.service.require_admin("doctor-fix"):
+        return 1
+
+    fixes = doctor.collect_fixes(checks)
+    if not fixes:
+        print("Nothing to fix automatically.")
+        return 1 if doctor.has_blocking_errors(checks) else 0
+    for fix in fixes:
+        print(f"\n{fix['description']}:")
+        for cmd in fix['commands']:
+            print(f"  {' '.join(cmd)}")
+        if fix['confirm'] and not args.yes and not confirm("Run this?"):
+            print("Skipped.")
+            continue
+        success, out = doctor.apply_fix(fix)
+        print(out, end="")
+        print("SUCCESS" if success else f"ERROR: {fix['description']} failed.")
+
+    print("\nAfter fixing:")
+    checks = doctor.run_checks()
+    for check in checks:
+        print(f"[{symbols[check['status']]}] {check['name']}: {check['detail']}")
+    return 1 if doctor.has_blocking_errors(checks) else 0
+
+def cmd_verify_boot(args):
+    report = bootaudit.audit(args.member)
+    status = 0 if bootaudit.passed(report) else 1
+    if args.format != "table":
+        return output.emit(args.format, "verify-boot", dict(report, passed=status == 0), status)
+    lost = report['member'] or "any one member"
+    print(f"Boot arrays: {', '.join(report['arrays']) or 'none'} ({report['firmware'].upper()}), assuming {lost} fails")
+    symbols = {'ok': 'PASS', 'warn': 'WARN', 'error': 'FAIL'}
+    for check in report['checks']:
+        print(f"[{symbols[check['status']]}] {check['name']}: {check['detail']}")
+        if check['fix'] and check['status'] != "ok":
+            print(f"       fix: {check['fix']['description']}")
+            for cmd in check['fix']['commands']:
+                print(f"         {shlex.join(cmd)}")
+    print("SUCCESS: The system boots with that member missing." if status == 0 else
+          "ERROR: The system would not boot with that member missing, see FAIL above.")
+    return status
+
+def cmd_show_snapshot(args):
+    if not args.run_id:
+        snapshots = snapshot.list_snapshots()
+        if not snapshots:
+            print("No snapshots recorded.")
+        for snap in snapshots:
+            created = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(snap['created']))
+            print(f"{snap['run_id']}  {created}  {snap['operation']} {snap['target']}")
+        return 0
+
+    snap = snapshot.read_snapshot(args.run_id)
+    if snap is None:
+        print(f"ERROR: Unknown run: {args.run_id}")
+        return 1
+    meta = snap['meta']
+    print(f"Run {meta['run_id']}: {meta['operation']} {meta['target']}")
+    for name, content in snap['files'].items():
+        if args.file and name != args.file:
+            continue
+        print(f"\n===== {name} =====")
+        print(content, end="" if content.endswith("\n") else "\n")
+    return 0
+
+def cmd_fstab(args):
+    if args.action == "list":
+        entries = fstab.FstabEditor(files.read_file(files.FSTAB_PATH)).entries()
+        if args.format != "table":
+            return output.emit(args.format, "fstab", {'entries': entries})
+        if not entries:
+            print(f"{files.FSTAB_PATH} has no entries.")
+        for entry in entries:
+            managed = f"  (LiveRAID: {entry['managed']})" if entry['managed'] else ""
+            print(f"{entry['spec']:<42} {entry['file']:<20} {entry['vfstype']:<6} {entry['mntops']}{managed}")
+        return 0
+
+    if not args.target:
+        print(f"ERROR: 'fstab {args.action}' needs {'an array and a mount point' if args.action == 'add' else 'an array or mount point'}.")
+        return 1
+    if args.action == "add":
+        if not args.mount_point:
+            print("ERROR: 'fstab add' needs the mount point, e.g. 'fstab add /dev/md0 /srv/data'.")
+            return 1
+        success = args.service.add_to_fstab(args.target, args.mount_point, ",".join(args.mount_option) or "defaults")
+    else:
+        success = args.service.remove_from_fstab(args.target)
+    return 0 if success else 1
+
+def cmd_changes(args):
+    changes = files.list_changes()
+    if not changes:
+        print("No file changes recorded.")
+    for change in changes:
+        stamp = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(change['timestamp']))
+        diff = files.render_diff(change['path'], change['before'], change['after']).splitlines()
+        added = sum(1 for line in diff if line.startswith("+") and not line.startswith("+++"))
+        removed = sum(1 for line in diff if line.startswith("-") and not line.startswith("---"))
+        note = f"  (undone by {change['undone_by']})" if change.get('undone_by') else ""
+        print(f"{change['id']}  {stamp}  {change['path']}  +{added} -{removed}{note}")
+        if args.diff:
+            print(files.render_diff(change['path'], change['before'], change['after'], color=sys.stdout.isatty()), end="")
+    return 0
+
+def cmd_undo(args):
+    success, out = files.undo_change(args.change_id)
+    print(out, end="")
+    return 0 if success else 1
+
+def cmd_grub(args):
+    if args.action == "show":
+        model = grub.parse(files.read_file(grub.GRUB_DEFAULT_PATH))
+        for key in ("GRUB_DEFAULT", "GRUB_TIMEOUT", "GRUB_CMDLINE_LINUX", "GRUB_CMDLINE_LINUX_DEFAULT", "GRUB_PRELOAD_MODULES"):
+            if key in model['settings']:
+                print(f"{key}={model['settings'][key]}")
+        if model['arrays']:
+            print("\nLiveRAID block:")
+            for arr in model['arrays']:
+                print(f"  {arr['name']}  UUID={arr['uuid']}")
+        else:
+            print(f"\nNo LiveRAID block in {grub.GRUB_DEFAULT_PATH}.")
+        backups = grub.list_backups()
+        if backups:
+            print("\nBackups (restore with 'grub restore <id>'):")
+            for backup in backups:
+                print(f"  {backup['id']}  {time.strftime('%Y-%m-%d %H:%M:%S', time.localtime(backup['mtime']))}")
+        return 0
+
+    if not args.target:
+        print(f"ERROR: 'grub {args.action}' needs {'a backup ID' if args.action == 'restore' else 'an array, e.g. /dev/md0'}.")
+        return 1
+    if args.action != "restore":
+        return edit_boot_arrays(args, "grub")
+    if not args.service.require_admin("grub", grub.GRUB_DEFAULT_PATH):
+        return 1
+    success, out = grub.restore(args.target)
+    print(out, end="")
+    return 0 if success else 1
+
+def edit_boot_arrays(args, loader):
+    # add-raid/remove-raid of 'grub' and 'bootloader', --diff only shows the edit
+    if args.diff:
+        diff, error = bootloader.diff(args.action, args.target, loader, color=sys.stdout.isatty())
+        if error:
+            print(f"ERROR: {error}")
+            return 1
+        print(diff or "Already up to date.\n", end="")
+        return 0
+    return 0 if args.service.update_boot(args.action, args.target, loader) else 1
+
+def cmd_bootloader(args):
+    try:
+        loader = bootloader.get_bootloader(args.loader)
+    except ValueError as e:
+        print(f"ERROR: {e}")
+        return 1
+    if args.action == "show":
+        detected = bootloader.detect()
+        print(f"Bootloader: {loader.title}" + (" (detected)" if loader.name == detected else f", the running system looks booted by {bootloader.BOOTLOADERS[detected].title}"))
+        for location in loader.locations():
+            print(f"  {location}")
+        arrays = loader.arrays()
+        print("Assembled at boot:" if arrays else "No arrays assembled at boot by rd.md.uuid=.")
+        for arr in arrays:
+            print(f"  {arr['name']}  UUID={arr['uuid']}" if arr['name'] else f"  UUID={arr['uuid']}")
+        return 0
+    if not args.target:
+        print(f"ERROR: 'bootloader {args.action}' needs an array, e.g. /dev/md0.")
+        return 1
+    return edit_boot_arrays(args, loader.name)
+
+def format_job(job):
+    created = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(job['created']))
+    state = "paused" if job['state'] == 'running' and job.get('paused') else job['state']
+    return f"{job['id']}  {job['kind']:<8} {job['target']:<12} {state:<10} {job['percent']:5.1f}%  {created}"
+
+def cmd_jobs(args):
+    if args.action == "list":
+        job_list = jobs.list_jobs()
+        if not job_list:
+            print("No jobs recorded.")
+        for job in job_list:
+            print(format_job(job))
+        return 0
+
+    if not args.job_id:
+        print(f"ERROR: 'jobs {args.action}' needs a job ID.")
+        return 1
+
+    if args.action == "show":
+        job = jobs.get_job(args.job_id)
+        if job is None:
+            print(f"ERROR: Unknown job: {args.job_id}")
+            return 1
+        print(format_job(job))
+        if job['message']:
+            print(job['message'])
+        return 0
+
+    if args.action in ("pause", "resume"):
+        success, out = jobs.pause_job(args.job_id, args.action == "pause")
+    else:
+        success, out = jobs.cancel_job(args.job_id)
+    print(out, end="")
+    return 0 if success else 1
+
+def cmd_events(args):
+    if args.action == "list":
+        history = events.read_events(args.since, args.array)
+        if args.format != "table":
+            return output.emit(args.format, "events", {'events': history})
+        if not history:
+            print("No events recorded.")
+        for event in history:
+            print(events.describe(event))
+        return 0
+
+    if args.action == "state":
+        arrays = events.state(args.all)
+        if args.format != "table":
+            return output.emit(args.format, "events", {'arrays': list(arrays.values())})
+        if not arrays:
+            print("No arrays recorded.")
+        for arr in arrays.values():
+            scrub = arr['last_scrub']
+            last_scrub = f"{time.strftime('%Y-%m-%d', time.localtime(scrub['timestamp']))} ({scrub['mismatches']} mismatches)" if scrub else "never"
+            gone = f"  destroyed {time.strftime('%Y-%m-%d', time.localtime(arr['destroyed']))}" if arr['destroyed'] else ""
+            print(f"{arr['array']:<12} RAID {arr['level'] or '?':<3} {arr['backend'] or '?':<6} {' '.join(arr['members'])}  "
+                  f"replacements: {arr['replacements']}  last scrub: {last_scrub}{gone}")
+        return 0
+
+    if args.seq is None:
+        print("ERROR: 'events undo' needs the sequence number of an event, see 'events list'.")
+        return 1
+    match = [e for e in events.read_events(args.seq - 1) if e['seq'] == args.seq]
+    if not match:
+        print(f"ERROR: No event {args.seq} recorded.")
+        return 1
+    reverse = events.compensation(match[0])
+    if args.format != "table":
+        return output.emit(args.format, "events", {'event': match[0], 'compensation': reverse})
+    # Only printed: whether reversing is still right depends on everything that happened since
+    print(events.describe(match[0]))
+    print(reverse['note'])
+    for cmd in reverse['commands']:
+        print(f"  {shlex.join(cmd)}")
+    return 0
+
+def cmd_geometry(args):
+    if args.action == "list":
+        records = geometry.list_geometries()
+        if not records:
+            print("No creation parameters recorded.")
+        for record in records:
+            print(geometry.format_geometry(record))
+        return 0
+
+    if args.action == "export":
+        if not args.target:
+            print("ERROR: 'geometry export' needs the file to write, e.g. 'geometry export /mnt/usb/arrays.json'.")
+            return 1
+        success, out = geometry.export_geometries(args.target)
+        print(out, end="")
+        return 0 if success else 1
+
+    if not args.target:
+        print(f"ERROR: 'geometry {args.action}' needs an array, e.g. /dev/md0 or its UUID.")
+        return 1
+    if args.action == "record":
+        success, out = geometry.save_geometry(args.target)
+        print(out, end="")
+        return 0 if success else 1
+
+    record = geometry.find_geometry(args.target)
+    if record is None:
+        print(f"ERROR: No creation parameters recorded for {args.target} (see 'geometry list').")
+        return 1
+    print(geometry.format_geometry(record))
+    return 0
+
+def cmd_recover_recreate(args):
+    if not args.expert:
+        print("Re-creating an array over its old members writes new superblocks to them. With the exact original parameters\n"
+              "the data comes back; with a wrong one (member order, chunk, layout, data offset) it can be lost for good.\n"
+              "Try 'mdadm --assemble --force' first and image the disks if the data matters. Pass --expert to continue.")
+        return 1
+
+    try:
+        records = geometry.load_export(args.source) if args.source else geometry.list_geometries()
+    except Exception as e:
+        print(f"ERROR: Failed to read {args.source}: {e}")
+        return 1
+    plain = args.target.replace(':', '')
+    record = next((r for r in records if r['uuid'].replace(':', '') == plain or args.target in (r['array'], r['name'])), None)
+    if record is None:
+        print(f"ERROR: No creation parameters recorded for {args.target} (see 'geometry list').")
+        return 1
+    print(geometry.format_geometry(record))
+
+    devices = {m['slot']: m['device'] for m in geometry.locate_members(record) if isinstance(m['slot'], int) and m['device']}
+    for override in args.member:
+        slot, _, device = override.partition("=")
+        if not slot.isdigit() or not device:
+            print(f"ERROR: --member takes SLOT=DEVICE, e.g. 0=/dev/sdc1, not {override}.")
+            return 1
+        devices[int(slot)] = device
+
+    array_name = args.array or arraynames.next_array_name()
+    print(f"\nMembers for the re-created {array_name}:")
+    for slot in range(record['raid_devices'] or 0):
+        print(f"  slot {slot:>2}  {devices.get(slot, 'missing')}")
+    errors, warnings = geometry.check_members(record, devices)
+    for error in errors:
+        print(f"ERROR: {error}")
+    if errors:
+        return 1
+    for warning in warnings:
+        print(f"WARNING: {warning}")
+
+    cmd = geometry.recreate_command(record, devices, array_name)
+    print(f"\nCommand: {' '.join(cmd)}")
+    print("The array is started read-only and its filesystem mounted read-only at "
+          f"{args.mount_point}, it is not added to mdadm.conf or fstab.")
+    if backend.DRY_RUN:
+        return 0 if args.service.recreate_array(record, devices, array_name, args.mount_point, keyfile=args.keyfile)[0] else 1
+
+    print("WARNING: This overwrites the md superblocks of the members listed above. "
+          "If any parameter is wrong, the data on them can be lost.")
+    if not confirm_action(args, "Re-create the array with these parameters?", destructive=True):
+        print("Aborted.")
+        return 1
+    # A second, typed confirmation that --yes does not skip
+    expected = record['uuid'].replace(':', '')[:8]
+    typed = args.confirm_uuid
+    if typed is None:
+        try:
+            typed = input(f"Type the first 8 characters of the array UUID ({expected}) to continue: ").strip()
+        except EOFError:
+            typed = ""
+    if typed != expected:
+        print("Aborted, the UUID did not match.")
+        return 1
+
+    passphrase = None
+    if args.encrypted and not args.keyfile:
+        try:
+            passphrase = getpass.getpass("Passphrase of the LUKS container: ")
+        except EOFError:
+            print("Aborted, no passphrase given.")
+            return 1
+    success, _ = args.service.recreate_array(record, devices, array_name, args.mount_point, passphrase, args.keyfile)
+    if not success:
+        print(f"ERROR: Re-creating {array_name} failed, see above. Nothing was mounted.")
+        return 1
+    print(f"SUCCESS: {array_name} is running read-only, its filesystem is mounted read-only at {args.mount_point}.")
+    print("Next steps:")
+    print(f"  1. Copy the data to another disk: cli.py migrate-data {args.mount_point} <target directory>")
+    print("  2. Check the copy before trusting it, the array was not verified.")
+    close = f" && cryptsetup close {luks.mapper_name(array_name)}" if args.encrypted or args.keyfile else ""
+    print(f"  3. Release it: umount {args.mount_point}{close} && mdadm --stop {array_name}")
+    return 0
+
+def cmd_mount_ro(args):
+    print(f"Mounting everything on {args.target} read-only under {args.mount_root}. Nothing is written to it.")
+    success, session = args.service.mount_read_only(args.target, args.mount_root)
+    for mount in session['mounts']:
+        print(f"  {mount['device']:<20} {mount['fstype']:<8} {mount['mount_point']}")
+    if success and not session['mounts']:
+        print(f"WARNING [no-filesystem]: No mountable filesystem found on {args.target}.")
+    if not success:
+        print(f"ERROR: Mounting {args.target} read-only failed, see above. Releasing what was set up.")
+    elif args.keep:
+        print("Left mounted. Release it with:")
+        for mount in reversed(session['mounts']):
+            print(f"  umount {mount['mount_point']}")
+        if session['assembled']:
+            print(f"  mdadm --stop {session['assembled']}")
+        for device in session['read_only']:
+            print(f"  blockdev --setrw {device}")
+        return 0
+    elif not backend.DRY_RUN:
+        try:
+            input("Inspect the data, then press Enter to unmount and release it... ")
+        except (EOFError, KeyboardInterrupt):
+            print()
+
+    if not args.service.release_read_only(session):
+        print("ERROR: Releasing failed, see above. Check 'findmnt' and /proc/mdstat.")
+        return 1
+    if success:
+        print(f"SUCCESS: {args.target} was unmounted and released.")
+    return 0 if success else 1
+
+def add_encryption_arguments(parser):
+    parser.add_argument("--encrypt", action="store_true", help="Put the filesystem in a LUKS2 container (passphrase asked for, or --keyfile)")
+    parser.add_argument("--cipher", metavar="CIPHER", help=f"With --encrypt: cipher (default: {luks.DEFAULT_CIPHER})")
+    parser.add_argument("--key-size", type=int, metavar="BITS", help=f"With --encrypt: key size in bits (default: {luks.DEFAULT_KEY_SIZE})")
+    parser.add_argument("--pbkdf", choices=luks.PBKDFS, help=f"With --encrypt: key derivation (default: {luks.PBKDFS[0]})")
+    parser.add_argument("--keyfile", metavar="PATH", help="With --encrypt: unlock with this keyfile instead of a passphrase (also used by crypttab at boot)")
+
+def add_md_arguments(parser):
+    parser.add_argument("--metadata", choices=backend.METADATA_VERSIONS, help="Superblock version (default: mdadm's, 1.2). "
+                                                                              "0.90 and 1.0 sit at the end of the members, readable as plain disks")
+    parser.add_argument("--bitmap", choices=backend.BITMAP_MODES, help="Write-intent bitmap: internal (fast resync after a crash) or none "
+                                                                       "(default: mdadm's, internal above 100 GB)")
+    parser.add_argument("--layout", choices=list(backend.RAID10_LAYOUTS), help="RAID 10 layout: near (default), far or offset (faster reads)")
+    parser.add_argument("--name", help="Array name in the superblock, linked as /dev/md/<name>")
+
+def cmd_serve(args):
+    try:
+        daemon.serve(args.socket)
+    except KeyboardInterrupt:
+        return 0
+    except (OSError, KeyError) as e:
+        print(f"ERROR: Could not start the daemon: {e}")
+        return 1
+    return 0
+
+def cmd_jsonrpc(args):
+    if not args.stdio:
+        print("ERROR: Only --stdio is offered here, 'serve' speaks the same protocol on a Unix socket.")
+        return 1
+    try:
+        daemon.serve_stdio()
+    except KeyboardInterrupt:
+        pass
+    return 0
+
+def remote_request(args):
+    """
+    The daemon method and parameters of a subcommand run with --remote, None when it needs no request
+    (aborted at the prompt).
+    """
+    if args.command in ("drives", "discover"):
+        drive_filter = drive_filter_arg(args)
+        return "discover", {'filter': None if drive_filter.is_empty() else drive_filter.describe(exact=True)}
+    if args.command == "status":
+        return "status", {}
+    if args.command == "plan":
+        return "plan", {'fields': plan_fields(args), 'suppress': args.suppress, 'strict': args.strict or None}
+    proposal = plan.load_plan(args.plan_file)
+    if not confirm_action(args, f"Apply this plan through the daemon to {', '.join(proposal['devices'] + proposal['spares'])}?", destructive=True):
+        return None
+    passphrase = ask_passphrase(proposal['encryption']) if proposal['encryption'] else None
+    return "apply", {'plan_data': dict(proposal, version=plan.PLAN_VERSION), 'suppress': args.suppress, 'strict': args.strict or None, 'passphrase': passphrase}
+
+def run_remote(args):
+    # The daemon does the work with its own privileges and policy, this process only prints the result
+    try:
+        request = remote_request(args)
+        if request is None:
+            print("Aborted.")
+            return 1
+        result = daemon.call(*request, path=args.socket)
+    except (OSError, ValueError, daemon.RpcError) as e:
+        print(f"ERROR: {e}")
+        return 1
+    print(result.pop('log'), end="")
+    if request[0] == "plan" and args.output and result['valid']:
+        plan.save_plan(plan.plan_from_dict(result['plan']), args.output)
+    status = 0 if result.get('valid', result.get('success', True)) else 1
+    # Tables are drawn from local state, a remote result is shown as the document itself
+    return output.emit(args.format if args.format != "table" else "yaml", "apply" if request[0] == "apply" else request[0], result, status)
+
+def build_parser():
+    parser = argparse.ArgumentParser(prog="liveraid", description="LiveRAID command line interface")
+    parser.add_argument("--dry-run", dest="global_dry_run", action="store_true", help="Log commands and file diffs without touching any disk or file")
+    parser.add_argument("--admin", action="store_true", help="Unlock admin mode in an operator session (safety_mode in config.toml)")
+    parser.add_argument("--units", choices=["iec", "si"], help="Show sizes as GiB (iec) or GB (si), overrides size_units in config.toml")
+    parser.add_argument("--format", choices=output.FORMATS, default="table",
+                        help="Print discover/drives, plan, status and apply results as a JSON or YAML document on stdout "
+                             "(prompts and log go to stderr)")
+    parser.add_argument("--output", dest="output_mode", choices=output.PROGRESS_MODES, default="text",
+                        help="progress-proto: report the progress of apply, sync, scrub, upgrade and migrate-data as PHASE/PERCENT "
+                             "lines on stdout for web UIs (log on stderr), see output.py")
+    parser.add_argument("--remote", action="store_true",
+                        help="Send discover/drives, plan, status or apply to the daemon ('serve') instead of running them here")
+    parser.add_argument("--socket", metavar="PATH", help="Daemon socket for serve and --remote (default: daemon_socket from config.toml)")
+    parser.add_argument("--simulate", choices=simulate.MODES,
+                        help="Run against simulated drives with system files in a sandbox: mock records the commands instead "
+                             "of running them (no root needed), loop runs them on loop devices (root). See simulate.py")
+    parser.add_argument("--simulate-drives", type=int, default=simulate.DRIVES, metavar="N", help="Number of simulated drives")
+    parser.add_argument("--simulate-size", type=size_arg, default=simulate.DRIVE_SIZE, metavar="SIZE", help="Size of each simulated drive")
+    subparsers = parser.add_subparsers(dest="command", required=True)
+
+    p_doctor = subparsers.add_parser("doctor", help="Check privileges, tools and kernel support")
+    p_doctor.add_argument("--fix", action="store_true", help="Install missing packages, load kernel modules, enable mdmonitor and create directories")
+    p_doctor.add_argument("-y", "--yes", action="store_true", help="Install packages and enable services without asking")
+    p_doctor.set_defaults(func=cmd_doctor)
+
+    p_verify_boot = subparsers.add_parser("verify-boot", help="Check whether the system still boots if a member of the array holding / or /boot fails "
+                                          "(ESPs, GRUB, initramfs, degraded boot, fstab nofail)")
+    p_verify_boot.add_argument("--member", metavar="DEVICE", help="The disk assumed to fail, e.g. /dev/sdb (default: any member)")
+    p_verify_boot.set_defaults(func=cmd_verify_boot)
+
+    p_drives = subparsers.add_parser("drives", aliases=["discover"], help="List the drives available for a new array")
+    p_drives.add_argument("--min-size", type=size_arg, metavar="SIZE", help="Only drives of at least e.g. 500G (binary) or 500GB (decimal)")
+    p_drives.add_argument("--max-size", type=size_arg, metavar="SIZE", help="Only drives of at most this size")
+    p_drives.add_argument("--media", choices=["ssd", "hdd"], help="Only solid state or only rotational drives")
+    p_drives.add_argument("--transport", action="append", default=[], choices=devicefilter.TRANSPORTS, help="Only drives on this bus (repeatable)")
+    p_drives.add_argument("--model", action="append", default=[], metavar="GLOB", help="Only drives whose model matches, e.g. 'Samsung*' (repeatable)")
+    p_drives.add_argument("--blank", action="store_true", help="Only drives without a partition table or filesystem")
+    p_drives.add_argument("--exclude-boot-disk", action="store_true", help="Leave out the disk holding /, /boot or the live medium")
+    p_drives.add_argument("--exclude-removable", action="store_true", help="Leave out USB sticks, card readers and other removable drives")
+    p_drives.add_argument("--filter", type=filter_arg, metavar="EXPR",
+                          help="All of the above as one expression, e.g. 'min-size=500G,transport=nvme,exclude-removable'")
+    p_drives.add_argument("--benchmark", type=int, metavar="RUNS", help="Only time drive discovery over this many runs")
+    p_drives.add_argument("--probe", metavar="DEVICE", help="Show everything known about one disk (identity, SMART, partitions, signatures, usage, RAID level eligibility)")
+    p_drives.add_argument("--with", dest="with_devices", action="append", default=[], metavar="DEVICE",
+                          help="With --probe: a drive already selected for the array, checked together with the probed one (repeatable)")
+    p_drives.add_argument("--speed-test", action="store_true", help="With --probe: measure sequential read speed (reads 1 GiB, writes nothing)")
+    p_drives.set_defaults(func=cmd_drives)
+
+    p_teardown = subparsers.add_parser("teardown-stack", help="Deactivate everything layered on a disk so it can be reused")
+    p_teardown.add_argument("disk", help="Disk to release, e.g. /dev/sdb")
+    p_teardown.add_argument("--dry-run", action="store_true", help="Only print the commands that would run")
+    p_teardown.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_teardown.set_defaults(func=cmd_teardown_stack)
+
+    p_create = subparsers.add_parser("create", help="Create a new RAID array")
+    p_create.add_argument("level", choices=["0", "1", "5", "6", "10"], help="RAID level")
+    p_create.add_argument("devices", nargs="+", help="Member drives, e.g. /dev/sdb /dev/sdc")
+    p_create.add_argument("--array", help="md device to create (default: the lowest free one, see md_naming in config.toml)")
+    p_create.add_argument("--chunk", default="Default", help="Chunk size, e.g. 64K")
+    p_create.add_argument("--assume-clean", action="store_true", help="Skip the initial sync (SSDs)")
+    add_md_arguments(p_create)
+    p_create.add_argument("--preserve-partitions", action="store_true", help="Keep existing partitions (ESP, other systems), build on a new partition in each disk's free space")
+    p_create.add_argument("--spare", action="append", default=[], metavar="DEVICE", help="Hot spare drive, repeat for several")
+    p_create.add_argument("--wait", action="store_true", help="Block until the initial sync has completed")
+    p_create.add_argument("--timeout", metavar="DURATION", help="Give up waiting after e.g. 90m or 6h (exit code 2)")
+    p_create.add_argument("--suppress", action="append", default=[], metavar="CODE", help="Silence a validation warning, e.g. mixed-media")
+    p_create.add_argument("--strict", action="store_true", help="Refuse drives with failing SMART health instead of warning")
+    p_create.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_create.set_defaults(func=cmd_create)
+
+    p_cleanup = subparsers.add_parser("cleanup-md", help="Stop inactive arrays and remove stale /dev/md nodes left by earlier runs")
+    p_cleanup.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_cleanup.set_defaults(func=cmd_cleanup_md)
+
+    p_plan = subparsers.add_parser("plan", help="Validate a complete array layout and print the steps that would build it")
+    p_plan.add_argument("level", nargs="?", help="RAID level (0, 1, 5, 6, 10), may be left out with --preset")
+    p_plan.add_argument("devices", nargs="*", help="Member drives, e.g. /dev/sdb /dev/sdc")
+    p_plan.add_argument("--preset", choices=list(plan.PRESETS), help="Start from a built-in preset (see 'presets'), other flags override it")
+    p_plan.add_argument("--array", help="md device to create (default: the lowest free one, see md_naming in config.toml), /dev/<vg>/<lv> with --backend lvm")
+    p_plan.add_argument("--backend", choices=list(raidbackend.BACKENDS), help="Build the array with mdadm, LVM RAID or btrfs (default: raid_backend from config.toml)")
+    p_plan.add_argument("--filesystem", help=f"Filesystem for the array ({', '.join(config.SUPPORTED_FILESYSTEMS)}, default: ext4)")
+    p_plan.add_argument("--mount-point", metavar="PATH", help="Where to mount the filesystem (also added to fstab)")
+    p_plan.add_argument("--mount-option", action="append", default=[], metavar="OPTION", help="Mount option such as noatime, repeat for several")
+    p_plan.add_argument("--label", help="Filesystem label")
+    p_plan.add_argument("--fs-opt", action="append", default=[], type=fs_opt_arg, metavar="KEY=VALUE",
+                        help="Filesystem tuning, repeat for several: ext4 inode_size, reserved, stride, stripe_width; xfs inode_size, su, sw; "
+                             "btrfs data_profile, metadata_profile. ext4/XFS stripe geometry is derived from --chunk and the members")
+    p_plan.add_argument("--chunk", help="Chunk size, e.g. 64K")
+    add_md_arguments(p_plan)
+    p_plan.add_argument("--write-mostly", action="append", default=[], metavar="DEVICE",
+                        help="RAID 1: read from this member only when the others can't answer (e.g. a slow USB mirror), repeat for several")
+    p_plan.add_argument("--write-behind", type=int, metavar="N", help="RAID 1: writes to the write-mostly members that may be outstanding (needs a bitmap)")
+    p_plan.add_argument("--auto-read-only", action="store_true", help="Keep the new array read-only (no resync) until the first write")
+    p_plan.add_argument("--existing", action="store_true",
+                        help="--array runs already (created by hand): apply records, formats and mounts it without creating it; "
+                             "level and members default to the array's")
+    p_plan.add_argument("--select", type=filter_arg, metavar="EXPR",
+                        help="Add every available drive matching a filter expression as member, e.g. 'transport=nvme,min-size=1T' (see 'discover --filter')")
+    p_plan.add_argument("--include-boot-disk", action="store_true", help="Let --select pick the disk holding /, /boot or the live medium too")
+    p_plan.add_argument("--spare", action="append", default=[], metavar="DEVICE", help="Hot spare drive, repeat for several")
+    p_plan.add_argument("--scrub", choices=plan.SCRUB_SCHEDULES, help="Scrub the array on this schedule")
+    p_plan.add_argument("--smart-short", choices=plan.SMART_SCHEDULES, metavar="SCHEDULE", help="Short SMART self-test of the members: daily, weekly or monthly")
+    p_plan.add_argument("--smart-long", choices=plan.SMART_SCHEDULES, metavar="SCHEDULE", help="Long SMART self-test of the members: daily, weekly or monthly")
+    p_plan.add_argument("--preserve-partitions", action="store_true", help="Keep existing partitions, use a new partition in each disk's free space")
+    p_plan.add_argument("--partition", action="append", default=[], type=partition_arg, metavar="DEVICE[=SIZE[@OFFSET]]",
+                        help="Build this member on a RAID partition of a new GPT instead of the whole disk, e.g. /dev/sdb=500G (repeatable)")
+    p_plan.add_argument("--wipe", choices=wipe.METHODS, help="Wipe members and spares first: none, signatures (wipefs), discard (SSDs), "
+                                                             "zero or random (full overwrite, slow). Default: wipe_policy from config.toml")
+    p_plan.add_argument("--wipe-block-size", metavar="SIZE", help="Block size of a zero/random overwrite, e.g. 1M (default: wipe_block_size)")
+    add_encryption_arguments(p_plan)
+    p_plan.add_argument("--suppress", action="append", default=[], metavar="CODE", help="Silence a validation warning, e.g. mixed-media")
+    p_plan.add_argument("--strict", action="store_true", help="Refuse drives with failing SMART health instead of warning")
+    p_plan.add_argument("--alongside", action="append", default=[], metavar="FILE",
+                        help="Plan file of another array to be applied in the same session, repeat for several: its array name and drives are skipped")
+    p_plan.add_argument("-o", "--output", "--save", dest="output", metavar="FILE", help="Write the validated plan as JSON, for 'apply' or the GUI's Compare Plans view")
+    p_plan.set_defaults(func=cmd_plan)
+
+    p_apply = subparsers.add_parser("apply", help="Validate a plan file written by 'plan --output' again and carry it out")
+    p_apply.add_argument("plan_file", help="Plan file, e.g. plan.json")
+    p_apply.add_argument("--suppress", action="append", default=[], metavar="CODE", help="Silence a validation warning, e.g. mixed-media")
+    p_apply.add_argument("--strict", action="store_true", help="Refuse drives with failing SMART health instead of warning")
+    p_apply.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_apply.add_argument("--wait", action="store_true", help="Block until the new array's initial sync has completed")
+    p_apply.add_argument("--timeout", metavar="DURATION", help="Give up waiting after e.g. 90m or 6h (exit code 2)")
+    p_apply.add_argument("--throughput-test", nargs="?", const=1024**3, type=size_arg, metavar="SIZE",
+                         help="Afterwards wait for the initial sync, write and read back SIZE (default 1G) on the new filesystem and check the throughput (exit 3 when it falls short)")
+    p_apply.add_argument("--notify-url", metavar="URL", help="POST the final provisioning report (JSON) here when done (default: notify_url from config.toml)")
+    p_apply.add_argument("--script", metavar="FILE", help="With --dry-run: save every command and file edit as a bash script ('-' prints it)")
+    p_apply.set_defaults(func=cmd_apply)
+
+    p_presets = subparsers.add_parser("presets", help="List the built-in plan presets")
+    p_presets.set_defaults(func=cmd_presets)
+
+    p_format = subparsers.add_parser("format", help="Partition and format an existing array without re-creating it")
+    p_format.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_format.add_argument("--filesystem", default="ext4", choices=["ext4", "btrfs", "xfs", "f2fs", "exfat", "ntfs", "vfat"])
+    p_format.add_argument("--boot", action="store_true", help="Set the partition as bootable")
+    p_format.add_argument("--trim", action="store_true", help="Enable TRIM/Discard support")
+    p_format.add_argument("--force", action="store_true", help="Format even if the array already holds data")
+    p_format.add_argument("--mkfs-opt", action="append", default=[], metavar="ARGS", help="Extra mkfs arguments, added after the config.toml defaults")
+    p_format.add_argument("--fs-opt", action="append", default=[], type=fs_opt_arg, metavar="KEY=VALUE",
+                          help="Filesystem tuning such as inode_size=512 or reserved=1, repeat for several; ext4/XFS stripe geometry is derived from the array")
+    add_encryption_arguments(p_format)
+    p_format.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_format.set_defaults(func=cmd_format)
+
+    p_destroy = subparsers.add_parser("destroy", help="Stop one array and clear its members' RAID metadata")
+    p_destroy.add_argument("array", help="The md device to destroy, e.g. /dev/md0 (only this array is touched)")
+    p_destroy.add_argument("--keep-superblocks", action="store_true", help="Only stop the array, leave member metadata intact")
+    p_destroy.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_destroy.set_defaults(func=cmd_destroy)
+
+    p_migrate = subparsers.add_parser("migrate-hwraid", help="Move a hardware RAID volume's files onto a new md array (lists volumes without arguments)")
+    p_migrate.add_argument("source", nargs="?", help="Filesystem on the hardware RAID volume, e.g. /dev/sda1")
+    p_migrate.add_argument("level", nargs="?", choices=["0", "1", "5", "6", "10"], help="RAID level of the new array")
+    p_migrate.add_argument("devices", nargs="*", help="Member drives of the new array, e.g. /dev/sdc /dev/sdd")
+    p_migrate.add_argument("--array", help="md device to create (default: the lowest free one, see md_naming in config.toml)")
+    p_migrate.add_argument("--filesystem", default="ext4", choices=["ext4", "btrfs", "xfs", "f2fs"])
+    p_migrate.add_argument("--repurpose", action="store_true", help="Wipe the hardware RAID volume after a successful copy")
+    p_migrate.add_argument("--suppress", action="append", default=[], metavar="CODE", help="Silence a validation warning, e.g. mixed-media")
+    p_migrate.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_migrate.set_defaults(func=cmd_migrate_hwraid)
+
+    p_copy = subparsers.add_parser("migrate-data", help="Copy a filesystem or block device with progress, throttling, verification and resume")
+    p_copy.add_argument("source", help="Directory or block device to copy from")
+    p_copy.add_argument("target", help="Directory or block device to copy to")
+    p_copy.add_argument("--mode", choices=["auto", "files", "block"], default="auto", help="files: rsync (mounting block devices), block: ddrescue/dd (default: block if both are devices)")
+    p_copy.add_argument("--rate", type=size_arg, metavar="RATE", help="Throughput limit per second, e.g. 50M")
+    p_copy.add_argument("--no-verify", action="store_true", help="Skip the checksum comparison after copying")
+    p_copy.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_copy.set_defaults(func=cmd_migrate_data)
+
+    p_tune = subparsers.add_parser("tune", help="Adjust member flags, access mode and SMART self-test schedule of a running array")
+    p_tune.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_tune.add_argument("--write-mostly", action="append", default=[], metavar="DEVICE", help="Flag a member as write-mostly (e.g. a slow USB mirror)")
+    p_tune.add_argument("--no-write-mostly", action="append", default=[], metavar="DEVICE", help="Clear the write-mostly flag of a member")
+    p_tune.add_argument("--write-behind", type=int, metavar="N", help="Maximum outstanding writes to write-mostly members (needs a bitmap)")
+    p_tune.add_argument("--mode", choices=["readwrite", "readonly", "read-auto"], help="Array access mode")
+    p_tune.add_argument("--smart-short", choices=plan.SMART_SCHEDULES + ["off"], metavar="SCHEDULE", help="Install (daily/weekly/monthly) or remove (off) the short SMART self-test timer")
+    p_tune.add_argument("--smart-long", choices=plan.SMART_SCHEDULES + ["off"], metavar="SCHEDULE", help="Same for the long SMART self-test")
+    p_tune.add_argument("--smart-device", action="append", metavar="DEVICE", help="Disks the timers test, for LVM/btrfs arrays (md arrays use their current members)")
+    p_tune.set_defaults(func=cmd_tune)
+
+    p_discard = subparsers.add_parser("discard", help="Check whether TRIM/discard propagates through an array to its SSDs")
+    p_discard.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_discard.add_argument("--enable-raid456", action="store_true", help="Set raid456 devices_handle_discard_safely=Y (now and on boot)")
+    p_discard.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_discard.set_defaults(func=cmd_discard)
+
+    p_scrub = subparsers.add_parser("scrub", help="Check an array for mismatches and read errors (offering remediation), show its scrub status or schedule scrubs")
+    # 'scrub /dev/md0' without an action is what older timers run, it stays an alias for 'scrub start /dev/md0'
+    p_scrub.add_argument("action", metavar="{start,status,schedule}", help="What to do, start when left out")
+    p_scrub.add_argument("array", nargs="?", help="Existing md device, e.g. /dev/md0")
+    p_scrub.add_argument("schedule", nargs="?", choices=plan.SCRUB_SCHEDULES + ["off"], help="For schedule: how often a timer checks the array, or off")
+    p_scrub.add_argument("--repair", action="store_true", help="Rewrite inconsistent stripes instead of only counting them")
+    p_scrub.add_argument("-y", "--yes", action="store_true", help="Start the remediation without asking")
+    p_scrub.set_defaults(func=cmd_scrub)
+
+    p_smart = subparsers.add_parser("smart-test", help="SMART self-test every member disk, record the results and run on_smart_failed hooks")
+    p_smart.add_argument("array", help="Existing array, e.g. /dev/md0")
+    p_smart.add_argument("--type", choices=["short", "long"], default="short", help="Self-test to run (long reads the whole disk and takes hours)")
+    p_smart.add_argument("--device", action="append", metavar="DEVICE", help="Disk to test instead of the md members, repeat for several (LVM/btrfs arrays)")
+    p_smart.set_defaults(func=cmd_smart_test)
+
+    p_remediate = subparsers.add_parser("remediate", help="Re-check, repair and SMART-test after a scrub reported problems")
+    p_remediate.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_remediate.set_defaults(func=cmd_remediate)
+
+    p_health = subparsers.add_parser("health", help="Show the health history (scrubs, remediations, SMART self-tests)")
+    p_health.add_argument("array", nargs="?", help="Only show events of this array")
+    p_health.set_defaults(func=cmd_health)
+
+    p_healthcheck = subparsers.add_parser("healthcheck", help="One-line array/SMART/scrub summary with Nagios exit codes (0 OK, 1 WARNING, 2 CRITICAL)")
+    p_healthcheck.add_argument("arrays", nargs="*", help="Arrays to check (default: every running array)")
+    p_healthcheck.set_defaults(func=cmd_healthcheck)
+
+    p_status = subparsers.add_parser("status", help="Show state, sync progress and members of every array")
+    p_status.add_argument("arrays", nargs="*", help="Arrays to show (default: every running array)")
+    p_status.add_argument("--watch", metavar="INTERVAL", help="Keep refreshing every e.g. 5s until Ctrl+C")
+    p_status.set_defaults(func=cmd_status)
+
+    p_members = subparsers.add_parser("members", help="Show which disk occupies which slot of an array")
+    p_members.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_members.set_defaults(func=cmd_members)
+
+    p_watch = subparsers.add_parser("watch", help="Run the [hooks] of array events and offer newly attached blank disks as replacements")
+    p_watch.add_argument("arrays", nargs="*", help="Arrays to watch (default: every running array)")
+    p_watch.add_argument("--interval", default="10s", metavar="DURATION", help="Polling interval, e.g. 30s")
+    p_watch.add_argument("--auto-add", action="store_true", help="Add replacements without asking (overrides hot_add_policy)")
+    p_watch.add_argument("--verify", action="store_true", help="Verify each rebuilt replacement before reporting success (see verify_rebuilds)")
+    p_watch.set_defaults(func=cmd_watch)
+
+    p_notify = subparsers.add_parser("notify", help="Send a test notification to the [notification] sinks, or forward an mdadm --monitor event to them")
+    p_notify.add_argument("action", choices=["test", "mdadm"], help="test: send a test message; mdadm: forward an event as mdadm's PROGRAM")
+    p_notify.add_argument("arguments", nargs="*", help="test: the array named in the message (default /dev/md0); "
+                                                       "mdadm: the event, md device and member mdadm passes")
+    p_notify.set_defaults(func=cmd_notify)
+
+    p_expand = subparsers.add_parser("expand-plan", help="Compare the ways an array could grow onto new disks")
+    p_expand.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_expand.add_argument("devices", nargs="*", help="Candidate new disks (default: every available drive)")
+    p_expand.set_defaults(func=cmd_expand_plan)
+
+    p_upgrade = subparsers.add_parser("upgrade", help="Replace every member with a larger disk one by one, then grow (resumable)")
+    p_upgrade.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_upgrade.add_argument("devices", nargs="*", help="One larger disk per member, in member order; leave out to resume")
+    p_upgrade.add_argument("--abandon", action="store_true", help="Forget an interrupted upgrade instead of resuming it")
+    p_upgrade.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_upgrade.set_defaults(func=cmd_upgrade)
+
+    p_grow = subparsers.add_parser("grow", help="Add disks to a RAID 5/6/10 array, reshape onto them and grow the filesystem (resumable)")
+    p_grow.add_argument("array", help="Existing md device, e.g. /dev/md0")
+    p_grow.add_argument("devices", nargs="*", help="Disks to add, at least as large as the members; leave out to resume")
+    p_grow.add_argument("--backup-file", metavar="PATH", help="mdadm reshape backup file, on a disk outside the array")
+    p_grow.add_argument("--abandon", action="store_true", help="Forget an interrupted grow instead of resuming it")
+    p_grow.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_grow.set_defaults(func=cmd_grow)
+
+    p_verify = subparsers.add_parser("verify-rebuild", help="Wait for a rebuild, then check the array and SMART-test the new member")
+    p_verify.add_argument("array", help="Rebuilding md device, e.g. /dev/md0")
+    p_verify.add_argument("device", help="The replacement member, e.g. /dev/sdd")
+    p_verify.set_defaults(func=cmd_verify_rebuild)
+
+    p_metrics = subparsers.add_parser("metrics", help="Record or export array/member metrics history")
+    p_metrics.add_argument("action", choices=["record", "export"])
+    p_metrics.add_argument("--interval", metavar="DURATION", help="record: keep sampling every e.g. 60s instead of once")
+    p_metrics.add_argument("--since", metavar="DURATION", help="export: only rows from the last e.g. 7d")
+    p_metrics.set_defaults(func=cmd_metrics)
+
+    p_support = subparsers.add_parser("support-bundle", help="Collect logs, array state, inventory and config (redacted) into a tarball for bug reports")
+    p_support.add_argument("-o", "--output", metavar="FILE", help="Bundle path (default: liveraid-support-<time>.tar.gz)")
+    p_support.set_defaults(func=cmd_support_bundle)
+
+    p_snapshot = subparsers.add_parser("show-snapshot", help="Show the storage state recorded before a run (lists runs without an ID)")
+    p_snapshot.add_argument("run_id", nargs="?", help="Run ID printed when the operation started")
+    p_snapshot.add_argument("--file", help="Only show one captured file, e.g. mdstat.txt")
+    p_snapshot.set_defaults(func=cmd_show_snapshot)
+
+    p_fstab = subparsers.add_parser("fstab", help="List /etc/fstab, or add/remove the managed entry mounting an array at boot (by filesystem UUID)")
+    p_fstab.add_argument("action", choices=["list", "add", "remove"])
+    p_fstab.add_argument("target", nargs="?", help="Array for add, array or mount point for remove")
+    p_fstab.add_argument("mount_point", nargs="?", help="Where add mounts the array, e.g. /srv/data")
+    p_fstab.add_argument("--mount-option", action="append", default=[], metavar="OPTION", help="Mount option such as noatime, repeat for several")
+    p_fstab.set_defaults(func=cmd_fstab)
+
+    p_serve = subparsers.add_parser("serve", help="Run as a daemon offering discover, plan, apply and status as JSON-RPC on a Unix socket")
+    p_serve.set_defaults(func=cmd_serve)
+
+    p_jsonrpc = subparsers.add_parser("jsonrpc", help="Speak the daemon's JSON-RPC 2.0 (discover, validate, plan, apply, status, subscribe) on stdin/stdout")
+    p_jsonrpc.add_argument("--stdio", action="store_true", help="Read requests from stdin and answer on stdout, one JSON document per line")
+    p_jsonrpc.set_defaults(func=cmd_jsonrpc)
+
+    p_changes = subparsers.add_parser("changes", help="List the recorded edits of mdadm.conf and other system files")
+    p_changes.add_argument("--diff", action="store_true", help="Show each edit as a diff")
+    p_changes.set_defaults(func=cmd_changes)
+
+    p_undo = subparsers.add_parser("undo", help="Revert a single recorded file edit (see 'changes')")
+    p_undo.add_argument("change_id", help="Change ID printed when the file was updated")
+    p_undo.set_defaults(func=cmd_undo)
+
+    p_grub = subparsers.add_parser("grub", help="Show or edit the LiveRAID block of /etc/default/grub (backed up, validated, grub.cfg regenerated)")
+    p_grub.add_argument("action", choices=["show", "add-raid", "remove-raid", "restore"])
+    p_grub.add_argument("target", nargs="?", help="Array for add-raid/remove-raid, backup ID or file for restore")
+    p_grub.add_argument("--diff", action="store_true", help="Show what add-raid/remove-raid would change without writing it")
+    p_grub.set_defaults(func=cmd_grub)
+
+    p_boot = subparsers.add_parser("bootloader", help="Have GRUB, systemd-boot or an EFI stub entry assemble boot arrays (rd.md.uuid=, root=UUID=)")
+    p_boot.add_argument("action", choices=["show", "add-raid", "remove-raid"])
+    p_boot.add_argument("target", nargs="?", help="Array for add-raid/remove-raid")
+    p_boot.add_argument("--loader", choices=list(bootloader.BOOTLOADERS), help="Bootloader to edit (default: 'bootloader' in config.toml, auto-detected)")
+    p_boot.add_argument("--diff", action="store_true", help="Show what add-raid/remove-raid would change without writing it")
+    p_boot.set_defaults(func=cmd_bootloader)
+
+    p_jobs = subparsers.add_parser("jobs", help="List, inspect, cancel or pause long-running operations")
+    p_jobs.add_argument("action", choices=["list", "show", "cancel", "pause", "resume"])
+    p_jobs.add_argument("job_id", nargs="?", help="Job ID for show/cancel/pause/resume")
+    p_jobs.set_defaults(func=cmd_jobs)
+
+    p_events = subparsers.add_parser("events", help="Show the event log of the arrays LiveRAID manages, their derived state, or what reverses an event")
+    p_events.add_argument("action", choices=["list", "state", "undo"])
+    p_events.add_argument("seq", nargs="?", type=int, help="Sequence number of the event for undo")
+    p_events.add_argument("--array", help="list: only the events of this array")
+    p_events.add_argument("--since", type=int, default=0, metavar="SEQ", help="list: only the events after this sequence number")
+    p_events.add_argument("--all", action="store_true", help="state: include destroyed arrays")
+    p_events.set_defaults(func=cmd_events)
+
+    p_geometry = subparsers.add_parser("geometry", help="Show, record or export the creation parameters (chunk, layout, data offset, member order) of arrays")
+    p_geometry.add_argument("action", choices=["list", "show", "record", "export"])
+    p_geometry.add_argument("target", nargs="?", help="Array or UUID for show/record, output file for export")
+    p_geometry.set_defaults(func=cmd_geometry)
+
+    p_recreate = subparsers.add_parser("recover-recreate", help="Last resort: re-create a lost array over its old members with the recorded parameters, read-only")
+    p_recreate.add_argument("target", help="Recorded array, UUID or md name (see 'geometry list')")
+    p_recreate.add_argument("--from", dest="source", metavar="FILE", help="Read the parameters from a 'geometry export' file instead of the state directory")
+    p_recreate.add_argument("--member", action="append", default=[], metavar="SLOT=DEVICE",
+                            help="Use this device for a slot instead of the one found by serial, e.g. 2=/dev/sdf1 (repeatable)")
+    p_recreate.add_argument("--array", help="md device to create (default: the lowest free one)")
+    p_recreate.add_argument("--mount-point", default="/mnt/liveraid-recovery", help="Where the filesystem is mounted read-only")
+    p_recreate.add_argument("--encrypted", action="store_true", help="The filesystem is in a LUKS container: ask for its passphrase to open it read-only")
+    p_recreate.add_argument("--keyfile", metavar="PATH", help="Open the LUKS container with this keyfile instead of a passphrase")
+    p_recreate.add_argument("--expert", action="store_true", help="Confirm that you know what re-creating an array involves")
+    p_recreate.add_argument("--confirm-uuid", metavar="PREFIX", help="First 8 characters of the array UUID, for unattended runs")
+    p_recreate.add_argument("-y", "--yes", action="store_true", help="Do not ask for confirmation")
+    p_recreate.set_defaults(func=cmd_recover_recreate)
+
+    p_throughput = subparsers.add_parser("throughput-test", help="Write and read back a test file on an array's filesystem and check the throughput against the estimate")
+    p_throughput.add_argument("array", help="Array to test, e.g. /dev/md0")
+    p_throughput.add_argument("--mount-point", metavar="PATH", help="Where its filesystem is mounted (default: found in /proc/mounts)")
+    p_throughput.add_argument("--size", type=size_arg, default=1024**3, metavar="SIZE", help="Test file size (default: 1G)")
+    p_throughput.set_defaults(func=cmd_throughput_test)
+
+    p_bench = subparsers.add_parser("bench", help="Measure an array's throughput, IOPS and latency with fio or the built-in tester")
+    p_bench.add_argument("array", help="Array to benchmark, e.g. /dev/md0")
+    p_bench.add_argument("--mount-point", metavar="PATH", help="Where its filesystem is mounted (default: found in /proc/mounts)")
+    p_bench.add_argument("--workload", action="append", choices=list(bench.WORKLOADS),
+                         help="Run only this workload, repeatable (default: all of them)")
+    p_bench.add_argument("--size", type=size_arg, default=1024**3, metavar="SIZE", help="Test file size (default: 1G)")
+    p_bench.add_argument("--runtime", type=int, metavar="SECONDS", help="Longest a workload runs (default: bench_runtime in config.toml)")
+    p_bench.add_argument("--baseline", action="store_true",
+                         help="Also run the read workloads on one member disk (read-only) and compare the array with it")
+    p_bench.add_argument("--engine", choices=bench.ENGINES, help="fio, the built-in tester, or fio when installed (default: bench_engine in config.toml)")
+    p_bench.set_defaults(func=cmd_bench)
+
+    p_mount_ro = subparsers.add_parser("mount-ro", help="Mount an array or disk strictly read-only to inspect its data before re-provisioning it")
+    p_mount_ro.add_argument("target", help="md array, disk or partition (members of a stopped array are assembled read-only)")
+    p_mount_ro.add_argument("--mount-root", default=READ_ONLY_ROOT, help="Each filesystem is mounted in a directory below this one")
+    p_mount_ro.add_argument("--keep", action="store_true", help="Leave it mounted and print the commands that release it")
+    p_mount_ro.set_defaults(func=cmd_mount_ro)
+
+    return parser
+
+# Subcommands that change disks or system files, refused up front in operator sessions
+# (the service checks again, this only keeps operators from answering prompts for nothing)
+ADMIN_COMMANDS = {"create": "create", "apply": "create", "format": "format", "destroy": "destroy", "cleanup-md": "cleanup-md",
+                  "migrate-hwraid": "create", "migrate-data": "migrate-data", "tune": "tune", "upgrade": "upgrade", "grow": "grow",
+                  "undo": "undo", "teardown-stack": "teardown-stack", "recover-recreate": "recreate"}
+
+# Subcommands --remote sends to the daemon, the daemon's own admin and policy checks apply there
+REMOTE_COMMANDS = ["drives", "discover", "plan", "status", "apply"]
+
+def main():
+    args = build_parser().parse_args()
+    if args.format != "table" and args.output_mode == "progress-proto":
+        print("ERROR: --output progress-proto and --format json|yaml both want stdout, pick one.")
+        sys.exit(1)
+    if args.format != "table" or args.output_mode == "progress-proto":
+        # Only the document goes to stdout, everything else (log, prompts, warnings) to stderr
+        output.STDOUT = sys.stdout
+        sys.stdout = sys.stderr
+    if args.output_mode == "progress-proto":
+        output.start_progress()
+    status = "failed"
+    try:
+        status = run(args)
+    except SystemExit as e:
+        status = e.code if isinstance(e.code, int) else int(e.code is not None)
+        raise
+    finally:
+        # Whatever ended the run, an exception or Ctrl-C included, the protocol closes with its RESULT
+        output.end_progress(status)
+    sys.exit(status)
+
+def run(args):
+    config.setup_logging()
+    if args.global_dry_run:
+        backend.DRY_RUN = True
+    units.SYSTEM = args.units
+    simulation = None
+    if args.simulate:
+        if args.remote:
+            print("ERROR: --simulate runs locally, it can't be combined with --remote.")
+            return 1
+        simulation, out = simulate.start(args.simulate, args.simulate_drives, args.simulate_size)
+        print(out, end="")
+        if simulation is None:
+            return 1
+    # Every subcommand goes through the same service the GUI uses
+    args.service = RaidService(color=sys.stdout.isatty(), admin=True if args.admin else None,
+                               runner=simulation['runner'] if simulation else None)
+    if args.remote:
+        if args.command not in REMOTE_COMMANDS:
+            print(f"ERROR: '{args.command}' can't run through the daemon, only {', '.join(REMOTE_COMMANDS)} can.")
+            return 1
+        return run_remote(args)
+    operation = ADMIN_COMMANDS.get(args.command)
+    if operation and not getattr(args, 'dry_run', False) and not args.service.require_admin(operation):
+        return 1
+    try:
+        return args.func(args)
+    finally:
+        if simulation:
+            print(simulate.stop(simulation), end="")
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,265 @@
+import logging
+import os
+import shlex
+import sys
+
+try:
+    import tomllib  # Python 3.11+
+except ImportError:
+    tomllib = None
+
+# Site-wide settings, see config.example.toml for the supported keys
+CONFIG_PATH = os.environ.get("LIVERAID_CONFIG", "/etc/liveraid/config.toml")
+
+# Persistent state (jobs, metrics) shared between the GUI and CLI
+STATE_DIR = os.environ.get("LIVERAID_STATE_DIR", "/var/lib/liveraid")
+
+SUPPORTED_FILESYSTEMS = ["ext4", "btrfs", "xfs", "f2fs", "exfat", "ntfs", "vfat"]
+
+# Top-level settings and their defaults when config.toml doesn't set them
+DEFAULTS = {
+    'log_level': "warning",               # debug | info | warning | error
+    'backup_existing_configs': True,      # copy system files to STATE_DIR/backups before editing them
+    'raid_device_prefix': "/dev/md",      # new arrays are named <prefix>0, <prefix>1, ...
+    'md_start_index': 0,                  # lowest number a new array is given (arraynames.py)
+    'md_reserved': [],                    # numbers never given to a new array, e.g. [127] for arrays assembled by other hosts
+    'md_naming': "numbered",              # numbered (<prefix>N) | named (/dev/md/<name>, from --name or liveraidN)
+    'raid_backend': "mdadm",              # mdadm | lvm | btrfs: what plans are built with (see raidbackend.py)
+    'bootloader': "auto",                 # auto | grub | systemd-boot | efistub: where boot arrays are recorded (bootloader.py)
+    'grub_timeout': 5,                    # GRUB_TIMEOUT of the managed /etc/default/grub block, seconds the boot menu waits; only written when set
+    'wipe_policy': "none",                # none | signatures | discard | zero | random: wipe stage of new plans (wipe.py)
+    'wipe_block_size': "4M",              # block size of a zero/random overwrite
+    'confirm_policy': "destructive",      # always | destructive | never: when frontends ask before acting
+    'safety_mode': "admin",               # admin | operator: operator sessions only plan, validate and monitor
+    'suppress_warnings': [],              # validation warning codes to silence, e.g. ["mixed-media"]
+    'hot_add_policy': "prompt",           # prompt | auto | never: blank disk attached while an array is degraded
+    'verify_rebuilds': False,             # after a hot-add rebuild: check pass + SMART test of the new member before reporting success
+    'size_units': "iec",                  # iec (GiB, like lsblk) | si (GB, like drive labels)
+    'scrub_warn_days': 35,                # healthcheck: WARNING when the last scrub is older than this
+    'scrub_crit_days': 90,                # healthcheck: CRITICAL when the last scrub is older than this
+    'smart_sector_warn': 1,               # healthcheck and discovery: WARNING from this many reallocated + pending sectors
+    'smart_discovery': True,              # read the SMART health of every drive during discovery (smartctl)
+    'smart_strict': False,                # refuse members with failing SMART health instead of warning (--strict)
+    'throughput_min_ratio': 0.5,          # throughput-test: FAIL below this share of the estimated throughput
+    'bench_engine': "auto",               # auto | fio | builtin: what runs the bench workloads (bench.py)
+    'bench_runtime': 10,                  # bench: longest a workload runs, in seconds
+    'notify_url': "",                     # apply: POST the final provisioning report here ('' = off, --notify-url overrides)
+    'notify_mqtt_broker': "",             # apply: publish the report to this MQTT broker, host or host:port ('' = off)
+    'notify_mqtt_topic': "liveraid/provisioning",  # MQTT topic of the provisioning report
+    'daemon_socket': "/run/liveraid/liveraid.sock",  # cli.py serve listens here, --remote connects here
+    'daemon_group': ""                    # group that may use the daemon socket besides root ('' = root only)
+}
+
+CHOICES = {
+    'log_level': ["debug", "info", "warning", "error"],
+    'raid_backend': ["mdadm", "lvm", "btrfs"],
+    'bootloader': ["auto", "grub", "systemd-boot", "efistub"],
+    'bench_engine': ["auto", "fio", "builtin"],
+    'md_naming': ["numbered", "named"],
+    'wipe_policy': ["none", "signatures", "discard", "zero", "random"],
+    'confirm_policy': ["always", "destructive", "never"],
+    'safety_mode': ["admin", "operator"],
+    'hot_add_policy': ["prompt", "auto", "never"],
+    'size_units': ["iec", "si"]
+}
+
+# [hooks] keys, one per array event (see hooks.py)
+HOOK_KEYS = ["on_degraded", "on_rebuilt", "on_finished", "on_smart_failed"]
+
+# Events a [[notification.sinks]] entry can be limited to (its 'events' list), the hook events plus the test message
+NOTIFICATION_EVENTS = [key.removeprefix("on_") for key in HOOK_KEYS] + ["test"]
+
+# [[notification.sinks]] types and the settings each takes, with their defaults (see notifications.py)
+NOTIFICATION_SINKS = {
+    'email': {'to': [], 'sender': "", 'smtp_host': "localhost", 'smtp_port': 25, 'starttls': False, 'username': "", 'password': ""},
+    'webhook': {'url': ""},
+    'desktop': {},
+}
+
+# [policy.<rule>] tables: an action (off | warn | error) and the rule's parameters with their defaults (see policy.py)
+POLICY_RULES = {
+    'no-raid0-data': {},
+    'spare-required': {'above_members': 4},
+    'raid6-large-members': {'member_size': "8TB"},
+    'redundant-swap': {},
+}
+POLICY_ACTIONS = ["off", "warn", "error"]
+
+log = logging.getLogger("liveraid")
+
+_cache = None
+
+def load_config(path=None, reload=False):
+    """
+    Returns the parsed config.toml as a dict, or {} when the file is missing or invalid.
+    """
+    global _cache
+    if _cache is not None and not reload and path is None:
+        return _cache
+
+    path = path or CONFIG_PATH
+    data = {}
+    if os.path.exists(path):
+        if tomllib is None:
+            log.warning(f"Python {sys.version_info.major}.{sys.version_info.minor} has no tomllib, ignoring {path}")
+        else:
+            try:
+                with open(path, 'rb') as f:
+                    data = tomllib.load(f)
+            except Exception as e:
+                log.error(f"Failed to read {path}: {e}")
+
+    for problem in validate_config(data):
+        log.warning(f"Config: {problem}")
+
+    if path == CONFIG_PATH:
+        _cache = data
+    return data
+
+def validate_config(data):
+    """
+    Checks a parsed config against the supported schema and returns a list of problems.
+    """
+    problems = []
+    for key, value in data.items():
+        if key in ('mkfs', 'hooks', 'policy', 'notification'):
+            continue
+        if key not in DEFAULTS:
+            problems.append(f"unknown setting '{key}'")
+        elif type(value) is not type(DEFAULTS[key]):
+            problems.append(f"'{key}' must be a {type(DEFAULTS[key]).__name__}")
+        elif key in CHOICES and value not in CHOICES[key]:
+            problems.append(f"'{key}' must be one of: {', '.join(CHOICES[key])}")
+        elif key == 'md_reserved' and not all(type(n) is int and n >= 0 for n in value):
+            problems.append("'md_reserved' must be a list of md numbers")
+        elif key == 'md_start_index' and value < 0:
+            problems.append("'md_start_index' must not be negative")
+
+    mkfs = data.get('mkfs', {})
+    if not isinstance(mkfs, dict):
+        problems.append("[mkfs] must be a table of per-filesystem sections")
+    for fs_type, section in mkfs.items() if isinstance(mkfs, dict) else ():
+        if fs_type not in SUPPORTED_FILESYSTEMS:
+            problems.append(f"[mkfs.{fs_type}] is not a supported filesystem")
+        elif not isinstance(section, dict) or not isinstance(section.get('options', []), (str, list)):
+            problems.append(f"[mkfs.{fs_type}] options must be a string or a list of strings")
+
+    hooks = data.get('hooks', {})
+    if not isinstance(hooks, dict):
+        problems.append("[hooks] must be a table of on_<event> commands")
+    for key, value in hooks.items() if isinstance(hooks, dict) else ():
+        if key not in HOOK_KEYS:
+            problems.append(f"[hooks] unknown event '{key}' (use {', '.join(HOOK_KEYS)})")
+        elif not isinstance(value, str) and not (isinstance(value, list) and all(isinstance(v, str) for v in value)):
+            problems.append(f"[hooks] {key} must be a command string or a list of command strings")
+
+    notification = data.get('notification', {})
+    sinks = notification.get('sinks', []) if isinstance(notification, dict) else None
+    if not isinstance(sinks, list):
+        problems.append("[notification] sinks must be a list of tables, e.g. [[notification.sinks]]")
+    problems += [f"[notification] unknown setting '{key}'" for key in (notification if isinstance(notification, dict) else {}) if key != "sinks"]
+    for i, sink in enumerate(sinks or []):
+        kind = sink.get('type') if isinstance(sink, dict) else None
+        if kind not in NOTIFICATION_SINKS:
+            problems.append(f"[notification] sink {i + 1}: type must be one of: {', '.join(NOTIFICATION_SINKS)}")
+            continue
+        for key, value in sink.items():
+            if key == "events":
+                if not isinstance(value, list) or any(event not in NOTIFICATION_EVENTS for event in value):
+                    problems.append(f"[notification] {kind} sink: events must be a list of: {', '.join(NOTIFICATION_EVENTS)}")
+            elif key != "type" and key not in NOTIFICATION_SINKS[kind]:
+                problems.append(f"[notification] {kind} sink: unknown setting '{key}'")
+            elif key != "type" and type(value) is not type(NOTIFICATION_SINKS[kind][key]):
+                problems.append(f"[notification] {kind} sink: {key} must be a {type(NOTIFICATION_SINKS[kind][key]).__name__}")
+        if kind == "email" and not sink.get('to'):
+            problems.append("[notification] email sink: to needs at least one address")
+        if kind == "webhook" and not sink.get('url'):
+            problems.append("[notification] webhook sink: url is missing")
+
+    policy = data.get('policy', {})
+    if not isinstance(policy, dict):
+        return problems + ["[policy] must be a table of [policy.<rule>] sections"]
+    for rule, section in policy.items():
+        if rule not in POLICY_RULES:
+            problems.append(f"[policy] unknown rule '{rule}' (use {', '.join(POLICY_RULES)})")
+        elif not isinstance(section, dict):
+            problems.append(f"[policy.{rule}] must be a table")
+        else:
+            for key, value in section.items():
+                if key == "action":
+                    if value not in POLICY_ACTIONS:
+                        problems.append(f"[policy.{rule}] action must be one of: {', '.join(POLICY_ACTIONS)}")
+                elif key not in POLICY_RULES[rule]:
+                    problems.append(f"[policy.{rule}] unknown setting '{key}'")
+                elif type(value) is not type(POLICY_RULES[rule][key]):
+                    problems.append(f"[policy.{rule}] {key} must be a {type(POLICY_RULES[rule][key]).__name__}")
+    return problems
+
+def get_setting(key):
+    """
+    Returns a top-level setting from config.toml, falling back to DEFAULTS when unset or invalid.
+    """
+    value = load_config().get(key, DEFAULTS[key])
+    if type(value) is not type(DEFAULTS[key]) or (key in CHOICES and value not in CHOICES[key]):
+        return DEFAULTS[key]
+    return value
+
+def setup_logging():
+    # Diagnostics go to stderr so they never mix with CLI output meant for scripts
+    logging.basicConfig(format="%(levelname)s: %(message)s", level=get_setting('log_level').upper())
+
+def should_confirm(destructive):
+    policy = get_setting('confirm_policy')
+    return policy == "always" or (policy == "destructive" and destructive)
+
+def split_options(options):
+    # Accept both `options = "-O metadata_csum,64bit"` and `options = ["-O", "metadata_csum,64bit"]`
+    if isinstance(options, str):
+        return shlex.split(options)
+    return [str(o) for o in options]
+
+def get_hook_commands(event, data=None):
+    """
+    Returns the commands configured for an array event as argument lists, e.g. [['/usr/local/bin/ticket', '--queue', 'storage']].
+    """
+    data = load_config() if data is None else data
+    hooks = data.get('hooks', {})
+    value = hooks.get(f"on_{event}", []) if isinstance(hooks, dict) else []
+    if isinstance(value, str):
+        value = [value]
+    if not isinstance(value, list):
+        return []
+    return [shlex.split(command) for command in value if isinstance(command, str) and command.strip()]
+
+def get_mkfs_defaults(fs_type, data=None):
+    """
+    Returns the site default mkfs arguments for a filesystem, e.g. ['-O', 'metadata_csum,64bit'].
+    """
+    data = load_config() if data is None else data
+    mkfs = data.get('mkfs', {})
+    section = mkfs.get(fs_type, {}) if isinstance(mkfs, dict) else {}
+    if fs_type not in SUPPORTED_FILESYSTEMS or not isinstance(section, dict):
+        return []
+    options = section.get('options', [])
+    if not isinstance(options, (str, list)):
+        return []
+    return split_options(options)
+
+def get_notification_sinks(kinds=None, data=None):
+    """
+    Returns the valid [[notification.sinks]] entries with their defaults filled in, only those of the given types if kinds is set.
+    Returns: [{'type': 'webhook', 'url': 'https://...', 'events': ['degraded', 'rebuilt', ...]}]
+    """
+    data = load_config() if data is None else data
+    notification = data.get('notification', {})
+    sinks = notification.get('sinks', []) if isinstance(notification, dict) else []
+    resolved = []
+    for sink in sinks if isinstance(sinks, list) else []:
+        if not isinstance(sink, dict) or sink.get('type') not in NOTIFICATION_SINKS or (kinds and sink['type'] not in kinds):
+            continue
+        settings = {key: sink[key] if type(sink.get(key)) is type(default) else default
+                    for key, default in NOTIFICATION_SINKS[sink['type']].items()}
+        events = sink.get('events')
+        settings['events'] = events if isinstance(events, list) else list(NOTIFICATION_EVENTS)
+        resolved.append(dict(settings, type=sink['type']))
+    return resolved
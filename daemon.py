@@ -0,0 +1,423 @@
+import grp
+import inspect
+import json
+import logging
+import os
+import socket
+import socketserver
+import struct
+import sys
+import threading
+import time
+import backend
+import config
+import devicefilter
+import events
+import hooks
+import jobs
+import notifications
+import output
+import plan
+from service import RaidService
+
+log = logging.getLogger("liveraid")
+
+# `cli.py serve`: one privileged process that orchestration tooling (and `cli.py --remote`) drives over a
+# Unix socket. Requests are JSON-RPC 2.0, one JSON document per line in each direction:
+#   {"jsonrpc": "2.0", "id": 1, "method": "status", "params": {}}
+# Callers are told apart by their socket credentials: root gets an admin session, everyone else who may
+# open the socket (daemon_group in config.toml) an operator session that can discover, plan and watch
+# but not apply. Every plan goes through the same validation and [policy] rules as on the command line.
+# `cli.py jsonrpc --stdio` speaks the same protocol on stdin/stdout, for a web frontend (Cockpit) or a
+# program in another language that spawns it, with the session of the user running it. On both, apply
+# reports its progress and subscribe streams status changes and new events as notifications (no id):
+#   {"jsonrpc": "2.0", "method": "progress", "params": {"step": 2, "steps": 5, "percent": 40.0, ...}}
+# apply with "background": true answers as soon as the apply started and sends its result as an 'applied'
+# notification (its log as 'log' notifications), so the same connection can go on with other requests
+# meanwhile, e.g. cancel to stop it or pause/resume to hold it between steps.
+
+# The socket daemon also watches the arrays and sends their events (degraded, rebuilt, ...) to the email
+# and webhook sinks of [notification] in config.toml (notifications.py).
+
+# JSON-RPC error codes, the -320xx ones are LiveRAID's own
+PARSE_ERROR = -32700
+INVALID_REQUEST = -32600
+METHOD_NOT_FOUND = -32601
+INVALID_PARAMS = -32602
+REFUSED = -32000
+BUSY = -32001
+
+class RpcError(Exception):
+    def __init__(self, code, message, data=None):
+        super().__init__(message)
+        self.code = code
+        self.data = data
+
+# How often a subscription looks for changes, in seconds
+SUBSCRIBE_INTERVAL = 2
+
+# How often the daemon polls the arrays for events to notify, in seconds
+MONITOR_INTERVAL = 10
+
+class Channel:
+    """
+    One client's side of the conversation: responses and notifications from subscription threads share
+    the stream, one whole line at a time.
+    """
+
+    def __init__(self, stream):
+        self.stream = stream
+        self.lock = threading.Lock()
+        self.closed = False
+
+    def send(self, document):
+        """
+        Returns False once the client is gone, which ends its subscriptions.
+        """
+        try:
+            with self.lock:
+                self.stream.write((json.dumps(document, default=str) + "\n").encode())
+                self.stream.flush()
+            return True
+        except (OSError, ValueError):
+            self.closed = True
+            return False
+
+    def notify(self, method, params):
+        return not self.closed and self.send({'jsonrpc': "2.0", 'method': method, 'params': params})
+
+def monitor(interval=MONITOR_INTERVAL):
+    """
+    Polls the arrays for events and hands them to the [hooks] commands and the notification sinks a daemon can reach,
+    for as long as the process runs.
+    """
+    detector = hooks.EventDetector()
+    while True:
+        try:
+            for event in detector.poll([arr['name'] for arr in backend.get_active_arrays()]):
+                log.warning(f"EVENT {event['event']}: {hooks.describe(event)}")
+                # Failing hooks are logged as errors by run_hooks() itself
+                _, hook_log = hooks.run_hooks(event)
+                if hook_log:
+                    log.info(hook_log.strip())
+                notifications.dispatch(event, notifications.SYSTEM_SINKS)
+        except Exception as e:
+            log.error(f"Monitoring the arrays failed: {e}")
+        time.sleep(interval)
+
+def peer_uid(conn):
+    # struct ucred {pid_t pid; uid_t uid; gid_t gid;}
+    creds = conn.getsockopt(socket.SOL_SOCKET, socket.SO_PEERCRED, struct.calcsize("3i"))
+    return struct.unpack("3i", creds)[1]
+
+def call(method, params=None, path=None):
+    """
+    Client side: sends one request to the daemon and waits for its answer (an apply can take minutes).
+    Returns the result, raises RpcError for an error response and OSError when no daemon listens.
+    """
+    path = path or config.get_setting('daemon_socket')
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as conn:
+        conn.connect(path)
+        conn.sendall((json.dumps({'jsonrpc': "2.0", 'id': 1, 'method': method, 'params': params or {}}) + "\n").encode())
+        with conn.makefile('r') as reply:
+            line = reply.readline()
+    if not line:
+        raise OSError(f"The daemon on {path} closed the connection without an answer")
+    response = json.loads(line)
+    if 'error' in response:
+        error = response['error']
+        raise RpcError(error['code'], error['message'], error.get('data'))
+    return response['result']
+
+class Daemon:
+    """
+    The methods the socket offers. Each call gets its own RaidService, so the log of one request never
+    ends up in another's response; apply runs one at a time. operations holds the background.Operation of
+    a running apply by array, for cancel.
+    """
+
+    METHODS = ("discover", "validate", "plan", "apply", "cancel", "pause", "resume", "status", "jobs", "job", "events", "subscribe", "methods")
+
+    def __init__(self):
+        self.apply_lock = threading.Lock()
+        self.operations = {}
+
+    def session(self, uid):
+        lines = []
+        return RaidService(log=lines.append, admin=uid == 0), lines
+
+    def call(self, method, params, uid, channel=None):
+        if method not in self.METHODS:
+            raise RpcError(METHOD_NOT_FOUND, f"Unknown method '{method}', use one of {', '.join(self.METHODS)}")
+        if not isinstance(params, dict):
+            raise RpcError(INVALID_PARAMS, "params must be an object of named parameters")
+        handler = getattr(self, f"rpc_{method}")
+        try:
+            inspect.signature(handler).bind(None, **params)
+        except TypeError as e:
+            raise RpcError(INVALID_PARAMS, f"{method}: {e}")
+        # Methods that talk back while they run get the client's channel, it is never a parameter of the request
+        if 'channel' in inspect.signature(handler).parameters:
+            params = dict(params, channel=channel)
+        service, lines = self.session(uid)
+        result = handler(service, **params)
+        result['log'] = "".join(lines)
+        return result
+
+    def rpc_methods(self, service):
+        return {'methods': list(self.METHODS), 'admin': service.admin, 'dry_run': backend.DRY_RUN}
+
+    def rpc_status(self, service):
+        return {'arrays': backend.get_arrays_status()}
+
+    def rpc_validate(self, service, level, devices, spares=(), suppress=None, strict=None, preserve_partitions=False,
+                     metadata=None, bitmap=None, layout=None, name=None):
+        # The quick check the GUI runs while disks are picked, plan validates a whole plan
+        report = service.validate(str(level), list(devices), suppress, preserve_partitions, list(spares), strict,
+                                  metadata=metadata, bitmap=bitmap, layout=layout, name=name)
+        return {'valid': not report['errors'], 'errors': report['errors'], 'warnings': report['warnings']}
+
+    def rpc_subscribe(self, service, interval=SUBSCRIBE_INTERVAL, channel=None):
+        """
+        Sends a 'status' notification whenever the state of an array changes (and once right away) and an
+        'event' notification for every new events.py event, until the client disconnects.
+        """
+        if channel is None:
+            raise RpcError(REFUSED, "subscribe needs a connection that stays open")
+        if not isinstance(interval, (int, float)) or interval < 1:
+            raise RpcError(INVALID_PARAMS, "interval must be at least 1 second")
+        history = events.read_events()
+        last_seq = history[-1]['seq'] if history else 0
+
+        def watch():
+            seq, previous = last_seq, None
+            while not channel.closed:
+                arrays = backend.get_arrays_status()
+                if arrays != previous and not channel.notify("status", {'arrays': arrays}):
+                    return
+                previous = arrays
+                for event in events.read_events(seq):
+                    if not channel.notify("event", event):
+                        return
+                    seq = event['seq']
+                time.sleep(interval)
+
+        threading.Thread(target=watch, daemon=True).start()
+        return {'subscribed': ["status", "event"], 'interval': interval, 'last_seq': last_seq}
+
+    def rpc_jobs(self, service, target=None, state=None):
+        # What 'cli.py jobs list' shows, newest first: applies, formats and the kernel's resyncs and scrubs
+        listed = [job for job in jobs.list_jobs() if target in (None, job['target']) and state in (None, job['state'])]
+        return {'jobs': listed}
+
+    def rpc_job(self, service, job_id):
+        job = jobs.get_job(job_id)
+        if job is None:
+            raise RpcError(INVALID_PARAMS, f"Unknown job: {job_id}")
+        return {'job': job}
+
+    def rpc_events(self, service, since=0, array=None):
+        # Frontends poll with the last seq they saw to pick up what other frontends did
+        history = events.read_events(since, array)
+        return {'events': history, 'last_seq': history[-1]['seq'] if history else since, 'arrays': events.state()}
+
+    def rpc_discover(self, service, filter=None):
+        drives = backend.get_available_drives()
+        if filter:
+            try:
+                drives = devicefilter.parse(filter).apply(drives)
+            except ValueError as e:
+                raise RpcError(INVALID_PARAMS, str(e))
+        return {'drives': [dict(d, flags=backend.predict_drive_conflicts(d, [])) for d in drives]}
+
+    def rpc_plan(self, service, fields, suppress=None, strict=None):
+        # fields are plan.new_plan()'s keyword arguments, e.g. {"level": "1", "devices": ["/dev/sdb", "/dev/sdc"]}
+        if not isinstance(fields, dict):
+            raise RpcError(INVALID_PARAMS, "fields must be an object, e.g. {\"level\": \"1\", \"devices\": [...]}")
+        try:
+            proposal = plan.new_plan(**fields)
+        except TypeError as e:
+            raise RpcError(INVALID_PARAMS, f"fields: {e}")
+        drives = service.inventory()
+        report = plan.validate_plan(proposal, drives, suppress, strict)
+        plan.record_identities(proposal, drives)
+        return {'valid': not report['errors'], 'errors': report['errors'], 'warnings': report['warnings'],
+                'capacity_bytes': report['capacity_bytes'], 'summary': report['summary'],
+                'plan': dict(proposal, version=plan.PLAN_VERSION), 'steps': plan.plan_steps(proposal)}
+
+    def rpc_apply(self, service, plan_data, suppress=None, strict=None, passphrase=None, background=False, channel=None):
+        # There is no prompt on a socket: sending apply is the confirmation
+        if not service.allowed("create"):
+            raise RpcError(REFUSED, "apply needs an admin session, connect as root")
+        try:
+            proposal = plan.plan_from_dict(plan_data)
+        except ValueError as e:
+            raise RpcError(INVALID_PARAMS, str(e))
+        if background and channel is None:
+            raise RpcError(REFUSED, "A background apply needs a connection that stays open for its result")
+        if not self.apply_lock.acquire(blocking=False):
+            raise RpcError(BUSY, "Another apply is running, try again when it finished")
+        released = False
+        try:
+            drives = service.inventory()
+            report = plan.validate_plan(proposal, drives, suppress, strict)
+            if report['errors']:
+                return {'success': False, 'array': proposal['array'], 'errors': report['errors'], 'warnings': report['warnings'], 'steps': []}
+            if proposal['encryption'] and not proposal['encryption']['keyfile'] and not passphrase and not backend.DRY_RUN:
+                raise RpcError(INVALID_PARAMS, "The plan encrypts without a keyfile, send the passphrase")
+            identities = plan.identities(proposal, drives)
+            on_progress = (lambda event: channel.notify("progress", dict(event, array=proposal['array']))) if channel else None
+            if not background:
+                success, job = service.apply_plan(proposal, identities, passphrase or "", on_progress)
+                return self.apply_result(service, proposal, report, success, job)
+
+            def on_done(operation):
+                success, job = operation.result if operation.error is None else (False, None)
+                result = self.apply_result(service, proposal, report, success, job)
+                self.operations.pop(proposal['array'], None)
+                self.apply_lock.release()
+                channel.notify("applied", dict(result, cancelled=operation.cancelled))
+
+            # The response is sent before the apply logs anything more, the rest goes out as 'log' notifications
+            service.log = lambda text: channel.notify("log", {'array': proposal['array'], 'text': text})
+            operation = service.apply_plan_async(proposal, identities, passphrase or "", on_progress, on_done)
+            # From here on on_done releases the lock once the apply ended, before that the finally below does
+            released = True
+            self.operations[proposal['array']] = operation
+            return {'started': True, 'array': proposal['array'], 'warnings': report['warnings']}
+        finally:
+            if not released:
+                self.apply_lock.release()
+
+    def apply_result(self, service, proposal, report, success, job):
+        applied = service.last_apply_job
+        return {'success': success, 'array': proposal['array'], 'errors': [], 'warnings': report['warnings'],
+                'capacity_bytes': report['capacity_bytes'], 'steps': applied['steps'] if applied and 'steps' in applied else [],
+                'apply_job': applied['id'] if applied else None, 'sync_job': job['id'] if job else None}
+
+    def rpc_cancel(self, service, array=None, job_id=None):
+        """
+        Stops the background apply of an array: the running step is interrupted and the completed ones rolled back,
+        its 'applied' notification follows once that is done. With job_id any job is cancelled like
+        'cli.py jobs cancel' does, whichever process runs it.
+        """
+        if (array is None) == (job_id is None):
+            raise RpcError(INVALID_PARAMS, "cancel takes either array or job_id")
+        if job_id is None:
+            self.operation(service, array, "cancel").cancel()
+            return {'cancelling': array}
+        if not service.allowed("create"):
+            raise RpcError(REFUSED, "cancel needs an admin session, connect as root")
+        success, out = jobs.cancel_job(job_id)
+        service.log(out)
+        return {'cancelling': job_id if success else None, 'job_id': job_id}
+
+    def rpc_pause(self, service, array):
+        """
+        Holds the background apply of an array before its next step and freezes the new array's resync.
+        """
+        success, out = self.operation(service, array, "pause").pause()
+        service.log(out)
+        return {'paused': success, 'array': array}
+
+    def rpc_resume(self, service, array):
+        success, out = self.operation(service, array, "resume").resume()
+        service.log(out)
+        return {'resumed': success, 'array': array}
+
+    def operation(self, service, array, method):
+        # The running background apply of an array, for the methods that act on it
+        if not service.allowed("create"):
+            raise RpcError(REFUSED, f"{method} needs an admin session, connect as root")
+        operation = self.operations.get(array)
+        if operation is None:
+            raise RpcError(INVALID_PARAMS, f"No background apply of {array} is running")
+        return operation
+
+def handle_line(daemon, line, uid, channel=None):
+    """
+    Answers one request line. Returns the response document, or None for a notification (no id).
+    """
+    request_id, notification = None, False
+    try:
+        try:
+            request = json.loads(line)
+        except ValueError as e:
+            raise RpcError(PARSE_ERROR, f"Not JSON: {e}")
+        if not isinstance(request, dict) or request.get('jsonrpc') != "2.0" or not isinstance(request.get('method'), str):
+            raise RpcError(INVALID_REQUEST, "Expected {\"jsonrpc\": \"2.0\", \"method\": ..., \"params\": {...}, \"id\": ...}")
+        request_id, notification = request.get('id'), 'id' not in request
+        result = daemon.call(request['method'], request.get('params', {}), uid, channel)
+        response = {'jsonrpc': "2.0", 'id': request_id, 'result': dict(result, schema_version=output.SCHEMA_VERSION)}
+    except RpcError as e:
+        response = {'jsonrpc': "2.0", 'id': request_id, 'error': {'code': e.code, 'message': str(e), 'data': e.data}}
+    except Exception as e:
+        log.exception("Request failed")
+        response = {'jsonrpc': "2.0", 'id': request_id, 'error': {'code': -32603, 'message': f"Internal error: {e}", 'data': None}}
+    return None if notification else response
+
+class RequestHandler(socketserver.StreamRequestHandler):
+    def handle(self):
+        uid = peer_uid(self.connection)
+        channel = Channel(self.wfile)
+        try:
+            for line in self.rfile:
+                if not line.strip():
+                    continue
+                response = handle_line(self.server.rpc, line.decode("utf-8", "replace"), uid, channel)
+                if response is not None and not channel.send(response):
+                    break
+        finally:
+            channel.closed = True
+
+class Server(socketserver.ThreadingMixIn, socketserver.UnixStreamServer):
+    daemon_threads = True
+
+def serve(path=None):
+    """
+    Listens on the daemon socket until interrupted. The socket is only accessible to root and daemon_group.
+    """
+    path = path or config.get_setting('daemon_socket')
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    if os.path.exists(path):
+        # A socket left behind by a daemon that died, unless one is still answering on it
+        probe = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        try:
+            probe.connect(path)
+            raise OSError(f"Another daemon is already listening on {path}")
+        except ConnectionRefusedError:
+            os.remove(path)
+        finally:
+            probe.close()
+    server = Server(path, RequestHandler)
+    server.rpc = Daemon()
+    group = config.get_setting('daemon_group')
+    os.chmod(path, 0o660 if group else 0o600)
+    if group:
+        os.chown(path, 0, grp.getgrnam(group).gr_gid)
+    log.warning(f"LiveRAID daemon listening on {path}")
+    if notifications.has_sinks(notifications.SYSTEM_SINKS):
+        threading.Thread(target=monitor, daemon=True).start()
+    try:
+        server.serve_forever()
+    finally:
+        server.server_close()
+        os.remove(path)
+
+def serve_stdio():
+    """
+    Answers requests from stdin on stdout until stdin closes, with the session of the user running it.
+    Nothing else may write to stdout meanwhile, the log of each request travels in its response.
+    """
+    rpc = Daemon()
+    channel = Channel(sys.stdout.buffer)
+    uid = os.geteuid()
+    try:
+        for line in sys.stdin:
+            if not line.strip():
+                continue
+            response = handle_line(rpc, line, uid, channel)
+            if response is not None and not channel.send(response):
+                break
+    finally:
+        channel.closed = True
@@ -0,0 +1,155 @@
+import fnmatch
+import backend
+import units
+
+# Narrows discovered drives (backend.get_available_drives()) down to the ones meant for an array, e.g. on
+# a server with a dozen bays: 'cli.py discover --min-size 500G --transport nvme --exclude-removable', or
+# the same as one expression for 'plan --select "min-size=500G,transport=nvme,exclude-removable"'.
+
+TRANSPORTS = ("nvme", "sata", "sas", "usb", "scsi", "virtio")
+
+# Expression terms without a value
+FLAG_TERMS = ("ssd", "hdd", "blank", "exclude-boot-disk", "exclude-removable")
+VALUE_TERMS = ("min-size", "max-size", "transport", "model")
+
+class DeviceFilter:
+    """
+    Builder of a drive filter, every method narrows it further and returns the filter:
+    DeviceFilter().min_size(500 * 1000**3).transport("nvme").exclude_removable().apply(drives)
+    An empty filter matches every drive.
+    """
+
+    def __init__(self):
+        self.min_bytes = None
+        self.max_bytes = None
+        self.rotational = None
+        self.transports = []
+        self.model_globs = []
+        self.no_boot_disk = False
+        self.no_removable = False
+        self.only_blank = False
+
+    def min_size(self, size_bytes):
+        self.min_bytes = size_bytes
+        return self
+
+    def max_size(self, size_bytes):
+        self.max_bytes = size_bytes
+        return self
+
+    def ssd(self):
+        self.rotational = False
+        return self
+
+    def hdd(self):
+        self.rotational = True
+        return self
+
+    def transport(self, *names):
+        # Repeated transports are alternatives: nvme or sata
+        self.transports += [name.lower() for name in names]
+        return self
+
+    def model(self, pattern):
+        # Shell-style and case-insensitive, like the model strings are written inconsistently: 'samsung*', '*EVO*'
+        self.model_globs.append(pattern.lower())
+        return self
+
+    def blank(self):
+        # No partition table and no filesystem or RAID signature on the whole disk
+        self.only_blank = True
+        return self
+
+    def exclude_boot_disk(self):
+        self.no_boot_disk = True
+        return self
+
+    def exclude_removable(self):
+        self.no_removable = True
+        return self
+
+    def is_empty(self):
+        return self.describe() == "all drives"
+
+    def matches(self, drive, boot_disks=()):
+        if self.min_bytes is not None and drive['size_bytes'] < self.min_bytes:
+            return False
+        if self.max_bytes is not None and drive['size_bytes'] > self.max_bytes:
+            return False
+        if self.rotational is not None and drive['rotational'] != self.rotational:
+            return False
+        if self.transports and drive_transport(drive) not in self.transports:
+            return False
+        if self.model_globs and not any(fnmatch.fnmatch((drive['model'] or "").lower(), g) for g in self.model_globs):
+            return False
+        if self.only_blank and drive['has_data']:
+            return False
+        if self.no_removable and drive['removable']:
+            return False
+        if self.no_boot_disk and drive['name'] in boot_disks:
+            return False
+        return True
+
+    def apply(self, drives):
+        """
+        Returns the drives that pass the filter, in their original order.
+        """
+        boot_disks = backend.get_boot_disks() if self.no_boot_disk else ()
+        return [d for d in drives if self.matches(d, boot_disks)]
+
+    def describe(self, exact=False):
+        """
+        Returns the filter in parse()'s expression syntax, e.g. 'min-size=465.7GiB,transport=nvme,exclude-removable'.
+        Sizes are rounded for display, exact gives them in bytes so parse() rebuilds the same filter.
+        """
+        size = str if exact else lambda value: units.format_size(value).replace(' ', '')
+        terms = []
+        if self.min_bytes is not None:
+            terms.append(f"min-size={size(self.min_bytes)}")
+        if self.max_bytes is not None:
+            terms.append(f"max-size={size(self.max_bytes)}")
+        if self.rotational is not None:
+            terms.append("hdd" if self.rotational else "ssd")
+        terms += [f"transport={name}" for name in self.transports]
+        terms += [f"model={pattern}" for pattern in self.model_globs]
+        if self.only_blank:
+            terms.append("blank")
+        if self.no_boot_disk:
+            terms.append("exclude-boot-disk")
+        if self.no_removable:
+            terms.append("exclude-removable")
+        return ",".join(terms) or "all drives"
+
+def drive_transport(drive):
+    # lsblk reports NVMe as 'nvme' and SATA disks behind AHCI as 'sata', but virtio disks have no transport at all
+    if drive['transport']:
+        return drive['transport'].lower()
+    return "virtio" if drive['name'].startswith("/dev/vd") else ""
+
+def parse(expression):
+    """
+    Builds a filter from comma separated terms: min-size=SIZE, max-size=SIZE, ssd, hdd, transport=NAME,
+    model=GLOB, blank, exclude-boot-disk, exclude-removable. Raises ValueError on an unknown term.
+    """
+    drive_filter = DeviceFilter()
+    for term in filter(None, (t.strip() for t in expression.split(","))):
+        key, _, value = term.partition("=")
+        key = key.strip().lower()
+        value = value.strip()
+        if key in FLAG_TERMS and not value:
+            getattr(drive_filter, key.replace("-", "_"))()
+        elif key in VALUE_TERMS and value:
+            if key == "min-size":
+                drive_filter.min_size(units.parse_size(value))
+            elif key == "max-size":
+                drive_filter.max_size(units.parse_size(value))
+            elif key == "transport":
+                if value.lower() not in TRANSPORTS:
+                    raise ValueError(f"Unknown transport '{value}', expected one of {', '.join(TRANSPORTS)}")
+                drive_filter.transport(value)
+            else:
+                drive_filter.model(value)
+        else:
+            raise ValueError(f"Cannot read '{term}', expected one of {', '.join(FLAG_TERMS)} "
+                             f"or {', '.join(k + '=...' for k in VALUE_TERMS)}")
+    return drive_filter
@@ -0,0 +1,285 @@
+import os
+import shutil
+import subprocess
+import backend
+import config
+import policy
+
+# Tools without which LiveRAID cannot create or delete arrays at all
+REQUIRED_TOOLS = ["mdadm", "parted", "lsblk", "wipefs", "udevadm"]
+
+# mkfs helper needed by each filesystem offered in the format section
+FILESYSTEM_TOOLS = {
+    "ext4": "mkfs.ext4",
+    "btrfs": "mkfs.btrfs",
+    "xfs": "mkfs.xfs",
+    "f2fs": "mkfs.f2fs",
+    "exfat": "mkfs.exfat",
+    "ntfs": "mkfs.ntfs",
+    "vfat": "mkfs.vfat"
+}
+
+# Package providing each tool, with per-package-manager names where they differ
+TOOL_PACKAGES = {
+    "mdadm": "mdadm", "parted": "parted", "lsblk": "util-linux", "wipefs": "util-linux",
+    "udevadm": {'apt': "udev", 'dnf': "systemd-udev", 'pacman': "systemd", 'zypper': "udev"},
+    "mkfs.ext4": "e2fsprogs", "mkfs.btrfs": "btrfs-progs", "mkfs.xfs": "xfsprogs", "mkfs.f2fs": "f2fs-tools",
+    "mkfs.exfat": "exfatprogs", "mkfs.vfat": "dosfstools",
+    "mkfs.ntfs": {'apt': "ntfs-3g", 'dnf': "ntfsprogs", 'pacman': "ntfs-3g", 'zypper': "ntfsprogs"},
+    "smartctl": "smartmontools", "cryptsetup": "cryptsetup", "efibootmgr": "efibootmgr", "lvcreate": "lvm2",
+    "sgdisk": {'apt': "gdisk", 'dnf': "gdisk", 'pacman': "gptfdisk", 'zypper': "gptfdisk"},
+}
+
+# Everything the GUI can call, with what it's needed for. Controls depending on a missing
+# tool are disabled up front instead of failing when clicked.
+CAPABILITIES = [
+    ("mdadm", "create, scrub and stop arrays"),
+    ("parted", "partition array members and formatted arrays"),
+    ("smartctl", "drive link speed, temperatures and SMART self-tests"),
+    ("cryptsetup", "encrypt new arrays (LUKS) and close LUKS mappings when tearing down old disks"),
+    ("efibootmgr", "UEFI boot entries for arrays holding a bootloader"),
+    ("lvcreate", "LVM RAID volumes (raid_backend = \"lvm\")"),
+    ("sgdisk", "move the backup GPT of a partitioned array to its new end when it is grown"),
+] + [(tool, f"format arrays as {fs}") for fs, tool in FILESYSTEM_TOOLS.items()]
+
+# Install commands of the package managers bootstrap.sh supports, in detection order
+PACKAGE_MANAGERS = [
+    ('apt', "apt-get", [["apt-get", "update", "-q"]], ["apt-get", "install", "-y", "-q"]),
+    ('dnf', "dnf", [], ["dnf", "install", "-y"]),
+    ('pacman', "pacman", [["pacman", "-Sy"]], ["pacman", "-S", "--noconfirm", "--needed"]),
+    ('zypper', "zypper", [], ["zypper", "--non-interactive", "install"]),
+]
+
+# Kernel module providing each md personality the GUI offers
+PERSONALITY_MODULES = {"raid0": "raid0", "raid1": "raid1", "raid5": "raid456", "raid6": "raid456", "raid10": "raid10"}
+
+# Markers left behind by the common live ISO initramfs implementations
+LIVE_MARKERS = ["/run/live", "/run/archiso", "/run/initramfs/live", "/cdrom/casper", "/lib/live/mount"]
+
+def detect_live_environment():
+    """
+    Returns True when running from a live ISO rather than an installed system.
+    """
+    if any(os.path.exists(marker) for marker in LIVE_MARKERS):
+        return True
+    try:
+        with open('/proc/mounts', 'r') as f:
+            for line in f:
+                parts = line.split()
+                if len(parts) >= 3 and parts[1] == '/' and parts[2] in ('overlay', 'squashfs', 'aufs'):
+                    return True
+    except Exception:
+        pass
+    return False
+
+def get_distro():
+    """
+    Returns the distro profile from /etc/os-release, e.g. {'id': 'ubuntu', 'name': 'Ubuntu 24.04 LTS'}.
+    """
+    info = {}
+    try:
+        with open('/etc/os-release', 'r') as f:
+            for line in f:
+                if '=' in line:
+                    key, value = line.strip().split('=', 1)
+                    info[key] = value.strip('"')
+    except Exception:
+        pass
+    return {'id': info.get('ID', 'unknown'), 'name': info.get('PRETTY_NAME', 'Unknown distribution')}
+
+def get_tool_version(tool):
+    """
+    Returns the first line of `<tool> --version` (mdadm prints it on stderr), or None if it isn't installed.
+    """
+    if shutil.which(tool) is None:
+        return None
+    try:
+        result = subprocess.run([tool, '--version'], capture_output=True, text=True, timeout=5)
+        lines = (result.stdout or result.stderr).strip().split('\n')
+        return lines[0] if lines and lines[0] else "installed"
+    except Exception:
+        return "installed"
+
+def get_md_personalities():
+    """
+    Returns the RAID levels the running kernel supports, e.g. ['raid0', 'raid1', 'raid10'].
+    Returns None when the md driver isn't loaded (/proc/mdstat missing).
+    """
+    try:
+        with open('/proc/mdstat', 'r') as f:
+            for line in f:
+                if line.startswith('Personalities'):
+                    return [p.strip('[]') for p in line.split(':', 1)[1].split()]
+        return []
+    except Exception:
+        return None
+
+def detect_package_manager():
+    """
+    Returns: ('apt', [['apt-get', 'update', '-q']], ['apt-get', 'install', '-y', '-q']) or None
+    """
+    for name, binary, prepare, install in PACKAGE_MANAGERS:
+        if shutil.which(binary):
+            return name, prepare, install
+    return None
+
+def install_fix(tools):
+    """
+    Returns the fix installing the packages that provide the given tools, or None without a known package manager.
+    """
+    manager = detect_package_manager()
+    if manager is None:
+        return None
+    name, prepare, install = manager
+    packages = []
+    for tool in tools:
+        package = TOOL_PACKAGES.get(tool, tool)
+        package = package.get(name) if isinstance(package, dict) else package
+        if package and package not in packages:
+            packages.append(package)
+    return {'description': f"Install {', '.join(packages)} with {install[0]}", 'commands': prepare + [install + packages],
+            'confirm': True, 'tools': list(tools)}
+
+def install_hint(tool):
+    """
+    Returns the command installing a tool on this distro, e.g. 'apt-get install -y -q smartmontools',
+    or a plain instruction when no supported package manager is found.
+    """
+    fix = install_fix([tool])
+    if fix is None:
+        package = TOOL_PACKAGES.get(tool, tool)
+        return f"install {package if isinstance(package, str) else tool} with your package manager"
+    return " ".join(fix['commands'][-1])
+
+def get_capabilities():
+    """
+    Checks which of the CAPABILITIES tools are installed.
+    Returns: {'smartctl': {'available': False, 'purpose': '...', 'hint': 'apt-get install -y -q smartmontools'}, ...}
+    where hint is None for installed tools.
+    """
+    capabilities = {}
+    for tool, purpose in CAPABILITIES:
+        available = shutil.which(tool) is not None
+        capabilities[tool] = {'available': available, 'purpose': purpose, 'hint': None if available else install_hint(tool)}
+    return capabilities
+
+def get_monitor_unit():
+    """
+    Returns the systemd unit running `mdadm --monitor` on this distro and whether it is active,
+    e.g. ('mdmonitor.service', False), or None without systemd or without such a unit.
+    """
+    if shutil.which("systemctl") is None:
+        return None
+    for unit in ("mdmonitor.service", "mdadm.service"):
+        try:
+            result = subprocess.run(["systemctl", "show", "-p", "LoadState,ActiveState", unit], capture_output=True, text=True, timeout=5)
+        except Exception:
+            return None
+        state = dict(line.split("=", 1) for line in result.stdout.splitlines() if "=" in line)
+        if state.get('LoadState') == "loaded":
+            return unit, state.get('ActiveState') == "active"
+    return None
+
+def run_checks():
+    """
+    Runs every environment check.
+    Problems that can be repaired safely carry a fix: {'description': '...', 'commands': [[...]], 'confirm': True}
+    where confirm marks fixes that change the installed system (packages, services).
+    Returns: [{'name': 'Privileges', 'status': 'ok' | 'warn' | 'error', 'detail': 'running as root', 'fix': None}, ...]
+    """
+    checks = []
+
+    def add(name, status, detail, fix=None):
+        checks.append({'name': name, 'status': status, 'detail': detail, 'fix': fix})
+
+    add("Environment", "ok", "live ISO session" if detect_live_environment() else "installed system")
+    add("Distribution", "ok", get_distro()['name'])
+
+    if os.geteuid() == 0:
+        add("Privileges", "ok", "running as root")
+    else:
+        add("Privileges", "error", "not running as root, disk operations will fail (use sudo)")
+
+    for tool in REQUIRED_TOOLS:
+        version = get_tool_version(tool)
+        if version:
+            add(tool, "ok", version)
+        else:
+            add(tool, "error", "missing, install it with bootstrap.sh or your package manager", install_fix([tool]))
+
+    missing_fs = [fs for fs, tool in FILESYSTEM_TOOLS.items() if shutil.which(tool) is None]
+    if missing_fs:
+        add("Filesystem tools", "warn", "cannot format as: " + ", ".join(missing_fs),
+            install_fix([FILESYSTEM_TOOLS[fs] for fs in missing_fs]))
+    else:
+        add("Filesystem tools", "ok", "all mkfs helpers installed")
+
+    personalities = get_md_personalities()
+    if personalities is None:
+        add("Kernel md driver", "error", "not loaded (/proc/mdstat missing), try: modprobe md_mod",
+            {'description': "Load the md driver", 'commands': [["modprobe", "md_mod"]], 'confirm': False})
+    else:
+        modules = sorted({module for level, module in PERSONALITY_MODULES.items() if level not in personalities})
+        if not personalities:
+            add("Kernel md driver", "warn", "loaded, no RAID personalities registered yet (loaded on first use)",
+                {'description': f"Load {', '.join(modules)}", 'commands': [["modprobe", "-a"] + modules], 'confirm': False})
+        else:
+            add("Kernel md driver", "ok", "personalities: " + " ".join(personalities))
+
+    monitor = get_monitor_unit()
+    if monitor and not monitor[1]:
+        # mdadm --monitor refuses to start without MAILADDR or PROGRAM in mdadm.conf
+        add("Array monitoring", "warn", f"{monitor[0]} is not running, failed disks go unnoticed",
+            {'description': f"Enable and start {monitor[0]}", 'commands': [["systemctl", "enable", "--now", monitor[0]]], 'confirm': True})
+    elif monitor:
+        add("Array monitoring", "ok", f"{monitor[0]} running")
+
+    # Only reported: swap that is already running doesn't stop LiveRAID from building new arrays
+    action, violations = policy.check_running()
+    if violations:
+        add("Storage policy", "warn", "redundant-swap: " + "; ".join(violations) + ", move swap to a redundant array")
+    elif action != "off":
+        add("Storage policy", "ok", "no swap on non-redundant arrays")
+
+    # Jobs, backups, change records and the health history all live here
+    if os.path.isdir(config.STATE_DIR):
+        add("State directory", "ok", config.STATE_DIR)
+    else:
+        add("State directory", "warn", f"{config.STATE_DIR} does not exist yet",
+            {'description': f"Create {config.STATE_DIR}", 'commands': [["mkdir", "-p", config.STATE_DIR]], 'confirm': False})
+
+    return checks
+
+def collect_fixes(checks):
+    """
+    Returns the fixes of every failed check, with all package installs merged into one
+    so the package index is refreshed only once.
+    """
+    fixes, tools = [], []
+    for check in checks:
+        fix = check['fix']
+        if check['status'] == "ok" or not fix:
+            continue
+        if 'tools' in fix:
+            tools.extend(fix['tools'])
+        else:
+            fixes.append(fix)
+    if tools:
+        fixes.insert(0, install_fix(tools))
+    return fixes
+
+def apply_fix(fix):
+    """
+    Runs the commands of a check's fix in order.
+    Returns: (success, log_str)
+    """
+    logs = []
+    for cmd in fix['commands']:
+        success, out = backend.run_command(cmd)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+    return True, "".join(logs)
+
+def has_blocking_errors(checks):
+    return any(c['status'] == 'error' for c in checks)
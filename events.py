@@ -0,0 +1,162 @@
+import fcntl
+import json
+import logging
+import os
+import socket
+import time
+import config
+import raidbackend
+
+log = logging.getLogger("liveraid")
+
+# What LiveRAID did to the arrays it manages, as an append-only event log from which their current state
+# is derived (state()). Every process (GUI, CLI, the daemon) appends under an exclusive lock and numbers
+# its event with the next sequence number, so concurrent frontends never overwrite each other and can
+# catch up on what the others did with read_events(since=<last seq they saw>).
+
+EVENTS_FILE = os.path.join(config.STATE_DIR, "events.jsonl")
+
+# Event kinds and the fields their data carries
+KINDS = {
+    'ArrayCreated': ("level", "members", "spares", "backend", "uuid"),
+    'MemberReplaced': ("old", "new"),
+    'Grown': ("members", "size_bytes"),
+    'Scrubbed': ("action", "mismatches", "member_errors"),
+    'Destroyed': ("uuid",),
+}
+
+def _read(f):
+    events = []
+    for line in f:
+        try:
+            events.append(json.loads(line))
+        except ValueError:
+            # A line cut short by a crash mid-write, everything after it is still readable
+            continue
+    return events
+
+def record(kind, array_name, uuid=None, **data):
+    """
+    Appends an event, uuid being that of the array (md UUID, or the filesystem's where there is none). Never raises,
+    the operation it records already happened.
+    Returns: {'seq': 12, 'timestamp': 1700000000, 'host': 'nas', 'kind': 'ArrayCreated', 'array': '/dev/md0', 'uuid': '...', 'data': {...}}
+    """
+    data = dict(data, uuid=uuid)
+    event = {'seq': None, 'timestamp': int(time.time()), 'host': socket.gethostname(), 'kind': kind, 'array': array_name,
+             'uuid': uuid, 'data': {field: data.get(field) for field in KINDS[kind]}}
+    try:
+        os.makedirs(os.path.dirname(EVENTS_FILE), exist_ok=True)
+        with open(EVENTS_FILE, 'a+') as f:
+            fcntl.flock(f, fcntl.LOCK_EX)
+            f.seek(0)
+            last = _read(f)
+            event['seq'] = (last[-1]['seq'] + 1) if last else 1
+            f.seek(0, os.SEEK_END)
+            f.write(json.dumps(event) + "\n")
+            f.flush()
+            os.fsync(f.fileno())
+    except Exception as e:
+        log.error(f"Failed to record {kind} of {array_name}: {e}")
+    return event
+
+def read_events(since=0, array_name=None):
+    """
+    Returns the events after sequence number since, oldest first, optionally only those of one array.
+    """
+    try:
+        with open(EVENTS_FILE, 'r') as f:
+            fcntl.flock(f, fcntl.LOCK_SH)
+            events = _read(f)
+    except FileNotFoundError:
+        return []
+    return [e for e in events if e['seq'] > since and (array_name is None or e['array'] == array_name)]
+
+def new_record(name, uuid, seq):
+    return {'array': name, 'uuid': uuid, 'level': None, 'backend': None, 'members': [], 'spares': [], 'created': None,
+            'size_bytes': None, 'last_scrub': None, 'replacements': 0, 'destroyed': None, 'seq': seq}
+
+def find_key(arrays, event):
+    """
+    The state record an event belongs to: by the array's UUID, for events without one (recorded before
+    events carried it) the newest record of that name not yet destroyed.
+    """
+    uuid = event.get('uuid') or event['data'].get('uuid')
+    if uuid:
+        return uuid
+    live = [key for key, arr in arrays.items() if arr['array'] == event['array'] and not arr['destroyed']]
+    return live[-1] if live else event['array']
+
+def apply_event(arrays, event):
+    """
+    Folds one event into the state of the arrays (see state()). Arrays are keyed by UUID, so an array assembled
+    under another name stays one record and a name reused after a Destroyed starts a new one.
+    """
+    data = event['data']
+    key = find_key(arrays, event)
+    if event['kind'] == "ArrayCreated":
+        arrays.pop(key, None)
+        arrays[key] = dict(new_record(event['array'], data['uuid'], event['seq']), level=data['level'], backend=data['backend'],
+                           members=list(data['members'] or []), spares=list(data['spares'] or []), created=event['timestamp'])
+        return arrays
+    current = arrays.setdefault(key, new_record(event['array'], event.get('uuid'), event['seq']))
+    # The name it was last seen under
+    current['array'] = event['array']
+    current['seq'] = event['seq']
+    if event['kind'] == "MemberReplaced":
+        if data['old'] in current['members']:
+            current['members'][current['members'].index(data['old'])] = data['new']
+        elif data['new'] not in current['members']:
+            current['members'].append(data['new'])
+        current['replacements'] += 1
+    elif event['kind'] == "Grown":
+        current['members'] = list(data['members'] or current['members'])
+        current['size_bytes'] = data['size_bytes']
+    elif event['kind'] == "Scrubbed":
+        current['last_scrub'] = {'timestamp': event['timestamp'], 'action': data['action'], 'mismatches': data['mismatches']}
+    elif event['kind'] == "Destroyed":
+        current['destroyed'] = event['timestamp']
+    return arrays
+
+def state(include_destroyed=False):
+    """
+    The current state of every array LiveRAID created, derived from the whole event log and keyed by UUID
+    (by name for arrays recorded without one).
+    Returns: {'a1b2c3d4:...': {'array': '/dev/md0', 'uuid': '...', 'level': '5', 'members': [...], 'last_scrub': {...}, 'destroyed': None, 'seq': 7, ...}}
+    """
+    arrays = {}
+    for event in read_events():
+        apply_event(arrays, event)
+    return {key: arr for key, arr in arrays.items() if include_destroyed or not arr['destroyed']}
+
+def compensation(event):
+    """
+    What reverses an event, derived from the event alone: the commands to run, or why it can't be reversed.
+    Returns: {'commands': [['mdadm', '--stop', '/dev/md0'], ...], 'note': '...'}
+    """
+    data, name = event['data'], event['array']
+    if event['kind'] == "ArrayCreated":
+        kind = data['backend'] or "mdadm"
+        if kind not in raidbackend.BACKENDS:
+            return {'commands': [], 'note': f"{name} was created by the unknown backend '{kind}', reverse it by hand."}
+        # The backend's own rollback of its create commands, last command first
+        undo = raidbackend.BACKENDS[kind].undo_commands({'array': name, 'spares': list(data['spares'] or [])}, list(data['members'] or []))
+        commands = [cmd for step in reversed(undo) for cmd in step]
+        notes = {'mdadm': f"Destroys {name} and everything on it (cli.py destroy {name} does the same and updates mdadm.conf).",
+                 'lvm': f"Removes the logical volume {name}, its volume group and physical volumes, and everything on them.",
+                 'btrfs': f"Wipes the btrfs filesystem {name} spans from every member, and everything on it."}
+        return {'commands': commands, 'note': notes[kind]}
+    if event['kind'] == "MemberReplaced":
+        commands = [["mdadm", name, "--fail", data['new'], "--remove", data['new']]]
+        if data['old']:
+            commands.append(["mdadm", name, "--add", data['old']])
+        return {'commands': commands, 'note': "Puts the old member back and rebuilds onto it, only sensible if it was healthy."}
+    if event['kind'] == "Grown":
+        return {'commands': [], 'note': "md arrays can't be shrunk back safely once the filesystem has grown, restore from backup instead."}
+    if event['kind'] == "Destroyed":
+        return {'commands': [], 'note': f"The superblocks are gone; 'cli.py recover-recreate {data['uuid'] or name}' can re-create it from its recorded geometry."}
+    return {'commands': [], 'note': "Nothing to reverse, a scrub only reads (a repair rewrote mismatched stripes in place)."}
+
+def describe(event):
+    when = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(event['timestamp']))
+    details = ", ".join(f"{key}={value}" for key, value in event['data'].items() if value not in (None, [], {}))
+    return f"{event['seq']:>5}  {when}  {event['kind']:<15} {event['array']:<12} {details}"
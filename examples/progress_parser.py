@@ -0,0 +1,53 @@
+#!/usr/bin/env python3
+import subprocess
+import sys
+
+# Reads the --output progress-proto lines of a LiveRAID command, the way a Cockpit plugin or another web UI
+# would (there with cockpit.spawn() and a stream callback), and draws a text progress bar per phase:
+#   examples/progress_parser.py apply plan.json --yes
+
+PROTOCOL = 1
+
+def parse_line(line):
+    """
+    Returns: ('phase', ('step-2', 'create /dev/md0')), ('percent', 40.0), ('result', 0), ('version', 1),
+    or None for a line this parser doesn't know (newer protocol versions only ever add line types).
+    """
+    kind, _, rest = line.rstrip("\n").partition(" ")
+    if kind == "LIVERAID-PROGRESS":
+        return 'version', int(rest)
+    if kind == "PHASE":
+        phase, _, description = rest.partition(" ")
+        return 'phase', (phase, description)
+    if kind == "PERCENT":
+        return 'percent', float(rest)
+    if kind == "RESULT":
+        # "failed" when the command crashed, there is no exit status then
+        return 'result', int(rest) if rest.lstrip("-").isdigit() else rest
+    return None
+
+def main():
+    cmd = [sys.executable, "cli.py", "--output", "progress-proto"] + sys.argv[1:]
+    # The log arrives on stderr and is passed through as it is
+    process = subprocess.Popen(cmd, stdout=subprocess.PIPE, text=True)
+    result = None
+    for line in process.stdout:
+        parsed = parse_line(line)
+        if parsed is None:
+            continue
+        kind, value = parsed
+        if kind == "version" and value != PROTOCOL:
+            print(f"Protocol version {value}, this parser knows {PROTOCOL}", file=sys.stderr)
+        elif kind == "phase":
+            print(f"\n{value[1] or value[0]}")
+        elif kind == "percent":
+            filled = int(value / 5)
+            print(f"\r[{'#' * filled}{'.' * (20 - filled)}] {value:5.1f}%", end="", flush=True)
+        elif kind == "result":
+            result = value
+    process.wait()
+    print(f"\n{'Done' if result == 0 else 'Failed'} (exit status {result if result is not None else process.returncode})")
+    return process.returncode
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,330 @@
+import threading
+import time
+import backend
+import jobs
+import runner
+import script
+
+# Multi-step operations (applying a plan) run as a list of reversible steps, so a failure halfway
+# doesn't leave a half-built array behind: the steps that already completed are undone in reverse
+# order, and whatever can't be undone automatically is printed as a recovery plan.
+
+# How often a paused run looks whether it was resumed, in seconds
+PAUSE_POLL = 1
+
+# What a step did to the system, as a configuration-management run reports it: a step whose check() finds its
+# part in place already is skipped (unchanged), one that finds it there but different brings it in line (updated)
+CREATED = "created"
+UPDATED = "updated"
+UNCHANGED = "unchanged"
+
+class JobHandle:
+    """
+    Control over a running Executor from another thread (a GUI button, the daemon) or a signal handler
+    (Ctrl-C in `cli.py apply`). cancel() stops the command of the running step, the completed steps are
+    rolled back. pause() holds the run before its next step (the running one finishes first) and freezes
+    the resync of the arrays it created (see watch()), resume() lets both go on where they stopped.
+    """
+
+    def __init__(self):
+        self.cancel_event = threading.Event()
+        self.running_event = threading.Event()
+        self.running_event.set()
+        self.arrays = []
+        self.lock = threading.Lock()
+
+    @property
+    def cancelled(self):
+        return self.cancel_event.is_set()
+
+    @property
+    def paused(self):
+        return not self.running_event.is_set()
+
+    def watch(self, array_name):
+        """
+        Adds an array whose resync pause() freezes, frozen right away when the run is paused already.
+        """
+        with self.lock:
+            self.arrays.append(array_name)
+            if self.paused:
+                backend.pause_sync(array_name)
+
+    def cancel(self):
+        self.cancel_event.set()
+        # A paused run wakes up to roll back, with the resync of its arrays going again
+        self.resume()
+
+    def pause(self):
+        """
+        Returns: (success, log_str) of freezing the resyncs
+        """
+        with self.lock:
+            if self.paused or self.cancelled:
+                return True, ""
+            self.running_event.clear()
+            results = [backend.pause_sync(array_name) for array_name in self.arrays]
+        return all(success for success, _ in results), "".join(out for _, out in results)
+
+    def resume(self):
+        with self.lock:
+            if not self.paused:
+                return True, ""
+            self.running_event.set()
+            results = [backend.resume_sync(array_name) for array_name in self.arrays]
+        return all(success for success, _ in results), "".join(out for _, out in results)
+
+    def finish(self):
+        """
+        Called when the run ended: a pause that came during its last step has no next step to hold before, and
+        would leave the resyncs frozen with nothing left to resume them. Returns: (success, log_str) of resume()
+        """
+        return self.resume()
+
+    def wait_while_paused(self):
+        """
+        Blocks while the run is paused. Returns False when it was cancelled meanwhile.
+        """
+        while self.paused and not self.cancelled:
+            self.running_event.wait(PAUSE_POLL)
+        return not self.cancelled
+
+class Step:
+    """
+    One operation of an Executor run.
+    run() and undo() log their own output and return True/False. recovery lists what undo() does as
+    commands, printed for doing it by hand when undo() fails. Steps without undo need no reversal
+    (e.g. a format, which goes away with the array underneath it).
+    check() looks at the system before the step runs and returns CREATED, UPDATED or UNCHANGED, or None (logging
+    why) when what is there already can't be reconciled without destroying it, which fails the step. Steps without
+    check always run and count as created. run() and undo() find what check() returned in outcome.
+    """
+
+    def __init__(self, name, run, undo=None, recovery=(), check=None):
+        self.name = name
+        self.run = run
+        self.undo = undo
+        self.recovery = list(recovery)
+        self.check = check
+        self.outcome = None
+
+class Executor:
+    """
+    Runs steps in order and records each one in a job (if given), so the progress of an apply can be
+    followed with `cli.py jobs show <id>`. On the first failure every completed step is rolled back.
+    results holds the outcome of every step: [{'name': 'create /dev/md0', 'state': 'done'}], where state
+    is pending, running, done, failed, cancelled, rolled-back or left (its undo failed, see the recovery plan).
+    Steps that are done also have an outcome: created, updated or unchanged (see Step). Unchanged steps
+    changed nothing, so there is nothing of them to roll back either.
+    on_progress(event) is called on every state change, so frontends can draw a progress bar or per-step spinners:
+    {'step': 2, 'steps': 5, 'name': 'create /dev/md0', 'state': 'done', 'percent': 40.0}
+    Long steps (a disk overwrite) also report their own progress through report(), as 'running' events
+    with 'step_percent'; follow_sync() reports an initial sync the same way.
+    runner (a runner.CommandRunner) runs the commands of the steps and their rollback instead of backend.RUNNER.
+    cancelled() is asked before every step and stops the command a step is running (see runner.cancellation()),
+    together with the cancellation of the calling thread (a background.Operation). A cancelled run rolls back
+    like a failed one, the rollback itself can't be cancelled; was_cancelled tells the two apart afterwards.
+    handle is the JobHandle that cancels or pauses the run (one of its own if not given); `cli.py jobs pause <id>`
+    pauses it through its job as well.
+    """
+
+    def __init__(self, log, job=None, on_progress=None, runner=None, cancelled=None, handle=None):
+        self.log = log
+        self.job = job
+        self.on_progress = on_progress
+        self.runner = runner
+        self.cancelled = cancelled
+        self.handle = handle or JobHandle()
+        self.was_cancelled = False
+        self.completed = []
+        self.steps = []
+        self.results = []
+        self.current = None
+
+    def _set_state(self, step, state):
+        index = self.steps.index(step)
+        self.results[index]['state'] = state
+        if self.job:
+            jobs.update_job(self.job['id'], steps=self.results)
+        if self.on_progress:
+            finished = sum(1 for r in self.results if r['state'] == 'done')
+            event = {'step': index + 1, 'steps': len(self.steps), 'name': step.name, 'state': state,
+                     'percent': 100.0 * finished / len(self.steps)}
+            if state == 'done':
+                event['outcome'] = step.outcome
+            self.on_progress(event)
+
+    def run(self, steps):
+        """
+        Returns True when every step succeeded. On failure the completed steps have been rolled back
+        (or a recovery plan was logged) when it returns False.
+        """
+        cancellation = runner.cancellation(lambda: self.handle.cancelled or bool(self.cancelled and self.cancelled()))
+        previous = backend.RUNNER
+        if self.runner is not None:
+            backend.RUNNER = self.runner
+        try:
+            with cancellation:
+                return self._run(steps)
+        finally:
+            self.log(self.handle.finish()[1])
+            if self.runner is not None:
+                backend.RUNNER = previous
+
+    def _run(self, steps):
+        self.completed = []
+        self.was_cancelled = False
+        self.steps = list(steps)
+        self.results = [{'name': step.name, 'state': 'pending'} for step in steps]
+        for i, step in enumerate(steps, 1):
+            self._hold(i, step)
+            if runner.cancel_requested():
+                self.log(f"Cancelled before step {i}/{len(steps)}: {step.name}\n")
+                self.was_cancelled = True
+                self._rollback()
+                return False
+            self.log(f"Step {i}/{len(steps)}: {step.name}\n")
+            if self.job:
+                jobs.update_job(self.job['id'], percent=100.0 * (i - 1) / len(steps), message=step.name)
+            self._set_state(step, 'running')
+            self.current = step
+            script.begin_step(step.name)
+            step.outcome = step.check() if step.check else CREATED
+            if step.outcome == UNCHANGED:
+                self.log(f"Unchanged: {step.name}\n")
+                self._finish(step)
+                continue
+            if step.outcome is None or not step.run():
+                # A step stopped by the cancellation fails like any other, only the log and its state differ
+                self.was_cancelled = runner.cancel_requested()
+                if self.was_cancelled:
+                    self.log(f"Cancelled during {step.name}.\n")
+                    self._set_state(step, 'cancelled')
+                else:
+                    self.log(f"ERROR: {step.name} failed.\n")
+                    self._set_state(step, 'failed')
+                self._rollback()
+                return False
+            self._finish(step)
+            self.completed.append(step)
+        if self.job:
+            jobs.update_job(self.job['id'], percent=100.0, message="done")
+        return True
+
+    def _finish(self, step):
+        self.results[self.steps.index(step)]['outcome'] = step.outcome
+        self._set_state(step, 'done')
+
+    def outcomes(self):
+        """
+        Returns how many steps of the last run had each outcome: {'created': 2, 'updated': 1, 'unchanged': 4}
+        """
+        counts = dict.fromkeys((CREATED, UPDATED, UNCHANGED), 0)
+        for result in self.results:
+            if result.get('outcome'):
+                counts[result['outcome']] += 1
+        return counts
+
+    def _job_field(self, field, default=None):
+        job = jobs.get_job(self.job['id'], refresh=False) if self.job else None
+        return (job or {}).get(field, default)
+
+    def _hold(self, i, step):
+        """
+        Waits before step i while the run is paused, through the handle or its job (`cli.py jobs pause|resume`).
+        """
+        if self._job_field('pause_requested') and not self.handle.paused:
+            self.log(self.handle.pause()[1])
+        if not self.handle.paused:
+            return
+        self.log(f"Paused before step {i}/{len(self.steps)}: {step.name}\n")
+        if self.job:
+            jobs.update_job(self.job['id'], paused=True, message=f"paused before {step.name}")
+        while self.handle.paused and not runner.cancel_requested():
+            time.sleep(PAUSE_POLL)
+            if not self._job_field('paused', True):
+                self.log(self.handle.resume()[1])
+        if self.job:
+            jobs.update_job(self.job['id'], paused=False, pause_requested=False)
+        if runner.cancel_requested():
+            # Cancelled while paused, the resyncs go on as the rollback starts
+            self.handle.resume()
+        else:
+            self.log("Resumed.\n")
+
+    def report(self, step_percent):
+        """
+        Progress (0-100) of the running step, for steps that take long enough to need their own bar.
+        """
+        if self.current is None:
+            return
+        index = self.steps.index(self.current)
+        finished = sum(1 for r in self.results if r['state'] == 'done')
+        percent = 100.0 * (finished + step_percent / 100.0) / len(self.steps)
+        if self.job:
+            jobs.update_job(self.job['id'], percent=percent)
+        if self.on_progress:
+            self.on_progress({'step': index + 1, 'steps': len(self.steps), 'name': self.current.name, 'state': 'running',
+                              'percent': percent, 'step_percent': step_percent})
+
+    def follow_sync(self, array_name, timeout=None):
+        """
+        Follows the initial sync of an array the steps created as one more row after them, its step_percent read
+        from backend.get_sync_progress() on every poll. Nothing is rolled back when the wait ends early: the row is
+        left 'cancelled' once the timeout (in seconds) ran out or the run was cancelled, and the sync goes on.
+        Returns True when the array is in sync.
+        """
+        step = Step(f"initial sync of {array_name}", None)
+        self.steps.append(step)
+        self.results.append({'name': step.name, 'state': 'pending'})
+        self._set_state(step, 'running')
+        self.current = step
+        previous = backend.RUNNER
+        if self.runner is not None:
+            backend.RUNNER = self.runner
+        try:
+            synced, out = backend.wait_for_sync(array_name, timeout, lambda progress: self.report(progress['percent']),
+                                                cancelled=lambda: self.handle.cancelled or bool(self.cancelled and self.cancelled()))
+        finally:
+            if self.runner is not None:
+                backend.RUNNER = previous
+        self.log(out)
+        self._set_state(step, 'done' if synced else 'cancelled')
+        return synced
+
+    def _rollback(self):
+        # The completed steps are undone all the same when the run was cancelled
+        with runner.cancellation(None):
+            return self.rollback()
+
+    def rollback(self):
+        """
+        Undoes the completed steps, last one first. Stops at the first undo that fails and logs
+        the recovery commands of everything still left in place.
+        Returns True when everything was undone.
+        """
+        if not self.completed:
+            return True
+        self.log(f"Rolling back {len(self.completed)} completed step(s)...\n")
+        remaining = list(reversed(self.completed))
+        while remaining:
+            step = remaining[0]
+            if step.undo is not None:
+                self.log(f"Undoing: {step.name}\n")
+                if not step.undo():
+                    self.log(f"ERROR: Could not undo {step.name}. Recovery plan, run these by hand:\n{self.recovery_plan(remaining)}")
+                    for left in remaining:
+                        self._set_state(left, 'left')
+                    return False
+            self._set_state(step, 'rolled-back')
+            remaining.pop(0)
+        self.completed = []
+        self.log("Rollback complete, the completed steps were undone.\n")
+        return True
+
+    def recovery_plan(self, steps):
+        lines = []
+        for step in steps:
+            for cmd in step.recovery:
+                lines.append(f"  {len(lines) + 1}. {cmd}  # undo {step.name}")
+        return "\n".join(lines) + "\n" if lines else "  (nothing recorded, check the log above)\n"
@@ -0,0 +1,366 @@
+import json
+import logging
+import os
+import subprocess
+import time
+import backend
+import config
+import migrate
+import units
+
+log = logging.getLogger("liveraid")
+
+# Disk-by-disk upgrades in progress, one file per array, so an interrupted upgrade (reboot,
+# closed terminal) picks up where it stopped
+UPGRADES_DIR = os.path.join(config.STATE_DIR, "upgrades")
+
+# Sustained rate assumed for rebuilds, reshapes and copies. md can go faster, but on a live system
+# with other I/O, large HDD arrays rarely average more, so estimates err on the long side.
+ESTIMATED_RATE = 120 * 1000**2
+
+# How each filesystem is grown into a larger device; {device} is the block device, {mount_point} where it is mounted
+GROW_COMMANDS = {
+    'ext4': ["resize2fs", "{device}"],
+    'xfs': ["xfs_growfs", "{mount_point}"],
+    'btrfs': ["btrfs", "filesystem", "resize", "max", "{mount_point}"],
+    'f2fs': ["resize.f2fs", "{device}"],
+    'ntfs': ["ntfsresize", "--force", "{device}"],
+}
+
+# Filesystems that can only be grown while mounted (xfs, btrfs) or only while unmounted (f2fs, ntfs)
+GROW_ONLINE = ['ext4', 'xfs', 'btrfs']
+GROW_OFFLINE = ['f2fs', 'ntfs']
+
+# Layers between an array and its filesystem that are looked through: a LUKS container or an LVM physical volume
+CONTAINER_TYPES = ('crypto_LUKS', 'LVM2_member')
+
+def get_filesystem(array_name):
+    """
+    Returns where the filesystem of an array lives: on its first partition, or directly on the array, and in
+    either case possibly inside a LUKS container (crypt, its /dev/mapper device) or an LVM volume (lvm).
+    table is the array's partition table type when it is partitioned.
+    Returns: {'device': '/dev/mapper/md0_crypt', 'partitioned': True, 'table': 'gpt', 'crypt': '/dev/mapper/md0_crypt',
+              'lvm': False, 'fstype': 'ext4', 'mount_point': '/srv/data'} or None
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,TYPE,FSTYPE,MOUNTPOINTS,PTTYPE', array_name],
+                                capture_output=True, text=True, check=True)
+        nodes = json.loads(result.stdout).get('blockdevices', [])
+    except Exception:
+        return None
+
+    def walk(node, stack):
+        stack = stack + [node]
+        if node.get('fstype') and node['fstype'] not in CONTAINER_TYPES:
+            return node, stack
+        for child in node.get('children', []):
+            found = walk(child, stack)
+            if found:
+                return found
+        return None
+
+    found = walk(nodes[0], []) if nodes else None
+    if found is None:
+        return None
+    node, stack = found
+    mountpoints = [m for m in node.get('mountpoints', []) if m]
+    crypt = next((n['name'] for n in stack if n.get('type') == "crypt"), None)
+    partitioned = any(n.get('type') == "part" for n in stack)
+    return {'device': node['name'], 'partitioned': partitioned, 'table': nodes[0].get('pttype') if partitioned else None,
+            'crypt': crypt, 'lvm': any(n.get('type') == "lvm" for n in stack), 'fstype': node['fstype'],
+            'mount_point': mountpoints[0] if mountpoints else None}
+
+def filesystem_grow_steps(array_name):
+    """
+    Returns the commands growing an array's partition, LUKS container and filesystem into the array's new size.
+    Returns: ([['sgdisk', '-e', '/dev/md0'], ['parted', '-s', '/dev/md0', 'resizepart', '1', '100%'], ['cryptsetup', 'resize', 'md0_crypt'],
+               ['resize2fs', '/dev/mapper/md0_crypt']], error or None)
+    """
+    fs = get_filesystem(array_name)
+    if fs is None:
+        return [], None
+    steps = []
+    if fs['partitioned']:
+        if fs['table'] == "gpt":
+            # The backup GPT is still where the array used to end, parted -s refuses the table until it is moved
+            steps.append(["sgdisk", "-e", array_name])
+        steps.append(["parted", "-s", array_name, "resizepart", "1", "100%"])
+    if fs['lvm']:
+        return steps, f"{fs['device']} is an LVM volume, grow it with 'pvresize' and 'lvextend -r' to use the new space."
+    if fs['crypt']:
+        # An open container keeps its old size until told, the filesystem inside only sees that
+        steps.append(["cryptsetup", "resize", os.path.basename(fs['crypt'])])
+    if fs['fstype'] not in GROW_COMMANDS:
+        return steps, f"{fs['fstype']} can't be grown in place, copy the data off and reformat to use the new space."
+    if fs['fstype'] in GROW_ONLINE and not fs['mount_point'] and "{mount_point}" in " ".join(GROW_COMMANDS[fs['fstype']]):
+        return steps, f"{fs['fstype']} is grown while mounted, mount {fs['device']} first."
+    if fs['fstype'] in GROW_OFFLINE and fs['mount_point']:
+        return steps, f"{fs['fstype']} is grown while unmounted, unmount {fs['mount_point']} first."
+    steps.append([arg.format(device=fs['device'], mount_point=fs['mount_point']) for arg in GROW_COMMANDS[fs['fstype']]])
+    return steps, None
+
+def current_layout(array_name):
+    """
+    Returns: {'level': '5', 'members': ['/dev/sdb', ...], 'component_bytes': 1000202043392, 'capacity_bytes': 2000404086784,
+              'used_bytes': 812345678901}
+    """
+    level = (backend.read_md_attribute(array_name, "level") or "").replace("raid", "")
+    component_kib = backend.read_md_attribute(array_name, "component_size")
+    members = [m['device'] for m in backend.get_array_members(array_name) if m['device'] and m['role'] == "active"]
+    component = int(component_kib) * 1024 if component_kib and component_kib.isdigit() else 0
+    fs = get_filesystem(array_name)
+    return {
+        'level': level,
+        'members': members,
+        'component_bytes': component,
+        'capacity_bytes': backend.estimate_array_capacity(level, [{'size_bytes': component}] * len(members)),
+        'used_bytes': migrate.get_used_bytes(fs['mount_point']) if fs and fs['mount_point'] else None,
+    }
+
+def expansion_paths(array_name, candidates):
+    """
+    Works out how an array could gain capacity from a set of new disks (drive dicts as from the inventory):
+      grow    - add them as members and reshape the array onto more disks
+      replace - swap every member for a larger disk one at a time, then grow into the new size
+      migrate - build a new array on them and copy the data across
+    Returns: [{'path': 'grow', 'title': '...', 'possible': True, 'reason': None, 'capacity_bytes': ..., 'gain_bytes': ...,
+               'duration_seconds': 21600, 'steps': ['mdadm ...'], 'risks': ['...']}]
+    """
+    layout = current_layout(array_name)
+    level, members, component = layout['level'], layout['members'], layout['component_bytes']
+    fs = get_filesystem(array_name)
+    fs_steps, fs_error = filesystem_grow_steps(array_name)
+    fs_steps = [" ".join(cmd) for cmd in fs_steps]
+    paths = []
+
+    def path(kind, title, capacity=0, duration=0, steps=(), risks=(), reason=None):
+        paths.append({'path': kind, 'title': title, 'possible': reason is None, 'reason': reason,
+                      'capacity_bytes': capacity, 'gain_bytes': max(0, capacity - layout['capacity_bytes']),
+                      'duration_seconds': duration, 'steps': list(steps), 'risks': list(risks)})
+
+    # Grow in place: more members of the current size
+    fitting = [d for d in candidates if d['size_bytes'] >= component]
+    count = len(members) + len(fitting)
+    if level == "1":
+        path("grow", "Add members", reason="RAID 1 keeps the size of one member, more disks only add copies.")
+    elif level not in ("0", "5", "6", "10"):
+        path("grow", "Add members", reason=f"Reshaping RAID {level or '?'} isn't supported.")
+    elif not fitting:
+        path("grow", "Add members", reason=f"No candidate is at least as large as the current members ({units.format_size(component)}).")
+    else:
+        risks = ["A reshape moves every stripe; a crash or power loss during it relies on the reshape backup to resume.",
+                 "The array stays usable but noticeably slower until the reshape finishes."]
+        if level == "0":
+            risks.append("RAID 0 is converted to RAID 4 for the reshape and back, it still has no redundancy at any point.")
+        if level == "10":
+            risks.append("RAID 10 reshapes need a recent kernel and mdadm and only work with the default near layout.")
+        if fs_error:
+            risks.append(fs_error)
+        path("grow", "Add members", backend.estimate_array_capacity(level, [{'size_bytes': component}] * count),
+             count * component / ESTIMATED_RATE,
+             [f"mdadm --manage {array_name} --add {' '.join(d['name'] for d in fitting)}",
+              f"mdadm --grow {array_name} --raid-devices={count}",
+              f"wait for the reshape of {array_name}"] + fs_steps,
+             risks)
+
+    # Replace every member with a larger disk
+    larger = sorted((d for d in candidates if d['size_bytes'] > component), key=lambda d: d['size_bytes'], reverse=True)
+    if level == "0":
+        path("replace", "Replace members with larger disks", reason="RAID 0 has no redundancy to rebuild a replaced member from.")
+    elif len(larger) < len(members):
+        path("replace", "Replace members with larger disks",
+             reason=f"Needs {len(members)} disks larger than {units.format_size(component)}, {len(larger)} available.")
+    else:
+        chosen = larger[:len(members)]
+        new_component = min(d['size_bytes'] for d in chosen)
+        steps = []
+        for old, new in zip(members, chosen):
+            steps += [f"mdadm --manage {array_name} --add {new['name']}",
+                      f"mdadm --manage {array_name} --replace {old} --with {new['name']}",
+                      f"wait for {new['name']} to be rebuilt, check health, then remove {old}"]
+        steps += [f"mdadm --grow {array_name} --size=max"] + fs_steps
+        risks = ["Needs a free port for each new disk while its predecessor is copied (mdadm --replace keeps full redundancy).",
+                 f"{len(members)} sequential rebuilds put every old disk under sustained load; a weak one may fail along the way.",
+                 "The new space is synchronised after the final grow, before it has redundancy."]
+        if fs_error:
+            risks.append(fs_error)
+        path("replace", "Replace members with larger disks",
+             backend.estimate_array_capacity(level, [{'size_bytes': new_component}] * len(members)),
+             (len(members) * component + (new_component - component)) / ESTIMATED_RATE, steps, risks)
+
+    # New array + data migration
+    minimum = backend.MIN_DEVICES.get(level, 2)
+    capacity = backend.estimate_array_capacity(level, candidates) if len(candidates) >= minimum else 0
+    used = layout['used_bytes']
+    if len(candidates) < minimum:
+        path("migrate", "New array and copy", reason=f"RAID {level} needs at least {minimum} new disks, {len(candidates)} available.")
+    elif used is not None and capacity < used:
+        path("migrate", "New array and copy", reason=f"A RAID {level} on the candidates holds {units.format_size(capacity)}, "
+                                                     f"less than the {units.format_size(used)} in use.")
+    else:
+        path("migrate", "New array and copy", capacity, (capacity + (used or layout['capacity_bytes'])) / ESTIMATED_RATE,
+             [f"cli.py create {level} {' '.join(d['name'] for d in candidates)} --array <new array>",
+              f"cli.py format <new array> --filesystem {fs['fstype'] if fs else 'ext4'}",
+              f"cli.py migrate-data {fs['mount_point'] if fs and fs['mount_point'] else array_name} <new array's mount point>",
+              f"point fstab and services at the new array, then retire {array_name}"],
+             ["Lowest risk: the old array stays untouched until the copy is verified.",
+              "All new disks must be attached at the same time as the old ones.",
+              "Services writing to the old array must be stopped for the final copy pass."])
+    return paths
+
+def _upgrade_path(array_name):
+    return os.path.join(UPGRADES_DIR, f"{os.path.basename(os.path.realpath(array_name))}.json")
+
+def new_upgrade(array_name, new_devices, identities=None):
+    """
+    Pairs the current members with the larger disks replacing them, in order.
+    Returns: {'array': '/dev/md0', 'phase': 'replace', 'pairs': [{'old': '/dev/sdb', 'new': '/dev/sde', 'state': 'pending',
+              'identity': {...}}], 'started': 1700000000}
+    """
+    members = current_layout(array_name)['members']
+    return {
+        'array': array_name,
+        'phase': "replace",
+        'pairs': [{'old': old, 'new': new, 'state': "pending", 'identity': (identities or {}).get(new)}
+                  for old, new in zip(members, new_devices)],
+        'started': int(time.time()),
+    }
+
+def save_upgrade(upgrade):
+    try:
+        os.makedirs(UPGRADES_DIR, exist_ok=True)
+        tmp_path = _upgrade_path(upgrade['array']) + ".tmp"
+        with open(tmp_path, 'w') as f:
+            json.dump(upgrade, f, indent=2)
+        os.replace(tmp_path, _upgrade_path(upgrade['array']))
+    except Exception as e:
+        log.error(f"Failed to save the upgrade of {upgrade['array']}: {e}")
+
+def load_upgrade(array_name):
+    try:
+        with open(_upgrade_path(array_name), 'r') as f:
+            return json.load(f)
+    except Exception:
+        return None
+
+def clear_upgrade(array_name):
+    try:
+        os.remove(_upgrade_path(array_name))
+    except FileNotFoundError:
+        pass
+
+# Levels 'cli.py grow' reshapes onto more members. RAID 0 is left to expansion_paths() (it goes through RAID 4),
+# RAID 1 only gains copies.
+GROW_LEVELS = ("5", "6", "10")
+
+def _grow_path(array_name):
+    return os.path.join(UPGRADES_DIR, f"{os.path.basename(os.path.realpath(array_name))}-grow.json")
+
+def new_grow(array_name, new_devices, backup_file=None, identities=None):
+    """
+    A grow plan: add new_devices as spares, reshape the array onto all of them, then grow its filesystem.
+    Returns: {'array': '/dev/md0', 'level': '5', 'members': ['/dev/sdb', ...], 'add': [{'device': '/dev/sde', 'identity': {...}}],
+              'raid_devices': 4, 'backup_file': None, 'capacity_bytes': ..., 'new_capacity_bytes': ..., 'phase': 'add', 'started': 1700000000}
+    """
+    layout = current_layout(array_name)
+    count = len(layout['members']) + len(new_devices)
+    return {
+        'array': array_name,
+        'level': layout['level'],
+        'members': layout['members'],
+        'add': [{'device': dev, 'identity': (identities or {}).get(dev)} for dev in new_devices],
+        'raid_devices': count,
+        'backup_file': backup_file,
+        'capacity_bytes': layout['capacity_bytes'],
+        'new_capacity_bytes': backend.estimate_array_capacity(layout['level'], [{'size_bytes': layout['component_bytes']}] * count),
+        'phase': "add",
+        'started': int(time.time()),
+    }
+
+def validate_grow(grow, drives):
+    """
+    Checks that the reshape of a grow plan can run: level, array health, the new disks and the backup file.
+    drives is the inventory ({'/dev/sde': drive dict}) the new disks must come from.
+    Returns: {'errors': ['...'], 'warnings': ['...']}
+    """
+    errors, warnings = [], []
+    array_name, level = grow['array'], grow['level']
+    if level not in GROW_LEVELS:
+        errors.append(f"{array_name} is RAID {level or '?'}, only RAID {', '.join(GROW_LEVELS)} can be reshaped onto more disks"
+                      + (" (RAID 1 keeps the size of one member)." if level == "1" else "."))
+    degraded = backend.read_md_attribute(array_name, "degraded")
+    if degraded not in (None, "0"):
+        errors.append(f"{array_name} is degraded ({degraded} missing), rebuild it before reshaping.")
+    progress = backend.get_sync_progress(array_name)
+    if progress:
+        errors.append(f"{array_name} is busy with a {progress['action']} ({progress['percent']:.1f}%), wait for it to finish.")
+    if level == "10":
+        # md's layout number: near copies in the low byte, far copies in the next, bit 16 for offset
+        layout = backend.read_md_attribute(array_name, "layout")
+        if layout and layout.isdigit() and (int(layout) >> 8 & 0xff > 1 or int(layout) & 0x10000):
+            errors.append(f"{array_name} uses a far or offset RAID 10 layout, md only reshapes the near layout.")
+    if not grow['add']:
+        errors.append("Give at least one disk to add.")
+    component = current_layout(array_name)['component_bytes']
+    for dev in (a['device'] for a in grow['add']):
+        if dev in grow['members']:
+            errors.append(f"{dev} is already a member of {array_name}.")
+        elif dev not in drives:
+            errors.append(f"{dev} not available (mounted, in use by an array, or missing).")
+        elif drives[dev]['size_bytes'] < component:
+            errors.append(f"{dev} ({units.format_size(drives[dev]['size_bytes'])}) is smaller than the current members "
+                          f"({units.format_size(component)}).")
+        elif drives[dev]['size_bytes'] > component * 1.1:
+            warnings.append(f"{dev} is {units.format_size(drives[dev]['size_bytes'] - component)} larger than the members, "
+                            "the extra space stays unused.")
+    fs = get_filesystem(array_name)
+    if grow['backup_file']:
+        # The backup protects the critical section of the reshape, it must survive the array misbehaving
+        if fs and fs['mount_point'] and os.path.realpath(grow['backup_file']).startswith(os.path.join(fs['mount_point'], "")):
+            errors.append(f"The backup file {grow['backup_file']} is on {array_name} itself, put it on another disk.")
+        elif os.path.exists(grow['backup_file']):
+            errors.append(f"The backup file {grow['backup_file']} already exists, mdadm needs a new one.")
+    elif level in ("5", "6"):
+        warnings.append("No --backup-file: mdadm relocates the data offset instead, which needs free space before the data "
+                        "(metadata 1.2 arrays created by recent mdadm have it).")
+    steps, fs_error = filesystem_grow_steps(array_name)
+    if fs_error:
+        warnings.append(fs_error)
+    if fs is None:
+        warnings.append(f"No filesystem found on {array_name}, only the array is grown.")
+    return {'errors': errors, 'warnings': warnings}
+
+def grow_steps(grow):
+    """
+    Returns the commands of a grow plan, for showing them before it runs.
+    Returns: ['mdadm --manage /dev/md0 --add /dev/sde', 'mdadm --grow /dev/md0 --raid-devices=4', 'wait for the reshape of /dev/md0', 'resize2fs /dev/md0']
+    """
+    array_name = grow['array']
+    backup = f" --backup-file={grow['backup_file']}" if grow['backup_file'] else ""
+    return ([f"mdadm --manage {array_name} --add {' '.join(a['device'] for a in grow['add'])}",
+             f"mdadm --grow {array_name} --raid-devices={grow['raid_devices']}{backup}",
+             f"wait for the reshape of {array_name}"]
+            + [" ".join(cmd) for cmd in filesystem_grow_steps(array_name)[0]])
+
+def save_grow(grow):
+    try:
+        os.makedirs(UPGRADES_DIR, exist_ok=True)
+        tmp_path = _grow_path(grow['array']) + ".tmp"
+        with open(tmp_path, 'w') as f:
+            json.dump(grow, f, indent=2)
+        os.replace(tmp_path, _grow_path(grow['array']))
+    except Exception as e:
+        log.error(f"Failed to save the grow of {grow['array']}: {e}")
+
+def load_grow(array_name):
+    try:
+        with open(_grow_path(array_name), 'r') as f:
+            return json.load(f)
+    except Exception:
+        return None
+
+def clear_grow(array_name):
+    try:
+        os.remove(_grow_path(array_name))
+    except FileNotFoundError:
+        pass
@@ -0,0 +1,268 @@
+import difflib
+import json
+import os
+import shlex
+import shutil
+import subprocess
+import time
+import uuid
+import backend
+import config
+import script
+
+# Debian/Ubuntu keep mdadm.conf in its own directory, Fedora/Arch/SUSE directly in /etc
+MDADM_CONF_CANDIDATES = ["/etc/mdadm/mdadm.conf", "/etc/mdadm.conf"]
+
+# Persists raid456 module options across reboots (the initramfs copy is refreshed by the distro hooks)
+MODPROBE_RAID456_CONF = "/etc/modprobe.d/liveraid-raid456.conf"
+
+# Stands in for an array's UUID in dry runs, mdadm assigns it at creation
+PLACEHOLDER_UUID = "<assigned at creation>"
+
+FSTAB_PATH = "/etc/fstab"
+CRYPTTAB_PATH = "/etc/crypttab"
+
+# Set by snapshot.take_snapshot() so backups made during a run land in that run's bundle
+current_run_id = None
+
+# Every managed edit is kept as a change record so it can be undone on its own
+CHANGES_DIR = os.path.join(config.STATE_DIR, "changes")
+
+ANSI = {'+': "\033[32m", '-': "\033[31m", '@': "\033[36m", 'reset': "\033[0m"}
+
+def read_file(path):
+    try:
+        with open(path, 'r') as f:
+            return f.read()
+    except FileNotFoundError:
+        return ""
+
+def render_diff(path, old_text, new_text, color=False):
+    """
+    Returns a unified diff between the current and proposed contents of a file ('' if unchanged).
+    """
+    lines = difflib.unified_diff(
+        old_text.splitlines(keepends=True), new_text.splitlines(keepends=True),
+        fromfile=f"{path} (current)", tofile=f"{path} (proposed)"
+    )
+    out = []
+    for line in lines:
+        if not line.endswith("\n"):
+            line += "\n"
+        if color and line[0] in "+-@" and not line.startswith(("+++", "---")):
+            line = f"{ANSI[line[0]]}{line.rstrip(chr(10))}{ANSI['reset']}\n"
+        out.append(line)
+    return "".join(out)
+
+def backup_file(path):
+    """
+    Copies a file into STATE_DIR/backups/<run-id or timestamp>/ before it is modified. Returns the backup path.
+    """
+    stamp = current_run_id or time.strftime("%Y%m%d-%H%M%S")
+    backup_path = os.path.join(config.STATE_DIR, "backups", stamp, path.lstrip("/"))
+    os.makedirs(os.path.dirname(backup_path), exist_ok=True)
+    shutil.copy2(path, backup_path)
+    return backup_path
+
+def write_file(path, new_text, dry_run=None, undoes=None):
+    """
+    Replaces a file's contents atomically and records the edit as a change (undoes: ID of the change it reverts).
+    In dry-run mode nothing is written and the log holds the diff.
+    """
+    if dry_run is None:
+        dry_run = backend.DRY_RUN
+
+    old_text = read_file(path)
+    if old_text == new_text:
+        return True, f"{path} already up to date.\n"
+
+    if dry_run:
+        script.record_write(path, new_text)
+        return True, f"[DRY RUN] Would modify {path}:\n" + render_diff(path, old_text, new_text)
+
+    logs = []
+    try:
+        if os.path.exists(path) and config.get_setting('backup_existing_configs'):
+            logs.append(f"Backed up {path} to {backup_file(path)}\n")
+
+        os.makedirs(os.path.dirname(path), exist_ok=True)
+        tmp_path = path + ".liveraid.tmp"
+        with open(tmp_path, 'w') as f:
+            f.write(new_text)
+        if os.path.exists(path):
+            os.chmod(tmp_path, os.stat(path).st_mode & 0o7777)
+        os.replace(tmp_path, path)
+        change = record_change(path, old_text, new_text, undoes)
+        logs.append(f"Updated {path} (change {change['id']})\n" if change else f"Updated {path}\n")
+        return True, "".join(logs)
+    except Exception as e:
+        logs.append(f"Failed to write {path}\nError: {e}\n")
+        return False, "".join(logs)
+
+def _save_change(change):
+    os.makedirs(CHANGES_DIR, exist_ok=True)
+    with open(os.path.join(CHANGES_DIR, f"{change['id']}.json"), 'w') as f:
+        json.dump(change, f, indent=2)
+
+def record_change(path, old_text, new_text, undoes=None):
+    """
+    Stores one edit of a managed file. Returns the change record, or None if it couldn't be saved.
+    """
+    change = {
+        'id': uuid.uuid4().hex[:8],
+        'path': path,
+        'timestamp': time.time(),
+        'run_id': current_run_id,
+        'before': old_text,
+        'after': new_text,
+        'undoes': undoes,
+        'undone_by': None
+    }
+    try:
+        _save_change(change)
+        original = get_change(undoes) if undoes else None
+        if original:
+            original['undone_by'] = change['id']
+            _save_change(original)
+    except Exception:
+        return None
+    return change
+
+def get_change(change_id):
+    try:
+        with open(os.path.join(CHANGES_DIR, f"{change_id}.json"), 'r') as f:
+            return json.load(f)
+    except Exception:
+        return None
+
+def list_changes():
+    """
+    Returns every recorded edit, newest first.
+    """
+    try:
+        names = os.listdir(CHANGES_DIR)
+    except FileNotFoundError:
+        return []
+    changes = [get_change(name[:-5]) for name in names if name.endswith(".json")]
+    return sorted([c for c in changes if c], key=lambda c: c['timestamp'], reverse=True)
+
+def reverse_apply(before, after, current):
+    """
+    Reverts the edit before -> after within the current text of a file, leaving later edits to other
+    lines alone. Each changed block must still be present exactly once next to its original
+    neighbouring lines. Returns the reverted text, or None if the edit no longer applies.
+    """
+    old_lines = before.splitlines(keepends=True)
+    new_lines = after.splitlines(keepends=True)
+    result = current.splitlines(keepends=True)
+    opcodes = [op for op in difflib.SequenceMatcher(None, old_lines, new_lines).get_opcodes() if op[0] != 'equal']
+
+    for tag, i1, i2, j1, j2 in reversed(opcodes):
+        block = new_lines[j1:j2]
+        pre = new_lines[j1 - 1] if j1 > 0 else None
+        post = new_lines[j2] if j2 < len(new_lines) else None
+        matches = []
+        for pos in range(len(result) - len(block) + 1):
+            if result[pos:pos + len(block)] != block:
+                continue
+            end = pos + len(block)
+            pre_ok = pos == 0 if pre is None else pos > 0 and result[pos - 1] == pre
+            post_ok = end == len(result) if post is None else end < len(result) and result[end] == post
+            # Changed lines only need one untouched neighbour, re-inserting removed lines needs both
+            if (pre_ok and post_ok) or (block and (pre_ok or post_ok)):
+                matches.append(pos)
+        if len(matches) != 1:
+            return None
+        result[matches[0]:matches[0] + len(block)] = old_lines[i1:i2]
+    return "".join(result)
+
+def undo_change(change_id, dry_run=None):
+    """
+    Reverts a single recorded edit, if the lines it touched haven't been changed again since.
+    The undo is itself recorded as a change, so it can be undone too.
+    """
+    change = get_change(change_id)
+    if change is None:
+        return False, f"Unknown change: {change_id}\n"
+    if change.get('undone_by'):
+        return False, f"Change {change_id} was already undone by change {change['undone_by']}.\n"
+
+    current = read_file(change['path'])
+    reverted = change['before'] if current == change['after'] else reverse_apply(change['before'], change['after'], current)
+    if reverted is None:
+        return False, f"Change {change_id} no longer applies, {change['path']} was edited at the same place since.\n"
+
+    return write_file(change['path'], reverted, dry_run, undoes=change_id)
+
+def get_mdadm_conf_path():
+    for path in MDADM_CONF_CANDIDATES:
+        if os.path.exists(path):
+            return path
+    return MDADM_CONF_CANDIDATES[0] if os.path.isdir("/etc/mdadm") else MDADM_CONF_CANDIDATES[1]
+
+def get_array_conf_line(array_name):
+    """
+    Returns the ARRAY line mdadm would use for an array, e.g. 'ARRAY /dev/md0 metadata=1.2 UUID=...'.
+    """
+    try:
+        return backend.query(['mdadm', '--detail', '--brief', array_name]).strip().split('\n')[0]
+    except Exception:
+        # Arrays that don't exist yet (dry run) only get their UUID at creation time, a script reads the line then
+        line = f"ARRAY {array_name} UUID={PLACEHOLDER_UUID}"
+        script.substitute(line, f"$(mdadm --detail --brief {shlex.quote(array_name)})")
+        return line
+
+def render_mdadm_conf(array_name, current_text, conf_line=None):
+    # Replace any previous definition of the same array so repeated runs stay idempotent
+    kept = [line for line in current_text.splitlines() if not line.startswith(f"ARRAY {array_name} ")]
+    kept.append(conf_line or get_array_conf_line(array_name))
+    return "\n".join(kept) + "\n"
+
+def mdadm_conf_diff(array_name, color=False):
+    path = get_mdadm_conf_path()
+    current = read_file(path)
+    return render_diff(path, current, render_mdadm_conf(array_name, current), color)
+
+def update_mdadm_conf(array_name, dry_run=None):
+    """
+    Records an array in mdadm.conf so it is assembled under the same name on the next boot.
+    """
+    path = get_mdadm_conf_path()
+    conf_line = get_array_conf_line(array_name)
+    if PLACEHOLDER_UUID in conf_line and not backend.nothing_created(dry_run):
+        return False, f"ERROR: Could not read the UUID of {array_name} from mdadm, not recording it in {path} without one.\n"
+    return write_file(path, render_mdadm_conf(array_name, read_file(path), conf_line), dry_run)
+
+def remove_array_from_mdadm_conf(uuid, dry_run=None):
+    """
+    Drops the ARRAY line of a destroyed array so it isn't searched for at boot.
+    """
+    path = get_mdadm_conf_path()
+    current = read_file(path)
+    kept = [line for line in current.splitlines() if not (line.startswith("ARRAY ") and f"UUID={uuid}" in line)]
+    new_text = "\n".join(kept) + "\n" if kept else ""
+    if new_text == current or not current:
+        return True, f"No {path} entry for UUID {uuid}.\n"
+    return write_file(path, new_text, dry_run)
+
+def get_filesystem_uuid(device_path):
+    try:
+        result = subprocess.run(['blkid', '-s', 'UUID', '-o', 'value', device_path], capture_output=True, text=True, check=True)
+        return result.stdout.strip() or None
+    except Exception:
+        return None
+
+def render_crypttab(name, device_path, keyfile, discard, current_text):
+    # One entry per mapping name, so re-running a plan replaces its earlier line
+    uuid = get_filesystem_uuid(device_path) or "<assigned by luksFormat>"
+    kept = [line for line in current_text.splitlines()
+            if not (line.split() and not line.lstrip().startswith("#") and line.split()[0] == name)]
+    kept.append(f"{name} UUID={uuid} {keyfile or 'none'} {'luks,discard' if discard else 'luks'}")
+    return "\n".join(kept) + "\n"
+
+def update_crypttab(name, device_path, keyfile=None, discard=False, dry_run=None):
+    """
+    Opens a LUKS container at boot by adding (or replacing) its /etc/crypttab entry, referenced by UUID.
+    Without a keyfile the passphrase is asked for during boot.
+    """
+    return write_file(CRYPTTAB_PATH, render_crypttab(name, device_path, keyfile, discard, read_file(CRYPTTAB_PATH)), dry_run)
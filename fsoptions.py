@@ -0,0 +1,158 @@
+import units
+
+# Filesystem tuning beyond the mkfs defaults, given as 'plan --fs-opt key=value' or 'format --fs-opt' and kept
+# in plans as fs_options. The stripe geometry of ext4 (stride/stripe_width) and XFS (su/sw) is derived from the
+# array's chunk size and number of data disks, so allocation and metadata line up with whole stripes and a
+# parity RAID isn't pushed into read-modify-write cycles; a value given explicitly wins over the derived one.
+
+# Keys each filesystem accepts, with what they take
+OPTIONS = {
+    'ext4': {'inode_size': "inode size in bytes: 128, 256, 512, ... 4096",
+             'reserved': "percentage of blocks reserved for root, 0-50 (mkfs default 5)",
+             'stride': "chunk size in filesystem blocks (derived)",
+             'stripe_width': "stride times the number of data disks (derived)"},
+    'xfs': {'inode_size': "inode size in bytes: 256, 512, 1024 or 2048",
+            'su': "stripe unit, the chunk size, e.g. 512K (derived)",
+            'sw': "stripe width in data disks (derived)"},
+    'btrfs': {'data_profile': "profile of data block groups, e.g. single",
+              'metadata_profile': "profile of metadata block groups, e.g. dup"},
+}
+
+BTRFS_PROFILES = ("single", "dup", "raid0", "raid1", "raid1c3", "raid1c4", "raid10", "raid5", "raid6")
+
+# What mdadm uses when a plan leaves the chunk size at Default, the LVM backend passes it to lvcreate explicitly
+DEFAULT_CHUNK_KIB = 512
+
+# ext4 counts stride and stripe_width in blocks of this size (mke2fs' default above 512 MiB)
+EXT4_BLOCK_SIZE = 4096
+
+def parse_assignment(text):
+    """
+    Parses 'inode_size=512' from the command line. Returns: ('inode_size', '512')
+    """
+    key, sep, value = text.partition("=")
+    if not sep or not key.strip() or not value.strip():
+        raise ValueError(f"'{text}' is not key=value, e.g. inode_size=512")
+    return key.strip().replace("-", "_"), value.strip()
+
+def data_disks(level, count):
+    """
+    The members holding distinct data in every stripe, None where there are no stripes (RAID 1).
+    RAID 10 assumes mdadm's two copies.
+    """
+    return {'0': count, '5': count - 1, '6': count - 2, '10': count // 2}.get(str(level))
+
+def chunk_kib(chunk):
+    # A plan's chunk ('Default', '64K', '1M') in KiB
+    if chunk in (None, "Default"):
+        return DEFAULT_CHUNK_KIB
+    try:
+        return units.parse_size(chunk) // 1024
+    except ValueError:
+        return None
+
+def derive(fs_type, level, count, chunk):
+    """
+    The stripe geometry of a filesystem on an array, chunk in KiB.
+    Returns: {'stride': 128, 'stripe_width': 384} for ext4, {'su': '512K', 'sw': 3} for XFS, {} where nothing applies
+    """
+    disks = data_disks(level, count)
+    if not disks or disks < 1 or not chunk:
+        return {}
+    if fs_type == "ext4":
+        stride = chunk * 1024 // EXT4_BLOCK_SIZE
+        return {'stride': stride, 'stripe_width': stride * disks} if stride else {}
+    if fs_type == "xfs":
+        return {'su': f"{chunk}K", 'sw': disks}
+    return {}
+
+def _int(value):
+    try:
+        return int(value)
+    except (TypeError, ValueError):
+        return None
+
+def check(fs_type, options):
+    """
+    Returns a message for every option the filesystem doesn't take or whose value is out of range.
+    """
+    messages = []
+    allowed = OPTIONS.get(fs_type, {})
+    for key, value in (options or {}).items():
+        if key not in allowed:
+            takes = ", ".join(allowed) if allowed else "none"
+            messages.append(f"{fs_type} has no option '{key}' (it takes: {takes}).")
+        elif key == "inode_size":
+            size = _int(value)
+            low = 128 if fs_type == "ext4" else 256
+            high = 4096 if fs_type == "ext4" else 2048
+            if size is None or size < low or size > high or size & (size - 1):
+                messages.append(f"inode_size must be a power of two from {low} to {high}, not '{value}'.")
+        elif key == "reserved":
+            percent = _int(value)
+            if percent is None or not 0 <= percent <= 50:
+                messages.append(f"reserved is a percentage from 0 to 50, not '{value}'.")
+        elif key in ("stride", "stripe_width", "sw"):
+            if _int(value) is None or _int(value) < 1:
+                messages.append(f"{key} must be a whole number of at least 1, not '{value}'.")
+        elif key == "su":
+            try:
+                su = units.parse_size(value)
+            except ValueError:
+                su = 0
+            if su < 4096 or su % 4096:
+                messages.append(f"su must be a multiple of 4K, e.g. 512K, not '{value}'.")
+        elif key in ("data_profile", "metadata_profile") and value not in BTRFS_PROFILES:
+            messages.append(f"{key} must be one of {', '.join(BTRFS_PROFILES)}, not '{value}'.")
+    if fs_type == "ext4" and options and 'stride' in options and 'stripe_width' in options:
+        stride, width = _int(options['stride']), _int(options['stripe_width'])
+        if stride and width and width % stride:
+            messages.append(f"stripe_width ({width}) must be a multiple of stride ({stride}).")
+    return messages
+
+def resolve(fs_type, options, level, count, chunk):
+    """
+    The derived stripe geometry with the explicit options on top. chunk is a plan's chunk ('Default', '64K').
+    Returns: {'stride': 128, 'stripe_width': 384, 'reserved': '1'}
+    """
+    return dict(derive(fs_type, level, count, chunk_kib(chunk)), **(options or {}))
+
+def mkfs_arguments(fs_type, options):
+    """
+    Returns: ['-I', '512', '-m', '1', '-E', 'stride=128,stripe_width=384'] for ext4, ['-d', 'su=512k,sw=3'] for XFS
+    """
+    options = options or {}
+    args = []
+    if fs_type == "ext4":
+        if 'inode_size' in options:
+            args += ["-I", str(options['inode_size'])]
+        if 'reserved' in options:
+            args += ["-m", str(options['reserved'])]
+        extended = [f"{key}={options[key]}" for key in ('stride', 'stripe_width') if key in options]
+        if extended:
+            args += ["-E", ",".join(extended)]
+    elif fs_type == "xfs":
+        if 'inode_size' in options:
+            args += ["-i", f"size={options['inode_size']}"]
+        data = [f"{key}={str(options[key]).lower()}" for key in ('su', 'sw') if key in options]
+        if data:
+            args += ["-d", ",".join(data)]
+    elif fs_type == "btrfs":
+        if 'data_profile' in options:
+            args += ["-d", options['data_profile']]
+        if 'metadata_profile' in options:
+            args += ["-m", options['metadata_profile']]
+    return args
+
+def plan_arguments(plan):
+    """
+    The tuning flags a plan's filesystem is made with, followed by its raw mkfs_options (which win where they overlap).
+    """
+    tuning = resolve(plan['filesystem'], plan['fs_options'], plan['level'], len(plan['devices']), plan['chunk'])
+    return mkfs_arguments(plan['filesystem'], tuning) + plan['mkfs_options']
+
+def describe(fs_type, options):
+    """
+    Returns: 'stride=128, stripe_width=384' or ''
+    """
+    return ", ".join(f"{key}={value}" for key, value in (options or {}).items() if key in OPTIONS.get(fs_type, {}))
@@ -0,0 +1,140 @@
+import os
+import shlex
+import subprocess
+import backend
+import files
+import luks
+import script
+
+# /etc/fstab entries of LiveRAID arrays. Each one is preceded by a marker comment naming its array, so
+# re-running add() updates the entry in place instead of appending a duplicate, and remove() only ever
+# takes out lines LiveRAID wrote. Entries refer to the filesystem UUID: md device numbers change when
+# the disks move to another machine, UUIDs don't.
+
+MARKER = "# managed by liveraid:"
+
+# Stands in for the UUID in dry runs, before mkfs has assigned one
+PLACEHOLDER_SPEC = "UUID=<assigned by mkfs>"
+
+FIELDS = ("spec", "file", "vfstype", "mntops", "freq", "passno")
+
+# What mount assumes for fields left out at the end of a line
+FIELD_DEFAULTS = ("", "", "auto", "defaults", "0", "0")
+
+def parse_line(line):
+    """
+    Returns the fields of an fstab entry as a dict, or None for comments and blank lines.
+    """
+    fields = line.split()
+    if not fields or fields[0].startswith("#") or len(fields) < 2:
+        return None
+    return dict(zip(FIELDS, fields[:6] + list(FIELD_DEFAULTS[len(fields):])))
+
+class FstabEditor:
+    """
+    Edits the text of an fstab, keeping every unmanaged line and comment as it is:
+    editor = FstabEditor(files.read_file(FSTAB_PATH)); editor.set_entry('/dev/md0', ...); editor.text()
+    """
+
+    def __init__(self, text):
+        self.lines = text.splitlines()
+
+    def entries(self):
+        """
+        Returns: [{'spec': 'UUID=...', 'file': '/srv', 'vfstype': 'ext4', 'mntops': 'defaults', 'freq': '0',
+                   'passno': '2', 'managed': '/dev/md0', 'line': 4}], managed is None for entries LiveRAID didn't write
+        """
+        found = []
+        for i, line in enumerate(self.lines):
+            entry = parse_line(line)
+            if entry is None:
+                continue
+            previous = self.lines[i - 1].strip() if i else ""
+            entry['managed'] = previous[len(MARKER):].strip() if previous.startswith(MARKER) else None
+            entry['line'] = i
+            found.append(entry)
+        return found
+
+    def _drop(self, entries):
+        # The entries and their marker comments, from the bottom up so the line numbers stay valid
+        for entry in sorted(entries, key=lambda e: e['line'], reverse=True):
+            start = entry['line'] - 1 if entry['managed'] else entry['line']
+            del self.lines[start:entry['line'] + 1]
+
+    def set_entry(self, array_name, spec, mount_point, fs_type, options="defaults", passno=2):
+        """
+        Adds the entry of an array, replacing its earlier entry and any other managed line mounting on the same
+        mount point or the same filesystem (those would fight over it at boot).
+        Returns the position of the new entry. Raises ValueError when a line LiveRAID didn't write clashes,
+        that one is the admin's to remove.
+        """
+        clashing = [e for e in self.entries()
+                    if e['managed'] == array_name or e['file'] == mount_point or (e['spec'] == spec and spec != PLACEHOLDER_SPEC)]
+        unmanaged = [e for e in clashing if not e['managed']]
+        if unmanaged:
+            raise ValueError(f"line {unmanaged[0]['line'] + 1} already mounts {unmanaged[0]['spec']} on {unmanaged[0]['file']}, "
+                             f"LiveRAID didn't write it and leaves it alone, remove it first")
+        position = min((e['line'] - (1 if e['managed'] else 0) for e in clashing), default=len(self.lines))
+        self._drop(clashing)
+        position = min(position, len(self.lines))
+        self.lines[position:position] = [f"{MARKER} {array_name}", f"{spec} {mount_point} {fs_type} {options} 0 {passno}"]
+        return position + 1
+
+    def remove(self, target):
+        """
+        Removes the managed entries of an array or mount point. Returns how many were removed.
+        """
+        matching = [e for e in self.entries() if e['managed'] and target in (e['managed'], e['file'])]
+        self._drop(matching)
+        return len(matching)
+
+    def text(self):
+        return "\n".join(self.lines) + "\n" if self.lines else ""
+
+def get_filesystem_type(device_path):
+    try:
+        result = subprocess.run(['blkid', '-s', 'TYPE', '-o', 'value', device_path], capture_output=True, text=True, check=True)
+        return result.stdout.strip() or None
+    except Exception:
+        return None
+
+def find_volume(array_name):
+    """
+    Returns the device holding the filesystem of a formatted array: the open LUKS mapping, the array's
+    partition, or the array itself.
+    """
+    for device in (luks.mapper_device(array_name), backend.partition_device(array_name)):
+        if os.path.exists(device):
+            return device
+    return array_name
+
+def add_entry(array_name, device_path, mount_point, fs_type=None, options="defaults", dry_run=None, uuid=None):
+    """
+    Mounts a filesystem at boot by adding (or updating) the managed fstab entry of its array, referenced by UUID
+    (uuid when the caller has read it already). Only dry runs and simulations write the placeholder in its place.
+    Returns: (success, log_str), in dry-run mode the log holds the diff.
+    """
+    fs_type = fs_type or get_filesystem_type(device_path)
+    if fs_type is None:
+        return False, f"ERROR: {device_path} holds no filesystem, format it first.\n"
+    uuid = uuid or files.get_filesystem_uuid(device_path)
+    if uuid is None and not backend.nothing_created(dry_run):
+        return False, f"ERROR: Could not read the filesystem UUID of {device_path}, not adding it to {files.FSTAB_PATH} without one.\n"
+    if uuid is None:
+        script.substitute(PLACEHOLDER_SPEC, f"UUID=$(blkid -s UUID -o value {shlex.quote(device_path)})")
+    editor = FstabEditor(files.read_file(files.FSTAB_PATH))
+    try:
+        editor.set_entry(array_name, f"UUID={uuid}" if uuid else PLACEHOLDER_SPEC, mount_point, fs_type, options)
+    except ValueError as e:
+        return False, f"ERROR: {files.FSTAB_PATH} {e}.\n"
+    return files.write_file(files.FSTAB_PATH, editor.text(), dry_run)
+
+def remove_entry(target, dry_run=None):
+    """
+    Removes the managed fstab entries of an array or mount point, lines LiveRAID didn't write are left alone.
+    Returns: (success, log_str)
+    """
+    editor = FstabEditor(files.read_file(files.FSTAB_PATH))
+    if not editor.remove(target):
+        return False, f"ERROR: {files.FSTAB_PATH} has no LiveRAID entry for {target}.\n"
+    return files.write_file(files.FSTAB_PATH, editor.text(), dry_run)
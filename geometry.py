@@ -0,0 +1,282 @@
+import json
+import logging
+import os
+import re
+import subprocess
+import time
+import backend
+import config
+
+log = logging.getLogger("liveraid")
+
+# One record per array UUID, kept after the array is gone: re-creating an array over its members
+# (mdadm --create --assume-clean) only gives the data back with exactly the original parameters
+GEOMETRY_DIR = os.path.join(config.STATE_DIR, "geometry")
+
+def _record_path(uuid):
+    return os.path.join(GEOMETRY_DIR, f"{uuid.replace(':', '')}.json")
+
+def parse_examine(text):
+    """
+    Parses the `key : value` lines of `mdadm --examine` on one member.
+    Returns: {'uuid': '...', 'name': 'host:0', 'level': 'raid5', 'metadata': '1.2', 'raid_devices': 3, 'chunk': '512K',
+              'layout': 'left-symmetric', 'data_offset_sectors': 264192, 'role': 0}
+    role is the member's slot, 'spare' for spares and None when unknown.
+    """
+    fields = {}
+    for line in text.splitlines():
+        if line.strip().startswith("this "):
+            fields['this'] = line.split(None, 1)[1]
+        elif " : " in line:
+            key, value = line.split(" : ", 1)
+            fields[key.strip()] = value.strip()
+    if 'Array UUID' not in fields and 'UUID' not in fields:
+        return None
+
+    role = None
+    role_text = fields.get('Device Role', '')
+    if role_text.startswith("Active device"):
+        role = int(role_text.split()[-1])
+    elif "spare" in role_text.lower():
+        role = 'spare'
+    elif 'this' in fields:
+        # 0.90 superblocks describe the member in a "this" table row: Number Major Minor RaidDevice State
+        numbers = fields['this'].split()
+        role = int(numbers[3]) if len(numbers) > 3 and numbers[3].isdigit() else None
+    offset = re.match(r"(\d+)", fields.get('Data Offset', ''))
+    devices = fields.get('Raid Devices', '')
+    return {
+        'uuid': fields.get('Array UUID') or fields.get('UUID'),
+        'name': fields.get('Name', ''),
+        'level': fields.get('Raid Level', ''),
+        'metadata': fields.get('Version', ''),
+        'raid_devices': int(devices) if devices.isdigit() else None,
+        'chunk': fields.get('Chunk Size'),
+        'layout': fields.get('Layout'),
+        # 0.90 superblocks sit at the end of the device, the data starts at sector 0
+        'data_offset_sectors': int(offset.group(1)) if offset else 0,
+        'role': role,
+    }
+
+def examine(device_path):
+    try:
+        examined = backend.query(['mdadm', '--examine', device_path])
+    except Exception as e:
+        log.error(f"Failed to examine {device_path}: {e}")
+        return None
+    return parse_examine(examined)
+
+def read_geometry(array_name):
+    """
+    Reads the creation parameters of a running array from its members' superblocks.
+    Returns: {'uuid': '...', 'array': '/dev/md0', 'name': 'host:0', 'level': 'raid5', 'metadata': '1.2', 'raid_devices': 3,
+              'chunk': '512K', 'layout': 'left-symmetric',
+              'members': [{'slot': 0, 'device': '/dev/sdb', 'serial': 'WD-123', 'wwn': '0x50014ee...', 'data_offset_sectors': 264192}],
+              'mdadm_version': 'mdadm - v4.2 ...', 'recorded': 1700000000.0}
+    or None when the array can't be read. Spares are listed with slot 'spare'.
+    """
+    members = []
+    info = None
+    for member in backend.get_array_members(array_name):
+        if not member['device']:
+            continue
+        examined = examine(member['device'])
+        if examined is None:
+            continue
+        info = info or examined
+        # The disk behind the member, so the right one is found again after the device names moved
+        identity = backend.get_device_identity(backend.get_parent_disk(member['device'])) or {}
+        members.append({'slot': examined['role'], 'device': member['device'], 'serial': identity.get('serial'),
+                        'wwn': identity.get('wwn'), 'data_offset_sectors': examined['data_offset_sectors']})
+    if info is None:
+        return None
+
+    try:
+        version = backend.RUNNER.query(['mdadm', '--version']).stderr.strip() or None
+    except Exception:
+        version = None
+    members.sort(key=lambda m: (m['slot'] == 'spare' or m['slot'] is None, m['slot'] if isinstance(m['slot'], int) else 0))
+    return {
+        'uuid': info['uuid'],
+        'array': array_name,
+        'name': info['name'],
+        'level': info['level'],
+        'metadata': info['metadata'],
+        'raid_devices': info['raid_devices'],
+        'chunk': info['chunk'],
+        'layout': info['layout'],
+        'members': members,
+        'mdadm_version': version,
+        'recorded': time.time(),
+    }
+
+def save_geometry(array_name):
+    """
+    Records (or refreshes) the creation parameters of an array.
+    Returns: (success, log_str)
+    """
+    record = read_geometry(array_name)
+    if record is None:
+        return False, f"Could not read the superblocks of {array_name}, its creation parameters were not recorded.\n"
+    if backend.DRY_RUN:
+        return True, f"[DRY RUN] Would record the creation parameters of {array_name} in {_record_path(record['uuid'])}\n"
+    try:
+        os.makedirs(GEOMETRY_DIR, exist_ok=True)
+        tmp_path = _record_path(record['uuid']) + ".tmp"
+        with open(tmp_path, 'w') as f:
+            json.dump(record, f, indent=2)
+        os.replace(tmp_path, _record_path(record['uuid']))
+    except Exception as e:
+        return False, f"Failed to record the creation parameters of {array_name}\nError: {e}\n"
+    return True, f"Creation parameters of {array_name} recorded ({record['level']}, chunk {record['chunk'] or '-'}, {record['layout'] or 'no layout'}, metadata {record['metadata']}).\n"
+
+def list_geometries():
+    """
+    Returns every recorded array, most recently recorded first.
+    """
+    try:
+        names = os.listdir(GEOMETRY_DIR)
+    except FileNotFoundError:
+        return []
+    records = []
+    for name in names:
+        if not name.endswith(".json"):
+            continue
+        try:
+            with open(os.path.join(GEOMETRY_DIR, name), 'r') as f:
+                records.append(json.load(f))
+        except Exception as e:
+            log.error(f"Failed to read {name}: {e}")
+    return sorted(records, key=lambda r: r.get('recorded', 0), reverse=True)
+
+def find_geometry(key):
+    """
+    Returns the record whose UUID, array device or md name matches key, or None.
+    """
+    plain = key.replace(':', '')
+    for record in list_geometries():
+        if record['uuid'].replace(':', '') == plain or key in (record['array'], record['name']) \
+                or record['name'].split(':')[-1] == os.path.basename(key):
+            return record
+    return None
+
+def export_geometries(path):
+    """
+    Writes every recorded array into one JSON file to keep off the machine (disaster recovery copy).
+    Returns: (success, log_str)
+    """
+    records = list_geometries()
+    try:
+        with open(path, 'w') as f:
+            json.dump({'exported': time.time(), 'arrays': records}, f, indent=2)
+    except Exception as e:
+        return False, f"Failed to write {path}\nError: {e}\n"
+    return True, f"Creation parameters of {len(records)} arrays exported to {path}. Keep a copy off this machine.\n"
+
+def format_geometry(record):
+    lines = [f"{record['array']} ({record['name'] or '-'}) UUID {record['uuid']}",
+             f"  {record['level']}, {record['raid_devices']} devices, chunk {record['chunk'] or '-'}, layout {record['layout'] or '-'}, metadata {record['metadata']}",
+             f"  Recorded {time.strftime('%Y-%m-%d %H:%M', time.localtime(record['recorded']))} with {record.get('mdadm_version') or 'unknown mdadm'}"]
+    for m in record['members']:
+        slot = "spare" if m['slot'] == 'spare' else f"slot {m['slot']}"
+        lines.append(f"  {slot:<8} {m['device']:<14} serial {m['serial'] or '-':<20} data offset {m['data_offset_sectors']} sectors")
+    return "\n".join(lines)
+
+def load_export(path):
+    """
+    Returns the records of a file written by export_geometries(), e.g. a copy kept on a USB stick.
+    """
+    with open(path, 'r') as f:
+        return json.load(f).get('arrays', [])
+
+def partition_suffix(device_path):
+    # '/dev/sdb1' -> '1', '/dev/nvme0n1p2' -> 'p2', whole disks -> ''
+    name = os.path.basename(device_path)
+    match = re.match(r"^(nvme\d+n\d+|mmcblk\d+)(p\d+)$", name) or re.match(r"^([a-z]+)(\d+)$", name)
+    return match.group(2) if match else ""
+
+def locate_members(record):
+    """
+    Finds each recorded member on the disks attached now, by the serial of the disk it was on
+    (device names move between boots and controllers). Members whose disk isn't found keep their recorded path.
+    Returns: [{'slot': 0, 'recorded': '/dev/sdb1', 'device': '/dev/sdc1', 'found_by': 'serial'|'path'|None}]
+    """
+    serials = {}
+    for name in sorted(os.listdir("/sys/block")):
+        if name.startswith(("loop", "ram", "md", "dm-", "zram", "sr")):
+            continue
+        identity = backend.get_device_identity(f"/dev/{name}")
+        if identity and identity.get('serial'):
+            serials[identity['serial']] = f"/dev/{name}"
+
+    located = []
+    for m in record['members']:
+        entry = {'slot': m['slot'], 'recorded': m['device'], 'device': None, 'found_by': None}
+        if m['serial'] and m['serial'] in serials:
+            entry['device'] = serials[m['serial']] + partition_suffix(m['device'])
+            entry['found_by'] = 'serial'
+        elif os.path.exists(m['device']):
+            entry['device'] = m['device']
+            entry['found_by'] = 'path'
+        located.append(entry)
+    return located
+
+def check_members(record, devices):
+    """
+    Compares what is left on the chosen devices ({slot: device}) with the record before re-creating over them.
+    Returns: (errors, warnings) as lists of messages. Errors mean the re-created array would not line up with the data.
+    """
+    errors, warnings = [], []
+    offsets = {m['data_offset_sectors'] for m in record['members'] if isinstance(m['slot'], int)}
+    if len(offsets) > 1:
+        errors.append(f"The members had different data offsets ({', '.join(str(o) for o in sorted(offsets))} sectors), "
+                      "re-create them by hand with per-device --data-offset.")
+    if len(set(devices.values())) != len(devices):
+        errors.append("A device is assigned to more than one slot.")
+    if record['raid_devices'] is not None:
+        missing = record['raid_devices'] - len(devices)
+        tolerance = {'raid1': record['raid_devices'] - 1, 'raid4': 1, 'raid5': 1, 'raid6': 2, 'raid10': 1}.get(record['level'], 0)
+        if missing > tolerance:
+            errors.append(f"{missing} of {record['raid_devices']} members missing, {record['level']} can't give the data back with more than {tolerance} missing.")
+        elif missing:
+            warnings.append(f"{missing} slot(s) re-created as 'missing', the array comes up degraded.")
+
+    for slot, device in sorted(devices.items()):
+        if not os.path.exists(device):
+            errors.append(f"Slot {slot}: {device} does not exist.")
+            continue
+        current = examine(device)
+        if current is None:
+            warnings.append(f"Slot {slot}: {device} has no md superblock left, relying on the record alone.")
+            continue
+        if current['uuid'].replace(':', '') != record['uuid'].replace(':', ''):
+            warnings.append(f"Slot {slot}: {device} carries a superblock of another array ({current['uuid']}).")
+        else:
+            if current['role'] != slot:
+                errors.append(f"Slot {slot}: {device}'s superblock says it is in slot {current['role']}.")
+            for key in ('level', 'chunk', 'layout', 'metadata'):
+                if current[key] != record[key]:
+                    warnings.append(f"Slot {slot}: {key} is {current[key]} on disk but {record[key]} in the record.")
+        if offsets and current['data_offset_sectors'] not in offsets:
+            warnings.append(f"Slot {slot}: data offset is {current['data_offset_sectors']} sectors on disk, {min(offsets)} in the record.")
+    return errors, warnings
+
+def recreate_command(record, devices, array_name):
+    """
+    Returns the `mdadm --create --assume-clean` command that lays an array over its old members with the recorded
+    parameters. devices: {slot: device}, slots without a device are passed as 'missing'.
+    """
+    offset = next((m['data_offset_sectors'] for m in record['members'] if isinstance(m['slot'], int)), None)
+    cmd = ["mdadm", "--create", "--verbose", "--run", "--assume-clean", array_name,
+           f"--metadata={record['metadata']}", f"--level={record['level']}", f"--raid-devices={record['raid_devices']}"]
+    if record['chunk']:
+        cmd.append(f"--chunk={record['chunk']}")
+    if record['layout']:
+        cmd.append(f"--layout={record['layout']}")
+    if offset and not record['metadata'].startswith("0."):
+        # mdadm takes the offset in KiB
+        cmd.append(f"--data-offset={offset // 2}K")
+    if record['name']:
+        cmd.append(f"--name={record['name']}")
+    cmd.extend(devices.get(slot, "missing") for slot in range(record['raid_devices']))
+    return cmd
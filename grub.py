@@ -0,0 +1,162 @@
+import glob
+import os
+import re
+import shutil
+import string
+import subprocess
+import tempfile
+import backend
+import config
+import files
+
+# Boot configuration for arrays the system boots from: LiveRAID keeps its settings in one marked block of
+# /etc/default/grub and never touches the lines around it, so distro and admin settings survive every edit.
+# The block preloads the md modules and asks the initramfs to assemble each array by UUID.
+
+GRUB_DEFAULT_PATH = "/etc/default/grub"
+
+BLOCK_BEGIN = "# BEGIN LiveRAID managed block, edit with 'cli.py grub'"
+BLOCK_END = "# END LiveRAID managed block"
+
+# One comment per array inside the block, so the block can be read back without mdadm
+ARRAY_LINE = re.compile(r"^# array (\S+) UUID=(\S+)$")
+
+ASSIGNMENT = re.compile(r"^\s*(?:export\s+)?([A-Za-z_][A-Za-z0-9_]*)=(.*)$")
+
+# Modules GRUB needs to read /boot off a partitioned md member, whatever the metadata version
+PRELOAD_MODULES = ["part_gpt", "part_msdos", "mdraid09", "mdraid1x"]
+
+# The settings lines of the block, extending whatever the lines above it set. $modules, $uuids and $timeout
+# (grub_timeout in config.toml) are filled in by render_block(), which leaves the GRUB_TIMEOUT line out unless
+# grub_timeout is set, the distro's own timeout stays then; a distro that wants other settings only changes these lines.
+BLOCK_TEMPLATE = [
+    string.Template('GRUB_PRELOAD_MODULES="$$GRUB_PRELOAD_MODULES $modules"'),
+    string.Template('GRUB_CMDLINE_LINUX="$$GRUB_CMDLINE_LINUX $uuids"'),
+    string.Template('GRUB_TIMEOUT=$timeout'),
+]
+
+# Regenerates grub.cfg after /etc/default/grub changed, first match wins
+MKCONFIG_COMMANDS = [["update-grub"], ["grub2-mkconfig", "-o", "/boot/grub2/grub.cfg"], ["grub-mkconfig", "-o", "/boot/grub/grub.cfg"]]
+
+def _unquote(value):
+    value = value.strip()
+    if len(value) >= 2 and value[0] == value[-1] and value[0] in "\"'":
+        return value[1:-1]
+    return value
+
+def parse(text):
+    """
+    Reads /etc/default/grub into its settings outside the managed block and the arrays inside it.
+    Returns: {'settings': {'GRUB_TIMEOUT': '5', ...}, 'arrays': [{'name': '/dev/md0', 'uuid': '...'}], 'has_block': True}
+    """
+    settings, arrays = {}, []
+    in_block = has_block = False
+    for line in text.splitlines():
+        stripped = line.strip()
+        if stripped == BLOCK_BEGIN:
+            in_block = has_block = True
+            continue
+        if stripped == BLOCK_END:
+            in_block = False
+            continue
+        if in_block:
+            match = ARRAY_LINE.match(stripped)
+            if match:
+                arrays.append({'name': match.group(1), 'uuid': match.group(2)})
+            continue
+        match = ASSIGNMENT.match(line)
+        if match and not stripped.startswith("#"):
+            settings[match.group(1)] = _unquote(match.group(2))
+    return {'settings': settings, 'arrays': arrays, 'has_block': has_block}
+
+def render_block(arrays):
+    if not arrays:
+        return []
+    lines = [BLOCK_BEGIN]
+    lines += [f"# array {arr['name']} UUID={arr['uuid']}" for arr in arrays]
+    values = {'modules': " ".join(PRELOAD_MODULES), 'uuids': " ".join(f"rd.md.uuid={arr['uuid']}" for arr in arrays)}
+    if 'grub_timeout' in config.load_config():
+        values['timeout'] = config.get_setting('grub_timeout')
+    lines += [template.substitute(values) for template in BLOCK_TEMPLATE
+              if 'timeout' in values or '$timeout' not in template.template]
+    lines.append(BLOCK_END)
+    return lines
+
+def render_grub(arrays, current_text):
+    # The block replaces an existing one in place, a new block goes to the end where it extends the settings above it
+    lines = current_text.splitlines()
+    begin = next((i for i, line in enumerate(lines) if line.strip() == BLOCK_BEGIN), None)
+    end = next((i for i, line in enumerate(lines) if line.strip() == BLOCK_END and begin is not None and i > begin), None)
+    if begin is not None and end is not None:
+        lines[begin:end + 1] = render_block(arrays)
+    else:
+        lines += render_block(arrays)
+    return "\n".join(lines) + "\n" if lines else ""
+
+def validate(text):
+    """
+    /etc/default/grub is sourced by grub-mkconfig, a syntax error there stops every later kernel update from
+    writing a boot menu. Returns: (valid, error message)
+    """
+    if not shutil.which("sh"):
+        return True, ""
+    with tempfile.NamedTemporaryFile('w', suffix=".grub", delete=False) as f:
+        f.write(text)
+    try:
+        result = subprocess.run(["sh", "-n", f.name], capture_output=True, text=True)
+    finally:
+        os.remove(f.name)
+    return result.returncode == 0, result.stderr.strip().replace(f.name, GRUB_DEFAULT_PATH)
+
+def regenerate(dry_run=None):
+    """
+    Rebuilds grub.cfg from /etc/default/grub with the distro's tool.
+    Returns: (success, log_str)
+    """
+    for cmd in MKCONFIG_COMMANDS:
+        if shutil.which(cmd[0]) and (len(cmd) == 1 or os.path.isdir(os.path.dirname(cmd[-1]))):
+            return backend.run_command(cmd, dry_run)
+    return True, "WARNING: No update-grub or grub-mkconfig found, regenerate grub.cfg before rebooting.\n"
+
+def write_arrays(arrays, dry_run=None):
+    current = files.read_file(GRUB_DEFAULT_PATH)
+    new_text = render_grub(arrays, current)
+    valid, error = validate(new_text)
+    if not valid:
+        return False, f"ERROR: Refusing to write {GRUB_DEFAULT_PATH}, the result would not parse: {error}\n"
+    success, log = files.write_file(GRUB_DEFAULT_PATH, new_text, dry_run)
+    if success and new_text != current:
+        success, out = regenerate(dry_run)
+        log += out
+    return success, log
+
+def list_backups():
+    """
+    Backups of /etc/default/grub taken by files.write_file(), newest first.
+    Returns: [{'id': '20240501-101500', 'path': '/var/lib/liveraid/backups/.../etc/default/grub', 'mtime': ...}]
+    """
+    pattern = os.path.join(config.STATE_DIR, "backups", "*", GRUB_DEFAULT_PATH.lstrip("/"))
+    backups = []
+    for path in glob.glob(pattern):
+        stamp = os.path.relpath(path, os.path.join(config.STATE_DIR, "backups")).split(os.sep)[0]
+        backups.append({'id': stamp, 'path': path, 'mtime': os.path.getmtime(path)})
+    return sorted(backups, key=lambda b: b['mtime'], reverse=True)
+
+def restore(backup, dry_run=None):
+    """
+    Puts a backup (ID from list_backups() or a file path) back in place. The current file is backed up and
+    the restore recorded as a change first, so it can be undone like any other edit.
+    Returns: (success, log_str)
+    """
+    path = backup if os.path.isfile(backup) else next((b['path'] for b in list_backups() if b['id'] == backup), None)
+    if path is None:
+        return False, f"ERROR: No backup of {GRUB_DEFAULT_PATH} named {backup} (see 'grub show').\n"
+    text = files.read_file(path)
+    valid, error = validate(text)
+    if not valid:
+        return False, f"ERROR: The backup {path} does not parse, not restoring it: {error}\n"
+    success, log = files.write_file(GRUB_DEFAULT_PATH, text, dry_run)
+    if success:
+        success, out = regenerate(dry_run)
+        log += out
+    return success, log
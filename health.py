@@ -0,0 +1,121 @@
+import json
+import logging
+import os
+import time
+import backend
+import config
+
+log = logging.getLogger("liveraid")
+
+# Append-only record of health findings and what was done about them, one JSON object per line
+HISTORY_FILE = os.path.join(config.STATE_DIR, "health.jsonl")
+
+def record_event(array_name, kind, summary, details=None):
+    """
+    Appends an entry to the health history.
+    Returns: {'timestamp': 1700000000, 'array': '/dev/md0', 'kind': 'scrub-remediation', 'summary': '...', 'details': {...}}
+    """
+    event = {'timestamp': int(time.time()), 'array': array_name, 'kind': kind, 'summary': summary, 'details': details or {}}
+    try:
+        os.makedirs(os.path.dirname(HISTORY_FILE), exist_ok=True)
+        with open(HISTORY_FILE, 'a') as f:
+            f.write(json.dumps(event) + "\n")
+    except Exception as e:
+        log.error(f"Failed to record health event for {array_name}: {e}")
+    return event
+
+def list_events(array_name=None):
+    """
+    Returns the recorded health events, oldest first, optionally only those of one array.
+    """
+    events = []
+    try:
+        with open(HISTORY_FILE, 'r') as f:
+            for line in f:
+                try:
+                    event = json.loads(line)
+                except ValueError:
+                    continue
+                if array_name is None or event.get('array') == array_name:
+                    events.append(event)
+    except FileNotFoundError:
+        pass
+    return events
+
+# Nagios plugin exit codes, also used as severities (higher is worse)
+OK, WARNING, CRITICAL, UNKNOWN = 0, 1, 2, 3
+STATUS_NAMES = {OK: "OK", WARNING: "WARNING", CRITICAL: "CRITICAL", UNKNOWN: "UNKNOWN"}
+
+def last_scrub(array_name):
+    """
+    Returns the most recent recorded check/repair pass of an array, or None if it was never scrubbed.
+    """
+    scrubs = [e for e in list_events(array_name) if e.get('kind') in ("check", "repair")]
+    return scrubs[-1] if scrubs else None
+
+def evaluate(array_names=None, now=None):
+    """
+    Rates array state, member SMART summaries and the age of the last scrub against the
+    thresholds in config.toml (scrub_warn_days, scrub_crit_days, smart_sector_warn).
+    Returns: (status, ['/dev/md0 degraded, no rebuild running', ...]) where status is OK, WARNING, CRITICAL or UNKNOWN
+    """
+    now = now or time.time()
+    # One mdstat read and parallel member lookups, so hosts with many arrays are checked quickly
+    arrays = backend.get_arrays_status(array_names)
+    if not arrays:
+        return UNKNOWN, ["no md arrays found"]
+
+    problems = []
+
+    def report(severity, message):
+        problems.append((severity, message))
+
+    for array in arrays:
+        name = array['name']
+        if array['status'] == "missing":
+            report(CRITICAL, f"{name} not running")
+            continue
+        if array['status'] != "active":
+            report(CRITICAL, f"{name} is {array['status']}")
+            continue
+
+        degraded = backend.read_md_attribute(name, "degraded")
+        if degraded and degraded != "0":
+            if backend.needs_replacement(name):
+                report(CRITICAL, f"{name} degraded ({degraded} missing), no rebuild running")
+            else:
+                progress = array['sync']
+                report(WARNING, f"{name} degraded, rebuilding" + (f" {progress['percent']:.1f}%" if progress else ""))
+
+        for member in array['members']:
+            if member['role'] == "faulty":
+                report(CRITICAL, f"{member['device'] or 'a member'} of {name} is faulty")
+                continue
+            if not member['device'] or member['role'] == "removed":
+                continue
+            smart = backend.get_smart_report(member['device'])
+            bad_sectors = (smart['reallocated'] or 0) + (smart['pending'] or 0)
+            if smart['passed'] is False:
+                report(CRITICAL, f"{member['device']} SMART overall health failed")
+            elif bad_sectors >= config.get_setting('smart_sector_warn'):
+                report(WARNING, f"{member['device']} has {bad_sectors} reallocated/pending sectors")
+            if smart['last_self_test_passed'] is False:
+                report(WARNING, f"{member['device']} failed its last SMART self-test")
+
+        if array['type'] == "raid0":
+            continue  # nothing to compare, scrubs don't apply
+        scrub = last_scrub(name)
+        if scrub is None:
+            report(WARNING, f"{name} was never scrubbed")
+            continue
+        age_days = int((now - scrub['timestamp']) // 86400)
+        if age_days >= config.get_setting('scrub_crit_days'):
+            report(CRITICAL, f"{name} last scrubbed {age_days} days ago")
+        elif age_days >= config.get_setting('scrub_warn_days'):
+            report(WARNING, f"{name} last scrubbed {age_days} days ago")
+        if scrub.get('details', {}).get('mismatches'):
+            report(WARNING, f"{name} last scrub found {scrub['details']['mismatches']} mismatched sectors")
+
+    # Worst first, a one-line summary may get truncated by the monitoring system
+    problems.sort(key=lambda p: -p[0])
+    return max((p[0] for p in problems), default=OK), [p[1] for p in problems]
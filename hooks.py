@@ -0,0 +1,101 @@
+import json
+import logging
+import socket
+import subprocess
+import time
+import backend
+import config
+
+log = logging.getLogger("liveraid")
+
+# How long a hook command may run before it is killed, so a hanging script can't stall monitoring
+HOOK_TIMEOUT = 60
+
+def array_state(array_name):
+    """
+    Returns the parts of an array's state that events are derived from.
+    Returns: {'degraded': 1, 'sync_action': 'recover', 'mismatch_cnt': 0}
+    """
+    degraded = backend.read_md_attribute(array_name, "degraded")
+    mismatches = backend.read_md_attribute(array_name, "mismatch_cnt")
+    return {
+        'degraded': int(degraded) if degraded and degraded.isdigit() else 0,
+        'sync_action': backend.read_md_attribute(array_name, "sync_action") or "idle",
+        'mismatch_cnt': int(mismatches) if mismatches and mismatches.isdigit() else 0,
+    }
+
+class EventDetector:
+    """
+    Compares successive polls of the arrays and reports what changed:
+      degraded - an array lost members (also reported for arrays already degraded on the first poll)
+      rebuilt  - a degraded array has all its members back
+      finished - a resync, recovery, reshape, check or repair pass ended
+    """
+
+    def __init__(self):
+        self.states = {}
+
+    def poll(self, array_names):
+        """
+        Returns: [{'event': 'degraded', 'array': '/dev/md0', 'timestamp': 1700000000, 'host': 'nas', 'state': {...}}]
+        """
+        events = []
+        for name in array_names:
+            state = array_state(name)
+            previous = self.states.get(name)
+            self.states[name] = state
+
+            def event(kind, **details):
+                events.append(dict({'event': kind, 'array': name, 'timestamp': int(time.time()),
+                                    'host': socket.gethostname(), 'state': state}, **details))
+
+            if state['degraded'] and (previous is None or state['degraded'] > previous['degraded']):
+                event("degraded", missing=state['degraded'])
+            if previous is None:
+                continue
+            if previous['degraded'] and not state['degraded']:
+                event("rebuilt")
+            if previous['sync_action'] != "idle" and state['sync_action'] == "idle":
+                event("finished", action=previous['sync_action'], mismatch_cnt=state['mismatch_cnt'])
+
+        # Arrays that were stopped are forgotten, they start over when they come back
+        for name in list(self.states):
+            if name not in array_names:
+                del self.states[name]
+        return events
+
+def describe(event):
+    if event['event'] == "degraded":
+        return f"{event['array']} is degraded ({event['missing']} missing)"
+    if event['event'] == "rebuilt":
+        return f"{event['array']} is complete again"
+    if event['event'] == "smart_failed":
+        return f"{event['array']}: {', '.join(event['devices'])} failed the {event['test']} SMART self-test"
+    return f"{event['array']}: {event['action']} finished ({event['mismatch_cnt']} mismatched sectors)"
+
+def run_hooks(event):
+    """
+    Runs every command configured as [hooks] on_<event> in config.toml with the event as JSON on stdin.
+    Returns: (success, log_str)
+    """
+    payload = json.dumps(event) + "\n"
+    logs = []
+    success = True
+    for cmd in config.get_hook_commands(event['event']):
+        if backend.DRY_RUN:
+            logs.append(f"[DRY RUN] Would run on_{event['event']} hook: {' '.join(cmd)}\n")
+            continue
+        try:
+            result = subprocess.run(cmd, input=payload, capture_output=True, text=True, timeout=HOOK_TIMEOUT)
+        except Exception as e:
+            log.error(f"on_{event['event']} hook {cmd[0]} failed: {e}")
+            logs.append(f"Hook {' '.join(cmd)} failed: {e}\n")
+            success = False
+            continue
+        if result.returncode != 0:
+            log.error(f"on_{event['event']} hook {cmd[0]} exited with {result.returncode}: {result.stderr.strip()}")
+            logs.append(f"Hook {' '.join(cmd)} exited with {result.returncode}\n")
+            success = False
+        else:
+            logs.append(f"Ran on_{event['event']} hook {' '.join(cmd)}\n")
+    return success, "".join(logs)
@@ -0,0 +1,187 @@
+import json
+import logging
+import os
+import time
+import uuid
+import backend
+import config
+
+log = logging.getLogger("liveraid")
+
+# Shared between the GUI and CLI so an operation started in one can be watched or cancelled from the other
+JOBS_DIR = os.path.join(config.STATE_DIR, "jobs")
+
+# Jobs whose progress is owned by the kernel (md sync_action) rather than by a LiveRAID process
+SYNC_KINDS = ("resync", "recovery", "check", "repair", "reshape")
+
+# Jobs of a LiveRAID process that look for a pause between their steps (executor.Executor: apply, the GUI's
+# create), the others (format, migrate, grow, upgrade, verify) run through and can only be cancelled
+PAUSABLE_KINDS = SYNC_KINDS + ("apply", "create")
+
+def _job_path(job_id):
+    return os.path.join(JOBS_DIR, f"{job_id}.json")
+
+def _save(job):
+    job['updated'] = time.time()
+    try:
+        os.makedirs(JOBS_DIR, exist_ok=True)
+        tmp_path = _job_path(job['id']) + ".tmp"
+        with open(tmp_path, 'w') as f:
+            json.dump(job, f, indent=2)
+        os.replace(tmp_path, _job_path(job['id']))
+    except Exception as e:
+        log.error(f"Failed to save job {job['id']}: {e}")
+    return job
+
+def _pid_alive(pid):
+    try:
+        os.kill(pid, 0)
+        return True
+    except ProcessLookupError:
+        return False
+    except PermissionError:
+        return True
+
+def create_job(kind, target):
+    """
+    Registers a new running job and returns its record.
+    Returns: {'id': '3f2a9c1e', 'kind': 'format', 'target': '/dev/md0', 'state': 'running', 'percent': 0.0, ...}
+    """
+    now = time.time()
+    job = {
+        'id': uuid.uuid4().hex[:8],
+        'kind': kind,
+        'target': target,
+        'state': 'running',
+        'percent': 0.0,
+        'message': '',
+        'pid': os.getpid(),
+        'cancel_requested': False,
+        'created': now,
+        'updated': now
+    }
+    return _save(job)
+
+def update_job(job_id, **fields):
+    job = get_job(job_id, refresh=False)
+    if job is None:
+        return None
+    job.update(fields)
+    return _save(job)
+
+def finish_job(job_id, success, message=""):
+    job = get_job(job_id, refresh=False)
+    if job is None:
+        return None
+    if job.get('cancel_requested') and not success:
+        job['state'] = 'cancelled'
+    else:
+        job['state'] = 'completed' if success else 'failed'
+    if success:
+        job['percent'] = 100.0
+    job['message'] = message.strip()
+    return _save(job)
+
+def is_cancel_requested(job_id):
+    job = get_job(job_id, refresh=False)
+    return bool(job and job.get('cancel_requested'))
+
+def _refresh(job):
+    if job['state'] != 'running':
+        return job
+
+    if job['kind'] in SYNC_KINDS:
+        # A frozen pass doesn't show in mdstat, it isn't finished though
+        if job.get('paused'):
+            return job
+        progress = backend.get_sync_progress(job['target'])
+        if progress is None:
+            job['state'] = 'completed'
+            job['percent'] = 100.0
+        else:
+            job['percent'] = progress['percent']
+            job['message'] = f"{progress['action']} finish={progress['finish']} speed={progress['speed']}"
+        return _save(job)
+
+    # The owning process died without recording a result (crash, reboot, killed window)
+    if not _pid_alive(job['pid']):
+        job['state'] = 'failed'
+        job['message'] = "Owning process exited before the job finished."
+        return _save(job)
+    return job
+
+def get_job(job_id, refresh=True):
+    try:
+        with open(_job_path(job_id), 'r') as f:
+            job = json.load(f)
+    except Exception:
+        return None
+    return _refresh(job) if refresh else job
+
+def list_jobs():
+    """
+    Returns every known job, newest first.
+    """
+    try:
+        names = os.listdir(JOBS_DIR)
+    except FileNotFoundError:
+        return []
+    jobs = [get_job(name[:-5]) for name in names if name.endswith(".json")]
+    return sorted([j for j in jobs if j], key=lambda j: j['created'], reverse=True)
+
+def cancel_job(job_id):
+    """
+    Asks a running job to stop. Kernel driven check/repair jobs are interrupted directly,
+    jobs owned by a LiveRAID process are stopped at their next cancellation point.
+    """
+    job = get_job(job_id)
+    if job is None:
+        return False, f"Unknown job: {job_id}\n"
+    if job['state'] != 'running':
+        return False, f"Job {job_id} is already {job['state']}.\n"
+
+    if job['kind'] in ("check", "repair"):
+        success, out = backend.write_sysfs(f"{backend.md_sysfs_dir(job['target'])}/sync_action", "idle")
+        if success:
+            update_job(job_id, state='cancelled', cancel_requested=True)
+        return success, out
+    if job['kind'] in SYNC_KINDS:
+        # md restarts an interrupted resync/reshape by itself, only release whoever is waiting on it
+        update_job(job_id, cancel_requested=True)
+        return True, f"Stopped waiting on job {job_id}; the {job['kind']} itself continues in the kernel.\n"
+
+    update_job(job_id, cancel_requested=True)
+    return True, f"Cancellation requested for job {job_id}.\n"
+
+def pause_job(job_id, pause=True):
+    """
+    Pauses (or resumes) a running job. Kernel driven resync/check jobs are frozen in sysfs (sync_action) and go on from
+    where they stopped; jobs owned by a LiveRAID process (an apply) hold before their next step. Other kinds are refused.
+    """
+    job = get_job(job_id)
+    if job is None:
+        return False, f"Unknown job: {job_id}\n"
+    if job['state'] != 'running':
+        return False, f"Job {job_id} is already {job['state']}.\n"
+    if job['kind'] not in PAUSABLE_KINDS:
+        return False, f"Job {job_id} ({job['kind']}) can't be paused, only cancelled.\n"
+    if bool(job.get('paused') or job.get('pause_requested')) == pause:
+        return False, f"Job {job_id} is {'already paused' if pause else 'not paused'}.\n"
+
+    if job['kind'] in SYNC_KINDS and pause:
+        # Read before the freeze ends the pass, a check or repair is started again from here
+        checkpoint = backend.sync_checkpoint(job['target'])
+        success, out = backend.pause_sync(job['target'])
+        if success:
+            update_job(job_id, paused=True, checkpoint=checkpoint, message=f"{job['kind']} paused")
+        return success, out
+    if job['kind'] in SYNC_KINDS:
+        success, out = backend.resume_sync(job['target'], job['kind'], job.get('checkpoint'))
+        if success:
+            update_job(job_id, paused=False, message=job['kind'])
+        return success, out
+    if pause:
+        update_job(job_id, pause_requested=True)
+        return True, f"Pause requested for job {job_id}, it holds once the running step finished.\n"
+    update_job(job_id, pause_requested=False, paused=False)
+    return True, f"Job {job_id} resumes.\n"
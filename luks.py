@@ -0,0 +1,122 @@
+import os
+import subprocess
+import backend
+
+# LUKS2 container between the array and its filesystem. The passphrase is never written to plan files
+# or logs: frontends ask for it and hand it to cryptsetup on stdin, keyfiles are referenced by path.
+
+DEFAULT_CIPHER = "aes-xts-plain64"
+DEFAULT_KEY_SIZE = 512
+PBKDFS = ["argon2id", "argon2i", "pbkdf2"]
+
+# Below this cryptsetup refuses the passphrase anyway, failing early keeps the array from being half set up
+MIN_PASSPHRASE_LENGTH = 8
+
+def new_encryption(cipher=None, key_size=None, pbkdf=None, keyfile=None):
+    """
+    Returns: {'cipher': 'aes-xts-plain64', 'key_size': 512, 'pbkdf': 'argon2id', 'keyfile': None}
+    With keyfile None the container is unlocked with a passphrase (asked at boot through crypttab).
+    """
+    return {
+        'cipher': cipher or DEFAULT_CIPHER,
+        'key_size': int(key_size or DEFAULT_KEY_SIZE),
+        'pbkdf': pbkdf or PBKDFS[0],
+        'keyfile': keyfile,
+    }
+
+def check(encryption):
+    """
+    Checks the encryption fields of a plan without touching the system.
+    Returns a list of messages.
+    """
+    errors = []
+    if encryption['pbkdf'] not in PBKDFS:
+        errors.append(f"Unknown key derivation '{encryption['pbkdf']}' (use {', '.join(PBKDFS)})")
+    if encryption['key_size'] <= 0 or encryption['key_size'] % 8:
+        errors.append(f"Invalid key size {encryption['key_size']}, give it in bits, e.g. 256 or 512.")
+    if not encryption['cipher'] or " " in encryption['cipher']:
+        errors.append(f"Invalid cipher '{encryption['cipher']}', e.g. {DEFAULT_CIPHER}.")
+    if encryption['keyfile'] is not None and not os.path.isabs(encryption['keyfile']):
+        errors.append(f"The keyfile '{encryption['keyfile']}' must be an absolute path, crypttab reads it at boot.")
+    return errors
+
+def mapper_name(array_name):
+    # '/dev/md0' -> 'md0_crypt', '/dev/md/data' -> 'data_crypt'
+    return f"{os.path.basename(array_name)}_crypt"
+
+def mapper_device(array_name):
+    return f"/dev/mapper/{mapper_name(array_name)}"
+
+def key_arguments(encryption):
+    # A passphrase is piped in on stdin ('-'), so it never shows up in the process list or the log
+    return ["--key-file", encryption['keyfile'] or "-"]
+
+def format_command(device_path, encryption):
+    return ["cryptsetup", "luksFormat", "--batch-mode", "--type", "luks2", "--cipher", encryption['cipher'],
+            "--key-size", str(encryption['key_size']), "--pbkdf", encryption['pbkdf']] + key_arguments(encryption) + [device_path]
+
+def open_command(device_path, name, encryption, allow_discards=False, read_only=False):
+    # dm-crypt drops TRIM requests unless told otherwise (it reveals which blocks are unused)
+    return ["cryptsetup", "open", "--type", "luks2"] + (["--allow-discards"] if allow_discards else []) + \
+           (["--readonly"] if read_only else []) + key_arguments(encryption) + [device_path, name]
+
+def close_command(name):
+    return ["cryptsetup", "close", name]
+
+def run_with_key(cmd, passphrase=None, dry_run=None):
+    """
+    Runs a cryptsetup command like backend.run_command(), feeding the passphrase on stdin.
+    Returns: (success, log_str)
+    """
+    if dry_run is None:
+        dry_run = backend.DRY_RUN
+    cmd_str = " ".join(cmd)
+    if dry_run:
+        return backend.would_execute(cmd)
+    try:
+        result = backend.RUNNER.run(cmd, input=passphrase or "")
+    except Exception as e:
+        return False, f"Execution failed: {e}\n"
+    if result.returncode != 0:
+        return False, f"Command failed: {cmd_str}\nError: {result.stderr}\n"
+    return True, (result.stdout + result.stderr).strip() + "\n"
+
+def encrypt(device_path, name, encryption, passphrase=None, allow_discards=False):
+    """
+    Creates a LUKS2 container on a device and opens it as /dev/mapper/<name>.
+    Returns: (success, log_str)
+    """
+    if encryption['keyfile'] is None and len(passphrase or "") < MIN_PASSPHRASE_LENGTH and not backend.DRY_RUN:
+        return False, f"The passphrase must be at least {MIN_PASSPHRASE_LENGTH} characters long.\n"
+    logs = []
+    for cmd in (format_command(device_path, encryption), open_command(device_path, name, encryption, allow_discards)):
+        success, out = run_with_key(cmd, passphrase)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+    return True, "".join(logs)
+
+def read_encryption(device_path):
+    """
+    Reads how an existing LUKS container is set up (`cryptsetup luksDump`), in the shape of new_encryption().
+    The keyfile can't be told from the header and is always None.
+    """
+    try:
+        result = subprocess.run(['cryptsetup', 'luksDump', device_path], capture_output=True, text=True, check=True)
+    except Exception:
+        return None
+    encryption = new_encryption()
+    for line in result.stdout.splitlines():
+        key, _, value = line.strip().partition(":")
+        value = value.strip()
+        if key == "cipher":
+            encryption['cipher'] = value
+        elif key == "Cipher key" and value.split()[0].isdigit():
+            encryption['key_size'] = int(value.split()[0])
+        elif key == "PBKDF":
+            encryption['pbkdf'] = value
+    return encryption
+
+def describe(encryption):
+    key = f"keyfile {encryption['keyfile']}" if encryption['keyfile'] else "passphrase"
+    return f"LUKS2 {encryption['cipher']} {encryption['key_size']}-bit, {encryption['pbkdf']}, unlocked with a {key}"
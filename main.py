@@ -1,9 +1,21 @@
+import argparse
 import gi
 gi.require_version('Gtk', '3.0')
 from gi.repository import Gtk
 from ui import LiveRaidWindow
+import config
+import tray
 
 def main():
+    parser = argparse.ArgumentParser(description="LiveRAID configurator")
+    parser.add_argument("--tray", action="store_true", help="Start as a status icon showing array health, the window opens on click")
+    args = parser.parse_args()
+    config.setup_logging()
+    if args.tray:
+        # Kept referenced for as long as the main loop runs; the applet shows the desktop notifications itself
+        applet = tray.TrayApplet(lambda: LiveRaidWindow(desktop_notifications=False))
+        Gtk.main()
+        return
     app = LiveRaidWindow()
     app.connect("destroy", Gtk.main_quit)
     app.show_all()
@@ -0,0 +1,90 @@
+import csv
+import os
+import re
+import time
+import backend
+import config
+
+# Long-format CSV: one row per (time, array, member, metric) so new metrics need no schema change
+METRICS_FILE = os.path.join(config.STATE_DIR, "metrics.csv")
+FIELDS = ["timestamp", "array", "member", "metric", "value"]
+
+# Ring buffer size: once the file grows past this, the oldest half of the rows is dropped
+MAX_BYTES = 16 * 1024 * 1024
+
+def parse_speed_kb(speed):
+    # '22208K/sec' -> 22208
+    match = re.match(r'(\d+)K/sec', speed or '')
+    return int(match.group(1)) if match else 0
+
+def sample_metrics():
+    """
+    Takes one sample of every active array and its members.
+    Returns: [{'timestamp': 1700000000, 'array': '/dev/md0', 'member': '', 'metric': 'sync_speed_kb', 'value': 22208}, ...]
+    """
+    now = int(time.time())
+    rows = []
+
+    def add(array, member, metric, value):
+        if value is not None:
+            rows.append({'timestamp': now, 'array': array, 'member': member, 'metric': metric, 'value': value})
+
+    for arr in backend.get_active_arrays():
+        name = arr['name']
+        progress = backend.get_sync_progress(name)
+        add(name, '', 'sync_speed_kb', parse_speed_kb(progress['speed']) if progress else 0)
+        add(name, '', 'sync_percent', progress['percent'] if progress else None)
+        add(name, '', 'degraded', backend.read_md_attribute(name, 'degraded'))
+        add(name, '', 'mismatch_cnt', backend.read_md_attribute(name, 'mismatch_cnt'))
+
+        for member in backend.get_array_members(name):
+            dev = member['device']
+            if not dev:
+                continue
+            add(name, dev, 'read_errors', backend.read_md_attribute(name, f"dev-{os.path.basename(dev)}/errors"))
+            add(name, dev, 'temperature_c', backend.get_drive_temperature(dev))
+    return rows
+
+def _read_rows():
+    try:
+        with open(METRICS_FILE, 'r', newline='') as f:
+            return list(csv.DictReader(f))
+    except FileNotFoundError:
+        return []
+
+def _trim():
+    if os.path.getsize(METRICS_FILE) <= MAX_BYTES:
+        return
+    rows = _read_rows()
+    rows = rows[len(rows) // 2:]
+    tmp_path = METRICS_FILE + ".tmp"
+    with open(tmp_path, 'w', newline='') as f:
+        writer = csv.DictWriter(f, fieldnames=FIELDS)
+        writer.writeheader()
+        writer.writerows(rows)
+    os.replace(tmp_path, METRICS_FILE)
+
+def record_sample():
+    """
+    Appends one sample to the on-disk ring buffer. Returns the number of rows written.
+    """
+    rows = sample_metrics()
+    os.makedirs(os.path.dirname(METRICS_FILE), exist_ok=True)
+    new_file = not os.path.exists(METRICS_FILE)
+    with open(METRICS_FILE, 'a', newline='') as f:
+        writer = csv.DictWriter(f, fieldnames=FIELDS)
+        if new_file:
+            writer.writeheader()
+        writer.writerows(rows)
+    _trim()
+    return len(rows)
+
+def export_metrics(since_seconds=None):
+    """
+    Returns recorded rows, optionally limited to the last `since_seconds`.
+    """
+    rows = _read_rows()
+    if since_seconds:
+        cutoff = time.time() - since_seconds
+        rows = [r for r in rows if int(r['timestamp']) >= cutoff]
+    return rows
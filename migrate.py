@@ -0,0 +1,305 @@
+import hashlib
+import json
+import logging
+import os
+import re
+import shutil
+import stat
+import subprocess
+import tempfile
+import backend
+import config
+import units
+
+log = logging.getLogger("liveraid")
+
+# Kernel drivers of RAID controllers that present their volumes to Linux as ordinary SCSI disks
+HW_RAID_DRIVERS = {"megaraid_sas", "hpsa", "smartpqi", "aacraid", "arcmsr", "3w-9xxx", "3w-sas", "ips"}
+
+# Controllers in HBA/JBOD mode pass physical disks through too, their volumes carry one of these models
+VOLUME_MODELS = ("PERC", "MR9", "MegaRAID", "LOGICAL VOLUME", "ServeRAID", "RAID", "-VOL#")
+
+def get_scsi_driver(disk_name):
+    """
+    Returns the driver of the SCSI host a disk hangs off, e.g. 'megaraid_sas', or None.
+    """
+    match = re.search(r"/host(\d+)/", os.path.realpath(f"/sys/block/{disk_name}/device"))
+    if not match:
+        return None
+    try:
+        with open(f"/sys/class/scsi_host/host{match.group(1)}/proc_name", 'r') as f:
+            return f.read().strip()
+    except Exception:
+        return None
+
+def detect_hardware_raid_volumes():
+    """
+    Finds virtual disks exported by hardware RAID controllers.
+    Returns: [{'name': '/dev/sda', 'size_gb': 1862.0, 'size_bytes': 1999307276288, 'model': 'PERC H730P Mini', 'driver': 'megaraid_sas',
+               'filesystems': [{'device': '/dev/sda1', 'fstype': 'ext4'}]}]
+    """
+    try:
+        result = subprocess.run(['lsblk', '-J', '-b', '-o', 'NAME,TYPE,SIZE,MODEL,VENDOR,FSTYPE'], capture_output=True, text=True, check=True)
+        data = json.loads(result.stdout)
+    except Exception as e:
+        log.error(f"Failed to list block devices: {e}")
+        return []
+
+    volumes = []
+    for disk in data.get('blockdevices', []):
+        if disk.get('type') != 'disk':
+            continue
+        driver = get_scsi_driver(disk['name'])
+        model = f"{disk.get('vendor') or ''} {disk.get('model') or ''}".strip()
+        if driver not in HW_RAID_DRIVERS or not any(k.lower() in model.lower() for k in VOLUME_MODELS):
+            continue
+
+        filesystems = [{'device': f"/dev/{disk['name']}", 'fstype': disk['fstype']}] if disk.get('fstype') else []
+        for child in disk.get('children', []):
+            if child.get('fstype') and child['fstype'] not in ('swap', 'LVM2_member', 'crypto_LUKS'):
+                filesystems.append({'device': f"/dev/{child['name']}", 'fstype': child['fstype']})
+        volumes.append({
+            'name': f"/dev/{disk['name']}",
+            'size_gb': round(int(disk.get('size', 0)) / (1024**3), 2),
+            'size_bytes': int(disk.get('size', 0)),
+            'model': model,
+            'driver': driver,
+            'filesystems': filesystems
+        })
+    return volumes
+
+def mount_temporary(device_path, read_only=False):
+    """
+    Mounts a filesystem below /tmp for the duration of a migration.
+    Returns: (mountpoint or None, log)
+    """
+    mountpoint = tempfile.mkdtemp(prefix="liveraid-")
+    cmd = ["mount"] + (["-o", "ro"] if read_only else []) + [device_path, mountpoint]
+    success, out = backend.run_command(cmd)
+    if not success:
+        os.rmdir(mountpoint)
+        return None, out
+    return mountpoint, out
+
+def unmount_temporary(mountpoint):
+    success, out = backend.run_command(["umount", mountpoint])
+    if success:
+        os.rmdir(mountpoint)
+    return success, out
+
+def get_used_bytes(mountpoint):
+    try:
+        return shutil.disk_usage(mountpoint).used
+    except Exception:
+        return 0
+
+def stream_command(cmd, parse_line, cancelled=None):
+    """
+    Runs a long copy command, handing every output line to parse_line (progress parsing).
+    Returns: (success, log) with the last non-progress lines as the error on failure.
+    """
+    messages = []
+
+    def on_line(line):
+        if not parse_line(line) and line.strip():
+            messages.append(line)
+
+    try:
+        returncode = backend.RUNNER.stream(cmd, on_line, cancelled)
+    except Exception as e:
+        return False, f"Execution failed: {e}\n"
+    if returncode is None:
+        return False, f"Cancelled: {' '.join(cmd)}\n"
+    if returncode != 0:
+        return False, f"Command failed: {' '.join(cmd)}\nError: {''.join(messages[-20:])}\n"
+    return True, ""
+
+def copy_files(source_dir, target_dir, on_progress=None, cancelled=None, rate_limit=None):
+    """
+    Copies a directory tree with rsync, preserving hard links, ACLs and xattrs. Re-running it
+    resumes an interrupted copy: finished files are skipped and --partial keeps half-copied ones.
+    on_progress receives {'phase': 'copy', 'percent': 45.0, 'speed': '12.34MB/s'}.
+    """
+    cmd = ["rsync", "-aHAX", "--numeric-ids", "--partial", "--info=progress2", "--no-inc-recursive"]
+    if rate_limit:
+        cmd.append(f"--bwlimit={units.parse_size(rate_limit) // 1024}")
+    cmd += [f"{source_dir}/", f"{target_dir}/"]
+    if backend.DRY_RUN:
+        return backend.would_execute(cmd)
+
+    def parse_line(line):
+        match = re.search(r"\s(\d+)%\s+(\S+/s)", line)
+        if match and on_progress:
+            on_progress({'phase': 'copy', 'percent': float(match.group(1)), 'speed': match.group(2)})
+        return bool(match)
+
+    success, out = stream_command(cmd, parse_line, cancelled)
+    return success, out if not success else f"Copied {source_dir} to {target_dir}\n"
+
+def verify_files(source_dir, target_dir):
+    """
+    Compares both trees by checksum. Returns (identical, log listing the first differences).
+    """
+    # mkfs.ext4 creates lost+found on the new filesystem, it isn't a difference when the source has none or another one
+    cmd = ["rsync", "-aHAX", "--numeric-ids", "--checksum", "--delete", "--dry-run", "--itemize-changes",
+           "--exclude=/lost+found", f"{source_dir}/", f"{target_dir}/"]
+    if backend.DRY_RUN:
+        return backend.would_execute(cmd)
+    try:
+        result = subprocess.run(cmd, capture_output=True, text=True, check=True)
+    except subprocess.CalledProcessError as e:
+        return False, f"Command failed: {' '.join(cmd)}\nError: {e.stderr}\n"
+    except Exception as e:
+        return False, f"Execution failed: {e}\n"
+
+    differences = [line for line in result.stdout.split('\n') if line.strip()]
+    if differences:
+        return False, f"{len(differences)} entries differ between {source_dir} and {target_dir}:\n" + "\n".join(differences[:20]) + "\n"
+    return True, f"Verified {target_dir} against {source_dir} by checksum.\n"
+
+def get_device_bytes(device_path):
+    sectors = backend.read_block_attribute(device_path, "size")
+    return sectors * 512 if sectors else None
+
+def mapfile_path(source, target):
+    # ddrescue's mapfile records which blocks are done, it is what makes a block copy resumable
+    name = f"{os.path.basename(source)}-to-{os.path.basename(target)}.map"
+    return os.path.join(config.STATE_DIR, "copies", name)
+
+def copy_block(source, target, on_progress=None, cancelled=None, rate_limit=None):
+    """
+    Copies a whole block device. Uses ddrescue when installed (resumable via its mapfile, can be
+    throttled), plain dd otherwise. on_progress receives {'phase': 'copy', 'percent': ..., 'speed': ...}.
+    """
+    size = get_device_bytes(source)
+    target_size = get_device_bytes(target)
+    if size and target_size and target_size < size:
+        return False, f"{target} ({target_size} bytes) is smaller than {source} ({size} bytes).\n"
+
+    if shutil.which("ddrescue"):
+        mapfile = mapfile_path(source, target)
+        cmd = ["ddrescue", "--force", "--no-scrape"]
+        if rate_limit:
+            cmd.append(f"--max-read-rate={units.parse_size(rate_limit)}")
+        cmd += [source, target, mapfile]
+        pattern = re.compile(r"pct rescued:\s+([\d.]+)%")
+        speed_pattern = re.compile(r"current rate:\s+([\d.]+ \S+/s)")
+    elif rate_limit:
+        return False, "Throttled block copies need ddrescue (gddrescue), it is not installed.\n"
+    else:
+        mapfile = None
+        cmd = ["dd", f"if={source}", f"of={target}", "bs=4M", "conv=fsync", "status=progress"]
+        pattern = re.compile(r"^(\d+) bytes")
+        speed_pattern = re.compile(r", ([\d.]+ \S+/s)")
+
+    if backend.DRY_RUN:
+        return backend.would_execute(cmd)
+    if mapfile:
+        os.makedirs(os.path.dirname(mapfile), exist_ok=True)
+
+    state = {'speed': ''}
+
+    def parse_line(line):
+        speed = speed_pattern.search(line)
+        if speed:
+            state['speed'] = speed.group(1)
+        match = pattern.search(line.strip())
+        if match and on_progress:
+            value = float(match.group(1))
+            percent = value if mapfile else (100.0 * value / size if size else 0.0)
+            on_progress({'phase': 'copy', 'percent': round(percent, 1), 'speed': state['speed']})
+        return bool(match or speed)
+
+    success, out = stream_command(cmd, parse_line, cancelled)
+    if not success:
+        return False, out + (f"Re-run the same copy to resume from {mapfile}.\n" if mapfile else "")
+    if mapfile:
+        os.remove(mapfile)
+    return True, f"Copied {source} to {target}\n"
+
+def verify_block(source, target, on_progress=None, cancelled=None, chunk_size=4 * 1024**2):
+    """
+    Compares the SHA-256 of the source with the same number of bytes read back from the target.
+    """
+    if backend.DRY_RUN:
+        return True, f"[DRY RUN] Would compare SHA-256 of {source} and {target}\n"
+    size = get_device_bytes(source)
+    if not size:
+        return False, f"Could not determine the size of {source}.\n"
+
+    digests = []
+    for index, path in enumerate((source, target)):
+        digest = hashlib.sha256()
+        done = 0
+        try:
+            with open(path, 'rb') as f:
+                while done < size:
+                    data = f.read(min(chunk_size, size - done))
+                    if not data:
+                        break
+                    digest.update(data)
+                    done += len(data)
+                    if cancelled and cancelled():
+                        return False, "Verification cancelled.\n"
+                    if on_progress:
+                        on_progress({'phase': 'verify', 'percent': round(50.0 * (index + done / size), 1), 'speed': ''})
+        except Exception as e:
+            return False, f"Failed to read {path}\nError: {e}\n"
+        digests.append(digest.hexdigest())
+
+    if digests[0] != digests[1]:
+        return False, f"Checksum mismatch: {source} {digests[0]} != {target} {digests[1]}\n"
+    return True, f"Verified {target} against {source} (sha256 {digests[0][:16]}…).\n"
+
+def is_block_device(path):
+    try:
+        return stat.S_ISBLK(os.stat(path).st_mode)
+    except Exception:
+        return False
+
+def copy_data(source, target, mode="auto", rate_limit=None, verify=True, on_progress=None, cancelled=None):
+    """
+    Copies data between filesystems (rsync) or block devices (ddrescue/dd), then optionally verifies it.
+    mode: 'files' | 'block' | 'auto' (block when both ends are block devices, files otherwise).
+    """
+    if mode == "auto":
+        mode = "block" if is_block_device(source) and is_block_device(target) else "files"
+    logs = []
+
+    if mode == "block":
+        success, out = copy_block(source, target, on_progress, cancelled, rate_limit)
+        logs.append(out)
+        if success and verify:
+            success, out = verify_block(source, target, on_progress, cancelled)
+            logs.append(out)
+        return success, "".join(logs)
+
+    # Block devices copied file by file are mounted for the duration, the source read-only
+    mounts = []
+    dirs = []
+    success = True
+    for path, read_only in ((source, True), (target, False)):
+        if not is_block_device(path):
+            dirs.append(path)
+            continue
+        mountpoint, out = mount_temporary(path, read_only)
+        logs.append(out)
+        if mountpoint is None:
+            success = False
+            break
+        mounts.append(mountpoint)
+        dirs.append(mountpoint)
+
+    try:
+        if success:
+            success, out = copy_files(dirs[0], dirs[1], on_progress, cancelled, rate_limit)
+            logs.append(out)
+        if success and verify:
+            if on_progress:
+                on_progress({'phase': 'verify', 'percent': 0.0, 'speed': ''})
+            success, out = verify_files(dirs[0], dirs[1])
+            logs.append(out)
+    finally:
+        for mountpoint in reversed(mounts):
+            logs.append(unmount_temporary(mountpoint)[1])
+    return success, "".join(logs)
@@ -0,0 +1,160 @@
+import json
+import logging
+import os
+import shutil
+import smtplib
+import socket
+import subprocess
+import time
+import urllib.error
+import urllib.parse
+import urllib.request
+from email.message import EmailMessage
+import backend
+import config
+import hooks
+
+log = logging.getLogger("liveraid")
+
+# Tells people when an array needs attention: the array events of hooks.py (degraded, rebuilt, finished,
+# smart_failed) go to every [[notification.sinks]] entry in config.toml that wants them. email sinks send
+# through SMTP, webhook sinks POST the event as JSON, desktop sinks show a notification in the session of
+# the GUI or tray icon. The events come from the daemon (`cli.py serve` polls the arrays), `cli.py watch`,
+# the GUI, SMART self-test timers and `mdadm --monitor` (PROGRAM calling `cli.py notify mdadm`).
+# A notification that can't be delivered is logged, it never stops what reported the event.
+
+# How long an SMTP server or webhook gets to accept a notification
+SEND_TIMEOUT = 15
+
+# The sinks a process without a desktop session can reach (the daemon, timers, mdadm --monitor)
+SYSTEM_SINKS = ("email", "webhook")
+
+# mdadm --monitor event -> LiveRAID event, the others (NewArray, RebuildStarted, ...) aren't notified
+MDADM_EVENTS = {
+    'Fail': "degraded",
+    'FailSpare': "degraded",
+    'DegradedArray': "degraded",
+    'DeviceDisappeared': "degraded",
+    'SpareActive': "rebuilt",
+    'RebuildFinished': "finished",
+    'TestMessage': "test",
+}
+
+def describe(event):
+    if event['event'] == "test":
+        return f"Test notification for {event['array']}"
+    return hooks.describe(event)
+
+def make_event(kind, array_name, **details):
+    """
+    An event for arrays the caller didn't poll itself (mdadm --monitor, `cli.py notify test`), like EventDetector's.
+    Returns: {'event': 'degraded', 'array': '/dev/md0', 'timestamp': 1700000000, 'host': 'nas', 'state': {...}, 'missing': 1}
+    """
+    state = hooks.array_state(array_name)
+    defaults = {'degraded': {'missing': state['degraded']},
+                'finished': {'action': "rebuild", 'mismatch_cnt': state['mismatch_cnt']}}.get(kind, {})
+    return dict({'event': kind, 'array': array_name, 'timestamp': int(time.time()), 'host': socket.gethostname(), 'state': state},
+                **defaults, **details)
+
+def from_mdadm(mdadm_event, array_name, component=None):
+    """
+    Translates what mdadm --monitor hands its PROGRAM (event, md device, component), None for events that aren't notified.
+    """
+    kind = MDADM_EVENTS.get(mdadm_event)
+    if kind is None:
+        return None
+    # SpareActive also fires for a spare taking over in an array that is still missing members
+    if kind == "rebuilt" and hooks.array_state(array_name)['degraded']:
+        return None
+    details = {'mdadm_event': mdadm_event}
+    if component:
+        details['device'] = component
+    return make_event(kind, array_name, **details)
+
+def send_email(sink, event):
+    """
+    Returns: (success, log_str)
+    """
+    sender = sink['sender'] or f"liveraid@{event['host']}"
+    if backend.DRY_RUN:
+        return True, f"[DRY RUN] Would email {', '.join(sink['to'])} through {sink['smtp_host']}:{sink['smtp_port']}: {describe(event)}\n"
+    message = EmailMessage()
+    message['Subject'] = f"LiveRAID on {event['host']}: {describe(event)}"
+    message['From'] = sender
+    message['To'] = ", ".join(sink['to'])
+    message.set_content(f"{describe(event)}\n\n{json.dumps(event, indent=2, default=str)}\n")
+    try:
+        with smtplib.SMTP(sink['smtp_host'], sink['smtp_port'], timeout=SEND_TIMEOUT) as smtp:
+            if sink['starttls']:
+                smtp.starttls()
+            if sink['username']:
+                smtp.login(sink['username'], sink['password'])
+            smtp.send_message(message)
+    except (smtplib.SMTPException, OSError) as e:
+        return False, f"WARNING: Could not email {', '.join(sink['to'])} through {sink['smtp_host']}: {e}\n"
+    return True, f"Notified {', '.join(sink['to'])} by email.\n"
+
+def masked_url(url):
+    """
+    Where a URL goes without what gets in: webhook URLs carry their secret in the path or query string.
+    Returns: 'https://chat.example.com/...'
+    """
+    parts = urllib.parse.urlsplit(url)
+    host = parts.netloc.rpartition("@")[2]
+    return f"{parts.scheme}://{host}/..." if parts.scheme and host else "<redacted URL>"
+
+def post_webhook(sink, event):
+    """
+    POSTs the event with its summary as JSON. Returns: (success, log_str)
+    """
+    url, shown = sink['url'], masked_url(sink['url'])
+    if backend.DRY_RUN:
+        return True, f"[DRY RUN] Would POST the {event['event']} event to {shown}\n"
+    body = json.dumps(dict(event, summary=describe(event)), default=str).encode()
+    request = urllib.request.Request(url, data=body, method="POST", headers={'Content-Type': "application/json", 'User-Agent': "LiveRAID"})
+    try:
+        with urllib.request.urlopen(request, timeout=SEND_TIMEOUT) as response:
+            return True, f"Notified {shown} (HTTP {response.status}).\n"
+    except urllib.error.HTTPError as e:
+        return False, f"WARNING: {shown} refused the {event['event']} notification: HTTP {e.code} {e.reason}\n"
+    except (urllib.error.URLError, OSError) as e:
+        return False, f"WARNING: Could not notify {shown}: {getattr(e, 'reason', e)}\n"
+
+def desktop_notify(summary, body=""):
+    # notify-send (libnotify) reaches whatever notification daemon the desktop runs
+    if shutil.which("notify-send"):
+        subprocess.run(["notify-send", "--app-name=LiveRAID", summary, body], capture_output=True)
+
+def show_desktop(sink, event):
+    """
+    Returns: (success, log_str)
+    """
+    if not os.environ.get("DBUS_SESSION_BUS_ADDRESS"):
+        return False, "WARNING: A desktop notification sink is configured, but this process has no desktop session.\n"
+    if shutil.which("notify-send") is None:
+        return False, "WARNING: A desktop notification sink is configured, but notify-send is missing (package libnotify-bin).\n"
+    desktop_notify(f"LiveRAID: {event['array']}", describe(event))
+    return True, "Showed a desktop notification.\n"
+
+SENDERS = {'email': send_email, 'webhook': post_webhook, 'desktop': show_desktop}
+
+def has_sinks(kinds=None):
+    return bool(config.get_notification_sinks(kinds))
+
+def dispatch(event, kinds=None):
+    """
+    Sends an event to every sink that wants it, only to sinks of the given types if kinds is set.
+    Returns: (all delivered, log_str)
+    """
+    results = []
+    for sink in config.get_notification_sinks(kinds):
+        if event['event'] not in sink['events']:
+            continue
+        try:
+            results.append(SENDERS[sink['type']](sink, event))
+        except Exception as e:
+            results.append((False, f"WARNING: The {sink['type']} notification of {event['array']} failed: {e}\n"))
+    for ok, out in results:
+        if not ok:
+            log.warning(out.strip().removeprefix("WARNING: "))
+    return all(ok for ok, _ in results), "".join(out for _, out in results)
@@ -0,0 +1,118 @@
+import json
+import re
+import sys
+
+# Machine-readable output for scripts and configuration management (cli.py --format json|yaml).
+# Every document carries schema_version and command; fields are only ever added to a schema
+# version, a rename or removal bumps it.
+
+FORMATS = ("table", "json", "yaml")
+SCHEMA_VERSION = 1
+
+# Line protocol for web UIs (Cockpit plugins, Proxmox) that embed long operations and draw a progress
+# bar: cli.py --output progress-proto. stdout carries only these lines, the log goes to stderr.
+# examples/progress_parser.py reads it.
+#   LIVERAID-PROGRESS 1          protocol version, always the first line
+#   PHASE <id> <description>     a new phase started, id is [a-z0-9-]; PERCENT restarts with it
+#   PERCENT <0.0-100.0>          progress of the current phase
+#   RESULT <exit status>|failed  always the last line, 0 is success, failed when the command crashed
+PROGRESS_PROTOCOL = 1
+PROGRESS_MODES = ("text", "progress-proto")
+progress_proto = False
+_phase = None
+
+# Where documents go. main() points sys.stdout at stderr in json/yaml mode so that prompts and
+# log output never mix with the document.
+STDOUT = sys.stdout
+
+# Strings YAML would read back as something else (booleans, null, numbers) are quoted
+YAML_RESERVED = ("true", "false", "yes", "no", "on", "off", "null", "~", "y", "n")
+YAML_PLAIN = re.compile(r"^[A-Za-z_/][\w./@+-]*$")
+
+def _yaml_scalar(value):
+    if value is None:
+        return "null"
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, (int, float)):
+        return json.dumps(value)
+    text = str(value)
+    if YAML_PLAIN.match(text) and text.lower() not in YAML_RESERVED:
+        return text
+    # A JSON string is a valid double-quoted YAML scalar
+    return json.dumps(text)
+
+def to_yaml(value, indent=0):
+    """
+    Block-style YAML for the JSON-compatible values the documents are made of (no PyYAML on the live image).
+    """
+    pad = "  " * indent
+    if isinstance(value, dict) and value:
+        lines = []
+        for key, item in value.items():
+            if isinstance(item, (dict, list)) and item:
+                lines.append(f"{pad}{_yaml_scalar(key)}:\n{to_yaml(item, indent + 1)}")
+            else:
+                lines.append(f"{pad}{_yaml_scalar(key)}: {to_yaml(item)}")
+        return "\n".join(lines)
+    if isinstance(value, list) and value:
+        lines = []
+        for item in value:
+            if isinstance(item, (dict, list)) and item:
+                nested = to_yaml(item, indent + 1)
+                lines.append(f"{pad}- {nested.lstrip()}")
+            else:
+                lines.append(f"{pad}- {to_yaml(item)}")
+        return "\n".join(lines)
+    if isinstance(value, dict):
+        return "{}"
+    if isinstance(value, list):
+        return "[]"
+    return _yaml_scalar(value)
+
+def emit(fmt, command, data, status=0):
+    """
+    Writes one document for a subcommand and passes its exit status through.
+    Returns: status, so a subcommand can end with 'return output.emit(args.format, "plan", {...}, 1)'
+    """
+    document = {'schema_version': SCHEMA_VERSION, 'command': command}
+    document.update(data)
+    # Round-trip through JSON so both formats see the same plain values (tuples, non-string keys)
+    document = json.loads(json.dumps(document, default=str))
+    if fmt == "yaml":
+        STDOUT.write("---\n" + to_yaml(document) + "\n")
+    else:
+        STDOUT.write(json.dumps(document, indent=2) + "\n")
+    STDOUT.flush()
+    return status
+
+def _progress_line(line):
+    STDOUT.write(line + "\n")
+    STDOUT.flush()
+
+def start_progress():
+    global progress_proto
+    progress_proto = True
+    _progress_line(f"LIVERAID-PROGRESS {PROGRESS_PROTOCOL}")
+
+def progress(phase, description, percent):
+    """
+    Reports the progress of a phase, announcing it first when it differs from the last one.
+    """
+    global _phase
+    phase = re.sub(r"[^a-z0-9-]+", "-", str(phase).lower()).strip("-") or "run"
+    # Descriptions are one line, whatever the step names hold
+    description = " ".join(str(description).split())
+    if (phase, description) != _phase:
+        _phase = (phase, description)
+        _progress_line(f"PHASE {phase} {description}".rstrip())
+    _progress_line(f"PERCENT {min(max(float(percent), 0.0), 100.0):.1f}")
+
+def end_progress(status):
+    """
+    Writes the closing RESULT line: the exit status, or "failed" when the command died of an exception
+    Returns: status; writes nothing outside progress-proto mode
+    """
+    if progress_proto:
+        _progress_line(f"RESULT {status if isinstance(status, int) else 'failed'}")
+    return status
@@ -0,0 +1,198 @@
+import logging
+import os
+import re
+import subprocess
+import backend
+import units
+
+log = logging.getLogger("liveraid")
+
+# New partitions start and end on 1 MiB boundaries, like parted/fdisk place them by default
+ALIGNMENT = 1024**2
+
+# Free regions smaller than this are leftovers between partitions, not room for an array member
+MIN_FREE_BYTES = 1024**3
+
+def get_partition_table(disk):
+    """
+    Reads a disk's partition table and free space from `parted -m ... print free`.
+    Returns: {'label': 'gpt', 'size_bytes': 500107862016,
+              'partitions': [{'number': 1, 'start': 1048576, 'end': 537919487, 'size': 536870912, 'fstype': 'fat32', 'name': 'EFI System Partition', 'flags': ['boot', 'esp']}],
+              'free': [{'start': 537919488, 'end': 500107845119, 'size': 499569925632}]}
+    or None when the disk can't be read. Disks without a partition table have label None and one free region.
+    """
+    try:
+        result = subprocess.run(['parted', '-m', '-s', disk, 'unit', 'B', 'print', 'free'], capture_output=True, text=True)
+    except Exception as e:
+        log.error(f"Failed to read the partition table of {disk}: {e}")
+        return None
+
+    table = {'label': None, 'size_bytes': 0, 'partitions': [], 'free': []}
+    for line in result.stdout.splitlines():
+        fields = line.rstrip(";").split(":")
+        if fields[0] == disk and len(fields) > 5:
+            table['size_bytes'] = int(fields[1].rstrip("B"))
+            table['label'] = fields[5] if fields[5] not in ("unknown", "") else None
+        elif fields[0].isdigit() and len(fields) >= 5:
+            start, end, size = (int(f.rstrip("B")) for f in fields[1:4])
+            if fields[4] == "free":
+                table['free'].append({'start': start, 'end': end, 'size': size})
+            else:
+                table['partitions'].append({
+                    'number': int(fields[0]), 'start': start, 'end': end, 'size': size, 'fstype': fields[4] or None,
+                    'name': fields[5] if len(fields) > 5 else "",
+                    'flags': [f.strip() for f in fields[6].split(",") if f.strip()] if len(fields) > 6 else [],
+                })
+
+    if not table['size_bytes']:
+        size = backend.read_block_attribute(disk, "size")
+        if not size:
+            return None
+        table['size_bytes'] = size * 512
+    if table['label'] is None:
+        # Blank disk: everything after the space GPT needs at both ends is free
+        table['free'] = [{'start': ALIGNMENT, 'end': table['size_bytes'] - ALIGNMENT - 1, 'size': table['size_bytes'] - 2 * ALIGNMENT}]
+    return table
+
+def largest_free_region(table):
+    """
+    Returns the largest free region of a partition table with its start/end moved onto
+    ALIGNMENT boundaries, or None when no region of at least MIN_FREE_BYTES is left.
+    """
+    best = None
+    for region in table['free']:
+        start = -(-region['start'] // ALIGNMENT) * ALIGNMENT
+        end = (region['end'] + 1) // ALIGNMENT * ALIGNMENT - 1
+        if end - start + 1 >= MIN_FREE_BYTES and (best is None or end - start > best['end'] - best['start']):
+            best = {'start': start, 'end': end, 'size': end - start + 1}
+    return best
+
+def partition_path(disk, number):
+    # nvme0n1 -> nvme0n1p3, mmcblk0 -> mmcblk0p3, sda -> sda3
+    return f"{disk}p{number}" if re.search(r"\d$", disk) else f"{disk}{number}"
+
+def next_partition_number(table):
+    # parted hands out the lowest unused partition number
+    used = {p['number'] for p in table['partitions']}
+    return next(n for n in range(1, len(used) + 2) if n not in used)
+
+def check_free_space(disk):
+    """
+    Checks that a disk can take a RAID partition without touching the existing ones.
+    Returns: (region, error) where region is the free space to use, error None when it fits.
+    """
+    table = get_partition_table(disk)
+    if table is None:
+        return None, f"Could not read the partition table of {disk}."
+    if table['label'] == "msdos" and len(table['partitions']) >= 4:
+        return None, f"{disk} has an MBR partition table with 4 partitions already, no room for another primary partition."
+    region = largest_free_region(table)
+    if region is None:
+        return None, f"{disk} has no free region of at least {MIN_FREE_BYTES // 1024**3} GiB outside its existing partitions."
+    return region, None
+
+def add_raid_partition(disk, dry_run=None):
+    """
+    Creates a 'Linux RAID' partition in the largest free region of a disk and leaves every existing
+    partition (ESP, recovery, other OS) as it is. Disks without a partition table get a new GPT first.
+    Returns: (success, log_str, partition_path)
+    """
+    table = get_partition_table(disk)
+    if table is None:
+        return False, f"Could not read the partition table of {disk}.\n", None
+    region, error = check_free_space(disk)
+    if error:
+        return False, f"{error}\n", None
+
+    logs = []
+    if table['label'] is None:
+        success, out = backend.run_command(["parted", "-s", disk, "mklabel", "gpt"], dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs), None
+
+    number = next_partition_number(table)
+    for cmd in (["parted", "-s", disk, "unit", "B", "mkpart", "primary", str(region['start']), str(region['end'])],
+                ["parted", "-s", disk, "set", str(number), "raid", "on"]):
+        success, out = backend.run_command(cmd, dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs), None
+
+    path = partition_path(disk, number)
+    dry_run = backend.DRY_RUN if dry_run is None else dry_run
+    if not dry_run:
+        # Let udev create the new partition's device node before mdadm opens it
+        subprocess.run(["udevadm", "settle"], capture_output=True)
+        if not os.path.exists(path):
+            logs.append(f"{path} did not appear after partitioning {disk}.\n")
+            return False, "".join(logs), None
+    logs.append(f"Created {path} ({units.format_size(region['size'])}) in the free space of {disk}.\n")
+    return True, "".join(logs), path
+
+def free_space_drives(drives):
+    """
+    Returns copies of drive dicts sized to their largest free region, so the usual array
+    validation and capacity estimate apply to the partitions that would be created.
+    Returns: (drives, errors)
+    """
+    sized, errors = [], []
+    for drive in drives:
+        region, error = check_free_space(drive['name'])
+        if error:
+            errors.append(error)
+        else:
+            sized.append(dict(drive, size_bytes=region['size']))
+    return sized, errors
+
+def layout_region(disk_size, layout):
+    """
+    Where the single RAID partition of a plan's partition layout ({'start': bytes or None, 'size': bytes or None})
+    goes on a disk of disk_size bytes, on ALIGNMENT boundaries. start defaults to the first MiB, size to the rest
+    of the disk (less the backup GPT at the end).
+    Returns: (region, error) like check_free_space()
+    """
+    start = -(-(layout.get('start') or ALIGNMENT) // ALIGNMENT) * ALIGNMENT
+    last = disk_size - ALIGNMENT - 1
+    end = start + layout['size'] - 1 if layout.get('size') else last
+    end = (end + 1) // ALIGNMENT * ALIGNMENT - 1
+    if end > last:
+        return None, f"A {units.format_size(layout['size'] or 0)} partition at {units.format_size(start)} does not fit on a {units.format_size(disk_size)} disk."
+    if end - start + 1 < MIN_FREE_BYTES:
+        return None, f"A RAID partition needs at least {MIN_FREE_BYTES // 1024**3} GiB."
+    return {'start': start, 'end': end, 'size': end - start + 1}, None
+
+def layout_commands(disk, region):
+    # A fresh GPT with one 'Linux RAID' partition, whatever was on the disk before
+    return [["parted", "-s", disk, "mklabel", "gpt"],
+            ["parted", "-s", disk, "unit", "B", "mkpart", "primary", str(region['start']), str(region['end'])],
+            ["parted", "-s", disk, "set", "1", "raid", "on"]]
+
+def create_layout(disk, layout, dry_run=None):
+    """
+    Replaces a disk's partition table with a new GPT holding one RAID partition placed by layout_region().
+    Returns: (success, log_str, partition_path)
+    """
+    size = backend.read_block_attribute(disk, "size")
+    if not size:
+        return False, f"Could not read the size of {disk}.\n", None
+    region, error = layout_region(size * 512, layout)
+    if error:
+        return False, f"{disk}: {error}\n", None
+
+    logs = []
+    for cmd in layout_commands(disk, region):
+        success, out = backend.run_command(cmd, dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs), None
+
+    path = partition_path(disk, 1)
+    dry_run = backend.DRY_RUN if dry_run is None else dry_run
+    if not dry_run:
+        subprocess.run(["udevadm", "settle"], capture_output=True)
+        if not os.path.exists(path):
+            logs.append(f"{path} did not appear after partitioning {disk}.\n")
+            return False, "".join(logs), None
+    logs.append(f"Created {path} ({units.format_size(region['size'])}) on a new GPT on {disk}.\n")
+    return True, "".join(logs), path
@@ -0,0 +1,721 @@
+import json
+import os
+import re
+import shutil
+import subprocess
+import arraynames
+import backend
+import config
+import doctor
+import fsoptions
+import fstab
+import luks
+import partition
+import policy
+import raidbackend
+import timers
+import units
+import wipe
+
+# A plan describes a complete array before anything is touched: members, mdadm options,
+# filesystem and where it ends up mounted. Frontends build one, validate it, then show its steps.
+
+# Built-in starting points for common homelab setups. Every value can still be overridden
+# field by field (e.g. `cli.py plan --preset nas-bulk --filesystem ext4 ...`).
+PRESETS = {
+    'nas-bulk': {
+        'title': "NAS bulk storage",
+        'description': "Large media/file shares on many HDDs: survives two failed disks, big chunks for "
+                       "sequential throughput, no access-time writes, checked for bit rot once a month and "
+                       "SMART self-tested weekly (short) and monthly (long).",
+        'level': "6", 'chunk': "512K", 'filesystem': "xfs", 'mount_options': ["noatime"], 'scrub': "monthly",
+        'smart_short': "weekly", 'smart_long': "monthly",
+    },
+    'vm-store': {
+        'title': "VM store",
+        'description': "Virtual machine images on SSDs or fast HDDs: striped mirrors for random I/O, "
+                       "small chunks and larger XFS log buffers for many concurrent small writes.",
+        'level': "10", 'chunk': "64K", 'filesystem': "xfs", 'mount_options': ["noatime", "logbufs=8", "logbsize=256k"],
+        'scrub': "monthly",
+    },
+    'backup': {
+        'title': "Backup target",
+        'description': "Two-disk mirror for backups: btrfs checksums every block and zstd compression "
+                       "stretches the capacity, with a monthly scrub.",
+        'level': "1", 'filesystem': "btrfs", 'mount_options': ["noatime", "compress=zstd"], 'scrub': "monthly",
+    },
+}
+
+SCRUB_SCHEDULES = ["weekly", "monthly"]
+# SMART self-tests of the members, installed as systemd timers (timers.py)
+SMART_SCHEDULES = list(timers.SCHEDULES)
+
+# Version of the plan file format written by save_plan(). Files without one come from before
+# versioning and have the same fields; files from a newer LiveRAID are refused rather than guessed at.
+# Version 2 added the RAID backend, older files were always built with mdadm.
+# Version 3 added the SMART self-test schedules (smart_short, smart_long).
+# Version 4 added LUKS encryption (never the passphrase, only how the container is unlocked).
+# Version 5 added partition layouts (members built on a new RAID partition instead of the whole disk).
+# Version 6 added the wipe stage (wipe.py), older files get wipe_policy from config.toml as before.
+# Version 7 added the mdadm tunables metadata, bitmap, layout (RAID 10) and name, left to mdadm when missing.
+# Version 8 added fs_options (fsoptions.py), older files get the derived stripe geometry only.
+# Version 9 added the identities (serial, WWN, size) of the disks as they were when the plan was made.
+# Version 10 added the RAID 1 tunables write_mostly, write_behind and auto_read_only, off when missing.
+# Version 11 added existing, for arrays created outside LiveRAID that apply only formats and configures.
+PLAN_VERSION = 11
+
+def new_plan(level=None, devices=(), array_name=None, filesystem=None, mount_point=None, label=None, chunk=None,
+             spares=None, mount_options=None, mkfs_options=None, scrub=None, preset=None, preserve_partitions=False,
+             backend=None, smart_short=None, smart_long=None, encryption=None, partitions=None, wipe=None, metadata=None,
+             bitmap=None, layout=None, name=None, planned=(), fs_options=None, identities=None, write_mostly=None,
+             write_behind=None, auto_read_only=None, existing=False):
+    """
+    Builds a plan from a preset (optional) and explicit values, which win over the preset's.
+    backend defaults to raid_backend from config.toml (see raidbackend.py).
+    Returns: {'level': '1', 'devices': ['/dev/sdb', '/dev/sdc'], 'array': '/dev/md0', 'backend': 'mdadm', 'filesystem': 'ext4',
+              'mount_point': '/srv/data', 'label': 'data', 'chunk': 'Default', 'spares': ['/dev/sdd'],
+              'mount_options': ['noatime'], 'mkfs_options': [], 'scrub': 'monthly', 'smart_short': 'weekly',
+              'smart_long': 'monthly', 'preset': 'backup', 'preserve_partitions': False,
+              'encryption': {...as luks.new_encryption()...} or None,
+              'partitions': {'/dev/sdb': {'start': None, 'size': 500107862016}}, 'wipe': {'method': 'zero', 'block_size': '4M'}}
+    partitions lists the members that get a new GPT with one RAID partition (start/size in bytes, None for the
+    first MiB/the rest of the disk) instead of being used whole. wipe is the wipe stage run on members and spares
+    before anything is built (see wipe.py). metadata ('1.2'), bitmap ('internal'/'none'), layout (RAID 10: 'near',
+    'far', 'offset') and name (the array's name in its superblock, /dev/md/<name>) are mdadm's defaults when None.
+    write_mostly (['/dev/sdc'], RAID 1 members read from only when the others can't be), write_behind (how many writes
+    to them may be outstanding, 0 for none) and auto_read_only (the array stays read-only until the first write)
+    are what the GUI's advanced options set. existing plans an md array that runs already (created by hand with
+    whatever mdadm flags it needed): apply records it in mdadm.conf, then formats, mounts and schedules it like its own.
+    planned lists the arrays of other plans in the same session, the default array name skips them.
+    fs_options ({'inode_size': '512', 'reserved': '1'}) tune the filesystem on top of the stripe geometry
+    derived from the chunk size and members (see fsoptions.py). identities ({'/dev/sdb': backend.drive_identity(...)})
+    are the disks the plan was made for, see record_identities().
+    """
+    if preset is not None and preset not in PRESETS:
+        raise ValueError(f"Unknown preset '{preset}' (available: {', '.join(PRESETS)})")
+    defaults = PRESETS.get(preset, {})
+
+    def pick(key, value, fallback):
+        return value if value is not None else defaults.get(key, fallback)
+
+    level = pick('level', level, None)
+    raid = raidbackend.get_backend(backend)
+    return {
+        'level': str(level) if level is not None else None,
+        'devices': list(devices),
+        'array': array_name or raid.default_name(list(devices), name, planned),
+        'backend': raid.name,
+        'filesystem': pick('filesystem', filesystem, "ext4"),
+        'mount_point': mount_point,
+        'label': label,
+        'chunk': pick('chunk', chunk, "Default"),
+        'spares': list(spares or []),
+        'mount_options': list(pick('mount_options', mount_options, [])),
+        'mkfs_options': list(pick('mkfs_options', mkfs_options, [])),
+        'fs_options': dict(pick('fs_options', fs_options, {})),
+        'scrub': pick('scrub', scrub, None),
+        'smart_short': pick('smart_short', smart_short, None),
+        'smart_long': pick('smart_long', smart_long, None),
+        'preset': preset,
+        'preserve_partitions': bool(preserve_partitions),
+        'encryption': luks.new_encryption(encryption.get('cipher'), encryption.get('key_size'), encryption.get('pbkdf'),
+                                          encryption.get('keyfile')) if encryption else None,
+        'partitions': {device: {'start': layout.get('start'), 'size': layout.get('size')} for device, layout in (partitions or {}).items()},
+        'wipe': wipe_policy(wipe, preserve_partitions or existing),
+        'metadata': pick('metadata', metadata, None),
+        'bitmap': pick('bitmap', bitmap, None),
+        'layout': pick('layout', layout, None),
+        'name': name,
+        'write_mostly': list(write_mostly or []),
+        'write_behind': write_behind or 0,
+        'auto_read_only': bool(auto_read_only),
+        'existing': bool(existing),
+        'identities': {device: dict(identity) for device, identity in (identities or {}).items()},
+    }
+
+def wipe_policy(fields, keep=False):
+    # Keeping the existing partitions (or array) means keeping the partition table, nothing is wiped unless asked for
+    if fields is None and keep:
+        return wipe.new_wipe("none")
+    fields = fields or {}
+    return wipe.new_wipe(fields.get('method'), fields.get('block_size'))
+
+def record_identities(plan, drives):
+    """
+    Keeps the identities of a plan's disks in the plan as discovered when it is made (drives from
+    RaidService.inventory()), so a disk swapped before 'apply' is noticed. Members of an applied plan's array
+    aren't discovered, they are read from the disk.
+    """
+    found = {dev: backend.drive_identity(drives[dev]) if dev in drives else backend.get_device_identity(dev)
+             for dev in plan['devices'] + plan['spares']}
+    plan['identities'] = {dev: identity for dev, identity in found.items() if identity}
+    return plan
+
+def identities(plan, drives):
+    """
+    The identities of a plan's disks that RaidService.apply_plan() checks before touching them: those recorded in the
+    plan, as discovered now for plans from before version 9 (or disks the plan didn't record).
+    """
+    missing = [dev for dev in plan['devices'] + plan['spares'] if dev not in plan['identities']]
+    found = record_identities(dict(plan, devices=missing, spares=[]), drives)['identities'] if missing else {}
+    return dict(plan['identities'], **found)
+
+def volume_device(plan):
+    # Where the filesystem goes: the array's partition with mdadm, the volume itself with LVM or btrfs,
+    # or the opened LUKS container on top of either
+    if plan['encryption']:
+        return luks.mapper_device(plan['array'])
+    return raidbackend.get_backend(plan['backend']).volume_device(plan)
+
+def parse_chunk(chunk):
+    """
+    Returns a chunk size like '64K' or '1M' in KiB, or None if it isn't one mdadm accepts
+    (a power of two of at least 4 KiB).
+    """
+    match = re.fullmatch(r"(\d+)([KM]?)", str(chunk).strip(), re.IGNORECASE)
+    if not match:
+        return None
+    kib = int(match.group(1)) * (1024 if match.group(2).upper() == "M" else 1)
+    if kib < 4 or kib & (kib - 1):
+        return None
+    return kib
+
+def check_fields(plan):
+    """
+    Checks that a plan's fields are consistent with each other, without looking at the drives or the running system.
+    Returns: [{'field': 'spares', 'message': '...'}]
+    """
+    errors = []
+
+    def error(field, message):
+        errors.append({'field': field, 'message': message})
+
+    level, devices, spares = plan['level'], plan['devices'], plan['spares']
+    if level is None:
+        error('level', f"No RAID level given (use {', '.join(backend.MIN_DEVICES)}, or a preset)")
+    elif level not in backend.MIN_DEVICES:
+        error('level', f"Unsupported RAID level: {level} (use {', '.join(backend.MIN_DEVICES)})")
+    duplicates = sorted({dev for dev in devices if devices.count(dev) > 1})
+    if duplicates:
+        error('devices', f"{', '.join(duplicates)} listed more than once.")
+
+    for dev in spares:
+        if dev in devices:
+            error('spares', f"{dev} is already an active member, it can't also be a spare.")
+    if spares and level == "0":
+        error('spares', "RAID 0 has no redundancy to rebuild, it cannot use spares.")
+    if len(set(spares)) != len(spares):
+        error('spares', "A spare is listed more than once.")
+
+    if level in backend.MIN_DEVICES:
+        for field, message in backend.check_md_options(level, plan['metadata'], plan['bitmap'], plan['layout'], plan['name']):
+            error(field, message)
+
+    if plan['existing']:
+        # Whoever created the array chose these, apply leaves the array as it is
+        for field in ('preserve_partitions', 'partitions', 'metadata', 'bitmap', 'layout', 'name', 'write_mostly', 'write_behind', 'auto_read_only'):
+            if plan[field]:
+                error(field, f"{plan['array']} exists already, this is set when an array is created.")
+        if plan['wipe']['method'] != "none":
+            error('wipe', f"Wiping the members of {plan['array']} would destroy it, use --wipe none.")
+
+    for dev in plan['write_mostly']:
+        if dev not in devices:
+            error('write_mostly', f"{dev} is not an active member, only members can be write-mostly.")
+    if plan['write_mostly'] and level != "1":
+        error('write_mostly', "Write-mostly members are only supported on RAID 1.")
+    if not isinstance(plan['write_behind'], int) or not 0 <= plan['write_behind'] <= backend.MAX_WRITE_BEHIND:
+        error('write_behind', f"Write-behind is a number of writes from 0 to {backend.MAX_WRITE_BEHIND}.")
+    elif plan['write_behind'] and not plan['write_mostly']:
+        error('write_behind', "Write-behind requires at least one write-mostly member.")
+    elif plan['write_behind'] and plan['bitmap'] == "none":
+        error('write_behind', "Write-behind needs the write-intent bitmap, it can't be combined with bitmap none.")
+
+    if plan['chunk'] != "Default":
+        if level == "1":
+            error('chunk', "RAID 1 mirrors whole devices and has no chunk size.")
+        elif parse_chunk(plan['chunk']) is None:
+            error('chunk', f"Invalid chunk size '{plan['chunk']}', use a power of two of at least 4K, e.g. 64K or 512K.")
+
+    fs_type = plan['filesystem']
+    if fs_type not in config.SUPPORTED_FILESYSTEMS:
+        error('filesystem', f"Unsupported filesystem: {fs_type} (use {', '.join(config.SUPPORTED_FILESYSTEMS)})")
+
+    for message in fsoptions.check(fs_type, plan['fs_options']):
+        error('fs_options', message)
+
+    label = plan['label']
+    if label is not None:
+        limit = backend.LABEL_MAX_LENGTH.get(fs_type)
+        if not label:
+            error('label', "The label can't be empty, leave it out instead.")
+        elif limit and len(label) > limit:
+            error('label', f"'{label}' is {len(label)} characters long, {fs_type} labels are limited to {limit}.")
+
+    mount_point = plan['mount_point']
+    if mount_point is not None:
+        if not os.path.isabs(mount_point):
+            error('mount_point', f"'{mount_point}' is not an absolute path.")
+        elif os.path.normpath(mount_point) == "/":
+            error('mount_point', "Mounting a new array over / would hide the running system.")
+
+    if any("," in option or not option.strip() for option in plan['mount_options']):
+        error('mount_options', "Give each mount option on its own, e.g. noatime and compress=zstd.")
+
+    if plan['scrub'] is not None and plan['scrub'] not in SCRUB_SCHEDULES:
+        error('scrub', f"Unknown scrub schedule '{plan['scrub']}' (use {', '.join(SCRUB_SCHEDULES)})")
+    if plan['scrub'] and level == "0":
+        error('scrub', "RAID 0 has no redundancy to compare, scrubbing it finds nothing.")
+    for field in ('smart_short', 'smart_long'):
+        if plan[field] is not None and plan[field] not in SMART_SCHEDULES:
+            error(field, f"Unknown SMART self-test schedule '{plan[field]}' (use {', '.join(SMART_SCHEDULES)})")
+
+    for device, layout in plan['partitions'].items():
+        if device not in devices:
+            error('partitions', f"{device} has a partition layout but is not a member.")
+        elif any(value is not None and (not isinstance(value, int) or value < 0) for value in layout.values()):
+            error('partitions', f"The partition start and size of {device} must be byte counts.")
+    if plan['partitions'] and plan['preserve_partitions']:
+        error('partitions', "Partition layouts replace the partition table, they can't be combined with keeping existing partitions.")
+
+    for message in wipe.check(plan['wipe']):
+        error('wipe', message)
+    if plan['preserve_partitions'] and plan['wipe']['method'] != "none":
+        error('wipe', "Wiping the disks erases the partitions that are to be kept, use --wipe none.")
+
+    if plan['encryption']:
+        for message in luks.check(plan['encryption']):
+            error('encryption', message)
+        if fs_type == "zfs":
+            error('encryption', "ZFS brings its own encryption, use it instead of LUKS.")
+
+    errors.extend(raidbackend.get_backend(plan['backend']).check(plan))
+    return errors
+
+def build_plan(**fields):
+    """
+    Same as new_plan(), but refuses to return a plan whose fields contradict each other
+    (e.g. a spare that is also a member, or a chunk size on RAID 1).
+    Raises ValueError listing every inconsistent field.
+    """
+    built = new_plan(**fields)
+    errors = check_fields(built)
+    if errors:
+        raise ValueError("; ".join(f"{e['field']}: {e['message']}" for e in errors))
+    return built
+
+def validate_plan(plan, drives, suppress=None, strict=None, others=()):
+    """
+    Checks every field of a plan against the current drive inventory (as returned by RaidService.inventory()).
+    Each error names the plan field it is about, so frontends can point at the option to change.
+    others are the session's other plans, not applied yet: they must not share the array name or any drive.
+    strict refuses drives with failing SMART health instead of warning (see backend.validate_array()).
+    A plan whose array runs already with its members (applied before, see reconcile.py) isn't refused for them
+    being in use, applying it again only adds what is missing.
+    Returns: {'errors': [{'field': 'chunk', 'message': '...'}], 'warnings': [{'code': 'mixed-media', 'message': '...'}],
+              'capacity_bytes': 1000204886016, 'summary': {...as backend.capacity_summary()...},
+              'checklist': [...as backend.validation_checklist()...]}
+    """
+    errors = check_fields(plan)
+
+    def error(field, message):
+        errors.append({'field': field, 'message': message})
+
+    level, devices, spares = plan['level'], plan['devices'], plan['spares']
+    report = {'errors': [], 'warnings': []}
+    warnings = []
+    raid = raidbackend.get_backend(plan['backend'])
+    applied = raid.applied(plan)
+    if level in backend.MIN_DEVICES and not applied:
+        unknown = [dev for dev in devices if dev not in drives]
+        if unknown:
+            error('devices', f"{', '.join(unknown)} not available (mounted, in use by an array, or missing).")
+        else:
+            members = [drives[dev] for dev in devices]
+            if plan['preserve_partitions']:
+                # Members are sized by the free space the new RAID partitions get, not the whole disk
+                members, space_errors = partition.free_space_drives(members)
+                for message in space_errors:
+                    error('devices', message)
+            members = layout_drives(plan, members)
+            for dev in devices:
+                if dev in plan['partitions']:
+                    message = partition.layout_region(drives[dev]['size_bytes'], plan['partitions'][dev])[1]
+                    if message:
+                        error('partitions', f"{dev}: {message}")
+            # The planned spares are checked below, validate_array() only looks at whether there are any
+            report = backend.validate_array(level, members, suppress, None if spares else [], strict, plan['metadata']) if len(members) == len(devices) else report
+            for message in report['errors']:
+                error('devices', message)
+
+    members = [drives[dev] for dev in devices if dev in drives]
+    if plan['preserve_partitions']:
+        members = partition.free_space_drives(members)[0]
+    members = layout_drives(plan, members)
+    smallest = min((d['size_bytes'] for d in members), default=0)
+    for dev in [] if applied else spares:
+        if dev in devices:
+            continue
+        if dev not in drives:
+            error('spares', f"{dev} not available (mounted, in use by an array, or missing).")
+        elif drives[dev]['size_bytes'] < smallest:
+            error('spares', f"{dev} ({units.format_size(drives[dev]['size_bytes'])}) is smaller than the members ({units.format_size(smallest)}), it could never replace one.")
+    # The members' SMART health is part of validate_array() above
+    suppressed = set(config.get_setting('suppress_warnings')) | set(suppress or [])
+    if plan['existing'] and not applied:
+        error('array', f"{plan['array']} is not a running md array, create or assemble it first.")
+    elif plan['existing']:
+        # Re-applying finds the planned filesystem, anything else on the array is someone's data
+        contents = backend.get_device_contents(plan['array'])
+        if contents is None and not (backend.DRY_RUN or backend.commands_recorded()):
+            error('array', f"Could not look at what {plan['array']} holds.")
+        elif contents and fstab.get_filesystem_type(volume_device(plan)) != plan['filesystem']:
+            error('array', f"{plan['array']} is not empty, it holds: {', '.join(contents)}. Only empty arrays are reused.")
+    if applied and not plan['existing'] and "already-applied" not in suppressed:
+        warnings.append({'code': "already-applied", 'message': f"{plan['array']} already runs with these members, "
+                                                               "applying the plan again only adds what is missing."})
+    for code, message in backend.smart_findings([drives[dev] for dev in spares if dev in drives and dev not in devices]):
+        if code == "smart-failing" and backend.smart_strict(strict):
+            error('spares', message)
+        elif code not in suppressed:
+            warnings.append({'code': code, 'message': message})
+
+    capacity = backend.estimate_array_capacity(level, members) if members else 0
+    if applied:
+        capacity = backend.get_device_size(os.path.basename(os.path.realpath(plan['array'])))
+    fs_type = plan['filesystem']
+    if fs_type in config.SUPPORTED_FILESYSTEMS:
+        mkfs_cmd = backend.build_mkfs_command(fs_type, volume_device(plan), extra_options=fsoptions.plan_arguments(plan), label=plan['label'])
+        for message in backend.check_filesystem_limits(fs_type, plan['array'], mkfs_cmd, size_bytes=capacity):
+            error('filesystem', message)
+
+    if raid.exists(plan) and not applied and raid.name == "btrfs":
+        error('devices', f"{plan['array']} belongs to another btrfs filesystem, which applying would format over. "
+                         f"Clear it with 'wipefs -a' first if its data can go.")
+    elif raid.exists(plan) and not applied:
+        error('array', f"{plan['array']} already exists, pick another name (or run 'cleanup-md' if it is a leftover).")
+    elif raid.name == "mdadm" and not applied:
+        collision = arraynames.collision(plan['array'])
+        if collision:
+            error('array', f"{plan['array']} is {collision}, pick another name.")
+    for field, message in session_conflicts(plan, others):
+        error(field, message)
+    missing = [tool for tool in raid.tools if shutil.which(tool) is None]
+    if missing:
+        message = f"The {raid.name} backend needs {', '.join(missing)}: {'; '.join(doctor.install_hint(tool) for tool in missing)}"
+        # A dry run only prints the steps (a mock simulation records them), so it can be planned on a machine without the tools
+        if backend.DRY_RUN or backend.commands_recorded():
+            warnings.append({'code': "missing-tools", 'message': message})
+        else:
+            error('backend', message)
+    if plan['wipe']['method'] == "discard":
+        unsupported = [dev for dev in devices + spares if dev in drives and not backend.device_supports_discard(dev)]
+        if unsupported:
+            error('wipe', f"{', '.join(unsupported)} can't discard (HDDs and most USB disks), wipe them with zero instead.")
+    if plan['encryption'] and shutil.which("cryptsetup") is None:
+        message = f"Encryption needs cryptsetup: {doctor.install_hint('cryptsetup')}"
+        if backend.DRY_RUN:
+            warnings.append({'code': "missing-tools", 'message': message})
+        else:
+            error('encryption', message)
+    if plan['encryption'] and plan['encryption']['keyfile'] and not os.path.isfile(plan['encryption']['keyfile']):
+        error('encryption', f"The keyfile {plan['encryption']['keyfile']} does not exist.")
+    if (plan['smart_short'] or plan['smart_long']) and shutil.which("smartctl") is None:
+        message = f"Scheduled SMART self-tests need smartctl: {doctor.install_hint('smartctl')}"
+        if backend.DRY_RUN:
+            warnings.append({'code': "missing-tools", 'message': message})
+        else:
+            error('smart_short' if plan['smart_short'] else 'smart_long', message)
+
+    mount_point = plan['mount_point']
+    if mount_point is not None and os.path.isabs(mount_point) and os.path.normpath(mount_point) != "/":
+        # The volume of an applied plan may be mounted there already
+        if os.path.ismount(mount_point) and not (applied and os.path.normpath(mount_point) in backend.get_mountpoints(volume_device(plan))):
+            error('mount_point', f"Something is already mounted on {mount_point}.")
+        elif os.path.exists(mount_point) and not os.path.isdir(mount_point):
+            error('mount_point', f"{mount_point} exists and is not a directory.")
+
+    if level in backend.MIN_DEVICES and members:
+        # An applied plan's array may hold swap already, a new one never does
+        swap = applied and plan['backend'] == "mdadm" and policy.active_swap(plan['array'])
+        verdict = policy.evaluate(level, members, [drives[dev] for dev in spares if dev in drives], mount_point, suppress, swap)
+        for violation in verdict['errors']:
+            error(violation['field'], violation['message'])
+        warnings += verdict['warnings']
+
+    summary = backend.capacity_summary(level, members, spares)
+    result = {'errors': errors, 'warnings': report['warnings'] + warnings, 'capacity_bytes': capacity, 'summary': summary}
+    result['checklist'] = backend.validation_checklist(result)
+    return result
+
+def session_conflicts(plan, others):
+    """
+    What a plan shares with the other plans of a session, which would clobber each other when applied.
+    Returns: [('array', '/dev/md0 is already planned for RAID 1 on /dev/sdb, /dev/sdc.'), ('devices', '...')]
+    """
+    conflicts = []
+    for other in others:
+        described = f"RAID {other['level']} on {', '.join(other['devices'])}"
+        if other['array'] == plan['array']:
+            conflicts.append(('array', f"{plan['array']} is already planned for {described}."))
+        taken = set(other['devices']) | set(other['spares'])
+        for field in ('devices', 'spares'):
+            shared = [dev for dev in plan[field] if dev in taken]
+            if shared:
+                conflicts.append((field, f"{', '.join(shared)} {'is' if len(shared) == 1 else 'are'} already part of the plan for "
+                                        f"{other['array']} ({described})."))
+    return conflicts
+
+def layout_drives(plan, drives):
+    """
+    Returns copies of the member drive dicts sized to the RAID partition their partition layout gives them,
+    so validation and the capacity estimate see what the array is built on (members that don't fit are left out).
+    """
+    sized = []
+    for drive in drives:
+        layout = plan['partitions'].get(drive['name'])
+        if layout is None:
+            sized.append(drive)
+            continue
+        region = partition.layout_region(drive['size_bytes'], layout)[0]
+        if region:
+            sized.append(dict(drive, size_bytes=region['size']))
+    return sized
+
+def select_devices(drive_filter, drives, taken=(), include_boot_disk=False):
+    """
+    Picks the members for a plan with a devicefilter.DeviceFilter: every matching drive not already taken
+    (listed as member or spare), sorted by name so the same hardware always gives the same plan. The disk holding
+    /, /boot or the live medium is never picked by a filter unless include_boot_disk says so.
+    Returns: ['/dev/nvme0n1', '/dev/nvme1n1']
+    """
+    if not include_boot_disk:
+        drive_filter.exclude_boot_disk()
+    return sorted(d['name'] for d in drive_filter.apply(drives) if d['name'] not in taken)
+
+def describe_layout(layout):
+    size = units.format_size(layout['size']) if layout['size'] else "rest of disk"
+    return f"{size} RAID partition at {units.format_size(layout['start'] or partition.ALIGNMENT)}"
+
+def mount_options(plan):
+    # nofail: a missing or degraded array must not drop the boot into emergency mode
+    return ",".join(["defaults"] + plan['mount_options'] + ["nofail"])
+
+def plan_steps(plan):
+    """
+    Returns the commands applying the plan runs, in order, as printable strings.
+    """
+    members = plan['devices']
+    steps = []
+    for disk in plan['devices'] + plan['spares']:
+        size = backend.read_block_attribute(disk, "size")
+        cmd = wipe.wipe_command(plan['wipe'], disk, size * 512 if size else None)
+        if cmd:
+            steps.append(" ".join(cmd))
+    if plan['preserve_partitions']:
+        members = []
+        for disk in plan['devices']:
+            # Only new partitions in free space, the existing ones are never touched
+            table = partition.get_partition_table(disk)
+            region = partition.largest_free_region(table) if table else None
+            number = partition.next_partition_number(table) if table else 1
+            if table and table['label'] is None:
+                steps.append(f"parted -s {disk} mklabel gpt")
+            where = f"{region['start']} {region['end']}" if region else "<free space>"
+            steps.append(f"parted -s {disk} unit B mkpart primary {where}")
+            steps.append(f"parted -s {disk} set {number} raid on")
+            members.append(partition.partition_path(disk, number))
+    elif plan['partitions']:
+        members = []
+        for disk in plan['devices']:
+            layout = plan['partitions'].get(disk)
+            if layout is None:
+                members.append(disk)
+                continue
+            size = backend.read_block_attribute(disk, "size")
+            region = partition.layout_region(size * 512, layout)[0] if size else None
+            if region:
+                steps += [" ".join(cmd) for cmd in partition.layout_commands(disk, region)]
+            else:
+                steps.append(f"parted -s {disk} mklabel gpt, then mkpart primary ({describe_layout(layout)}) and set 1 raid on")
+            members.append(partition.partition_path(disk, 1))
+    raid = raidbackend.get_backend(plan['backend'])
+    partition_dev = volume_device(plan)
+    if plan['existing']:
+        steps.append(f"use the running {plan['array']} instead of creating it")
+    else:
+        steps += [" ".join(cmd) for cmd in raid.create_commands(plan, members)]
+    if plan['auto_read_only']:
+        steps.append(f"echo read-auto > {backend.md_sysfs_dir(plan['array'])}/array_state")
+    if raid.record_step(plan):
+        steps.append(raid.record_step(plan))
+    steps += [" ".join(cmd or [f"mkfs.{plan['filesystem']}", partition_dev]) for cmd in raid.format_commands(plan)]
+    if plan['encryption']:
+        steps.append(f"add to /etc/crypttab: {luks.mapper_name(plan['array'])} UUID=<new LUKS container> "
+                     f"{plan['encryption']['keyfile'] or 'none'} luks")
+    if plan['mount_point']:
+        steps.append(f"mkdir -p {plan['mount_point']}")
+        steps.append(f"mount -o {mount_options(plan)} {partition_dev} {plan['mount_point']}")
+        steps.append(f"add to /etc/fstab: UUID=<new filesystem> {plan['mount_point']} {plan['filesystem']} {mount_options(plan)} 0 2")
+    if plan['scrub']:
+        steps.append(f"schedule a {plan['scrub']} scrub (check) of {plan['array']}")
+    for test in ("short", "long"):
+        if plan[f'smart_{test}']:
+            steps.append(f"install systemd timer {timers.unit_name(f'smart-{test}', plan['array'])}.timer: "
+                         f"{plan[f'smart_{test}']} {test} SMART self-test of the members")
+    return steps
+
+# Fields shown when two plans are compared, in display order
+COMPARE_FIELDS = ['array', 'backend', 'level', 'devices', 'partitions', 'spares', 'chunk', 'layout', 'metadata', 'bitmap', 'name',
+                  'write_mostly', 'write_behind', 'auto_read_only', 'encryption',
+                  'filesystem', 'label', 'mount_point', 'mount_options', 'scrub', 'smart_short', 'smart_long']
+
+def save_plan(plan, path):
+    """
+    Writes a plan as JSON. Raises ValueError instead of saving a plan whose fields contradict each other.
+    """
+    errors = check_fields(plan)
+    if errors:
+        raise ValueError("; ".join(f"{e['field']}: {e['message']}" for e in errors))
+    with open(path, 'w') as f:
+        json.dump(dict(plan, version=PLAN_VERSION), f, indent=2)
+        f.write("\n")
+
+def load_plan(path):
+    """
+    Reads a plan file written by save_plan(). Fields missing from older files (version 0) get their defaults.
+    Raises ValueError when the file isn't a plan or its fields contradict each other (e.g. after a hand edit).
+    """
+    with open(path, 'r') as f:
+        data = json.load(f)
+    return plan_from_dict(data, path)
+
+def plan_from_dict(data, source="the plan"):
+    """
+    Same as load_plan() for a plan that was already parsed, e.g. one sent to the daemon (see daemon.py).
+    source names it in error messages.
+    """
+    if not isinstance(data, dict) or 'devices' not in data:
+        raise ValueError(f"{source} is not a LiveRAID plan")
+    version = data.get('version', 0)
+    if not isinstance(version, int) or version > PLAN_VERSION:
+        raise ValueError(f"{source} is a version {version} plan, this LiveRAID reads up to version {PLAN_VERSION}. Update LiveRAID or re-create the plan.")
+    known = ['level', 'devices', 'filesystem', 'mount_point', 'label', 'chunk', 'spares', 'mount_options', 'mkfs_options', 'scrub', 'preset',
+             'preserve_partitions', 'smart_short', 'smart_long', 'encryption', 'partitions', 'wipe', 'metadata', 'bitmap', 'layout', 'name', 'fs_options',
+             'identities', 'write_mostly', 'write_behind', 'auto_read_only', 'existing']
+    try:
+        return build_plan(array_name=data.get('array'), backend=data.get('backend', "mdadm"),
+                          **{key: data[key] for key in known if key in data})
+    except ValueError as e:
+        raise ValueError(f"{source}: {e}")
+
+def plan_from_array(array_name):
+    """
+    Describes a running array as a plan, so what was actually built can be compared with what was planned.
+    """
+    level = backend.read_md_attribute(array_name, "level") or ""
+    chunk_bytes = backend.read_md_attribute(array_name, "chunk_size")
+    members = backend.get_array_members(array_name)
+    current = new_plan(
+        level.replace("raid", "") or None,
+        [m['device'] for m in members if m['device'] and m['role'] in ("active", "rebuilding")],
+        array_name,
+        chunk=f"{int(chunk_bytes) // 1024}K" if chunk_bytes and chunk_bytes.isdigit() and int(chunk_bytes) else "Default",
+        spares=[m['device'] for m in members if m['device'] and m['role'] == "spare"],
+        backend="mdadm",
+    )
+    current['filesystem'] = None
+    current['metadata'] = backend.read_md_attribute(array_name, "metadata_version")
+    location = backend.read_md_attribute(array_name, "bitmap/location")
+    current['bitmap'] = None if location is None else ("none" if location == "none" else "internal")
+    current['layout'] = raid10_layout(backend.read_md_attribute(array_name, "layout")) if current['level'] == "10" else None
+    current['name'] = md_name(array_name)
+    current['write_mostly'] = [m['device'] for m in members if m['device'] and "writemostly" in m['state']]
+    backlog = backend.read_md_attribute(array_name, "bitmap/backlog")
+    current['write_behind'] = int(backlog) if backlog and backlog.isdigit() else 0
+    current['auto_read_only'] = backend.read_md_attribute(array_name, "array_state") == "read-auto"
+    for timer in timers.list_timers("smart-"):
+        for test in ("short", "long"):
+            if timer['name'] == timers.unit_name(f"smart-{test}", array_name):
+                current[f'smart_{test}'] = timer['schedule']
+
+    # The filesystem lives on the first partition (or directly on the array if it was formatted unpartitioned)
+    try:
+        result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,FSTYPE,LABEL,MOUNTPOINTS', array_name], capture_output=True, text=True, check=True)
+        nodes = json.loads(result.stdout).get('blockdevices', [])
+    except Exception:
+        nodes = []
+    # Array -> partition -> (LUKS container -> opened mapping), nearest filesystem first
+    candidates, level_nodes = [], nodes
+    while level_nodes:
+        level_nodes = [child for node in level_nodes for child in node.get('children', [])]
+        candidates = level_nodes + candidates
+    candidates += nodes
+    container = next((node for node in candidates if node.get('fstype') == "crypto_LUKS"), None)
+    if container:
+        current['encryption'] = luks.read_encryption(container['name'])
+    fs = next((node for node in candidates if node.get('fstype') and node['fstype'] != "crypto_LUKS"), None)
+    if fs:
+        mountpoints = [m for m in fs.get('mountpoints', []) if m]
+        current.update(filesystem=fs['fstype'], label=fs.get('label'), mount_point=mountpoints[0] if mountpoints else None)
+        if current['mount_point']:
+            current['mount_options'] = get_mount_options(current['mount_point'])
+    return current
+
+def get_mount_options(mount_point):
+    """
+    Returns the options a filesystem is mounted with, minus the ones every mount has (rw, relatime, ...).
+    """
+    try:
+        with open('/proc/mounts', 'r') as f:
+            for line in f:
+                fields = line.split()
+                if len(fields) > 3 and fields[1] == mount_point:
+                    return [o for o in fields[3].split(",") if o not in ("rw", "relatime", "seclabel")]
+    except Exception:
+        pass
+    return []
+
+def diff_plans(left, right):
+    """
+    Compares two plans field by field.
+    Returns: [{'field': 'level', 'left': '5', 'right': '6', 'changed': True}, ...] for every COMPARE_FIELDS entry
+    """
+    rows = []
+    for field in COMPARE_FIELDS:
+        a, b = left.get(field), right.get(field)
+        # Member order doesn't matter for the comparison, only which disks take part
+        if isinstance(a, list) and isinstance(b, list):
+            changed = sorted(a) != sorted(b)
+        else:
+            changed = (a or None) != (b or None)
+        rows.append({'field': field, 'left': a, 'right': b, 'changed': changed})
+    return rows
+
+def md_name(array_name):
+    # udev links named arrays as /dev/md/<name>
+    try:
+        links = os.listdir("/dev/md")
+    except OSError:
+        return None
+    target = os.path.realpath(array_name)
+    return next((link for link in sorted(links) if os.path.realpath(os.path.join("/dev/md", link)) == target), None)
+
+def raid10_layout(value):
+    # md encodes RAID 10 layouts as near copies | far copies << 8 | offset flag << 16
+    if not value or not value.isdigit():
+        return None
+    value = int(value)
+    if (value >> 8) & 0xff > 1:
+        return "offset" if value & 0x10000 else "far"
+    return "near"
+
+def format_value(value):
+    if isinstance(value, dict) and 'cipher' in value:
+        return luks.describe(value)
+    if isinstance(value, dict):
+        # Partition layouts by member
+        return ", ".join(f"{device}: {describe_layout(layout)}" for device, layout in value.items()) or "whole disks"
+    if isinstance(value, list):
+        return ", ".join(value) or "-"
+    return "-" if value in (None, "") else str(value)
@@ -0,0 +1,105 @@
+import backend
+import config
+import units
+
+# Organizational storage standards from the [policy] tables of config.toml (rules and their parameters
+# are listed in config.POLICY_RULES), checked whenever an array is validated: plan, apply, the GUI's
+# Create button and adding an array to fstab. A rule set to "error" refuses the array; "warn" only reports
+# it as warning policy-<rule>, which --suppress or suppress_warnings can accept. Swap already running on
+# any array is checked by 'doctor'.
+#
+#   [policy.raid6-large-members]
+#   action = "error"
+#   member_size = "8TB"
+
+# Levels a single disk failure can't take down
+REDUNDANT_LEVELS = ("1", "5", "6", "10")
+
+def get_rules():
+    """
+    Returns every rule with its configured action and parameters, invalid or missing values at their defaults.
+    Returns: {'spare-required': {'action': 'warn', 'above_members': 4}, ...}
+    """
+    section = config.load_config().get('policy', {})
+    section = section if isinstance(section, dict) else {}
+    rules = {}
+    for name, params in config.POLICY_RULES.items():
+        settings = section.get(name) if isinstance(section.get(name), dict) else {}
+        rules[name] = dict(params, action="off")
+        for key, value in settings.items():
+            if key == "action" and value in config.POLICY_ACTIONS:
+                rules[name]['action'] = value
+            elif key in params and type(value) is type(params[key]):
+                rules[name][key] = value
+    return rules
+
+def evaluate(level, members, spares=(), mount_point=None, suppress=None, swap=False):
+    """
+    Checks a new array against the configured rules, members and spares are drive dicts. mount_point is where its
+    data goes (True when that isn't known yet), swap whether the array holds active swap (see active_swap()).
+    Returns: {'errors': [{'rule': 'no-raid0-data', 'field': 'level', 'message': '...'}],
+              'warnings': [{'code': 'policy-spare-required', 'message': '...'}]}
+    """
+    level = str(level)
+    suppressed = set(config.get_setting('suppress_warnings')) | set(suppress or [])
+    report = {'errors': [], 'warnings': []}
+
+    def violation(rule, field, message):
+        action = rules[rule]['action']
+        if action == "error":
+            report['errors'].append({'rule': rule, 'field': field, 'message': f"Policy {rule}: {message}"})
+        elif action == "warn" and f"policy-{rule}" not in suppressed:
+            report['warnings'].append({'code': f"policy-{rule}", 'message': f"Policy {rule}: {message}"})
+
+    rules = get_rules()
+    if level == "0" and mount_point:
+        where = f" mounted on {mount_point}" if isinstance(mount_point, str) else ""
+        violation('no-raid0-data', 'level', f"RAID 0 must not hold data{where}, use a redundant level.")
+    if swap and level not in REDUNDANT_LEVELS:
+        violation('redundant-swap', 'level', f"Swap must not be on RAID {level or '?'}, one failed disk would crash whatever is swapped out.")
+
+    limit = rules['spare-required']['above_members']
+    if len(members) > limit and not spares and level in REDUNDANT_LEVELS:
+        violation('spare-required', 'spares', f"{len(members)} members need at least one hot spare (more than {limit}).")
+
+    try:
+        large = units.parse_size(rules['raid6-large-members']['member_size'])
+    except ValueError:
+        large = None
+    biggest = max((d['size_bytes'] for d in members), default=0)
+    if large and biggest > large and level != "6":
+        violation('raid6-large-members', 'level',
+                  f"Members of {units.format_size(biggest)} need RAID 6 (above {units.format_size(large)}), not RAID {level}.")
+    return report
+
+def active_swap(array_name, swaps=None):
+    """
+    Whether swap is active on an array itself or on one of its partitions (/dev/md1p2). swaps: the devices in /proc/swaps.
+    """
+    if swaps is None:
+        try:
+            with open('/proc/swaps', 'r') as f:
+                swaps = [line.split()[0] for line in f.readlines()[1:] if line.strip()]
+        except OSError:
+            return False
+    return any(swap == array_name or swap.startswith(array_name + "p") for swap in swaps)
+
+def check_running():
+    """
+    Checks the swap already active on md arrays against redundant-swap, for 'doctor'.
+    Returns: (action, ['/dev/md1 (raid0) holds active swap']), no violations when the rule is off.
+    """
+    action = get_rules()['redundant-swap']['action']
+    if action == "off":
+        return action, []
+    try:
+        with open('/proc/swaps', 'r') as f:
+            swaps = [line.split()[0] for line in f.readlines()[1:] if line.strip()]
+    except OSError:
+        return action, []
+    violations = []
+    for status in backend.get_arrays_status():
+        level = (status.get('type') or "").replace("raid", "")
+        if active_swap(status['name'], swaps) and level not in REDUNDANT_LEVELS:
+            violations.append(f"{status['name']} ({status.get('type') or 'no level'}) holds active swap")
+    return action, violations
@@ -0,0 +1,130 @@
+import json
+import shutil
+import socket
+import subprocess
+import time
+import urllib.error
+import urllib.request
+import backend
+import config
+import files
+import notifications
+import output
+import plan
+
+# Tells a bare-metal provisioning pipeline (Foreman, MAAS, a custom installer) when storage is ready:
+# 'apply' sends its final report as JSON to --notify-url / notify_url (HTTP POST) and to the MQTT
+# topic notify_mqtt_topic on notify_mqtt_broker (through mosquitto_pub, no MQTT library on the live image).
+# A notification that can't be delivered is reported but never fails the apply itself.
+
+# How long a provisioning server gets to accept the report
+NOTIFY_TIMEOUT = 15
+
+def get_machine_id():
+    # Provisioning servers know hosts by machine-id or hostname, the live image may not have a stable hostname
+    try:
+        with open("/etc/machine-id", 'r') as f:
+            return f.read().strip() or None
+    except OSError:
+        return None
+
+def build_report(proposal, validation, success, apply_job=None, sync_job=None, throughput=None):
+    """
+    The final report of an apply run, in the output.py schema so it reads like 'apply --format json'.
+    Returns: {'schema_version': 1, 'command': 'apply', 'event': 'provisioned', 'host': 'node17', 'success': True,
+              'array': '/dev/md0', 'array_uuid': '...', 'filesystem_uuid': '...', 'steps': [...],
+              'discard': {...as discard_setting()...}, ...}
+    """
+    # A plan file that couldn't be read is reported as failed all the same, the pipeline is waiting for it
+    proposal = proposal or {'array': None, 'level': None, 'backend': None, 'devices': [], 'spares': [], 'filesystem': None, 'mount_point': None}
+    array_name = proposal['array']
+    done = success and not backend.DRY_RUN
+    return {
+        'schema_version': output.SCHEMA_VERSION,
+        'command': "apply",
+        'event': "provisioned" if success else "provisioning-failed",
+        'timestamp': int(time.time()),
+        'host': socket.gethostname(),
+        'machine_id': get_machine_id(),
+        'dry_run': backend.DRY_RUN,
+        'success': success,
+        'array': array_name,
+        'level': proposal['level'],
+        'backend': proposal['backend'],
+        'devices': proposal['devices'],
+        'spares': proposal['spares'],
+        'filesystem': proposal['filesystem'],
+        'mount_point': proposal['mount_point'],
+        'array_uuid': backend.get_array_uuid(array_name) if done and proposal['backend'] == "mdadm" else None,
+        'filesystem_uuid': files.get_filesystem_uuid(plan.volume_device(proposal)) if done else None,
+        'capacity_bytes': validation.get('capacity_bytes'),
+        'discard': discard_setting(proposal) if done else None,
+        'errors': validation.get('errors', []),
+        'warnings': validation['warnings'],
+        'steps': apply_job['steps'] if apply_job and 'steps' in apply_job else [],
+        'apply_job': apply_job['id'] if apply_job else None,
+        'sync_job': sync_job['id'] if sync_job else None,
+        'throughput': throughput,
+    }
+
+def discard_setting(proposal):
+    """
+    How TRIM is set up on the provisioned array: whether discards reach its members (md arrays, see
+    backend.check_discard()) and whether the filesystem is mounted with online discard.
+    Returns: {'array': True, 'members': {'/dev/sdb': True}, 'raid456_safe': None, 'warnings': [], 'mount_option': False}
+    """
+    report = backend.check_discard(proposal['array']) if proposal['backend'] == "mdadm" else {}
+    return dict(report, mount_option="discard" in proposal['mount_options'])
+
+def post_url(url, report):
+    """
+    Returns: (success, log_str)
+    """
+    shown = notifications.masked_url(url)
+    if backend.DRY_RUN:
+        return True, f"[DRY RUN] Would POST the provisioning report to {shown}\n"
+    body = json.dumps(report, default=str).encode()
+    request = urllib.request.Request(url, data=body, method="POST", headers={'Content-Type': "application/json", 'User-Agent': "LiveRAID"})
+    try:
+        with urllib.request.urlopen(request, timeout=NOTIFY_TIMEOUT) as response:
+            return True, f"Provisioning report sent to {shown} (HTTP {response.status}).\n"
+    except urllib.error.HTTPError as e:
+        return False, f"WARNING: {shown} refused the provisioning report: HTTP {e.code} {e.reason}\n"
+    except (urllib.error.URLError, OSError) as e:
+        return False, f"WARNING: Could not send the provisioning report to {shown}: {getattr(e, 'reason', e)}\n"
+
+def publish_mqtt(broker, topic, report):
+    """
+    Publishes the report to broker ('host' or 'host:port') on topic, retained so a pipeline that
+    subscribes late still sees the last result. Returns: (success, log_str)
+    """
+    host, _, port = broker.partition(":")
+    cmd = ["mosquitto_pub", "-h", host, "-p", port or "1883", "-t", topic, "-r", "-s"]
+    if backend.DRY_RUN:
+        return True, f"[DRY RUN] Would publish the provisioning report: {' '.join(cmd)}\n"
+    if shutil.which("mosquitto_pub") is None:
+        return False, "WARNING: notify_mqtt_broker is set but mosquitto_pub is missing (package mosquitto-clients).\n"
+    try:
+        result = subprocess.run(cmd, input=json.dumps(report, default=str), capture_output=True, text=True, timeout=NOTIFY_TIMEOUT)
+    except subprocess.TimeoutExpired:
+        return False, f"WARNING: {broker} did not accept the provisioning report within {NOTIFY_TIMEOUT}s.\n"
+    if result.returncode != 0:
+        return False, f"WARNING: Could not publish the provisioning report to {broker}: {result.stderr.strip()}\n"
+    return True, f"Provisioning report published to {topic} on {broker}.\n"
+
+def has_targets(url=None):
+    return bool(url or config.get_setting('notify_url') or config.get_setting('notify_mqtt_broker'))
+
+def notify(report, url=None):
+    """
+    Sends the report to every configured target, url overrides notify_url from config.toml.
+    Returns: (all delivered, log_str)
+    """
+    results = []
+    url = url or config.get_setting('notify_url')
+    if url:
+        results.append(post_url(url, report))
+    broker = config.get_setting('notify_mqtt_broker')
+    if broker:
+        results.append(publish_mqtt(broker, config.get_setting('notify_mqtt_topic'), report))
+    return all(ok for ok, _ in results), "".join(out for _, out in results)
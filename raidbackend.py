@@ -0,0 +1,301 @@
+import os
+import re
+import arraynames
+import backend
+import config
+import files
+import fsoptions
+import luks
+
+# Plans (plan.py) describe the array independently of what builds it. A RaidBackend turns a plan
+# into the commands of one RAID implementation; the planner validates and renders plans through
+# it and RaidService.apply_plan() runs them.
+
+class RaidBackend:
+    name = None
+    title = None
+    # Programs the commands need, as listed in doctor.CAPABILITIES
+    tools = []
+
+    def default_name(self, devices, name=None, planned=()):
+        """
+        Returns the name a plan gets when none is given, e.g. '/dev/md0'. name is the plan's name field,
+        planned the arrays the session's other plans took.
+        """
+        raise NotImplementedError
+
+    def check(self, plan):
+        """
+        Checks the plan fields this backend can't honour, like check_fields() in plan.py.
+        Returns: [{'field': 'spares', 'message': '...'}]
+        """
+        errors = []
+        if plan['preserve_partitions']:
+            errors.append({'field': 'preserve_partitions', 'message': f"Keeping existing partitions is only supported with mdadm, not {self.name}."})
+        if plan['encryption']:
+            errors.append({'field': 'encryption', 'message': f"Encryption (LUKS) is only supported with mdadm, not {self.name}."})
+        if plan['partitions']:
+            errors.append({'field': 'partitions', 'message': f"Partition layouts are only supported with mdadm, not {self.name}."})
+        for field in ('metadata', 'bitmap', 'layout', 'name'):
+            if plan[field] is not None:
+                errors.append({'field': field, 'message': f"--{field} is an mdadm option, {self.name} arrays don't have it."})
+        for field in ('write_mostly', 'write_behind', 'auto_read_only'):
+            if plan[field]:
+                errors.append({'field': field, 'message': f"--{field.replace('_', '-')} is an mdadm option, {self.name} arrays don't have it."})
+        if plan['existing']:
+            errors.append({'field': 'existing', 'message': f"Only md arrays created beforehand can be reused, not {self.name} volumes."})
+        return errors
+
+    def exists(self, plan):
+        return os.path.exists(plan['array'])
+
+    def applied(self, plan):
+        """
+        True when the plan's array is there already, built from the planned members: an earlier apply of the same
+        plan, which applying it again picks up instead of refusing the name (see reconcile.py), or the array an
+        existing plan reuses.
+        """
+        return False
+
+    def volume_device(self, plan):
+        """
+        Returns the device the filesystem is created on and mounted from.
+        """
+        raise NotImplementedError
+
+    def record_step(self, plan):
+        """
+        Returns the step that keeps the array across reboots, listed after the create commands, or None.
+        """
+        return None
+
+    def create_commands(self, plan, members):
+        """
+        Returns the commands building the RAID volume from the member devices.
+        """
+        raise NotImplementedError
+
+    def undo_commands(self, plan, members):
+        """
+        Returns, for each command of create_commands(), the commands reversing it (used to roll back a failed apply).
+        """
+        raise NotImplementedError
+
+    def format_commands(self, plan):
+        """
+        Returns the commands putting the plan's filesystem on the volume built by create_commands().
+        """
+        device = luks.mapper_device(plan['array']) if plan['encryption'] else self.volume_device(plan)
+        return [backend.build_mkfs_command(plan['filesystem'], device, extra_options=fsoptions.plan_arguments(plan), label=plan['label'])]
+
+class MdadmBackend(RaidBackend):
+    name = "mdadm"
+    title = "Linux md (mdadm)"
+    tools = ["mdadm", "parted"]
+
+    def default_name(self, devices, name=None, planned=()):
+        return arraynames.next_array_name(name, planned)
+
+    def check(self, plan):
+        # Partition preservation and encryption are built on md arrays, so the base class check doesn't apply
+        return []
+
+    def applied(self, plan):
+        if not backend.is_array_active(plan['array']):
+            return False
+        if plan['existing']:
+            return True
+        # Members are the planned disks themselves or the RAID partitions made on them
+        disks = {backend.get_parent_disk(m['device']) for m in backend.get_array_members(plan['array'], serials={}) if m['device']}
+        return disks == set(plan['devices'] + plan['spares']) and backend.read_md_attribute(plan['array'], "level") == f"raid{plan['level']}"
+
+    def volume_device(self, plan):
+        return backend.partition_device(plan['array'])
+
+    def record_step(self, plan):
+        return f"record {plan['array']} in {files.get_mdadm_conf_path()}"
+
+    def create_commands(self, plan, members):
+        return [backend.build_create_command(plan['level'], members, plan['array'], plan['chunk'],
+                                             write_mostly=write_mostly_members(plan, members), write_behind=plan['write_behind'],
+                                             spares=plan['spares'], metadata=plan['metadata'], bitmap=plan['bitmap'],
+                                             layout=plan['layout'], name=plan['name'])]
+
+    def undo_commands(self, plan, members):
+        return [[["mdadm", "--stop", plan['array']]] + [["mdadm", "--zero-superblock", dev] for dev in members + plan['spares']]]
+
+    def format_commands(self, plan):
+        cmds = [["parted", "-s", plan['array'], "mklabel", "gpt"],
+                ["parted", "-s", plan['array'], "mkpart", "primary", "0%", "100%"]]
+        if plan['encryption']:
+            container = self.volume_device(plan)
+            cmds += [luks.format_command(container, plan['encryption']),
+                     luks.open_command(container, luks.mapper_name(plan['array']), plan['encryption'])]
+        return cmds + super().format_commands(plan)
+
+class LvmBackend(RaidBackend):
+    """
+    LVM RAID: every array gets its own volume group over the members, holding one RAID logical volume
+    that fills it. Arrays are named /dev/<volume group>/<logical volume>.
+    """
+    name = "lvm"
+    title = "LVM RAID (lvcreate --type raidN)"
+    tools = ["lvcreate"]
+
+    VG_PREFIX = "liveraid"
+
+    # lvcreate's own minimums: raid6 takes at least 3 stripes besides its 2 parities, raid10 mirrors pairs of stripes
+    MIN_DEVICES = {"6": 5, "10": 4}
+
+    def default_name(self, devices, name=None, planned=()):
+        number = 0
+        while os.path.exists(f"/dev/{self.VG_PREFIX}{number}") or f"/dev/{self.VG_PREFIX}{number}/data" in planned:
+            number += 1
+        return f"/dev/{self.VG_PREFIX}{number}/data"
+
+    def split_name(self, plan):
+        # '/dev/liveraid0/data' -> ('liveraid0', 'data')
+        match = re.fullmatch(r"/dev/([\w.+-]+)/([\w.+-]+)", plan['array'] or "")
+        return (match.group(1), match.group(2)) if match else (None, None)
+
+    def check(self, plan):
+        errors = super().check(plan)
+        if self.split_name(plan)[0] is None:
+            errors.append({'field': 'array', 'message': f"LVM arrays are named /dev/<volume group>/<volume>, not {plan['array']}."})
+        if plan['spares']:
+            errors.append({'field': 'spares', 'message': "LVM RAID has no hot spares, leave free disks in the volume group and run 'lvconvert --repair' instead."})
+        count = len(plan['devices'])
+        if count < self.MIN_DEVICES.get(plan['level'], 0):
+            errors.append({'field': 'devices', 'message': f"LVM RAID {plan['level']} needs at least {self.MIN_DEVICES[plan['level']]} disks, "
+                                                          f"{count} given."})
+        elif plan['level'] == "10" and count % 2:
+            errors.append({'field': 'devices', 'message': f"LVM RAID 10 mirrors pairs of disks, give an even number ({count} given)."})
+        return errors
+
+    def exists(self, plan):
+        volume_group = self.split_name(plan)[0]
+        return volume_group is not None and os.path.exists(f"/dev/{volume_group}")
+
+    def applied(self, plan):
+        volume_group, volume = self.split_name(plan)
+        if volume_group is None or not os.path.exists(plan['array']):
+            return False
+        try:
+            segment = backend.query(["lvs", "--noheadings", "-o", "segtype", f"{volume_group}/{volume}"]).strip()
+            pvs = backend.query(["pvs", "--noheadings", "-o", "pv_name", "--select", f"vg_name={volume_group}"]).split()
+        except Exception:
+            return False
+        return segment == f"raid{plan['level']}" and set(pvs) == set(plan['devices'])
+
+    def volume_device(self, plan):
+        # The filesystem goes straight onto the logical volume, without a partition table
+        return plan['array']
+
+    def create_commands(self, plan, members):
+        volume_group, volume = self.split_name(plan)
+        count = len(members)
+        layout = {
+            "0": ["-i", str(count)],
+            "1": ["-m", str(count - 1)],
+            "5": ["-i", str(count - 1)],
+            "6": ["-i", str(count - 2)],
+            "10": ["-i", str(count // 2), "-m", "1"],
+        }[plan['level']]
+        lvcreate = ["lvcreate", "-y", "--type", f"raid{plan['level']}"] + layout
+        if plan['level'] != "1":
+            # lvcreate's own default stripe size is 64K, the filesystem's stripe geometry assumes mdadm's 512K
+            chunk = plan['chunk'].lower() if plan['chunk'] != "Default" else f"{fsoptions.DEFAULT_CHUNK_KIB}k"
+            lvcreate += ["-I", chunk]
+        lvcreate += ["-l", "100%FREE", "-n", volume, volume_group]
+        # Old md superblocks or filesystem signatures on a disk make pvcreate stop and ask, wiping has no undo
+        return [["wipefs", "-a"] + members, ["pvcreate", "-y"] + members, ["vgcreate", volume_group] + members, lvcreate]
+
+    def undo_commands(self, plan, members):
+        volume_group, volume = self.split_name(plan)
+        return [[], [["pvremove", "-y"] + members], [["vgremove", "-y", volume_group]], [["lvremove", "-y", f"{volume_group}/{volume}"]]]
+
+class BtrfsBackend(RaidBackend):
+    """
+    Btrfs native RAID: mkfs.btrfs spreads data and metadata over the members itself, so the filesystem
+    is the array. It is mounted through its first member (btrfs finds the others by scanning).
+    """
+    name = "btrfs"
+    title = "Btrfs RAID profiles"
+    tools = ["mkfs.btrfs"]
+
+    # Metadata is mirrored at least as strongly as the data, never striped with parity
+    METADATA_PROFILES = {"0": "raid1", "1": "raid1", "5": "raid1", "6": "raid1c3", "10": "raid10"}
+
+    def default_name(self, devices, name=None, planned=()):
+        return devices[0] if devices else None
+
+    def check(self, plan):
+        errors = super().check(plan)
+        if plan['filesystem'] != "btrfs":
+            errors.append({'field': 'filesystem', 'message': f"The btrfs backend creates a btrfs filesystem, not {plan['filesystem']}."})
+        if plan['spares']:
+            errors.append({'field': 'spares', 'message': "Btrfs has no hot spares, add a disk with 'btrfs replace' when one fails."})
+        if {'data_profile', 'metadata_profile'} & set(plan['fs_options']):
+            errors.append({'field': 'fs_options', 'message': "The btrfs backend sets the profiles from the RAID level, leave data_profile and metadata_profile out."})
+        if plan['chunk'] != "Default":
+            errors.append({'field': 'chunk', 'message': "Btrfs picks its own stripe size, leave the chunk size at Default."})
+        if plan['devices'] and plan['array'] != plan['devices'][0]:
+            errors.append({'field': 'array', 'message': f"A btrfs array is named after its first member ({plan['devices'][0]})."})
+        return errors
+
+    def filesystem(self, plan):
+        """
+        The btrfs filesystem the plan's first member belongs to, None when it holds none.
+        Returns: {'label': 'data' or None, 'uuid': '...', 'devices': ['/dev/sdb', '/dev/sdc']}
+        """
+        if not plan['array']:
+            return None
+        try:
+            shown = backend.query(["btrfs", "filesystem", "show", plan['array']])
+        except Exception:
+            return None
+        match = re.search(r"Label:\s+(?:'(.*)'|none)\s+uuid:\s+(\S+)", shown)
+        if not match:
+            return None
+        return {'label': match.group(1), 'uuid': match.group(2), 'devices': re.findall(r"\bpath\s+(\S+)", shown)}
+
+    def exists(self, plan):
+        # Any btrfs filesystem on the first member, mkfs.btrfs -f would format over it
+        return self.filesystem(plan) is not None
+
+    def applied(self, plan):
+        found = self.filesystem(plan)
+        return found is not None and set(found['devices']) == set(plan['devices']) and found['label'] == (plan['label'] or None)
+
+    def volume_device(self, plan):
+        return plan['array']
+
+    def create_commands(self, plan, members):
+        mkfs_cmd = backend.build_mkfs_command("btrfs", members[0], extra_options=plan['mkfs_options'], label=plan['label'])
+        profiles = ["-d", f"raid{plan['level']}", "-m", self.METADATA_PROFILES[plan['level']]]
+        return [mkfs_cmd[:-1] + profiles + members]
+
+    def undo_commands(self, plan, members):
+        return [[["wipefs", "-a", dev] for dev in members]]
+
+    def format_commands(self, plan):
+        return []
+
+BACKENDS = {b.name: b for b in (MdadmBackend(), LvmBackend(), BtrfsBackend())}
+
+def get_backend(name=None):
+    """
+    Returns the backend called name, or the one set as raid_backend in config.toml.
+    Raises ValueError for unknown names.
+    """
+    name = name or config.get_setting('raid_backend')
+    if name not in BACKENDS:
+        raise ValueError(f"Unknown RAID backend '{name}' (available: {', '.join(BACKENDS)})")
+    return BACKENDS[name]
+
+def write_mostly_members(plan, members):
+    """
+    The members made of a plan's write-mostly disks: the disks themselves, or the RAID partitions on them.
+    members are in the order of plan['devices'], as apply builds them.
+    """
+    return [member for disk, member in zip(plan['devices'], members) if disk in plan['write_mostly']]
@@ -0,0 +1,107 @@
+import os
+import backend
+import executor
+import files
+import fstab
+import luks
+import plan
+import raidbackend
+import timers
+
+# Applying a plan again, after it went through or stopped halfway, picks up what is there: each step of
+# RaidService.apply_plan() first looks at the system through these checks and is skipped when its part is in
+# place already (unchanged), brought in line when it is there but differs (updated) or run as usual (created).
+# What can't be reconciled without destroying data, another filesystem on the array or something else mounted
+# on the mount point, fails the step before it changes anything. Each check returns (outcome, log_str), the
+# outcome being one of executor.CREATED, UPDATED, UNCHANGED or None for such a conflict.
+# md arrays, LVM volumes and btrfs filesystems built from the planned members are picked up (RaidBackend.applied()),
+# a volume group or btrfs filesystem that exists with other members or another label fails validation.
+
+def member_disks(proposal):
+    """
+    The wipe and partitioning steps of the members, which are done once the array runs with them.
+    """
+    if raidbackend.get_backend(proposal['backend']).applied(proposal):
+        return executor.UNCHANGED, f"{', '.join(proposal['devices'])} are members of {proposal['array']} already.\n"
+    return executor.CREATED, ""
+
+def volume(proposal):
+    """
+    The create commands of the LVM and btrfs backends: a volume built from the planned members is kept.
+    """
+    raid = raidbackend.get_backend(proposal['backend'])
+    if raid.applied(proposal):
+        return executor.UNCHANGED, f"{proposal['array']} exists with the planned members already.\n"
+    if raid.exists(proposal):
+        return None, f"ERROR: {proposal['array']} exists but wasn't built from the planned members, it is left alone.\n"
+    return executor.CREATED, ""
+
+def array(proposal):
+    """
+    The array and its mdadm.conf line: an array running with the planned members is kept, only recorded
+    in mdadm.conf when its line is missing or out of date.
+    """
+    name = proposal['array']
+    raid = raidbackend.get_backend(proposal['backend'])
+    if not raid.applied(proposal):
+        if raid.exists(proposal):
+            return None, f"ERROR: {name} exists but doesn't run as RAID {proposal['level']} of the planned members, it is left alone.\n"
+        return executor.CREATED, ""
+    path = files.get_mdadm_conf_path()
+    current = files.read_file(path)
+    if files.render_mdadm_conf(name, current) == current:
+        return executor.UNCHANGED, f"{name} runs with the planned members and is recorded in {path}.\n"
+    return executor.UPDATED, f"{name} runs with the planned members, its line in {path} is missing or out of date.\n"
+
+def filesystem(proposal):
+    """
+    The partition, LUKS container and filesystem on the array. Another filesystem is never formatted over.
+    """
+    device = plan.volume_device(proposal)
+    fs_type = fstab.get_filesystem_type(device) if os.path.exists(device) else None
+    if fs_type == proposal['filesystem']:
+        return executor.UNCHANGED, f"{device} already holds the planned {fs_type} filesystem.\n"
+    if fs_type:
+        return None, (f"ERROR: {device} holds a {fs_type} filesystem, not the planned {proposal['filesystem']}. Formatting it again "
+                      f"would destroy its data, use 'format' to replace it on purpose.\n")
+    container = raidbackend.get_backend(proposal['backend']).volume_device(proposal)
+    if proposal['encryption'] and os.path.exists(container) and fstab.get_filesystem_type(container) == "crypto_LUKS":
+        return None, (f"ERROR: {container} holds a LUKS container that isn't open, open it with "
+                      f"'cryptsetup open {container} {luks.mapper_name(proposal['array'])}' and apply again.\n")
+    return executor.CREATED, ""
+
+def mount(device, mount_point):
+    mount_point = os.path.normpath(mount_point)
+    if os.path.exists(device) and mount_point in backend.get_mountpoints(device):
+        return executor.UNCHANGED, f"{device} is mounted on {mount_point} already.\n"
+    if os.path.ismount(mount_point):
+        return None, f"ERROR: Something else is mounted on {mount_point}, unmount it first.\n"
+    return executor.CREATED, ""
+
+def fstab_entry(array_name, mount_point, fs_type, options, uuid):
+    """
+    The managed fstab entry, compared with what fstab.add_entry() would write.
+    """
+    current = files.read_file(files.FSTAB_PATH)
+    editor = fstab.FstabEditor(current)
+    present = any(entry['managed'] == array_name or entry['file'] == mount_point for entry in editor.entries())
+    try:
+        editor.set_entry(array_name, f"UUID={uuid}" if uuid else fstab.PLACEHOLDER_SPEC, mount_point, fs_type, options)
+    except ValueError as e:
+        return None, f"ERROR: {files.FSTAB_PATH} {e}.\n"
+    if editor.text() == current:
+        return executor.UNCHANGED, f"{files.FSTAB_PATH} mounts {mount_point} as planned already.\n"
+    if present:
+        return executor.UPDATED, f"The {files.FSTAB_PATH} entry of {mount_point} differs from the plan, it is replaced.\n"
+    return executor.CREATED, ""
+
+def schedules(wanted):
+    """
+    Timers installed by timers.install_timer(), wanted: {'liveraid-scrub-md0': 'monthly'}.
+    """
+    installed = {timer['name']: timer['schedule'] for timer in timers.list_timers()}
+    if all(installed.get(name) == timers.SCHEDULES.get(schedule, schedule) for name, schedule in wanted.items()):
+        return executor.UNCHANGED, f"Already on the planned schedule: {', '.join(wanted)}.\n"
+    if any(name in installed for name in wanted):
+        return executor.UPDATED, f"On another schedule, replaced: {', '.join(name for name in wanted if name in installed)}.\n"
+    return executor.CREATED, ""
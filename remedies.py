@@ -0,0 +1,96 @@
+import re
+import backend
+import doctor
+
+# Failures seen often enough to explain in plain language. Each entry matches the output of the
+# failed command; the first match wins, so the more specific patterns come first.
+REMEDIES = [
+    {
+        'code': "tool-missing",
+        'pattern': r"No such file or directory: '(?P<target>[\w.]+)'",
+        'title': "A required program is not installed",
+        'explanation': "LiveRAID calls {target} for this step, but it isn't installed in this live session.",
+        'steps': ["Install it with the button below, or run `cli.py doctor --fix`.", "Then retry the operation."],
+        'action': {'label': "Install {target}", 'kind': "install", 'operation': "doctor-fix"},
+    },
+    {
+        'code': "privileges",
+        'pattern': r"must be super-user|Permission denied|Operation not permitted",
+        'title': "Not enough privileges",
+        'explanation': "Changing disks, arrays and system files needs root, but LiveRAID is running as a normal user.",
+        'steps': ["Close LiveRAID.", "Start it again as root: `sudo bash bootstrap.sh`."],
+        'action': None,
+    },
+    {
+        'code': "array-exists",
+        'pattern': r"(?P<target>/dev/md[\w/]*) (?:is already in use|already exists)",
+        'title': "The array name is already taken",
+        'explanation': "{target} is still assembled, often left over from an earlier attempt or another system's disks.",
+        'steps': ["If {target} is a leftover, stop it with the button below (`cli.py cleanup-md` finds all of them), then retry.",
+                  "If it holds data you need, create the new array under another name instead (raid_device_prefix in config.toml)."],
+        'action': {'label': "Stop {target}", 'kind': "stop-array", 'operation': "destroy"},
+    },
+    {
+        'code': "device-busy",
+        'pattern': r"(?P<target>/[\w/.-]+?)(?::|\s)[^\n]*?(?:Device or resource busy|target is busy|is busy)",
+        'title': "A disk is still in use",
+        'explanation': "{target} is mounted, used as swap, or held by LVM, LUKS or another array, so it can't be changed.",
+        'steps': ["Close any file manager window or terminal open on it, then unmount it with the button below.",
+                  "If it's held by LVM/LUKS/swap, release the whole stack: `cli.py teardown-stack <disk>`.",
+                  "Then retry."],
+        'action': {'label': "Unmount now", 'kind': "unmount", 'operation': "teardown-stack"},
+    },
+]
+
+def diagnose(output):
+    """
+    Matches the output of a failed operation against the known failures.
+    Returns: {'code': 'device-busy', 'title': '...', 'explanation': '...', 'steps': ['...'],
+              'action': {'label': 'Unmount now', 'kind': 'unmount', 'operation': 'teardown-stack', 'target': '/dev/sdb1'} or None}
+    where operation is the RaidService.allowed() operation the action counts as.
+    or None when the failure isn't a known one.
+    """
+    for remedy in REMEDIES:
+        match = re.search(remedy['pattern'], output)
+        if not match:
+            continue
+        target = match.groupdict().get('target') or ""
+        action = None
+        if remedy['action']:
+            action = dict(remedy['action'], label=remedy['action']['label'].format(target=target), target=target)
+        return {
+            'code': remedy['code'],
+            'title': remedy['title'],
+            'explanation': remedy['explanation'].format(target=target),
+            'steps': [step.format(target=target) for step in remedy['steps']],
+            'action': action,
+        }
+    return None
+
+def apply_action(action):
+    """
+    Runs the fix offered with a diagnosis.
+    Returns: (success, log_str)
+    """
+    if action['kind'] == "install":
+        fix = doctor.install_fix([action['target']])
+        if fix is None:
+            return False, f"No supported package manager found to install {action['target']}.\n"
+        return doctor.apply_fix(fix)
+
+    if action['kind'] == "stop-array":
+        return backend.run_command(["mdadm", "--stop", action['target']])
+
+    if action['kind'] == "unmount":
+        logs = []
+        # Nested mounts first, or the outer one stays busy
+        # umount reports the busy mount point itself, everything else the device
+        mountpoints = backend.get_mountpoints(action['target']) if action['target'].startswith("/dev/") else [action['target']]
+        for mountpoint in sorted(mountpoints, key=len, reverse=True):
+            success, out = backend.run_command(["umount", mountpoint])
+            logs.append(out)
+            if not success:
+                return False, "".join(logs)
+        return True, "".join(logs) or f"Nothing is mounted from {action['target']}.\n"
+
+    return False, f"Unknown action {action['kind']}\n"
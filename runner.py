@@ -0,0 +1,264 @@
+import contextlib
+import hashlib
+import os
+import shlex
+import subprocess
+import threading
+
+# How the commands that change disks and system state are run: backend.run_command(), the cryptsetup
+# calls fed a passphrase, the long overwrite and copy passes (migrate.stream_command()) and sysfs writes
+# all go through backend.RUNNER. RaidService(runner=...) and executor.Executor(runner=...) swap it.
+# MockRunner stands in for the real system in tests and 'cli.py --simulate': it records every command
+# and answers with canned output, so plan and apply can be exercised without root or spare disks.
+# The md queries (mdadm --detail/--examine, /proc/mdstat) go through query() and read() as well, which a
+# MockRunner answers from the arrays its recorded commands built instead of the host's. Other read-only
+# queries (lsblk, smartctl) still run directly, simulations stand in for them with backend.SIMULATED_DRIVES.
+# Commands run inside cancellation() stop when its cancelled() asks them to, so an Operation (background.py)
+# can interrupt a long mkfs or dd on its own thread without touching what other threads run.
+
+# The exit code of a command stopped by a cancellation
+CANCELLED = -15
+
+# How often a running command looks at its cancellation, in seconds
+CANCEL_POLL = 0.5
+
+_local = threading.local()
+
+@contextlib.contextmanager
+def cancellation(cancelled):
+    """
+    with runner.cancellation(event.is_set): ... makes the commands run on this thread stoppable. Nested blocks
+    add to the outer ones, cancellation(None) shields a block (a rollback) from all of them.
+    """
+    previous = getattr(_local, 'cancelled', None)
+    if cancelled is not None and previous is not None:
+        _local.cancelled = lambda: cancelled() or previous()
+    else:
+        _local.cancelled = cancelled
+    try:
+        yield
+    finally:
+        _local.cancelled = previous
+
+def cancel_requested():
+    """
+    Returns whether the cancellation of the current thread (if any) asks to stop.
+    """
+    cancelled = getattr(_local, 'cancelled', None)
+    return bool(cancelled and cancelled())
+
+def _stop(cancelled):
+    return bool(cancelled and cancelled()) or cancel_requested()
+
+class CommandResult:
+    def __init__(self, returncode=0, stdout="", stderr=""):
+        self.returncode = returncode
+        self.stdout = stdout
+        self.stderr = stderr
+
+class CommandRunner:
+    def run(self, cmd, input=None):
+        """
+        Runs a command to completion, input is fed on stdin. Returns a CommandResult, with the
+        returncode CANCELLED when the thread's cancellation stopped it.
+        """
+        raise NotImplementedError
+
+    def stream(self, cmd, on_line, cancelled=None):
+        """
+        Runs a long command, handing every output line (stdout and stderr) to on_line as it comes.
+        Returns the exit code, or None when cancelled() (or the thread's cancellation) asked to stop it.
+        """
+        raise NotImplementedError
+
+    def write(self, path, value):
+        """
+        Writes a value to a sysfs attribute (or any file), raises OSError on failure.
+        """
+        raise NotImplementedError
+
+    def query(self, cmd):
+        """
+        Runs a read-only command, which changes nothing and isn't recorded. Returns a CommandResult.
+        """
+        raise NotImplementedError
+
+    def read(self, path):
+        """
+        Returns the text of a kernel status file such as /proc/mdstat, raises OSError when it can't be read.
+        """
+        raise NotImplementedError
+
+class SubprocessRunner(CommandRunner):
+    def run(self, cmd, input=None):
+        if getattr(_local, 'cancelled', None) is None:
+            result = subprocess.run(cmd, input=input, capture_output=True, text=True)
+            return CommandResult(result.returncode, result.stdout, result.stderr)
+        proc = subprocess.Popen(cmd, stdin=subprocess.PIPE if input is not None else None,
+                                stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True)
+        while True:
+            try:
+                # Only the first round sends the input, the later ones pick up where it left off
+                stdout, stderr = proc.communicate(input, timeout=CANCEL_POLL)
+                return CommandResult(proc.returncode, stdout, stderr)
+            except subprocess.TimeoutExpired:
+                input = None
+                if cancel_requested():
+                    proc.terminate()
+                    stdout, stderr = proc.communicate()
+                    return CommandResult(CANCELLED, stdout, f"{stderr}Cancelled, the command was stopped.\n")
+
+    def stream(self, cmd, on_line, cancelled=None):
+        # Text mode turns the carriage-return progress updates of rsync/dd into separate lines
+        proc = subprocess.Popen(cmd, stdout=subprocess.PIPE, stderr=subprocess.STDOUT, text=True)
+        for line in proc.stdout:
+            on_line(line)
+            if _stop(cancelled):
+                proc.terminate()
+                proc.wait()
+                return None
+        return proc.wait()
+
+    def write(self, path, value):
+        with open(path, 'w') as f:
+            f.write(f"{value}\n")
+
+    def query(self, cmd):
+        result = subprocess.run(cmd, capture_output=True, text=True)
+        return CommandResult(result.returncode, result.stdout, result.stderr)
+
+    def read(self, path):
+        with open(path, 'r') as f:
+            return f.read()
+
+class MockRunner(CommandRunner):
+    """
+    Records every command instead of running it and answers from canned results, matched by command prefix
+    (the most recently added match wins). Commands without one succeed with no output:
+    runner = MockRunner().respond(["mdadm", "--detail", "--brief"], stdout="ARRAY /dev/md0 UUID=...").fail(["mkfs.xfs"])
+    runner.calls -> [['mdadm', '--create', '/dev/md0', ...], ['write', '/sys/block/md0/md/sync_speed_max', '200000']]
+    Arrays created by a recorded `mdadm --create` show up in /proc/mdstat and mdadm --detail/--examine until
+    `mdadm --stop`, the host's arrays never do: runner.arrays -> {'/dev/md0': {'level': 'raid1', 'devices': [...], ...}}
+    """
+
+    def __init__(self):
+        self.calls = []
+        self.responses = []
+        self.arrays = {}
+
+    def respond(self, prefix, stdout="", stderr="", returncode=0, lines=()):
+        # lines are what stream() hands out, e.g. dd's progress lines
+        self.responses.insert(0, (list(prefix), CommandResult(returncode, stdout, stderr), list(lines)))
+        return self
+
+    def fail(self, prefix, stderr="simulated failure", returncode=1):
+        return self.respond(prefix, stderr=stderr, returncode=returncode)
+
+    def answer(self, cmd):
+        for prefix, result, lines in self.responses:
+            if list(cmd[:len(prefix)]) == prefix:
+                return result, lines
+        return CommandResult(), []
+
+    def run(self, cmd, input=None):
+        # A cancelled command never gets to run
+        if cancel_requested():
+            return CommandResult(CANCELLED, "", "Cancelled, the command was stopped.\n")
+        self.calls.append(list(cmd))
+        result = self.answer(cmd)[0]
+        if result.returncode == 0:
+            self.track(cmd)
+        return result
+
+    def stream(self, cmd, on_line, cancelled=None):
+        self.calls.append(list(cmd))
+        result, lines = self.answer(cmd)
+        for line in lines:
+            on_line(line)
+            if _stop(cancelled):
+                return None
+        return result.returncode
+
+    def write(self, path, value):
+        self.calls.append(["write", path, str(value)])
+
+    def query(self, cmd):
+        for prefix, result, _ in self.responses:
+            if list(cmd[:len(prefix)]) == prefix:
+                return result
+        if cmd[:2] == ["mdadm", "--detail"]:
+            return self.detail(cmd[-1], cmd[2:-1])
+        if cmd[:2] == ["mdadm", "--examine"]:
+            return self.examine(cmd[-1], cmd[2:-1])
+        return CommandResult()
+
+    def read(self, path):
+        if path != "/proc/mdstat":
+            raise FileNotFoundError(f"{path} is not simulated")
+        lines = ["Personalities : [raid0] [raid1] [raid4] [raid5] [raid6] [raid10]"]
+        for name, arr in self.arrays.items():
+            members = [f"{os.path.basename(dev)}[{i}]" for i, dev in enumerate(arr['devices'])]
+            members += [f"{os.path.basename(dev)}[{i}](S)" for i, dev in enumerate(arr['spares'], len(arr['devices']))]
+            count = len(arr['devices'])
+            lines.append(f"{name.removeprefix('/dev/')} : active {arr['level']} {' '.join(members)}")
+            lines.append(f"      0 blocks super {arr['metadata']} [{count}/{count}] [{'U' * count}]")
+            lines.append("")
+        lines.append("unused devices: <none>")
+        return "\n".join(lines) + "\n"
+
+    def track(self, cmd):
+        # The md arrays the recorded commands create and stop, what the md queries above report
+        if cmd[:2] == ["mdadm", "--create"]:
+            options = dict(arg[2:].split("=", 1) for arg in cmd if arg.startswith("--") and "=" in arg)
+            name = next(arg for arg in cmd[2:] if not arg.startswith("-"))
+            devices = [arg for arg in cmd[2:] if arg.startswith("/dev/") and arg != name]
+            count = int(options.get('raid-devices', len(devices)))
+            level = options.get('level', "1")
+            level = level if level.startswith("raid") else f"raid{level}"
+            # Mirrors have no chunk size
+            chunk = None if level == "raid1" else f"{cmd[cmd.index('--chunk') + 1] if '--chunk' in cmd else 512}K"
+            self.arrays[name] = {'level': level, 'devices': devices[:count], 'spares': devices[count:],
+                                 'metadata': options.get('metadata', "1.2"), 'chunk': chunk,
+                                 'name': options.get('name', os.path.basename(name)),
+                                 'uuid': ":".join(hashlib.md5(name.encode()).hexdigest()[i:i + 8] for i in range(0, 32, 8))}
+        elif cmd[:2] == ["mdadm", "--stop"]:
+            self.arrays.pop(cmd[2], None)
+
+    def detail(self, name, flags):
+        arr = self.arrays.get(name)
+        if arr is None:
+            return CommandResult(1, "", f"mdadm: cannot open {name}: No such file or directory\n")
+        if "--export" in flags:
+            return CommandResult(0, f"MD_LEVEL={arr['level']}\nMD_DEVICES={len(arr['devices'])}\nMD_METADATA={arr['metadata']}\n"
+                                    f"MD_UUID={arr['uuid']}\nMD_DEVNAME={os.path.basename(name)}\n")
+        if "--brief" in flags:
+            return CommandResult(0, f"ARRAY {name} metadata={arr['metadata']} name={arr['name']} UUID={arr['uuid']}\n")
+        rows = [f"{i:>7} {0:>7} {0:>7} {i:>8}      active sync   {dev}" for i, dev in enumerate(arr['devices'])]
+        rows += [f"{i:>7} {0:>7} {0:>7} {'-':>8}      spare   {dev}" for i, dev in enumerate(arr['spares'], len(arr['devices']))]
+        return CommandResult(0, f"{name}:\n           Version : {arr['metadata']}\n        Raid Level : {arr['level']}\n"
+                                f"      Raid Devices : {len(arr['devices'])}\n             State : clean\n"
+                                f"              UUID : {arr['uuid']}\n\n    Number   Major   Minor   RaidDevice State\n" + "\n".join(rows) + "\n")
+
+    def examine(self, device, flags):
+        for arr in self.arrays.values():
+            members = arr['devices'] + arr['spares']
+            if device not in members:
+                continue
+            slot = members.index(device)
+            if "--export" in flags:
+                return CommandResult(0, f"MD_LEVEL={arr['level']}\nMD_DEVICES={len(arr['devices'])}\nMD_NAME={arr['name']}\n"
+                                        f"MD_UUID={arr['uuid']}\n")
+            role = f"Active device {slot}" if slot < len(arr['devices']) else "spare"
+            return CommandResult(0, f"{device}:\n          Magic : a92b4efc\n        Version : {arr['metadata']}\n"
+                                    f"     Array UUID : {arr['uuid']}\n           Name : {arr['name']}\n"
+                                    f"     Raid Level : {arr['level']}\n   Raid Devices : {len(arr['devices'])}\n"
+                                    f"    Data Offset : 264192 sectors\n" + (f"     Chunk Size : {arr['chunk']}\n" if arr['chunk'] else "") +
+                                    f"    Device Role : {role}\n")
+        return CommandResult(1, "", f"mdadm: No md superblock detected on {device}.\n")
+
+    def script(self):
+        """
+        Returns the recorded commands as the shell script they amount to.
+        """
+        lines = [f"echo {shlex.quote(call[2])} > {call[1]}" if call[0] == "write" else shlex.join(call) for call in self.calls]
+        return "\n".join(lines) + "\n" if lines else ""
@@ -0,0 +1,112 @@
+import contextlib
+import shlex
+import time
+
+# What a dry run would do, in order: every command (mdadm, wipefs, mkfs, mount, ...), file edit (mdadm.conf,
+# fstab, /etc/default/grub, ...) and sysfs write the real run makes, recorded where the dry run skips it.
+# 'apply --dry-run' prints the result or saves it as a bash script (--script), the GUI shows it after a dry-run create.
+
+HEREDOC_END = "LIVERAID_EOF"
+
+# cryptsetup reads a LUKS passphrase on stdin ('--key-file -', see luks.key_arguments()): the script asks for it
+# once and pipes it into each of those commands, it never ends up in the file
+PASSPHRASE_VARIABLE = "LIVERAID_PASSPHRASE"
+
+class ExecutionScript:
+    """
+    entries: [{'step': 'create /dev/md0', 'kind': 'command', 'argv': ['mdadm', '--create', ...]},
+              {'step': ..., 'kind': 'write', 'path': '/etc/fstab', 'text': '...', 'substitutions': {'UUID=<assigned by mkfs>': 'UUID=$(blkid ...)'}},
+              {'step': ..., 'kind': 'sysfs', 'path': '/sys/block/md0/md/sync_speed_max', 'value': '200000'}]
+    step is the executor.Step the entry belongs to, None outside of one.
+    """
+
+    def __init__(self):
+        self.entries = []
+        self.step = None
+        # Placeholders for what only exists once the commands ran (UUIDs), with the shell that reads them then
+        self.substitutions = {}
+
+    def add(self, kind, **fields):
+        self.entries.append(dict(step=self.step, kind=kind, **fields))
+
+    def commands(self):
+        return [entry['argv'] for entry in self.entries if entry['kind'] == "command"]
+
+    def to_bash(self):
+        """
+        The entries as a script that makes the same changes: commands as they are, file edits as the complete
+        new contents, sysfs writes as echo. Returns: '#!/bin/bash\nset -euo pipefail\n...'
+        """
+        lines = ["#!/bin/bash", f"# Generated by LiveRAID on {time.strftime('%Y-%m-%d %H:%M:%S')} from a dry run", "set -euo pipefail"]
+        step = None
+        asked = False
+        for entry in self.entries:
+            if entry['step'] != step and entry['step']:
+                lines += ["", f"# {entry['step']}"]
+            step = entry['step']
+            if entry['kind'] == "command" and reads_passphrase(entry['argv']):
+                if not asked:
+                    lines += [f"read -rsp 'Passphrase for the encrypted array: ' {PASSPHRASE_VARIABLE}", "echo"]
+                    asked = True
+                lines.append(f'printf %s "${PASSPHRASE_VARIABLE}" | {shlex.join(entry["argv"])}')
+            elif entry['kind'] == "command":
+                lines.append(shlex.join(entry['argv']))
+            elif entry['kind'] == "sysfs":
+                lines.append(f"echo {shlex.quote(str(entry['value']))} > {shlex.quote(entry['path'])}")
+            else:
+                lines.append(f"mkdir -p {shlex.quote(entry['path'].rsplit('/', 1)[0] or '/')}")
+                text = entry['text'].rstrip("\n")
+                if entry['substitutions']:
+                    # An unquoted here-document runs the substitutions, everything else is escaped to stay literal
+                    text = text.replace("\\", "\\\\").replace("$", "\\$").replace("`", "\\`")
+                    for placeholder, shell in entry['substitutions'].items():
+                        text = text.replace(placeholder, shell)
+                    lines.append(f"cat > {shlex.quote(entry['path'])} <<{HEREDOC_END}")
+                else:
+                    lines.append(f"cat > {shlex.quote(entry['path'])} <<'{HEREDOC_END}'")
+                lines.append(text)
+                lines.append(HEREDOC_END)
+        return "\n".join(lines) + "\n"
+
+def reads_passphrase(argv):
+    return argv[:1] == ["cryptsetup"] and any(a == "--key-file" and b == "-" for a, b in zip(argv, argv[1:]))
+
+# The script dry runs record into while recording() is active
+CURRENT = None
+
+@contextlib.contextmanager
+def recording():
+    """
+    with script.recording() as recorded: ... runs a dry run and collects what it skipped in recorded (an ExecutionScript).
+    """
+    global CURRENT
+    previous, CURRENT = CURRENT, ExecutionScript()
+    try:
+        yield CURRENT
+    finally:
+        CURRENT = previous
+
+def begin_step(name):
+    if CURRENT is not None:
+        CURRENT.step = name
+
+def record_command(cmd):
+    if CURRENT is not None:
+        CURRENT.add("command", argv=[str(part) for part in cmd])
+
+def record_write(path, text):
+    if CURRENT is not None:
+        CURRENT.add("write", path=path, text=text,
+                    substitutions={placeholder: shell for placeholder, shell in CURRENT.substitutions.items() if placeholder in text})
+
+def substitute(placeholder, shell):
+    """
+    Has the script fill in a placeholder of later file edits when it runs, e.g. 'UUID=<assigned by mkfs>'
+    with 'UUID=$(blkid -s UUID -o value /dev/md0p1)'.
+    """
+    if CURRENT is not None:
+        CURRENT.substitutions[placeholder] = shell
+
+def record_sysfs(path, value):
+    if CURRENT is not None:
+        CURRENT.add("sysfs", path=path, value=str(value))
@@ -0,0 +1,54 @@
+import backend
+import health
+import timers
+
+# Consistency checks of md arrays: a 'check' pass (echo check > /sys/block/mdX/md/sync_action) reads every stripe
+# and counts the sectors whose copies or parity disagree in mismatch_cnt, a 'repair' pass rewrites them. Passes are
+# started on demand (`cli.py scrub start`, the GUI's Scrub button) or by a systemd timer (`cli.py scrub schedule`),
+# the results land in the health history that healthcheck rates by age.
+
+ACTIONS = ("check", "repair")
+
+def start(array_name, action="check"):
+    """
+    Starts a scrub pass: 'check' only counts inconsistent stripes (mismatch_cnt), 'repair' also rewrites them.
+    Read errors hit during either pass are rewritten from redundancy so the drive can remap the sector.
+    """
+    return backend.write_sysfs(f"{backend.md_sysfs_dir(array_name)}/sync_action", action)
+
+def mismatch_count(array_name):
+    # Counted up while a pass runs, kept after it ended until the next one starts
+    value = backend.read_md_attribute(array_name, "mismatch_cnt")
+    return int(value) if value and value.isdigit() else 0
+
+def timer_name(array_name):
+    return timers.unit_name("scrub", array_name)
+
+def schedule(array_name, when, dry_run=None):
+    """
+    Installs the systemd timer running `cli.py scrub start <array>` on a plan.SCRUB_SCHEDULES schedule, or removes it for 'off'.
+    Returns: (success, log_str)
+    """
+    if when == "off":
+        return timers.remove_timer(timer_name(array_name), dry_run=dry_run)
+    return timers.install_timer(timer_name(array_name), f"LiveRAID scrub (check) of {array_name}",
+                                ["scrub", "start", array_name], when, dry_run=dry_run)
+
+def scheduled(array_name):
+    """
+    Returns the OnCalendar schedule of an array's scrub timer ('monthly'), None without one.
+    """
+    name = timer_name(array_name)
+    return next((timer['schedule'] for timer in timers.list_timers("scrub") if timer['name'] == name), None)
+
+def status(array_name):
+    """
+    Returns: {'array': '/dev/md0', 'action': 'check', 'running': True, 'percent': 42.1, 'finish': '80.2min', 'speed': '120000K/sec',
+    'mismatches': 0, 'last': {'timestamp': ..., 'kind': 'check', 'summary': ...} or None, 'schedule': 'monthly' or None}
+    """
+    sync_action = backend.read_md_attribute(array_name, "sync_action")
+    progress = backend.get_sync_progress(array_name) if sync_action in ACTIONS else None
+    return {'array': array_name, 'action': sync_action, 'running': progress is not None,
+            'percent': progress['percent'] if progress else None, 'finish': progress['finish'] if progress else None,
+            'speed': progress['speed'] if progress else None, 'mismatches': mismatch_count(array_name),
+            'last': health.last_scrub(array_name), 'schedule': scheduled(array_name)}
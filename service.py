@@ -0,0 +1,1602 @@
+import json
+import os
+import re
+import shutil
+import socket
+import subprocess
+import time
+import acceptance
+import arraynames
+import background
+import backend
+import bench
+import bootloader
+import config
+import events
+import executor
+import expand
+import files
+import fsoptions
+import fstab
+import geometry
+import health
+import jobs
+import luks
+import migrate
+import notifications
+import partition
+import hooks
+import plan
+import policy
+import raidbackend
+import reconcile
+import script
+import scrub
+import snapshot
+import timers
+import units
+import wipe
+
+def _print(msg):
+    print(msg, end="", flush=True)
+
+# Operations that change disks, arrays or system files. Operator sessions (safety_mode = "operator")
+# can plan, validate and monitor; only admin sessions run these (dry runs are always allowed).
+ADMIN_OPERATIONS = ["create", "format", "destroy", "hot-add", "upgrade", "grow", "migrate-data", "teardown-stack",
+                    "teardown-old-array", "cleanup-md", "repair", "discard", "tune", "undo", "doctor-fix", "recreate", "grub", "bootloader", "fstab"]
+
+# Read-only mount options that also skip journal replay, which would write to a re-created array or inspected disk
+RECOVERY_MOUNT_OPTIONS = {'ext4': "ro,noload", 'ext3': "ro,noload", 'xfs': "ro,norecovery", 'btrfs': "ro,rescue=usebackuproot"}
+
+# Where mount_read_only() mounts each filesystem it finds, one directory per device
+READ_ONLY_ROOT = "/mnt/liveraid-ro"
+
+# Block device contents that hold no mountable filesystem themselves
+NOT_MOUNTABLE = ("linux_raid_member", "crypto_LUKS", "LVM2_member", "swap", "zfs_member")
+
+# How long smart_test() waits for self-tests to finish (long tests read the whole disk)
+SMART_TEST_TIMEOUTS = {'short': 30 * 60, 'long': 48 * 3600}
+
+class RaidService:
+    """
+    Single entry point shared by every frontend (GUI, CLI, ...). Owns the site config,
+    the command executor (dry-run or live), the persistent state (snapshots, jobs, backups)
+    and the drive inventory, and runs each mutating operation in the same order everywhere:
+    snapshot -> mdadm/mkfs -> system files -> job tracking.
+
+    Progress is reported through a `log` callback (default: stdout) so each frontend only
+    decides where the text goes. Operations return True/False like the backend helpers.
+    """
+
+    def __init__(self, dry_run=None, log=None, color=False, admin=None, runner=None):
+        if dry_run is not None:
+            backend.DRY_RUN = dry_run
+        # A runner.MockRunner records the commands instead of running them (tests, cli.py --simulate)
+        if runner is not None:
+            backend.RUNNER = runner
+        self.config = config.load_config()
+        self.log = log or _print
+        # Colored diffs only make sense on a terminal
+        self.color = color
+        # admin=True unlocks an operator session up front (cli.py --admin)
+        self.admin = admin if admin is not None else config.get_setting('safety_mode') == "admin"
+        self.last_apply_job = None
+
+    @property
+    def dry_run(self):
+        return backend.DRY_RUN
+
+    def allowed(self, operation):
+        """
+        Returns whether this session may run an operation (see ADMIN_OPERATIONS), so frontends can disable it up front.
+        """
+        return self.admin or self.dry_run or operation not in ADMIN_OPERATIONS
+
+    def require_admin(self, operation, target=None):
+        if self.allowed(operation):
+            return True
+        scope = f" of {target}" if target else ""
+        self.log(f"ERROR: {operation}{scope} is only available in admin mode, this is an operator session "
+                 f"(safety_mode in config.toml). Unlock it with --admin or the GUI's Unlock Admin button.\n")
+        return False
+
+    def unlock_admin(self):
+        """
+        Switches an operator session to admin mode once polkit (pkexec) has authenticated an administrator.
+        Polkit doesn't ask root for a password, so in a root session this guards against mistakes, not people.
+        Returns: (success, log_str)
+        """
+        if self.admin:
+            return True, "Admin mode is already unlocked.\n"
+        if shutil.which("pkexec") is None:
+            return False, "pkexec (polkit) is not installed, restart with --admin to unlock admin mode.\n"
+        try:
+            result = subprocess.run(["pkexec", "true"], capture_output=True, text=True)
+        except Exception as e:
+            return False, f"pkexec failed: {e}\n"
+        if result.returncode != 0:
+            return False, "Authentication failed or was cancelled, staying in operator mode.\n"
+        self.admin = True
+        return True, "Admin mode unlocked.\n"
+
+    def inventory(self, smart=True):
+        """
+        Returns: {'/dev/sdb': {'name': '/dev/sdb', 'size_gb': 931.5, ...}, ...} for every drive free to use.
+        Without smart the drives' SMART summaries are left empty, see backend.get_available_drives().
+        """
+        return {d['name']: d for d in backend.get_available_drives(smart)}
+
+    def inventory_async(self, on_done=None):
+        """
+        inventory() on a background.Operation, for frontends that stay responsive while lsblk and smartctl answer.
+        on_done(operation) gets the drives as operation.result.
+        """
+        return background.Operation(self.inventory, on_done=on_done).start()
+
+    def arrays(self):
+        return backend.get_active_arrays()
+
+    def probe(self, device):
+        """
+        Collects everything known about one disk for a deep inspection (`cli.py drives --probe`), available or not.
+        Returns: {'drive': {...as backend.get_available_drives()...}, 'available': False, 'smart': {...as backend.get_smart_report()...},
+                  'temperature': 34, 'partitions': {...as partition.get_partition_table()...} or None,
+                  'signatures': [...as backend.get_signatures()...], 'mountpoints': ['/mnt/old'], 'holders': ['md0'], 'raid_member': True}
+        or None when the device isn't a disk.
+        """
+        drive = backend.read_drive(device)
+        if drive is None:
+            return None
+        return {
+            'drive': drive,
+            'available': drive['name'] in self.inventory(),
+            'smart': backend.get_smart_report(drive['name']),
+            'temperature': backend.get_drive_temperature(drive['name']),
+            'partitions': partition.get_partition_table(drive['name']),
+            'signatures': backend.get_signatures(drive['name']),
+            'mountpoints': backend.get_mountpoints(drive['name']),
+            'holders': backend.get_holders(drive['name'].removeprefix("/dev/")),
+            'raid_member': drive['name'].removeprefix("/dev/") in backend.get_used_raid_drives(),
+        }
+
+    def status(self, array_names=None):
+        """
+        State, sync progress and members of the given arrays (all running arrays by default), see backend.get_arrays_status().
+        """
+        return backend.get_arrays_status(array_names)
+
+    def foreign_data(self, devices):
+        """
+        NTFS, exFAT and HFS+ filesystems on the disks about to be wiped, see backend.foreign_data_summary().
+        Returns: {'/dev/sdb': [{'device': '/dev/sdb2', 'fstype': 'NTFS', ...}]}, only disks that hold any.
+        """
+        summaries = {device: backend.foreign_data_summary(device) for device in devices}
+        return {device: summary for device, summary in summaries.items() if summary}
+
+    def stale_md_devices(self):
+        return backend.find_stale_md_devices()
+
+    def cleanup_stale_md(self, entries):
+        """
+        Stops inactive arrays and removes stale md nodes/links left by earlier runs (see backend.find_stale_md_devices()).
+        """
+        if not self.require_admin("cleanup-md", ", ".join(e['path'] for e in entries)):
+            return False
+        self.log(snapshot.snapshot_message("cleanup-md", ", ".join(e['path'] for e in entries)))
+        success, out = backend.cleanup_stale_md(entries)
+        self.log(out)
+        return success
+
+    def validate(self, level, devices, suppress=None, preserve_partitions=False, spares=(), strict=None, **md_options):
+        """
+        Same report as backend.validate_array(), for device paths instead of drive dicts.
+        Devices that are missing or already in use are reported as errors. With preserve_partitions
+        each disk counts with the free space its new RAID partition would get (spares stay whole disks).
+        md_options are the mdadm tunables of create_array() (metadata, bitmap, layout, name).
+        """
+        errors = [message for _, message in backend.check_md_options(level, **md_options)]
+        if errors:
+            return {'errors': errors, 'warnings': []}
+        # Called on every change of a selection: smartctl is only asked about the selected disks
+        drives = self.inventory(smart=False)
+        unknown = [dev for dev in list(devices) + list(spares) if dev not in drives]
+        if unknown:
+            return {'errors': [f"{', '.join(unknown)} not available (mounted, in use by an array, or missing)."], 'warnings': []}
+        backend.attach_smart([drives[dev] for dev in list(devices) + list(spares)])
+        members = [drives[dev] for dev in devices]
+        if preserve_partitions:
+            members, errors = partition.free_space_drives(members)
+            if errors:
+                return {'errors': errors, 'warnings': []}
+        return backend.validate_array(level, members, suppress, [drives[dev] for dev in spares], strict, md_options.get('metadata'))
+
+    def validate_format(self, array_name, fs_type, trim_discard=False, mkfs_options=None, encryption=None):
+        """
+        Returns: (mkfs_cmd, errors) for formatting an array, errors is empty when the filesystem fits.
+        """
+        device = luks.mapper_device(array_name) if encryption else backend.partition_device(array_name)
+        mkfs_cmd = backend.build_mkfs_command(fs_type, device, trim_discard, mkfs_options)
+        return mkfs_cmd, backend.check_filesystem_limits(fs_type, array_name, mkfs_cmd)
+
+    def verify_identities(self, identities):
+        """
+        Aborts a destructive step when a device path no longer points at the disk chosen when it was planned.
+        identities: {'/dev/sdb': backend.drive_identity(drive), ...} captured by the frontend.
+        """
+        errors = backend.verify_device_identities(identities or {})
+        for error in errors:
+            self.log(f"ERROR: {error}\n")
+        return not errors
+
+    def create_array(self, level, devices, array_name=None, chunk_size="Default", ssd_mode=False, identities=None,
+                     preserve_partitions=False, **advanced):
+        """
+        Creates an array, records it in mdadm.conf and tracks its initial sync.
+        With preserve_partitions the members are new partitions in each disk's free space and
+        existing partitions (ESP, other systems) are left alone.
+        Returns: (success, job) where job is None when no resync runs (SSD mode / --assume-clean).
+        """
+        array_name = array_name or arraynames.next_array_name()
+        if not self.require_admin("create", array_name) or not self.verify_identities(identities):
+            return False, None
+        self.log(snapshot.snapshot_message("create", array_name))
+
+        if preserve_partitions:
+            members = []
+            for disk in devices:
+                success, out, path = partition.add_raid_partition(disk)
+                self.log(out)
+                if not success:
+                    return False, None
+                members.append(path)
+            devices = members
+        success, out = backend.create_raid(level, devices, array_name, chunk_size, ssd_mode, **advanced)
+        self.log(out)
+        if not success:
+            return False, None
+
+        # Persist the array definition so it comes back under the same name after a reboot
+        if self.dry_run:
+            path = files.get_mdadm_conf_path()
+            script.record_write(path, files.render_mdadm_conf(array_name, files.read_file(path)))
+            self.log(f"[DRY RUN] Would modify {path}:\n")
+            self.log(files.mdadm_conf_diff(array_name, color=self.color))
+        else:
+            self.log(files.update_mdadm_conf(array_name)[1])
+            # Chunk, layout, data offset and member order, the values a last-resort re-create needs
+            self.log(geometry.save_geometry(array_name)[1])
+        self._record("ArrayCreated", array_name, level=str(level), members=devices, spares=advanced.get('spares') or [],
+                     backend="mdadm", uuid=backend.get_array_uuid(array_name))
+
+        if ssd_mode:
+            return True, None
+        return True, jobs.create_job("resync", array_name)
+
+    def format_array(self, array_name, fs_type, boot_flag=False, trim_discard=False, mkfs_options=None, label=None,
+                     encryption=None, passphrase=None):
+        """
+        Partitions an array and creates a filesystem on it. With encryption (luks.new_encryption()) the partition
+        becomes a LUKS2 container opened as luks.mapper_device(array_name) and recorded in /etc/crypttab,
+        and the filesystem goes inside it.
+        """
+        if not self.require_admin("format", array_name):
+            return False
+        self.log(snapshot.snapshot_message("format", array_name))
+        job = jobs.create_job("format", array_name)
+        if encryption:
+            success, out = self._format_encrypted(array_name, fs_type, boot_flag, trim_discard, mkfs_options, label, encryption, passphrase)
+        else:
+            success, out = backend.format_device(array_name, fs_type, boot_flag, trim_discard, mkfs_options, label)
+        self.log(out)
+
+        # TRIM was asked for, record whether it can actually reach the SSDs through this stack
+        if trim_discard:
+            discard = "Discard check:\n" + backend.format_discard_report(backend.check_discard(array_name))
+            self.log(discard)
+            out += discard
+        jobs.finish_job(job['id'], success, out)
+        return success
+
+    def _format_encrypted(self, array_name, fs_type, boot_flag, trim_discard, mkfs_options, label, encryption, passphrase):
+        container, name = backend.partition_device(array_name), luks.mapper_name(array_name)
+        mkfs_cmd = backend.build_mkfs_command(fs_type, luks.mapper_device(array_name), trim_discard, mkfs_options, label)
+        if mkfs_cmd is None:
+            return False, "ERROR: ZFS unsupported in this GUI design as it handles its own RAID (RAID-Z).\n"
+        logs = []
+        for i, step in enumerate((lambda: backend.partition_array(array_name, boot_flag),
+                                  lambda: luks.encrypt(container, name, encryption, passphrase, allow_discards=trim_discard),
+                                  lambda: backend.run_command(mkfs_cmd),
+                                  lambda: files.update_crypttab(name, container, encryption['keyfile'], trim_discard))):
+            success, out = step()
+            logs.append(out)
+            if not success:
+                if i > 1:
+                    # Don't leave the container open, it would keep the array busy
+                    logs.append(backend.run_command(luks.close_command(name))[1])
+                return False, "".join(logs)
+        return True, "".join(logs)
+
+    def create_array_steps(self, level, devices, array_name, chunk_size="Default", ssd_mode=False, identities=None, cleanup=(),
+                           preserve_partitions=False, handle=None, **advanced):
+        """
+        create_array() as executor.Steps for run_steps(), the way the GUI creates arrays: stale md devices are
+        cleaned up first (cleanup, see stale_md_devices()), and when a later step fails or the run is cancelled
+        the new RAID partitions are deleted again and the array stopped, its superblocks zeroed and its mdadm.conf line removed.
+        handle (an executor.JobHandle) gets the new array to freeze its resync on pause.
+        Returns: (steps, sync) where sync['job'] is the initial sync's job once the create step ran, or (None, None)
+        when admin mode or the disks' identities refuse the create.
+        """
+        if not self.require_admin("create", array_name) or not self.verify_identities(identities):
+            return None, None
+        members = [] if preserve_partitions else list(devices)
+        spares = list(advanced.get('spares') or [])
+        sync = {'job': None}
+        steps = []
+        if cleanup:
+            # Only inactive arrays are stopped, nothing to put back
+            steps.append(executor.Step("clean up stale md devices", lambda: self.cleanup_stale_md(cleanup)))
+        for disk in devices if preserve_partitions else []:
+            steps.append(self._partition_step(disk, members))
+
+        def create():
+            success, sync['job'] = self.create_array(level, members, array_name, chunk_size, ssd_mode, **advanced)
+            if success and handle and not backend.nothing_created(self.dry_run):
+                handle.watch(array_name)
+            return success
+
+        def undo_create():
+            uuid = backend.get_array_uuid(array_name)
+            if sync['job']:
+                jobs.finish_job(sync['job']['id'], False, "Array removed by the rollback of a cancelled or failed create.\n")
+                sync['job'] = None
+            undo = raidbackend.get_backend("mdadm").undo_commands({'array': array_name, 'spares': spares}, members)[0]
+            if not self._run_commands(undo):
+                return False
+            if uuid:
+                self.log(files.remove_array_from_mdadm_conf(uuid)[1])
+            self._record("Destroyed", array_name, uuid=uuid)
+            return True
+
+        steps.append(executor.Step(f"create {array_name} and record it in {files.get_mdadm_conf_path()}", create, undo_create,
+                                   [f"mdadm --stop {array_name}", "mdadm --zero-superblock <each member>",
+                                    f"remove the {array_name} line from {files.get_mdadm_conf_path()}"]))
+        return steps, sync
+
+    def format_array_steps(self, array_name, fs_type, boot_flag=False, trim_discard=False, mkfs_options=None, label=None,
+                           encryption=None, passphrase=None):
+        """
+        format_array() as executor.Steps for run_steps(), the way the GUI formats arrays: when mkfs fails or the run is
+        cancelled, the LUKS container is closed, /etc/crypttab put back and the new partition table wiped again.
+        Returns: the steps, None when admin mode refuses the format or the filesystem can't be made here.
+        """
+        if not self.require_admin("format", array_name):
+            return None
+        container = backend.partition_device(array_name)
+        name = luks.mapper_name(array_name)
+        device = luks.mapper_device(array_name) if encryption else container
+        mkfs_cmd = backend.build_mkfs_command(fs_type, device, trim_discard, mkfs_options, label)
+        if mkfs_cmd is None:
+            self.log("ERROR: ZFS unsupported in this GUI design as it handles its own RAID (RAID-Z).\n")
+            return None
+        self.log(snapshot.snapshot_message("format", array_name))
+        crypttab = {}
+
+        def logged(result):
+            self.log(result[1])
+            return result[0]
+
+        def update_crypttab():
+            crypttab['previous'] = files.read_file(files.CRYPTTAB_PATH)
+            return logged(files.update_crypttab(name, container, encryption['keyfile'], trim_discard))
+
+        def check_discard():
+            # TRIM was asked for, record whether it can actually reach the SSDs through this stack
+            self.log("Discard check:\n" + backend.format_discard_report(backend.check_discard(array_name)))
+            return True
+
+        steps = [executor.Step(f"partition {array_name}", lambda: logged(backend.partition_array(array_name, boot_flag)),
+                               lambda: self._run_commands([["wipefs", "-a", array_name]]), [f"wipefs -a {array_name}"])]
+        if encryption:
+            steps.append(executor.Step(f"encrypt {container}", lambda: logged(luks.encrypt(container, name, encryption, passphrase,
+                                                                                            allow_discards=trim_discard)),
+                                       lambda: self._run_commands([luks.close_command(name)]), [" ".join(luks.close_command(name))]))
+        steps.append(executor.Step(f"create the {fs_type} filesystem on {device}", lambda: self._run_commands([mkfs_cmd])))
+        if encryption:
+            steps.append(executor.Step(f"add {name} to {files.CRYPTTAB_PATH}", update_crypttab,
+                                       lambda: logged(files.write_file(files.CRYPTTAB_PATH, crypttab['previous'])),
+                                       [f"restore {files.CRYPTTAB_PATH}"]))
+        if trim_discard:
+            steps.append(executor.Step("check that discards reach the drives", check_discard))
+        return steps
+
+    def run_steps(self, kind, target, steps, on_progress=None, handle=None):
+        """
+        Runs executor.Steps (create_array_steps(), format_array_steps()) as a job of kind on target. When a step fails,
+        or handle (an executor.JobHandle) or `cli.py jobs cancel` cancels the run, the completed steps are rolled back.
+        on_progress receives the executor.Executor events. Returns: True when every step succeeded
+        """
+        job = jobs.create_job(kind, target)
+        runner = executor.Executor(self.log, job, on_progress, cancelled=lambda: jobs.is_cancel_requested(job['id']), handle=handle)
+        success = runner.run(steps)
+        jobs.update_job(job['id'], cancel_requested=runner.was_cancelled or jobs.is_cancel_requested(job['id']))
+        if success:
+            message = f"{kind.capitalize()} of {target} done.\n"
+        else:
+            message = "Cancelled, see the log for the rollback.\n" if runner.was_cancelled else "Failed, see the log for the rollback.\n"
+        jobs.finish_job(job['id'], success, message)
+        return success
+
+    def add_to_fstab(self, array_name, mount_point, options="defaults"):
+        """
+        Mounts a formatted array at boot: adds or updates its managed /etc/fstab entry (see fstab.py).
+        """
+        if not self.require_admin("fstab", array_name):
+            return False
+        if not os.path.isabs(mount_point) or os.path.normpath(mount_point) == "/":
+            self.log(f"ERROR: '{mount_point}' is not a mount point for an array, use an absolute path like /srv/data.\n")
+            return False
+        level = (backend.read_md_attribute(array_name, "level") or "").replace("raid", "")
+        verdict = policy.evaluate(level, [], mount_point=mount_point, swap=policy.active_swap(array_name))
+        for warning in verdict['warnings']:
+            self.log(f"WARNING [{warning['code']}]: {warning['message']}\n")
+        if verdict['errors']:
+            for error in verdict['errors']:
+                self.log(f"ERROR: {error['message']}\n")
+            return False
+        success, out = fstab.add_entry(array_name, fstab.find_volume(array_name), mount_point, options=options)
+        self.log(out)
+        return success
+
+    def remove_from_fstab(self, target):
+        """
+        Removes the managed /etc/fstab entry of an array or mount point, other entries are never touched.
+        """
+        if not self.require_admin("fstab", target):
+            return False
+        success, out = fstab.remove_entry(target)
+        self.log(out)
+        return success
+
+    def update_boot(self, action, array_name, loader=None):
+        """
+        Has the bootloader (loader, default: 'bootloader' in config.toml) assemble an array the system boots from
+        ('add-raid') or no longer ('remove-raid'), see bootloader.py. bootloader.diff() shows the change beforehand.
+        """
+        if not self.require_admin("bootloader", array_name):
+            return False
+        success, out = bootloader.update(action, array_name, loader, self.dry_run)
+        self.log(out)
+        return success
+
+    def apply_plan(self, proposal, identities=None, passphrase=None, on_progress=None, handle=None, wait=False, timeout=None):
+        """
+        Carries out a validated plan (plan.py) in the order plan.plan_steps() lists: create the array and record it
+        in mdadm.conf, partition (and encrypt) and format it, then mount it and add it to fstab. LVM and btrfs plans run the
+        commands of their raidbackend.py backend instead of the mdadm steps. passphrase unlocks an encrypted
+        plan's LUKS container when it has no keyfile.
+        The steps run through an executor.Executor tracked as an 'apply' job: when one fails, the completed ones
+        are undone (unmount, fstab restored, array stopped and its superblocks zeroed) or a recovery plan is logged.
+        Returns: (success, job) where job tracks the initial sync, as for create_array() (always None for LVM/btrfs).
+        The apply job itself, with the outcome of every step under 'steps' and the UUIDs read back from the new array
+        and filesystem under 'uuids' ({'array': md UUID or None, 'filesystem': ...}), is kept in self.last_apply_job.
+        on_progress receives the executor.Executor events of every step as it starts, finishes, fails or is rolled back.
+        `cli.py jobs cancel <apply job>` (or cancelling the background.Operation of apply_plan_async()) stops the
+        running step and rolls back the completed ones, the job then ends as 'cancelled'. handle (an executor.JobHandle)
+        cancels or pauses the run from another thread or a signal handler; pausing also freezes the new array's resync.
+        Applying a plan again skips what is in place already and brings in line what differs (reconcile.py), each
+        step's outcome (created, updated, unchanged) is kept with it in the job's 'steps'.
+        wait follows the initial sync through the executor as one more step (executor.Executor.follow_sync()) before
+        returning; 'synced' in the apply job is False when timeout (in seconds) ran out or the wait was cancelled first.
+        """
+        array_name = proposal['array']
+        self.last_apply_job = None
+        if not self.require_admin("create", array_name) or not self.verify_identities(identities):
+            return False, None
+        raid = raidbackend.get_backend(proposal['backend'])
+        members = [] if proposal['preserve_partitions'] else list(proposal['devices'])
+        sync = {'job': None}
+        # Read back once they exist, fstab is written with the real filesystem UUID rather than whatever a lookup finds later
+        uuids = {'array': None, 'filesystem': None}
+        steps = []
+        # The wipe stage goes first, an overwrite pass reports its progress within its step. Members of the array
+        # an earlier apply built are neither wiped nor partitioned again, whatever the backend
+        if proposal['wipe']['method'] != "none":
+            for disk in proposal['devices'] + proposal['spares']:
+                steps.append(self._wipe_step(disk, proposal['wipe'], lambda event: runner.report(event['percent'])))
+        for step in steps:
+            step.check = self._reconciled(lambda: reconcile.member_disks(proposal))
+
+        def read_filesystem_uuid():
+            if backend.nothing_created(self.dry_run):
+                return True
+            device = plan.volume_device(proposal)
+            uuids['filesystem'] = files.get_filesystem_uuid(device)
+            if uuids['filesystem'] is None:
+                self.log(f"ERROR: {device} was formatted but blkid reports no filesystem UUID for it.\n")
+            return uuids['filesystem'] is not None
+
+        # The UUIDs are read back in steps of their own: when that fails, the create or format before it completed
+        # and is rolled back with the rest instead of being left behind by a step that failed after its command ran
+        def uuid_step(key, device, run):
+            return executor.Step(f"read the {key} UUID of {device}", run,
+                                 check=lambda: executor.UNCHANGED if uuids[key] else executor.CREATED)
+
+        def check_format():
+            outcome, out = reconcile.filesystem(proposal)
+            self.log(out)
+            if outcome == executor.UNCHANGED:
+                uuids['filesystem'] = files.get_filesystem_uuid(plan.volume_device(proposal))
+            return outcome
+
+        if proposal['backend'] == "mdadm":
+            for disk in proposal['devices'] if proposal['preserve_partitions'] else []:
+                steps.append(self._partition_step(disk, members))
+            for index, disk in enumerate(proposal['devices']):
+                if disk in proposal['partitions']:
+                    steps.append(self._layout_step(disk, proposal['partitions'][disk], members, index))
+            for step in steps:
+                step.check = step.check or self._reconciled(lambda: reconcile.member_disks(proposal))
+            conf = {}
+
+            def check_create():
+                outcome, out = reconcile.array(proposal)
+                self.log(out)
+                if outcome == executor.UNCHANGED:
+                    uuids['array'] = backend.get_array_uuid(array_name)
+                return outcome
+
+            def create():
+                if create_step.outcome == executor.UPDATED:
+                    # The array runs already, only its mdadm.conf line is written
+                    conf['previous'] = files.read_file(files.get_mdadm_conf_path())
+                    create_step.recovery = [f"restore {files.get_mdadm_conf_path()}"]
+                    success, out = files.update_mdadm_conf(array_name, self.dry_run)
+                    self.log(out)
+                    uuids['array'] = backend.get_array_uuid(array_name)
+                    return success
+                success, sync['job'] = self.create_array(proposal['level'], members, array_name, proposal['chunk'], False,
+                                                         write_mostly=raidbackend.write_mostly_members(proposal, members),
+                                                         write_behind=proposal['write_behind'], auto_read_only=proposal['auto_read_only'],
+                                                         spares=proposal['spares'], metadata=proposal['metadata'], bitmap=proposal['bitmap'],
+                                                         layout=proposal['layout'], name=proposal['name'])
+                create_step.recovery = [" ".join(cmd) for cmd in raid.undo_commands(proposal, members)[0]] + \
+                                       [f"remove the {array_name} line from {files.get_mdadm_conf_path()}"]
+                if success and not backend.nothing_created(self.dry_run):
+                    runner.handle.watch(array_name)
+                return success
+
+            def read_array_uuid():
+                if backend.nothing_created(self.dry_run):
+                    return True
+                uuids['array'] = backend.get_array_uuid(array_name)
+                if uuids['array'] is None:
+                    self.log(f"ERROR: {array_name} was created but mdadm --detail reports no UUID for it.\n")
+                return uuids['array'] is not None
+
+            def undo_create():
+                if create_step.outcome == executor.UPDATED:
+                    # The array was there before this apply, only its mdadm.conf line goes back to what it was
+                    success, out = files.write_file(files.get_mdadm_conf_path(), conf['previous'])
+                    self.log(out)
+                    return success
+                uuid = backend.get_array_uuid(array_name)
+                if sync['job']:
+                    jobs.finish_job(sync['job']['id'], False, "Array removed by the rollback of a failed apply.\n")
+                    sync['job'] = None
+                if not self._run_commands(raid.undo_commands(proposal, members)[0]):
+                    return False
+                if uuid:
+                    self.log(files.remove_array_from_mdadm_conf(uuid)[1])
+                self._record("Destroyed", array_name, uuid=uuid)
+                return True
+
+            create_step = executor.Step(f"create {array_name}", create, undo_create, check=check_create)
+            steps.append(create_step)
+            steps.append(uuid_step('array', array_name, read_array_uuid))
+            encryption = proposal['encryption']
+            crypttab = {}
+
+            def format_volume():
+                crypttab['previous'] = files.read_file(files.CRYPTTAB_PATH)
+                return self.format_array(array_name, proposal['filesystem'], mkfs_options=fsoptions.plan_arguments(proposal), label=proposal['label'],
+                                         encryption=encryption, passphrase=passphrase)
+
+            def undo_encryption():
+                # The open container holds the array, it has to go before the array can be stopped
+                if not self._run_commands([luks.close_command(luks.mapper_name(array_name))]):
+                    return False
+                success, out = files.write_file(files.CRYPTTAB_PATH, crypttab['previous'])
+                self.log(out)
+                return success
+
+            steps.append(executor.Step(f"partition{', encrypt' if encryption else ''} and format {array_name} as {proposal['filesystem']}",
+                                       format_volume, undo_encryption if encryption else None,
+                                       [" ".join(luks.close_command(luks.mapper_name(array_name))),
+                                        f"restore {files.CRYPTTAB_PATH}"] if encryption else (), check_format))
+            steps.append(uuid_step('filesystem', plan.volume_device(proposal), read_filesystem_uuid))
+        else:
+            self.log(snapshot.snapshot_message("create", array_name))
+            undo = raid.undo_commands(proposal, members)
+            format_commands = raid.format_commands(proposal)
+
+            def check_volume():
+                outcome = self._reconciled(lambda: reconcile.volume(proposal))()
+                # btrfs has no format commands, its create command is the mkfs
+                if outcome == executor.UNCHANGED and not format_commands:
+                    uuids['filesystem'] = files.get_filesystem_uuid(plan.volume_device(proposal))
+                return outcome
+
+            for cmd, undo_cmds in zip(raid.create_commands(proposal, members), undo):
+                steps.append(executor.Step(" ".join(cmd), lambda cmd=cmd: self._run_commands([cmd]),
+                                           (lambda undo_cmds=undo_cmds: self._run_commands(undo_cmds)) if undo_cmds else None,
+                                           [" ".join(c) for c in undo_cmds],
+                                           check_volume))
+            for cmd in format_commands:
+                steps.append(executor.Step(" ".join(cmd), lambda cmd=cmd: self._run_commands([cmd]), check=check_format))
+            steps.append(uuid_step('filesystem', plan.volume_device(proposal), read_filesystem_uuid))
+
+        mount_point = proposal['mount_point']
+        if mount_point:
+            device = plan.volume_device(proposal)
+            options = plan.mount_options(proposal)
+            saved = {}
+
+            def update_fstab():
+                saved['previous'] = files.read_file(files.FSTAB_PATH)
+                success, out = fstab.add_entry(array_name, device, mount_point, proposal['filesystem'], options, uuid=uuids['filesystem'])
+                self.log(out)
+                return success
+
+            def restore_fstab():
+                success, out = files.write_file(files.FSTAB_PATH, saved['previous'])
+                self.log(out)
+                return success
+
+            steps.append(executor.Step(f"mount {device} on {mount_point}",
+                                       lambda: self._run_commands([["mkdir", "-p", mount_point], ["mount", "-o", options, device, mount_point]]),
+                                       lambda: self._run_commands([["umount", mount_point]]), [f"umount {mount_point}"],
+                                       self._reconciled(lambda: reconcile.mount(device, mount_point))))
+            steps.append(executor.Step(f"add {mount_point} to {files.FSTAB_PATH}", update_fstab, restore_fstab,
+                                       [f"remove the {mount_point} line from {files.FSTAB_PATH}"],
+                                       self._reconciled(lambda: reconcile.fstab_entry(array_name, mount_point, proposal['filesystem'],
+                                                                                       options, uuids['filesystem']))))
+
+        if proposal['scrub'] and proposal['backend'] == "mdadm":
+            scrub_timer = {}
+
+            def check_scrub():
+                # A timer that was there before the apply is put back on its schedule by the rollback, not removed
+                scrub_timer['previous'] = scrub.scheduled(array_name)
+                return self._reconciled(lambda: reconcile.schedules({scrub.timer_name(array_name): proposal['scrub']}))()
+
+            steps.append(executor.Step(f"schedule a {proposal['scrub']} scrub (check) of {array_name}",
+                                       lambda: self.schedule_scrub(array_name, proposal['scrub']),
+                                       lambda: self.schedule_scrub(array_name, scrub_timer['previous'] or "off"),
+                                       [f"systemctl disable --now {scrub.timer_name(array_name)}.timer"], check_scrub))
+
+        smart = {test: proposal[f'smart_{test}'] for test in ("short", "long") if proposal[f'smart_{test}']}
+        if smart:
+            # md arrays look up their members on every run, the others have no membership to ask
+            devices = None if proposal['backend'] == "mdadm" else list(proposal['devices'])
+            smart_timers = {}
+
+            def check_smart():
+                installed = {timer['name']: timer['schedule'] for timer in timers.list_timers("smart")}
+                smart_timers['previous'] = {test: installed.get(timers.unit_name(f"smart-{test}", array_name)) for test in smart}
+                return self._reconciled(lambda: reconcile.schedules({timers.unit_name('smart-' + test, array_name): schedule
+                                                                     for test, schedule in smart.items()}))()
+
+            steps.append(executor.Step("install the SMART self-test timers", lambda: self.schedule_smart_tests(array_name, smart, devices),
+                                       lambda: self.schedule_smart_tests(array_name, smart_timers['previous'], devices),
+                                       [f"systemctl disable --now {timers.unit_name('smart-' + test, array_name)}.timer" for test in smart],
+                                       check_smart))
+
+        job = jobs.create_job("apply", array_name)
+        runner = executor.Executor(self.log, job, on_progress, cancelled=lambda: jobs.is_cancel_requested(job['id']), handle=handle)
+        success = runner.run(steps)
+        if success and wait and sync['job']:
+            jobs.update_job(job['id'], synced=runner.follow_sync(array_name, timeout))
+        # Cancelled from this process rather than through the job, the job still ends as cancelled
+        jobs.update_job(job['id'], uuids=uuids, cancel_requested=runner.was_cancelled or jobs.is_cancel_requested(job['id']))
+        if success:
+            message = f"Applied the plan for {array_name}: {', '.join(f'{count} {outcome}' for outcome, count in runner.outcomes().items())}.\n"
+        else:
+            message = "Cancelled, see the log for the rollback.\n" if runner.was_cancelled else "Failed, see the log for the rollback.\n"
+        self.last_apply_job = jobs.finish_job(job['id'], success, message)
+        if not success:
+            return False, None
+        if proposal['backend'] != "mdadm":
+            # create_array() records the md arrays
+            self._record("ArrayCreated", array_name, level=proposal['level'], members=list(proposal['devices']),
+                         spares=list(proposal['spares']), backend=proposal['backend'], uuid=uuids['array'] or uuids['filesystem'])
+        if proposal['scrub'] and proposal['backend'] != "mdadm":
+            self.log(f"WARNING: Scheduled scrubs are only set up for md arrays, check {array_name} with "
+                     f"{'btrfs scrub start' if proposal['backend'] == 'btrfs' else 'lvchange --syncaction check'} instead.\n")
+        return True, sync['job']
+
+    def apply_plan_async(self, proposal, identities=None, passphrase=None, on_progress=None, on_done=None):
+        """
+        apply_plan() on a background.Operation: returns right away, operation.cancel() stops the step that
+        is running and rolls back the rest, operation.pause() holds it before the next step.
+        on_done(operation) gets (success, job) as operation.result.
+        """
+        handle = executor.JobHandle()
+        return background.Operation(self.apply_plan, proposal, identities, passphrase, on_progress, handle,
+                                    on_done=on_done, handle=handle).start()
+
+    def _reconciled(self, check):
+        # A reconcile.py check as an executor.Step check, what it found goes to the log
+        def step_check():
+            outcome, out = check()
+            self.log(out)
+            return outcome
+        return step_check
+
+    def _wipe_step(self, disk, policy, on_progress):
+        def run():
+            success, out = wipe.wipe_device(policy, disk, on_progress)
+            self.log(out)
+            return success
+
+        # Nothing to undo, what was wiped is gone
+        return executor.Step(f"wipe {disk} ({wipe.describe(policy)})", run)
+
+    def _partition_step(self, disk, members):
+        """
+        Returns the step adding a RAID partition in a disk's free space (preserve_partitions) and appending
+        it to members; rolling it back deletes that partition again and leaves the others as they were.
+        """
+        created = {}
+
+        def run():
+            success, out, path = partition.add_raid_partition(disk)
+            self.log(out)
+            if success:
+                members.append(path)
+                created['number'] = re.search(r"(\d+)$", path).group(1)
+                step.recovery = [f"parted -s {disk} rm {created['number']}"]
+            return success
+
+        step = executor.Step(f"add a RAID partition to {disk}", run, lambda: self._run_commands([["parted", "-s", disk, "rm", created['number']]]))
+        return step
+
+    def _layout_step(self, disk, layout, members, index):
+        """
+        Returns the step giving a disk a new GPT with one RAID partition (a plan's partition layout) and putting that
+        partition in its place in members, so the member order (RAID 10 mirror pairs) stays as planned. The old
+        partition table is gone at that point, rolling back only clears the new one.
+        """
+        def run():
+            success, out, path = partition.create_layout(disk, layout)
+            self.log(out)
+            if success:
+                members[index] = path
+            return success
+
+        return executor.Step(f"partition {disk} ({plan.describe_layout(layout)})", run,
+                             lambda: self._run_commands([["wipefs", "-a", disk]]), [f"wipefs -a {disk}"])
+
+    def _record(self, kind, array_name, **data):
+        # The event log (events.py) only holds what really happened, dry runs leave no trace. The UUID ties the
+        # event to its array when the md name changes (md0 assembled as md127 on another system)
+        if not self.dry_run:
+            events.record(kind, array_name, uuid=data.pop('uuid', None) or backend.get_array_uuid(array_name), **data)
+
+    def _run_commands(self, cmds):
+        for cmd in cmds:
+            success, out = backend.run_command(cmd)
+            self.log(out)
+            if not success:
+                return False
+        return True
+
+    def _job_progress(self, job):
+        """
+        Returns an on_progress callback for the migrate.py copy engine that mirrors its events into a job.
+        """
+        last = {}
+
+        def on_progress(event):
+            # rsync reports many times a second, only persist actual changes
+            if event != last:
+                last.update(event)
+                jobs.update_job(job['id'], percent=event['percent'], message=f"{event['phase']} {event['speed']}".strip())
+        return on_progress
+
+    def migrate_data(self, source, target, mode="auto", rate_limit=None, verify=True, on_progress=None):
+        """
+        Copies a filesystem or block device with the migrate.py engine, tracked as a 'migrate' job.
+        Re-running the same copy resumes it.
+        """
+        if not self.require_admin("migrate-data", target):
+            return False
+        self.log(snapshot.snapshot_message("migrate-data", target))
+        job = jobs.create_job("migrate", target)
+        track = self._job_progress(job)
+
+        def progress(event):
+            track(event)
+            if on_progress:
+                on_progress(event)
+
+        self.log(f"Copying {source} to {target} (job {job['id']})...\n")
+        success, out = migrate.copy_data(source, target, mode, rate_limit, verify, progress, cancelled=lambda: jobs.is_cancel_requested(job['id']))
+        jobs.finish_job(job['id'], success, out)
+        self.log(out)
+        return success
+
+    def migrate_hardware_raid(self, source_fs, level, devices, fs_type="ext4", array_name=None, old_volume=None, identities=None):
+        """
+        Moves the files of a hardware RAID volume onto a new md array: mount the source read-only,
+        check the data fits, create and format the array, rsync everything across, then optionally
+        wipe the old volume (old_volume) so its disks can be reused once the controller releases them.
+        """
+        array_name = array_name or arraynames.next_array_name()
+        if not self.require_admin("create", array_name):
+            return False
+        drives = self.inventory()
+        capacity = backend.estimate_array_capacity(level, [drives[dev] for dev in devices if dev in drives])
+
+        source_mnt, out = migrate.mount_temporary(source_fs, read_only=True)
+        self.log(out)
+        if source_mnt is None:
+            return False
+
+        success = False
+        try:
+            # Keep a little headroom, filesystem metadata on the new array eats into its capacity
+            used = migrate.get_used_bytes(source_mnt)
+            if used > capacity * 0.95:
+                self.log(f"ERROR: {source_fs} holds {units.format_size(used)}, the new RAID {level} only offers {units.format_size(capacity)}.\n")
+                return False
+
+            created, _ = self.create_array(level, devices, array_name, identities=identities)
+            if not created or not self.format_array(array_name, fs_type):
+                return False
+
+            target_mnt, out = migrate.mount_temporary(backend.partition_device(array_name))
+            self.log(out)
+            if target_mnt is None:
+                return False
+
+            job = jobs.create_job("migrate", array_name)
+            cancelled = lambda: jobs.is_cancel_requested(job['id'])
+            try:
+                self.log(f"Copying {source_fs} to {array_name} (job {job['id']})...\n")
+                success, out = migrate.copy_files(source_mnt, target_mnt, self._job_progress(job), cancelled)
+                self.log(out)
+                if success:
+                    success, verified = migrate.verify_files(source_mnt, target_mnt)
+                    self.log(verified)
+                    out += verified
+                jobs.finish_job(job['id'], success, out)
+            finally:
+                self.log(migrate.unmount_temporary(target_mnt)[1])
+        finally:
+            self.log(migrate.unmount_temporary(source_mnt)[1])
+
+        if success and old_volume:
+            if not self.teardown_disk(old_volume):
+                return False
+            self.log(f"Delete the virtual disk in the controller's setup utility (or switch it to HBA/JBOD mode) "
+                     f"to expose the disks behind {old_volume} for a new array.\n")
+        return success
+
+    def enable_raid456_discard(self):
+        """
+        Sets raid456 devices_handle_discard_safely=Y now and for future boots.
+        Running arrays only pick it up the next time they are assembled.
+        """
+        if not self.require_admin("discard", "raid456"):
+            return False
+        success, out = backend.write_sysfs(backend.RAID456_DISCARD_PARAM, "Y")
+        self.log(out)
+        if not success:
+            return False
+        success, out = files.write_file(files.MODPROBE_RAID456_CONF, "options raid456 devices_handle_discard_safely=Y\n")
+        self.log(out)
+        return success
+
+    def destroy_array(self, array_name, keep_superblocks=False):
+        if not self.require_admin("destroy", array_name):
+            return False
+        self.log(snapshot.snapshot_message("destroy", array_name))
+        uuid = backend.get_array_uuid(array_name)
+        success, out = backend.destroy_array(array_name, zero_superblocks=not keep_superblocks, wipe_members=not keep_superblocks)
+        self.log(out)
+        if success and uuid:
+            self.log(files.remove_array_from_mdadm_conf(uuid)[1])
+        if success:
+            self._record("Destroyed", array_name, uuid=uuid)
+        return success
+
+    def recreate_array(self, record, devices, array_name, mount_point, passphrase=None, keyfile=None):
+        """
+        Last-resort recovery: lays a new superblock over the old members with the recorded parameters
+        (geometry.recreate_command(), --assume-clean so nothing is resynced), keeps the array read-only and
+        mounts its filesystem read-only at mount_point so the data can be copied off. The filesystem is looked for
+        on the array and on its first partition; a LUKS container there is opened read-only with the passphrase
+        or keyfile first. devices: {slot: device}.
+        Returns: (success, log_str); the array stays assembled read-only if the mount fails.
+        """
+        if not self.require_admin("recreate", array_name):
+            return False, ""
+        self.log(snapshot.snapshot_message("recreate", array_name))
+        out = []
+
+        def run(cmd):
+            success, text = backend.run_command(cmd)
+            self.log(text)
+            out.append(text)
+            return success
+
+        def fail(message):
+            self.log(message)
+            return False, "".join(out) + message
+
+        def probe(device):
+            return subprocess.run(['blkid', '-o', 'value', '-s', 'TYPE', device], capture_output=True, text=True).stdout.strip()
+
+        # New arrays start read-only, so not even a resync or superblock update writes to the members
+        start_ro = "/sys/module/md_mod/parameters/start_ro"
+        previous = backend.read_sysfs_text(start_ro)
+        self.log(backend.write_sysfs(start_ro, "1")[1])
+        created = run(geometry.recreate_command(record, devices, array_name))
+        if previous is not None:
+            self.log(backend.write_sysfs(start_ro, previous)[1])
+        if not created or not run(["mdadm", "--readonly", array_name]):
+            return False, "".join(out)
+
+        # LiveRAID and most installers partition the array, the filesystem (or its container) is on partition 1
+        device, fstype = array_name, ""
+        if not self.dry_run:
+            device = next((d for d in (array_name, backend.partition_device(array_name)) if probe(d)), array_name)
+            fstype = probe(device)
+            if not fstype:
+                return fail(f"No filesystem found on {array_name} or {backend.partition_device(array_name)}. The parameters may be wrong: "
+                            f"stop it with 'mdadm --stop {array_name}' before trying others.\n")
+        if fstype == "crypto_LUKS":
+            name = luks.mapper_name(array_name)
+            if keyfile is None and not passphrase:
+                return fail(f"{device} is a LUKS container and no passphrase or keyfile was given. Open it with "
+                            f"'cryptsetup open --readonly {device} {name}' and mount {luks.mapper_device(array_name)} read-only.\n")
+            success, text = luks.run_with_key(luks.open_command(device, name, luks.new_encryption(keyfile=keyfile), read_only=True), passphrase)
+            self.log(text)
+            out.append(text)
+            if not success:
+                return fail(f"Could not open the LUKS container on {device}, check the passphrase or keyfile.\n")
+            device = luks.mapper_device(array_name)
+            fstype = probe(device)
+            if not fstype:
+                return fail(f"No filesystem found in the LUKS container on {array_name}.\n")
+        options = RECOVERY_MOUNT_OPTIONS.get(fstype, "ro")
+        if not run(["mkdir", "-p", mount_point]) or not run(["mount", "-o", options, device, mount_point]):
+            return False, "".join(out)
+        return True, "".join(out)
+
+    def throughput_test(self, array_name, mount_point, size_bytes=1024**3):
+        """
+        Acceptance test of a provisioned array: sequential write and read of size_bytes on its mounted filesystem,
+        compared with the estimate for its members (acceptance.py). Known causes of a shortfall are listed.
+        Returns: {'array': '/dev/md0', 'level': '5', 'write': {...}, 'read': {...}, 'passed': True, 'causes': [...]}, or None
+        when the array isn't running.
+        """
+        status = next(iter(backend.get_arrays_status([array_name])), None)
+        if status is None or status['status'] == "missing":
+            self.log(f"ERROR: {array_name} is not running.\n")
+            return None
+        level = (status['type'] or "").replace("raid", "")
+        drives = [backend.read_drive(backend.get_parent_disk(m['device'])) for m in status['members']
+                  if m['device'] and m['role'] in ("active", "rebuilding")]
+        expected = acceptance.expected_throughput(level, drives)
+        if not backend.DRY_RUN and not os.path.ismount(mount_point):
+            self.log(f"ERROR: Nothing is mounted on {mount_point}.\n")
+            return None
+        if backend.DRY_RUN:
+            self.log(f"[DRY RUN] Would write and read back {units.format_size(size_bytes)} in {mount_point}\n")
+            measured = expected
+        else:
+            self.log(f"Writing and reading back {units.format_size(size_bytes)} in {mount_point}...\n")
+            measured = acceptance.measure(mount_point, size_bytes)
+        report = dict(acceptance.check(expected, measured), array=array_name, level=level)
+        report['causes'] = acceptance.find_causes(array_name) if not report['passed'] else []
+        return report
+
+    def benchmark(self, array_name, mount_point, workloads=None, size_bytes=1024**3, runtime=None, baseline=False, engine=None):
+        """
+        Runs the bench.py workloads (all of them by default) on a test file in the array's mounted filesystem, with fio
+        or the built-in tester (engine, bench_engine in config.toml). baseline repeats the read workloads read-only
+        on the first active member and compares the array with it.
+        Returns: {'array': '/dev/md0', 'level': '5', 'members': 4, 'engine': 'fio', 'results': [{'workload': 'seq-write', ...}],
+        'baseline': {'device': '/dev/sdb', 'results': [...]} or None, 'comparison': [...], 'passed': True}, or None
+        when the array isn't running or fio was asked for but isn't installed.
+        """
+        status = next(iter(backend.get_arrays_status([array_name])), None)
+        if status is None or status['status'] == "missing":
+            self.log(f"ERROR: {array_name} is not running.\n")
+            return None
+        engine = bench.resolve_engine(engine)
+        if engine is None:
+            self.log("ERROR: fio is not installed, install it or use the built-in tester (--engine builtin).\n")
+            return None
+        workloads = list(workloads or bench.WORKLOADS)
+        runtime = runtime or config.get_setting('bench_runtime')
+        level = (status['type'] or "").replace("raid", "")
+        members = [m['device'] for m in status['members'] if m['device'] and m['role'] == "active"]
+        report = {'array': array_name, 'level': level, 'members': len(members), 'engine': engine,
+                  'results': [], 'baseline': None, 'comparison': [], 'passed': True}
+        if backend.DRY_RUN:
+            self.log(f"[DRY RUN] Would run {', '.join(workloads)} with {engine} on {units.format_size(size_bytes)} in {mount_point}"
+                     + (f" and the read workloads on {members[0]}" if baseline and members else "") + "\n")
+            return report
+        if not os.path.ismount(mount_point):
+            self.log(f"ERROR: Nothing is mounted on {mount_point}.\n")
+            return None
+
+        path = os.path.join(mount_point, bench.TEST_FILE)
+        self.log(f"Benchmarking {array_name} with {engine}: {', '.join(workloads)}, {units.format_size(size_bytes)} "
+                 f"for at most {runtime}s each...\n")
+        try:
+            if engine == "builtin" and not set(workloads) & {"seq-write", "rand-write"} and not bench.prepare(path, size_bytes):
+                self.log(f"ERROR: Could not write the test file {path}.\n")
+                return None
+            report['results'] = bench.run(path, workloads, size_bytes, runtime, engine)
+        finally:
+            if os.path.exists(path):
+                os.remove(path)
+        reads = [w for w in workloads if w in bench.READ_WORKLOADS]
+        if baseline and members and reads:
+            self.log(f"Baseline: reading from {members[0]} alone (read-only)...\n")
+            results = bench.run(members[0], reads, size_bytes, runtime, engine, readonly=True)
+            report['baseline'] = {'device': members[0], 'results': results}
+            report['comparison'] = bench.compare(report['results'], results, level, len(members), engine)
+        report['passed'] = all(report['results']) and all(c['passed'] for c in report['comparison'])
+        return report
+
+    def mount_read_only(self, target, mount_root=READ_ONLY_ROOT):
+        """
+        Mounts every filesystem on a disk, partition or array strictly read-only for inspection before it is
+        re-provisioned: the devices are flagged read-only (blockdev --setro), md members of a stopped array are
+        assembled with --readonly and journals are not replayed (RECOVERY_MOUNT_OPTIONS).
+        Returns: (success, session) where session is {'mounts': [{'device': '/dev/md127p1', 'fstype': 'ext4', 'mount_point': '...'}],
+                 'assembled': '/dev/md127' or None, 'read_only': ['/dev/sdb', ...]}, to be handed to release_read_only().
+        """
+        session = {'mounts': [], 'assembled': None, 'read_only': []}
+
+        def set_read_only(device):
+            success, out = backend.run_command(["blockdev", "--setro", device])
+            self.log(out)
+            if success:
+                session['read_only'].append(device)
+            return success
+
+        device = target
+        if not backend.is_array_active(target):
+            # Members of a stopped array: assemble the array from every disk carrying it, read-only
+            disks = [f"/dev/{name}" for name in sorted(os.listdir("/sys/block")) if not name.startswith(("loop", "ram", "md", "dm-", "zram", "sr"))]
+            info = backend.examine_member(target)
+            arrays = [a for a in backend.find_old_arrays(disks) if info and a['uuid'] == info['uuid']] or \
+                     [a for a in backend.find_old_arrays([target])]
+            if arrays:
+                members = arrays[0]['members']
+                device = arraynames.next_array_name()
+                self.log(f"{target} holds members of array '{arrays[0]['name'] or arrays[0]['uuid']}', assembling it read-only as {device}.\n")
+                if not all(set_read_only(m) for m in members):
+                    return False, session
+                success, out = backend.run_command(["mdadm", "--assemble", "--readonly", "--run", device] + members)
+                self.log(out)
+                if not success:
+                    return False, session
+                session['assembled'] = device
+        if not set_read_only(device):
+            return False, session
+
+        try:
+            result = subprocess.run(['lsblk', '-J', '-p', '-o', 'NAME,FSTYPE,MOUNTPOINTS', device], capture_output=True, text=True, check=True)
+            nodes = json.loads(result.stdout).get('blockdevices', [])
+        except Exception as e:
+            self.log(f"ERROR: Failed to list the filesystems on {device}: {e}\n")
+            return False, session
+        filesystems = []
+        while nodes:
+            filesystems += [n for n in nodes if n.get('fstype') and n['fstype'] not in NOT_MOUNTABLE and not any(n.get('mountpoints') or [])]
+            nodes = [child for n in nodes for child in n.get('children', [])]
+
+        for fs in filesystems:
+            mount_point = os.path.join(mount_root, os.path.basename(fs['name']))
+            if fs['name'] != device and not set_read_only(fs['name']):
+                return False, session
+            for cmd in (["mkdir", "-p", mount_point], ["mount", "-o", RECOVERY_MOUNT_OPTIONS.get(fs['fstype'], "ro"), fs['name'], mount_point]):
+                success, out = backend.run_command(cmd)
+                self.log(out)
+                if not success:
+                    return False, session
+            session['mounts'].append({'device': fs['name'], 'fstype': fs['fstype'], 'mount_point': mount_point})
+        return True, session
+
+    def release_read_only(self, session):
+        """
+        Undoes mount_read_only(): unmounts, stops the array it assembled and makes the devices writable again.
+        Returns True when everything was released.
+        """
+        cmds = [["umount", m['mount_point']] for m in reversed(session['mounts'])]
+        cmds += [["rmdir", m['mount_point']] for m in reversed(session['mounts'])]
+        if session['assembled']:
+            cmds.append(["mdadm", "--stop", session['assembled']])
+        cmds += [["blockdev", "--setrw", device] for device in session['read_only']]
+        released = True
+        for cmd in cmds:
+            success, out = backend.run_command(cmd)
+            self.log(out)
+            released = released and success
+        return released
+
+    def add_replacement(self, array_name, device, identity=None):
+        """
+        Hot-adds a replacement disk to a degraded array and tracks the rebuild.
+        Returns: (success, job)
+        """
+        if not self.require_admin("hot-add", array_name):
+            return False, None
+        if identity and not self.verify_identities({device: identity}):
+            return False, None
+        self.log(snapshot.snapshot_message("hot-add", array_name))
+        success, out = backend.add_member(array_name, device)
+        self.log(out)
+        if not success:
+            return False, None
+        # The failed member is already gone from the array, which disk it was is only known from earlier events
+        self._record("MemberReplaced", array_name, old=None, new=device)
+        return True, jobs.create_job("recovery", array_name)
+
+    def verify_rebuild(self, array_name, device, on_progress=None):
+        """
+        Waits for the rebuild onto a new member to finish, then reads the whole array back (check pass)
+        and runs a SMART short self-test on the new member. Only a rebuild passing both counts as complete;
+        the outcome is recorded in the health history.
+        Returns: (success, summary)
+        """
+        job = jobs.create_job("verify", array_name)
+        self.log(f"Waiting for {array_name} to finish rebuilding onto {device}...\n")
+        if not self.wait_for_sync(array_name, job, on_progress=on_progress):
+            summary = f"The rebuild of {array_name} did not finish, {device} was not verified."
+            jobs.finish_job(job['id'], False, summary)
+            return False, summary
+        degraded = backend.read_md_attribute(array_name, "degraded")
+
+        self.log(f"Verification 1/2: reading back every stripe of {array_name} (check)...\n")
+        ok, mismatches, errors = self.scrub(array_name, "check", on_progress)
+        self.log(f"Verification 2/2: SMART short self-test on {device}...\n")
+        report = self.smart_retest(device)
+
+        problems = []
+        if degraded not in (None, "0"):
+            problems.append(f"{array_name} is still missing {degraded} members")
+        if not ok:
+            problems.append("the check pass did not complete")
+        if mismatches:
+            problems.append(f"{mismatches} mismatched sectors")
+        if errors.get(device):
+            problems.append(f"{errors[device]} read errors corrected on {device}")
+        if report['passed'] is False or report['last_self_test_passed'] is False:
+            problems.append(f"{device} failed its SMART self-test")
+        summary = (f"{device} rebuilt into {array_name} and verified." if not problems
+                   else f"Verification of {device} in {array_name} failed: {', '.join(problems)}.")
+        health.record_event(array_name, "rebuild-verification", summary,
+                            {'device': device, 'mismatches': mismatches, 'member_errors': errors, 'smart': report})
+        jobs.finish_job(job['id'], not problems, summary)
+        return not problems, summary
+
+    def upgrade_blocker(self, array_name, new_device=None):
+        """
+        Health check run before every step of a capacity upgrade. Returns why the next step must wait, or None.
+        """
+        degraded = backend.read_md_attribute(array_name, "degraded")
+        if degraded not in (None, "0"):
+            return f"{array_name} is degraded ({degraded} missing)"
+        progress = backend.get_sync_progress(array_name)
+        if progress:
+            return f"{array_name} is busy with a {progress['action']} ({progress['percent']:.1f}%)"
+        faulty = [m['device'] for m in backend.get_array_members(array_name) if m['role'] == "faulty"]
+        if faulty:
+            return f"{', '.join(faulty)} of {array_name} are faulty"
+        if new_device and backend.get_smart_report(new_device)['passed'] is False:
+            return f"{new_device} fails its SMART health check"
+        return None
+
+    def upgrade_members(self, upgrade, on_progress=None):
+        """
+        Runs (or resumes) a disk-by-disk capacity upgrade from expand.new_upgrade(): every member is copied onto
+        its larger successor with mdadm --replace and removed, with a health check before each step; then the
+        array and its filesystem are grown. Progress is saved after every step, so an upgrade interrupted by a
+        reboot continues where it stopped when run again.
+        Returns True when the upgrade is complete.
+        """
+        array_name = upgrade['array']
+        if not self.require_admin("upgrade", array_name):
+            return False
+        job = jobs.create_job("upgrade", array_name)
+
+        def save():
+            # A dry run must not leave an upgrade behind that a later real run would resume
+            if not self.dry_run:
+                expand.save_upgrade(upgrade)
+
+        def fail(message):
+            self.log(f"ERROR: {message}\n")
+            jobs.finish_job(job['id'], False, message)
+            return False
+
+        total = len(upgrade['pairs'])
+        if upgrade['phase'] == "replace":
+            self.log(snapshot.snapshot_message("upgrade", array_name))
+            for i, pair in enumerate(upgrade['pairs'], 1):
+                if pair['state'] == "done":
+                    continue
+                if pair['state'] == "pending":
+                    problem = self.upgrade_blocker(array_name, pair['new'])
+                    if problem:
+                        return fail(f"Stopped before replacing {pair['old']}: {problem}.")
+                    if pair.get('identity') and not self.verify_identities({pair['new']: pair['identity']}):
+                        return fail(f"Stopped before replacing {pair['old']}: {pair['new']} is not the disk that was chosen.")
+                    self.log(f"Step {i}/{total}: copying {pair['old']} onto {pair['new']}...\n")
+                    for step in (lambda: backend.add_member(array_name, pair['new']),
+                                 lambda: backend.replace_member(array_name, pair['old'], pair['new'])):
+                        success, out = step()
+                        self.log(out)
+                        if not success:
+                            return fail(f"Could not start replacing {pair['old']} with {pair['new']}.")
+                    pair['state'] = "replacing"
+                    save()
+                    if not self.dry_run:
+                        # mdstat only shows the copy once md has started it
+                        time.sleep(2)
+
+                # Also reached after a resume: md carries on with an interrupted copy by itself
+                if not self.wait_for_sync(array_name, job, on_progress=on_progress):
+                    return fail(f"The copy onto {pair['new']} did not finish, run the upgrade again to resume it.")
+                if any(m['device'] == pair['old'] for m in backend.get_array_members(array_name)):
+                    success, out = backend.remove_member(array_name, pair['old'])
+                    self.log(out)
+                    if not success:
+                        return fail(f"Could not remove the replaced member {pair['old']}.")
+                pair['state'] = "done"
+                save()
+                self._record("MemberReplaced", array_name, old=pair['old'], new=pair['new'])
+                jobs.update_job(job['id'], percent=100.0 * i / (total + 1), message=f"{i}/{total} members replaced")
+                self.log(f"{pair['old']} can be disconnected now.\n")
+            upgrade['phase'] = "grow"
+            save()
+
+        if upgrade['phase'] == "grow":
+            problem = self.upgrade_blocker(array_name)
+            if problem:
+                return fail(f"Stopped before growing {array_name}: {problem}.")
+            self.log(f"Growing {array_name} onto the full size of its new members...\n")
+            success, out = backend.grow_to_max_size(array_name)
+            self.log(out)
+            if not success:
+                return fail(f"Could not grow {array_name}.")
+            if not self.dry_run:
+                time.sleep(2)
+            if not self.wait_for_sync(array_name, job, on_progress=on_progress):
+                return fail(f"The resync of the new space on {array_name} did not finish, run the upgrade again to resume it.")
+            upgrade['phase'] = "filesystem"
+            save()
+
+        if upgrade['phase'] == "filesystem":
+            steps, error = expand.filesystem_grow_steps(array_name)
+            for cmd in steps:
+                success, out = backend.run_command(cmd)
+                self.log(out)
+                if not success:
+                    return fail(f"Growing the filesystem on {array_name} failed, run the upgrade again to retry.")
+            if error:
+                self.log(f"WARNING: {error}\n")
+
+        if not self.dry_run:
+            expand.clear_upgrade(array_name)
+        self._record("Grown", array_name, members=[m['device'] for m in backend.get_array_members(array_name) if m['device']],
+                     size_bytes=backend.get_device_size(os.path.basename(os.path.realpath(array_name))))
+        summary = f"All {total} members of {array_name} replaced and the array grown."
+        jobs.finish_job(job['id'], True, summary)
+        self.log(f"SUCCESS: {summary}\n")
+        return True
+
+    def grow_array(self, grow, on_progress=None):
+        """
+        Runs (or resumes) a grow plan from expand.new_grow(): adds the new disks as spares, reshapes the array onto
+        them with mdadm --grow --raid-devices and follows the reshape, then grows the filesystem. Progress is saved
+        after every phase; md carries on with an interrupted reshape by itself, running the grow again waits for it.
+        Returns True when the array and its filesystem are grown.
+        """
+        array_name = grow['array']
+        if not self.require_admin("grow", array_name):
+            return False
+        job = jobs.create_job("grow", array_name)
+
+        def save():
+            if not self.dry_run:
+                expand.save_grow(grow)
+
+        def fail(message):
+            self.log(f"ERROR: {message}\n")
+            jobs.finish_job(job['id'], False, message)
+            return False
+
+        new_devices = [a['device'] for a in grow['add']]
+        if grow['phase'] == "add":
+            problem = self.upgrade_blocker(array_name)
+            if problem:
+                return fail(f"Stopped before growing {array_name}: {problem}.")
+            identities = {a['device']: a['identity'] for a in grow['add'] if a.get('identity')}
+            if identities and not self.verify_identities(identities):
+                return fail(f"Stopped before growing {array_name}: the disks to add are not the ones that were chosen.")
+            self.log(snapshot.snapshot_message("grow", array_name))
+            members = {m['device'] for m in backend.get_array_members(array_name)}
+            for device in new_devices:
+                if device in members:
+                    continue
+                self.log(f"Adding {device} to {array_name}...\n")
+                success, out = backend.add_member(array_name, device)
+                self.log(out)
+                if not success:
+                    return fail(f"Could not add {device} to {array_name}.")
+            self.log(f"Reshaping {array_name} onto {grow['raid_devices']} members...\n")
+            success, out = backend.grow_raid_devices(array_name, grow['raid_devices'], grow['backup_file'])
+            self.log(out)
+            if not success:
+                return fail(f"mdadm refused to reshape {array_name}, the new disks stay attached as spares.")
+            grow['phase'] = "reshape"
+            save()
+            if not self.dry_run:
+                # mdstat only shows the reshape once md has started it
+                time.sleep(2)
+
+        if grow['phase'] == "reshape":
+            jobs.update_job(job['id'], message=f"reshaping onto {grow['raid_devices']} members")
+            if not self.wait_for_sync(array_name, job, on_progress=on_progress):
+                return fail(f"The reshape of {array_name} did not finish, run the grow again to keep following it.")
+            grow['phase'] = "filesystem"
+            save()
+
+        if grow['phase'] == "filesystem":
+            steps, error = expand.filesystem_grow_steps(array_name)
+            for cmd in steps:
+                success, out = backend.run_command(cmd)
+                self.log(out)
+                if not success:
+                    return fail(f"Growing the filesystem on {array_name} failed, run the grow again to retry.")
+            if error:
+                self.log(f"WARNING: {error}\n")
+
+        if not self.dry_run:
+            expand.clear_grow(array_name)
+        self._record("Grown", array_name, members=[m['device'] for m in backend.get_array_members(array_name) if m['device']],
+                     size_bytes=backend.get_device_size(os.path.basename(os.path.realpath(array_name))))
+        summary = f"{array_name} reshaped onto {grow['raid_devices']} members ({', '.join(new_devices)} added) and grown."
+        jobs.finish_job(job['id'], True, summary)
+        self.log(f"SUCCESS: {summary}\n")
+        return True
+
+    def scrub(self, array_name, action="check", on_progress=None, record=False):
+        """
+        Runs a check or repair pass to completion, optionally recording the outcome in the health history.
+        on_progress gets the backend.get_sync_progress() dicts with the mismatch_cnt so far under 'mismatches'.
+        Returns: (success, mismatch_cnt, {'/dev/sda': corrected read errors, ...})
+        """
+        # A check only reads, a repair rewrites stripes
+        if action != "check" and not self.require_admin("repair", array_name):
+            return False, 0, {}
+        job = jobs.create_job(action, array_name)
+        success, out = scrub.start(array_name, action)
+        self.log(out)
+        if success and not self.dry_run:
+            # mdstat only shows the pass once md has picked up the new sync_action
+            time.sleep(2)
+        if success:
+            # mismatch_cnt grows while the pass runs, frontends show it next to the progress
+            progress = (lambda event: on_progress(dict(event, mismatches=scrub.mismatch_count(array_name)))) if on_progress else None
+            success = self.wait_for_sync(array_name, job, on_progress=progress)
+        mismatches = scrub.mismatch_count(array_name)
+        errors = backend.get_member_errors(array_name)
+        if success:
+            self._record("Scrubbed", array_name, action=action, mismatches=mismatches, member_errors=errors)
+        if record and success:
+            health.record_event(array_name, action, f"{mismatches} mismatched sectors, {sum(errors.values())} read errors corrected",
+                                {'mismatches': mismatches, 'member_errors': errors})
+        return success, mismatches, errors
+
+    def schedule_scrub(self, array_name, when):
+        """
+        Installs the systemd timer running a monthly or weekly check of an array (scrub.py), or removes it for 'off'.
+        """
+        if not self.require_admin("tune", array_name):
+            return False
+        success, out = scrub.schedule(array_name, when)
+        self.log(out)
+        return success
+
+    def smart_retest(self, device, timeout=1800, poll_interval=30):
+        """
+        Runs a short SMART self-test on a member and waits for its result. Returns the SMART report.
+        """
+        success, out = backend.start_smart_test(device)
+        self.log(out)
+        if success and not self.dry_run:
+            deadline = time.monotonic() + timeout
+            time.sleep(poll_interval)
+            while backend.get_smart_report(device)['self_test_running'] and time.monotonic() < deadline:
+                time.sleep(poll_interval)
+        return backend.get_smart_report(device)
+
+    def smart_targets(self, array_name, devices=None):
+        """
+        Returns the disks to SMART-test for an array: its members (or the given devices) with partitions
+        resolved to their disk, each disk once.
+        """
+        if not devices:
+            devices = [m['device'] for m in backend.get_array_members(array_name) if m['device']]
+        disks = []
+        for dev in devices:
+            disk = backend.get_parent_disk(dev)
+            if disk not in disks:
+                disks.append(disk)
+        return disks
+
+    def smart_test(self, array_name, test="short", devices=None, poll_interval=60):
+        """
+        Starts a SMART self-test on every member disk at once, waits for all of them and records the results
+        in the health history. Failed tests run the on_smart_failed hooks. Used by the timers of schedule_smart_tests().
+        Returns: (success, {'/dev/sda': SMART report, ...}) where success means every disk passed
+        """
+        disks = self.smart_targets(array_name, devices)
+        if not disks:
+            self.log(f"ERROR: No member disks found for {array_name}.\n")
+            return False, {}
+        job = jobs.create_job(f"smart-{test}", array_name)
+        started = []
+        for disk in disks:
+            success, out = backend.start_smart_test(disk, test)
+            self.log(out)
+            if success:
+                started.append(disk)
+        if self.dry_run:
+            jobs.finish_job(job['id'], True, "Dry run, no self-test was started.\n")
+            return True, {}
+
+        running = list(started)
+        deadline = time.monotonic() + SMART_TEST_TIMEOUTS[test]
+        while running and time.monotonic() < deadline:
+            time.sleep(poll_interval)
+            running = [disk for disk in running if backend.get_smart_report(disk)['self_test_running']]
+            jobs.update_job(job['id'], percent=100.0 * (len(started) - len(running)) / len(started),
+                            message=f"{len(running)} of {len(started)} self-tests running")
+
+        reports = {disk: backend.get_smart_report(disk) for disk in disks}
+        failed = [disk for disk, report in reports.items()
+                  if disk not in started or report['passed'] is False or report['last_self_test_passed'] is False]
+        if failed:
+            summary = f"{', '.join(failed)} failed the {test} SMART self-test (or could not run it)"
+        elif running:
+            summary = f"The {test} SMART self-test of {', '.join(running)} did not finish in time"
+        else:
+            summary = f"{len(disks)} member disks passed the {test} SMART self-test"
+        health.record_event(array_name, f"smart-{test}", summary, {'test': test, 'failed': failed, 'unfinished': running, 'reports': reports})
+        self.log(f"{'WARNING' if failed or running else 'SUCCESS'}: {summary}\n")
+        if failed:
+            event = {'event': "smart_failed", 'array': array_name, 'timestamp': int(time.time()),
+                     'host': socket.gethostname(), 'test': test, 'devices': failed}
+            self.log(hooks.run_hooks(event)[1])
+            # Usually run by a timer, so only the sinks that don't need a desktop session
+            self.log(notifications.dispatch(event, notifications.SYSTEM_SINKS)[1])
+        jobs.finish_job(job['id'], not failed, summary + "\n")
+        return not failed and not running, reports
+
+    def schedule_smart_tests(self, array_name, schedules, devices=None):
+        """
+        Installs the systemd timers (timers.py) running `cli.py smart-test` on an array's members, or removes them.
+        schedules: {'short': 'weekly', 'long': 'off'}, tests left out keep their current timer.
+        devices pins the disks to test for arrays without md membership (LVM, btrfs); md arrays look up
+        their members on every run, so replaced disks are tested too.
+        """
+        if not self.require_admin("tune", array_name):
+            return False
+        ok = True
+        for test, schedule in schedules.items():
+            name = timers.unit_name(f"smart-{test}", array_name)
+            if schedule in (None, "off"):
+                success, out = timers.remove_timer(name)
+            else:
+                cli_args = ["smart-test", array_name, "--type", test] + [arg for dev in devices or [] for arg in ("--device", dev)]
+                success, out = timers.install_timer(name, f"LiveRAID {test} SMART self-test of {array_name}", cli_args, schedule)
+            self.log(out)
+            ok = ok and success
+        return ok
+
+    def remediate_scrub(self, array_name, on_progress=None):
+        """
+        Follow-up for a scrub that found mismatches or read errors: re-check, rewrite the inconsistent
+        stripes if the problem persists, SMART-test the members that produced read errors, then record
+        a recommendation in the health history. Returns the recommendation text.
+        """
+        # 1. Re-check: one-off mismatches (e.g. an interrupted write to swap) don't survive a second pass
+        self.log(f"Step 1/3: re-checking {array_name}...\n")
+        ok, mismatches, errors = self.scrub(array_name, "check", on_progress)
+        details = {'recheck_mismatches': mismatches, 'member_errors': errors}
+
+        # 2. Rewrite the affected stripes from redundancy, then confirm they are consistent now
+        if ok and mismatches and not self.allowed("repair"):
+            self.log(f"Step 2/3: {mismatches} mismatched sectors remain, rewriting them needs admin mode, skipped.\n")
+            details['repair_skipped'] = True
+        elif ok and mismatches:
+            self.log(f"Step 2/3: {mismatches} mismatched sectors remain, rewriting them (repair)...\n")
+            ok, _, _ = self.scrub(array_name, "repair", on_progress)
+            if ok:
+                ok, mismatches, errors = self.scrub(array_name, "check", on_progress)
+            details.update(after_repair_mismatches=mismatches, member_errors=errors)
+        else:
+            self.log("Step 2/3: no persistent mismatches, nothing to rewrite.\n")
+
+        # 3. The members md had to correct read errors on are the suspects
+        suspects = [dev for dev, count in errors.items() if count > 0]
+        reports = {}
+        for dev in suspects:
+            self.log(f"Step 3/3: SMART short self-test on {dev}...\n")
+            reports[dev] = self.smart_retest(dev)
+        if not suspects:
+            self.log("Step 3/3: no member reported read errors, skipping SMART tests.\n")
+        details['smart'] = reports
+
+        advice = []
+        if not ok:
+            advice.append("A scrub pass did not complete, run the remediation again once the array is idle.")
+        for dev, report in reports.items():
+            if report['passed'] is False or report['last_self_test_passed'] is False:
+                advice.append(f"Replace {dev}: SMART reports it as failing.")
+            elif report['pending'] or report['reallocated']:
+                advice.append(f"Plan to replace {dev}: {report['pending'] or 0} pending and {report['reallocated'] or 0} reallocated sectors.")
+            else:
+                advice.append(f"{dev} passed its self-test, watch its error counter (cable or controller issue?).")
+        if details.get('repair_skipped'):
+            advice.append(f"{mismatches} mismatches were left as they are (operator session), run the remediation again in admin mode to rewrite them.")
+        elif ok and mismatches:
+            advice.append(f"{mismatches} mismatches persist after repair, check RAM, cables and the controller.")
+        if not advice:
+            advice.append("The array is consistent again, the errors were corrected by rewriting them.")
+
+        recommendation = " ".join(advice)
+        self.log(f"Recommendation: {recommendation}\n")
+        health.record_event(array_name, "scrub-remediation", recommendation, details)
+        return recommendation
+
+    def teardown_disk(self, disk, identity=None):
+        if not self.require_admin("teardown-stack", disk):
+            return False
+        if identity and not self.verify_identities({disk: identity}):
+            return False
+        self.log(snapshot.snapshot_message("teardown-stack", disk))
+        success, out = backend.teardown_disk(disk)
+        self.log(out)
+        return success
+
+    def teardown_old_array(self, old, identities=None):
+        """
+        Stops an array found by backend.find_old_arrays(), clears the superblocks of its members and forgets it
+        in mdadm.conf. Nothing else on its disks is touched.
+        """
+        if not self.require_admin("teardown-old-array", old['uuid']) or not self.verify_identities(identities):
+            return False
+        self.log(snapshot.snapshot_message("teardown-old-array", old['uuid']))
+        success, out = backend.teardown_old_array(old)
+        self.log(out)
+        if not success:
+            self.log(f"ERROR: Failed to release the members of {old['uuid']} ({', '.join(old['members'])}).\n")
+            return False
+        self.log(files.remove_array_from_mdadm_conf(old['uuid'])[1])
+        return True
+
+    def wait_for_sync(self, array_name, job, timeout=None, on_progress=None):
+        success, out = backend.wait_for_sync(array_name, timeout, on_progress, cancelled=lambda: jobs.is_cancel_requested(job['id']))
+        self.log(out)
+        return success
@@ -0,0 +1,163 @@
+import os
+import re
+import shutil
+import subprocess
+import tempfile
+import backend
+import config
+import events
+import expand
+import files
+import geometry
+import grub
+import health
+import jobs
+import metrics
+import runner
+import snapshot
+import timers
+import units
+
+# 'cli.py --simulate' runs any command (plan, apply, create, ...) against simulated disks instead of the real ones:
+#   mock - drives that exist only in discovery and a runner.MockRunner that records every command instead of
+#          running it, printed as a script at the end. Needs neither root nor spare disks (CI, development).
+#   loop - sparse files attached as loop devices and real commands, so mdadm and mkfs actually run. Needs root,
+#          but never touches a real disk; the arrays and loop devices are torn down again at the end.
+# Both keep the system files (mdadm.conf, fstab, crypttab, systemd units) and the state directory in a temporary
+# directory, so a simulated apply leaves the host as it found it.
+
+MODES = ("mock", "loop")
+
+DRIVES = 4
+DRIVE_SIZE = "10G"
+
+# Module paths moved into the sandbox: (module, attribute, path below the sandbox root)
+SANDBOXED_PATHS = [
+    (config, 'STATE_DIR', "var/lib/liveraid"),
+    (events, 'EVENTS_FILE', "var/lib/liveraid/events.jsonl"),
+    (expand, 'UPGRADES_DIR', "var/lib/liveraid/upgrades"),
+    (files, 'CHANGES_DIR', "var/lib/liveraid/changes"),
+    (geometry, 'GEOMETRY_DIR', "var/lib/liveraid/geometry"),
+    (health, 'HISTORY_FILE', "var/lib/liveraid/health.jsonl"),
+    (jobs, 'JOBS_DIR', "var/lib/liveraid/jobs"),
+    (metrics, 'METRICS_FILE', "var/lib/liveraid/metrics.csv"),
+    (snapshot, 'BACKUP_DIR', "var/lib/liveraid/backups"),
+    (files, 'FSTAB_PATH', "etc/fstab"),
+    (files, 'CRYPTTAB_PATH', "etc/crypttab"),
+    (files, 'MODPROBE_RAID456_CONF', "etc/modprobe.d/liveraid-raid456.conf"),
+    (grub, 'GRUB_DEFAULT_PATH', "etc/default/grub"),
+    (timers, 'SYSTEMD_DIR', "etc/systemd/system"),
+]
+
+def simulated_drive(name, size_bytes, index):
+    # The shape of a backend.get_available_drives() entry, a healthy blank SSD
+    return {'name': name, 'size_gb': round(size_bytes / (1024**3), 2), 'size_bytes': size_bytes, 'serial': f"LIVERAID-SIM{index:04d}",
+            'wwn': None, 'model': "LiveRAID Simulated Disk", 'firmware': "1.0",
+            'link': {'current': None, 'max': None, 'unit': None, 'width': None, 'max_width': None},
+            'transport': "", 'removable': False, 'rotational': False, 'has_data': False, 'has_esp': False, 'controller': None,
+            'smart': {'health': "healthy", 'reallocated': 0, 'pending': 0, 'power_on_hours': 0}}
+
+def sandbox(root):
+    """
+    Moves the system files and the state directory under root, starting from copies of the real system files
+    so the diffs read the same. Returns: {(module, attribute): original value} for restoring them.
+    """
+    saved = {}
+    for module, attribute, relative in SANDBOXED_PATHS:
+        original = getattr(module, attribute)
+        saved[(module, attribute)] = original
+        path = os.path.join(root, relative)
+        if os.path.isfile(original):
+            os.makedirs(os.path.dirname(path), exist_ok=True)
+            shutil.copyfile(original, path)
+        setattr(module, attribute, path)
+    saved[(files, 'MDADM_CONF_CANDIDATES')] = files.MDADM_CONF_CANDIDATES
+    conf = os.path.join(root, "etc/mdadm/mdadm.conf")
+    os.makedirs(os.path.dirname(conf), exist_ok=True)
+    # Created even when the host has none, get_mdadm_conf_path() only picks existing candidates
+    if os.path.isfile(files.get_mdadm_conf_path()):
+        shutil.copyfile(files.get_mdadm_conf_path(), conf)
+    else:
+        open(conf, 'w').close()
+    files.MDADM_CONF_CANDIDATES = [conf]
+    return saved
+
+def attach_loops(root, count, size_bytes):
+    """
+    Returns: (['/dev/loop3', ...], log_str), the devices attached so far when one fails.
+    """
+    loops = []
+    for index in range(count):
+        image = os.path.join(root, f"disk{index}.img")
+        with open(image, 'wb') as f:
+            f.truncate(size_bytes)
+        try:
+            result = subprocess.run(["losetup", "--find", "--show", image], capture_output=True, text=True, check=True)
+        except (OSError, subprocess.CalledProcessError) as e:
+            return loops, f"ERROR: Could not attach {image} as a loop device: {getattr(e, 'stderr', None) or e}\n"
+        loops.append(result.stdout.strip())
+    return loops, ""
+
+def start(mode, count=DRIVES, size_bytes=None):
+    """
+    Sets up a simulation: simulated drives in discovery, sandboxed system files and (mock) the recording runner.
+    Returns: ({'mode': 'mock', 'root': '/tmp/liveraid-sim-x1y2', 'runner': MockRunner or None, 'loops': [], 'saved': {...}}, log_str),
+    None instead of the simulation when it couldn't be set up.
+    """
+    size_bytes = size_bytes or units.parse_size(DRIVE_SIZE)
+    if mode == "loop" and os.geteuid() != 0:
+        return None, "ERROR: --simulate loop attaches loop devices and needs root, --simulate mock doesn't.\n"
+    root = tempfile.mkdtemp(prefix="liveraid-sim-")
+    simulation = {'mode': mode, 'root': root, 'runner': None, 'loops': [], 'saved': sandbox(root)}
+    if mode == "mock":
+        simulation['runner'] = runner.MockRunner()
+        names = [f"/dev/sim{index}" for index in range(count)]
+    else:
+        names, out = attach_loops(root, count, size_bytes)
+        simulation['loops'] = names
+        if out:
+            return None, out + stop(simulation)
+    backend.SIMULATED_DRIVES = [simulated_drive(name, size_bytes, index) for index, name in enumerate(names)]
+    return simulation, (f"SIMULATION ({mode}): {count} simulated drives of {units.format_size(size_bytes)} ({', '.join(names)}), "
+                        f"system files and state under {root}\n")
+
+def loop_arrays(loops):
+    # md arrays with a member on one of the loop devices, read from /proc/mdstat
+    kernel_names = {os.path.basename(loop) for loop in loops}
+    arrays = []
+    try:
+        with open('/proc/mdstat', 'r') as f:
+            for line in f:
+                match = re.match(r"(md\w+) : (.*)", line)
+                if match and kernel_names & {m.split("[")[0] for m in match.group(2).split()}:
+                    arrays.append(f"/dev/{match.group(1)}")
+    except OSError:
+        pass
+    return arrays
+
+def stop(simulation):
+    """
+    Ends a simulation: prints what a mock run would have executed, tears down the arrays built on the loop devices
+    and detaches them, then puts the real paths back and removes the sandbox.
+    Returns: log_str
+    """
+    logs = []
+    if simulation['runner'] is not None:
+        script = simulation['runner'].script() or "(nothing)\n"
+        logs.append(f"SIMULATION: {len(simulation['runner'].calls)} commands recorded, a real run would execute:\n{script}")
+    if simulation['loops']:
+        for array in loop_arrays(simulation['loops']):
+            with open('/proc/mounts', 'r') as f:
+                mounts = [line.split()[1] for line in f if line.startswith(array)]
+            for mount_point in reversed(mounts):
+                subprocess.run(["umount", mount_point], capture_output=True)
+            stopped = subprocess.run(["mdadm", "--stop", array], capture_output=True, text=True)
+            logs.append(f"SIMULATION: stopped {array}\n" if stopped.returncode == 0 else
+                        f"WARNING: Could not stop simulated array {array}: {stopped.stderr.strip()}\n")
+        for loop in simulation['loops']:
+            subprocess.run(["losetup", "-d", loop], capture_output=True)
+    for (module, attribute), original in simulation['saved'].items():
+        setattr(module, attribute, original)
+    backend.SIMULATED_DRIVES = None
+    shutil.rmtree(simulation['root'], ignore_errors=True)
+    return "".join(logs)
@@ -0,0 +1,104 @@
+import json
+import os
+import subprocess
+import time
+import uuid
+import backend
+import config
+import files
+import geometry
+
+# Snapshots live next to the file backups of the same run
+BACKUP_DIR = os.path.join(config.STATE_DIR, "backups")
+
+# name in the snapshot -> command whose output is captured
+COMMANDS = {
+    "lsblk.json": ["lsblk", "-J", "-b", "-p", "-o", "NAME,TYPE,SIZE,FSTYPE,UUID,LABEL,MOUNTPOINTS,SERIAL,MODEL"],
+    "blkid.txt": ["blkid"],
+    "mdadm-detail-scan.txt": ["mdadm", "--detail", "--scan", "--verbose"]
+}
+
+# name in the snapshot -> file copied verbatim
+SOURCES = {
+    "mdstat.txt": "/proc/mdstat",
+    "fstab": "/etc/fstab"
+}
+
+def _snapshot_dir(run_id):
+    return os.path.join(BACKUP_DIR, run_id, "snapshot")
+
+def take_snapshot(operation, target):
+    """
+    Records the storage state of the host before a mutating operation. Returns the run ID,
+    or None in dry-run mode (nothing is going to change).
+    """
+    if backend.DRY_RUN:
+        return None
+
+    run_id = f"{time.strftime('%Y%m%d-%H%M%S')}-{uuid.uuid4().hex[:4]}"
+    path = _snapshot_dir(run_id)
+    os.makedirs(path, exist_ok=True)
+
+    for name, cmd in COMMANDS.items():
+        try:
+            result = subprocess.run(cmd, capture_output=True, text=True)
+            output = result.stdout + result.stderr
+        except Exception as e:
+            output = f"Failed to run {' '.join(cmd)}: {e}\n"
+        with open(os.path.join(path, name), 'w') as f:
+            f.write(output)
+
+    sources = dict(SOURCES, **{"mdadm.conf": files.get_mdadm_conf_path()})
+    for name, source in sources.items():
+        with open(os.path.join(path, name), 'w') as f:
+            f.write(files.read_file(source))
+
+    # Refresh the creation parameters of every array while its superblocks are still intact
+    for arr in backend.get_active_arrays():
+        geometry.save_geometry(arr['name'])
+
+    with open(os.path.join(path, "meta.json"), 'w') as f:
+        json.dump({'run_id': run_id, 'operation': operation, 'target': target, 'created': time.time()}, f, indent=2)
+    files.current_run_id = run_id
+    return run_id
+
+def snapshot_message(operation, target):
+    # Frontends log this line before mutating anything; a failed snapshot never blocks the operation
+    try:
+        run_id = take_snapshot(operation, target)
+    except Exception as e:
+        return f"WARNING: Could not snapshot storage state: {e}\n"
+    return f"Storage state snapshot saved as run {run_id}.\n" if run_id else ""
+
+def list_snapshots():
+    """
+    Returns the metadata of every stored snapshot, newest first.
+    """
+    snapshots = []
+    try:
+        run_ids = os.listdir(BACKUP_DIR)
+    except FileNotFoundError:
+        return []
+    for run_id in run_ids:
+        try:
+            with open(os.path.join(_snapshot_dir(run_id), "meta.json"), 'r') as f:
+                snapshots.append(json.load(f))
+        except Exception:
+            continue
+    return sorted(snapshots, key=lambda s: s['created'], reverse=True)
+
+def read_snapshot(run_id):
+    """
+    Returns {'meta': {...}, 'files': {'lsblk.json': '...', ...}} or None if the run is unknown.
+    """
+    path = _snapshot_dir(run_id)
+    try:
+        with open(os.path.join(path, "meta.json"), 'r') as f:
+            meta = json.load(f)
+    except Exception:
+        return None
+    contents = {}
+    for name in sorted(os.listdir(path)):
+        if name != "meta.json":
+            contents[name] = files.read_file(os.path.join(path, name))
+    return {'meta': meta, 'files': contents}
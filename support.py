@@ -0,0 +1,115 @@
+import io
+import json
+import os
+import platform
+import re
+import subprocess
+import tarfile
+import time
+import backend
+import config
+import doctor
+import files
+import geometry
+import health
+import jobs
+import snapshot
+
+# Extra command output in a bundle on top of what a snapshot captures; journal excerpts are bounded
+# so a bundle stays small enough to attach to an issue
+COMMANDS = dict(snapshot.COMMANDS, **{
+    "mdadm-version.txt": ["mdadm", "--version"],
+    "journal-kernel.txt": ["journalctl", "-k", "-b", "--no-pager", "-n", "500"],
+    "journal-mdmonitor.txt": ["journalctl", "-u", "mdmonitor", "-u", "mdadm", "--no-pager", "-n", "200"],
+    "smart-scan.txt": ["smartctl", "--scan"],
+})
+
+# Config keys and command line flags whose values are replaced before anything is written to a bundle
+SECRET_WORDS = r"token|secret|password|passwd|key|auth|webhook"
+
+def redact(text):
+    """
+    Masks credentials in config or log text: secret-looking keys and flags, whole URL settings, URL passwords and query strings.
+    """
+    text = re.sub(rf"^(\s*[\w.-]*(?:{SECRET_WORDS})[\w.-]*\s*=\s*).+$", r'\1"<redacted>"', text, flags=re.IGNORECASE | re.MULTILINE)
+    text = re.sub(rf"(--?[\w-]*(?:{SECRET_WORDS})[\w-]*[ =])[^\s\"',\]]+", r"\1<redacted>", text, flags=re.IGNORECASE)
+    # Webhook URLs (url of a [[notification.sinks]] entry, notify_url, --notify-url) carry their secret in the path
+    text = re.sub(r"^(\s*(?:[\w.-]*_)?url\s*=\s*)(?!\"\")\S.*$", r'\1"<redacted>"', text, flags=re.IGNORECASE | re.MULTILINE)
+    text = re.sub(r"(--[\w-]*url[ =])[^\s\"',\]]+", r"\1<redacted>", text, flags=re.IGNORECASE)
+    text = re.sub(r"(\w+://[^/\s:@\"']+:)[^@\s\"']+@", r"\1<redacted>@", text)
+    text = re.sub(r"(\w+://[^\s\"'?]+)\?[^\s\"']+", r"\1?<redacted>", text)
+    return text
+
+def get_version():
+    # Checkouts report the commit, installed copies have no git metadata
+    try:
+        result = subprocess.run(['git', '-C', os.path.dirname(os.path.abspath(__file__)), 'describe', '--always', '--dirty'],
+                                capture_output=True, text=True, check=True, timeout=5)
+        return result.stdout.strip()
+    except Exception:
+        return "unknown"
+
+def collect():
+    """
+    Gathers everything a bug report needs into {name in the bundle: contents}.
+    """
+    contents = {}
+    for name, cmd in COMMANDS.items():
+        try:
+            result = subprocess.run(cmd, capture_output=True, text=True, timeout=60)
+            contents[name] = result.stdout + result.stderr
+        except Exception as e:
+            contents[name] = f"Failed to run {' '.join(cmd)}: {e}\n"
+
+    sources = dict(snapshot.SOURCES, **{"mdadm.conf": files.get_mdadm_conf_path()})
+    for name, source in sources.items():
+        contents[name] = files.read_file(source)
+
+    # Per array detail, the drive inventory and the config as LiveRAID sees them
+    for arr in backend.get_active_arrays():
+        try:
+            result = subprocess.run(['mdadm', '--detail', arr['name']], capture_output=True, text=True, timeout=30)
+            contents[f"mdadm-detail-{os.path.basename(arr['name'])}.txt"] = result.stdout + result.stderr
+        except Exception as e:
+            contents[f"mdadm-detail-{os.path.basename(arr['name'])}.txt"] = f"Failed: {e}\n"
+    contents["inventory.json"] = json.dumps(backend.get_available_drives(), indent=2)
+    contents["doctor.json"] = json.dumps(doctor.run_checks(), indent=2, default=str)
+    contents["config.toml"] = redact(files.read_file(config.CONFIG_PATH)) or "# no config.toml, defaults in use\n"
+
+    # LiveRAID's own history: jobs, health events and which managed files were changed
+    contents["jobs.json"] = json.dumps(jobs.list_jobs(), indent=2)
+    contents["geometry.json"] = json.dumps(geometry.list_geometries(), indent=2)
+    contents["health.json"] = json.dumps(health.list_events(), indent=2)
+    contents["changes.json"] = json.dumps([{k: c.get(k) for k in ('id', 'path', 'timestamp', 'run_id', 'undoes', 'undone_by')}
+                                           for c in files.list_changes()], indent=2)
+
+    contents["version.json"] = json.dumps({
+        'liveraid': get_version(),
+        'python': platform.python_version(),
+        'kernel': platform.release(),
+        'distro': doctor.get_distro()['name'],
+        'created': time.strftime("%Y-%m-%d %H:%M:%S %z"),
+        'dry_run': backend.DRY_RUN,
+    }, indent=2)
+    # Hook commands and journal lines may carry credentials as well
+    return {name: redact(text) for name, text in contents.items()}
+
+def create_bundle(path=None):
+    """
+    Writes a support bundle (.tar.gz) for attaching to bug reports.
+    Returns: (success, log_str, path)
+    """
+    path = path or f"liveraid-support-{time.strftime('%Y%m%d-%H%M%S')}.tar.gz"
+    folder = os.path.basename(path).split(".tar")[0]
+    try:
+        contents = collect()
+        with tarfile.open(path, "w:gz") as tar:
+            for name, text in sorted(contents.items()):
+                data = text.encode()
+                info = tarfile.TarInfo(f"{folder}/{name}")
+                info.size = len(data)
+                info.mtime = int(time.time())
+                tar.addfile(info, io.BytesIO(data))
+    except Exception as e:
+        return False, f"Failed to write the support bundle {path}\nError: {e}\n", None
+    return True, f"Support bundle with {len(contents)} files written to {path}. Secrets in config and logs were redacted, review it before sharing.\n", path
@@ -0,0 +1,86 @@
+import os
+import sys
+import unittest
+
+sys.path.insert(0, os.path.dirname(os.path.dirname(os.path.abspath(__file__))))
+
+import files
+import plan
+import simulate
+from service import RaidService
+
+# Plan and apply against the simulated drives of `cli.py --simulate mock`: nothing runs, the MockRunner records
+# every command, and the tests check that list. Run with: python3 -m unittest discover tests
+
+class ApplyTest(unittest.TestCase):
+    def setUp(self):
+        self.simulation, _ = simulate.start("mock")
+        self.runner = self.simulation['runner']
+        self.log = []
+        self.service = RaidService(log=self.log.append, admin=True, runner=self.runner)
+
+    def tearDown(self):
+        simulate.stop(self.simulation)
+
+    def apply(self, level, devices, **fields):
+        proposal = plan.new_plan(level, devices, **fields)
+        drives = self.service.inventory(smart=False)
+        report = plan.validate_plan(proposal, drives)
+        self.assertEqual(report['errors'], [])
+        success, _ = self.service.apply_plan(proposal, plan.identities(proposal, drives))
+        return proposal, success
+
+    def commands(self, program):
+        return [call for call in self.runner.calls if call[0] == program]
+
+    def test_mdadm_mirror(self):
+        mount_point = os.path.join(self.simulation['root'], "srv", "data")
+        proposal, success = self.apply("1", ["/dev/sim0", "/dev/sim1"], mount_point=mount_point)
+        self.assertTrue(success, "".join(self.log))
+        create = self.commands("mdadm")[0]
+        self.assertEqual(create[:3], ["mdadm", "--create", "--verbose"])
+        self.assertIn("--level=1", create)
+        self.assertIn("--raid-devices=2", create)
+        self.assertEqual(create[-2:], ["/dev/sim0", "/dev/sim1"])
+        self.assertIn(["parted", "-s", "/dev/md0", "mklabel", "gpt"], self.runner.calls)
+        self.assertIn(["mkfs.ext4", "/dev/md0p1"], self.runner.calls)
+        self.assertIn(["mount", "-o", plan.mount_options(proposal), "/dev/md0p1", mount_point], self.runner.calls)
+        self.assertIn(mount_point, files.read_file(files.FSTAB_PATH))
+
+    def test_swapped_disk_is_refused(self):
+        proposal = plan.new_plan("1", ["/dev/sim0", "/dev/sim1"])
+        identities = plan.identities(proposal, self.service.inventory(smart=False))
+        identities["/dev/sim1"] = dict(identities["/dev/sim1"], serial="ANOTHER-DISK")
+        success, _ = self.service.apply_plan(proposal, identities)
+        self.assertFalse(success)
+        self.assertEqual(self.runner.calls, [])
+
+    def test_failed_format_rolls_back(self):
+        self.runner.fail(["mkfs.ext4"])
+        _, success = self.apply("5", ["/dev/sim0", "/dev/sim1", "/dev/sim2"])
+        self.assertFalse(success)
+        self.assertIn(["mdadm", "--stop", "/dev/md0"], self.runner.calls)
+        for device in ("/dev/sim0", "/dev/sim1", "/dev/sim2"):
+            self.assertIn(["mdadm", "--zero-superblock", device], self.runner.calls)
+        self.assertEqual(self.runner.arrays, {})
+
+    def test_lvm_stripe_size(self):
+        _, success = self.apply("5", ["/dev/sim0", "/dev/sim1", "/dev/sim2"], array_name="/dev/liveraid0/data", backend="lvm")
+        self.assertTrue(success, "".join(self.log))
+        lvcreate = self.commands("lvcreate")
+        self.assertEqual(len(lvcreate), 1)
+        self.assertEqual(self.runner.calls[0][:2], ["wipefs", "-a"])
+        self.assertIn("-I", lvcreate[0])
+        self.assertEqual(lvcreate[0][lvcreate[0].index("-I") + 1], "512k")
+
+    def test_btrfs_profiles(self):
+        _, success = self.apply("1", ["/dev/sim0", "/dev/sim1"], backend="btrfs", filesystem="btrfs")
+        self.assertTrue(success, "".join(self.log))
+        mkfs = self.commands("mkfs.btrfs")
+        self.assertEqual(len(mkfs), 1)
+        self.assertEqual(mkfs[0][-2:], ["/dev/sim0", "/dev/sim1"])
+        self.assertIn("raid1", mkfs[0])
+        self.assertEqual(self.commands("mdadm"), [])
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,106 @@
+import os
+import re
+import shlex
+import sys
+import backend
+import files
+import script
+
+# Recurring jobs (SMART self-tests) are installed as systemd timers that call back into cli.py.
+# The unit files go through files.write_file(), so they show up in `cli.py changes`, can be undone
+# and are shown as diffs in dry runs.
+
+SYSTEMD_DIR = "/etc/systemd/system"
+# Every unit LiveRAID installs starts with this, so they can be listed and removed again
+UNIT_PREFIX = "liveraid-"
+CLI_PATH = os.path.abspath(os.path.join(os.path.dirname(__file__), "cli.py"))
+
+# Named schedules offered by the frontends, as systemd OnCalendar expressions
+SCHEDULES = {'daily': "daily", 'weekly': "weekly", 'monthly': "monthly"}
+
+def unit_name(kind, target):
+    """
+    Returns: 'liveraid-smart-short-md0' for ('smart-short', '/dev/md0')
+    """
+    name = re.sub(r"[^\w.-]+", "-", target.removeprefix("/dev/")).strip("-")
+    return f"{UNIT_PREFIX}{kind}-{name}"
+
+def render_service(description, cli_args):
+    return (f"[Unit]\nDescription={description}\n\n"
+            f"[Service]\nType=oneshot\nExecStart={shlex.join([sys.executable, CLI_PATH] + cli_args)}\n")
+
+def render_timer(description, schedule):
+    # Persistent: a run missed while the machine was off happens at the next boot.
+    # The random delay keeps the timers of several arrays from starting on the same second.
+    return (f"[Unit]\nDescription={description}\n\n"
+            f"[Timer]\nOnCalendar={SCHEDULES.get(schedule, schedule)}\nPersistent=true\nRandomizedDelaySec=1h\n\n"
+            f"[Install]\nWantedBy=timers.target\n")
+
+def install_timer(name, description, cli_args, schedule, dry_run=None):
+    """
+    Writes <name>.service running `cli.py <cli_args>` and <name>.timer starting it on the schedule, then enables the timer.
+    Returns: (success, log_str)
+    """
+    logs = []
+    for path, text in ((f"{SYSTEMD_DIR}/{name}.service", render_service(description, cli_args)),
+                       (f"{SYSTEMD_DIR}/{name}.timer", render_timer(description, schedule))):
+        success, out = files.write_file(path, text, dry_run=dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+    for cmd in (["systemctl", "daemon-reload"], ["systemctl", "enable", "--now", f"{name}.timer"]):
+        success, out = backend.run_command(cmd, dry_run=dry_run)
+        logs.append(out)
+        if not success:
+            return False, "".join(logs)
+    return True, "".join(logs)
+
+def remove_timer(name, dry_run=None):
+    """
+    Disables a timer installed by install_timer() and deletes its unit files.
+    Returns: (success, log_str)
+    """
+    if dry_run is None:
+        dry_run = backend.DRY_RUN
+    logs = []
+    timer_path = f"{SYSTEMD_DIR}/{name}.timer"
+    if not os.path.exists(timer_path):
+        return True, f"{name}.timer is not installed.\n"
+    success, out = backend.run_command(["systemctl", "disable", "--now", f"{name}.timer"], dry_run=dry_run)
+    logs.append(out)
+    if not success:
+        return False, "".join(logs)
+    for path in (timer_path, f"{SYSTEMD_DIR}/{name}.service"):
+        if dry_run:
+            script.record_command(["rm", "-f", path])
+            logs.append(f"[DRY RUN] Would remove {path}\n")
+            continue
+        try:
+            os.remove(path)
+            logs.append(f"Removed {path}\n")
+        except FileNotFoundError:
+            pass
+        except Exception as e:
+            logs.append(f"Failed to remove {path}\nError: {e}\n")
+            return False, "".join(logs)
+    success, out = backend.run_command(["systemctl", "daemon-reload"], dry_run=dry_run)
+    logs.append(out)
+    return success, "".join(logs)
+
+def list_timers(kind=None):
+    """
+    Returns the timers installed by LiveRAID, optionally only those whose name starts with liveraid-<kind>.
+    Returns: [{'name': 'liveraid-smart-short-md0', 'schedule': 'weekly'}]
+    """
+    timers = []
+    prefix = f"{UNIT_PREFIX}{kind}" if kind else UNIT_PREFIX
+    try:
+        entries = sorted(os.listdir(SYSTEMD_DIR))
+    except OSError:
+        return timers
+    for entry in entries:
+        if not (entry.startswith(prefix) and entry.endswith(".timer")):
+            continue
+        match = re.search(r"^OnCalendar=(.+)$", files.read_file(f"{SYSTEMD_DIR}/{entry}"), re.MULTILINE)
+        timers.append({'name': entry.removesuffix(".timer"), 'schedule': match.group(1).strip() if match else None})
+    return timers
@@ -0,0 +1,86 @@
+import threading
+from gi.repository import Gtk, GLib
+import backend
+import hooks
+import notifications
+
+# `main.py --tray`: a status icon for desktops that keep LiveRAID around (e.g. on a NAS). It shows the
+# worst health of all arrays, raises a desktop notification when an array changes state and opens the
+# full window on click. Closing that window only hides it again.
+
+# Icon of the worst array health, most severe first
+HEALTH_ICONS = [("degraded", "dialog-error"), ("inactive", "dialog-error"), ("rebuilding", "dialog-warning"),
+                ("resyncing", "view-refresh"), ("healthy", "drive-harddisk")]
+
+POLL_SECONDS = 10
+
+def overall_health(statuses):
+    """
+    Returns the most severe health of any array (see backend.array_health()), 'none' without arrays.
+    """
+    healths = {arr['health'] for arr in statuses}
+    return next((health for health, _ in HEALTH_ICONS if health in healths), "none")
+
+class TrayApplet:
+    def __init__(self, window_factory):
+        self.window_factory = window_factory
+        self.window = None
+        self.event_detector = hooks.EventDetector()
+        self.polling = False
+
+        self.icon = Gtk.StatusIcon()
+        self.icon.set_title("LiveRAID")
+        self.icon.set_from_icon_name("drive-harddisk")
+        self.icon.set_tooltip_text("LiveRAID: checking arrays...")
+        self.icon.connect("activate", self.on_activate)
+        self.icon.connect("popup-menu", self.on_popup_menu)
+
+        self.menu = Gtk.Menu()
+        item_open = Gtk.MenuItem(label="Open LiveRAID")
+        item_open.connect("activate", self.on_activate)
+        self.menu.append(item_open)
+        item_quit = Gtk.MenuItem(label="Quit")
+        item_quit.connect("activate", lambda *_: Gtk.main_quit())
+        self.menu.append(item_quit)
+        self.menu.show_all()
+
+        self.poll()
+        GLib.timeout_add_seconds(POLL_SECONDS, self.poll)
+
+    def poll(self):
+        # mdadm --detail runs off the UI thread, a slow poll is never stacked
+        if not self.polling:
+            self.polling = True
+            thread = threading.Thread(target=self.poll_worker)
+            thread.daemon = True
+            thread.start()
+        return True
+
+    def poll_worker(self):
+        try:
+            statuses = backend.get_arrays_status()
+            events = self.event_detector.poll([arr['name'] for arr in statuses])
+        finally:
+            self.polling = False
+        GLib.idle_add(self.render, statuses, events)
+
+    def render(self, statuses, events):
+        health = overall_health(statuses)
+        self.icon.set_from_icon_name(dict(HEALTH_ICONS).get(health, "drive-harddisk"))
+        lines = [f"{arr['name']}: {arr['health']}" + (f" ({arr['sync']['action']} {arr['sync']['percent']:.0f}%)" if arr['sync'] else "")
+                 for arr in statuses]
+        self.icon.set_tooltip_text("LiveRAID\n" + ("\n".join(lines) if lines else "No md arrays running."))
+        for event in events:
+            notifications.desktop_notify(f"LiveRAID: {event['array']}", hooks.describe(event))
+
+    def on_activate(self, *_):
+        if self.window is None:
+            self.window = self.window_factory()
+            # Closing the window goes back to the tray instead of quitting
+            self.window.connect("delete-event", lambda window, _: window.hide_on_delete())
+            self.window.show_all()
+        else:
+            self.window.present()
+
+    def on_popup_menu(self, icon, button, time):
+        self.menu.popup(None, None, Gtk.StatusIcon.position_menu, icon, button, time)
@@ -0,0 +1,312 @@
+import argparse
+import curses
+import logging
+import threading
+import backend
+import config
+import plan
+import simulate
+import units
+from service import RaidService
+
+# Terminal frontend for live rescue systems without an X server (the console, or over SSH): the GUI's create
+# workflow on the same RaidService. Pick the drives, a level and a filesystem, preview the validated plan,
+# then apply it while every step's progress and the log are shown.
+#   python3 tui.py [--dry-run] [--admin] [--simulate mock|loop]
+
+LEVELS = ["0", "1", "5", "6", "10"]
+
+# Lines of log kept for the log pane
+LOG_LINES = 500
+
+# Key help of each screen, shown on the bottom line
+KEYS = {
+    'drives': "↑↓ move  Space select  s spare  l level  f filesystem  m mount point  p preview  r rescan  q quit",
+    'plan': "a apply  b back  q quit",
+    'apply': "applying, the log follows below",
+    'done': "b back to the drives  q quit",
+}
+
+# Step states (executor.Executor) and how they're marked
+STEP_MARKS = {'pending': " ", 'running': ">", 'done': "✓", 'failed': "✗", 'cancelled': "⊘", 'rolled-back': "↺", 'left': "!"}
+
+class LogPaneHandler(logging.Handler):
+    # Diagnostics would scribble over the screen on stderr, they go to the log pane instead
+    def __init__(self, log):
+        super().__init__()
+        self.log = log
+
+    def emit(self, record):
+        self.log(f"{record.levelname}: {record.getMessage()}\n")
+
+class TuiApp:
+    def __init__(self, screen, admin=None, runner=None):
+        self.screen = screen
+        self.lock = threading.Lock()
+        self.log_lines = []
+        logging.basicConfig(level=config.get_setting('log_level').upper(), handlers=[LogPaneHandler(self.log)], force=True)
+        self.service = RaidService(log=self.log, admin=admin, runner=runner)
+        self.mode = 'drives'
+        self.cursor = 0
+        self.selected = []
+        self.spares = set()
+        self.level = "1"
+        self.filesystem = "ext4"
+        self.mount_point = None
+        self.proposal = None
+        self.report = None
+        self.progress = None
+        self.result = None
+        self.drives = {}
+        self.rescan()
+
+    def log(self, text):
+        # Called from the apply thread as well as the main loop
+        with self.lock:
+            self.log_lines.extend(line for line in text.rstrip("\n").split("\n") if line.strip())
+            del self.log_lines[:-LOG_LINES]
+
+    def rescan(self):
+        self.drives = self.service.inventory()
+        self.selected = [name for name in self.selected if name in self.drives]
+        self.spares &= set(self.selected)
+        self.cursor = min(self.cursor, max(len(self.drives) - 1, 0))
+
+    def members(self):
+        return [name for name in self.selected if name not in self.spares]
+
+    # --- drawing ---
+
+    def put(self, y, x, text, attr=0):
+        height, width = self.screen.getmaxyx()
+        if 0 <= y < height and x < width:
+            self.screen.addnstr(y, x, text, width - x - (1 if y == height - 1 else 0), attr)
+
+    def draw(self):
+        self.screen.erase()
+        height, width = self.screen.getmaxyx()
+        title = "LiveRAID" + (" [DRY RUN]" if self.service.dry_run else "") + (" [SIMULATION]" if backend.SIMULATED_DRIVES is not None else "")
+        self.put(0, 0, title.ljust(width), curses.A_REVERSE)
+        top = getattr(self, f"draw_{self.mode if self.mode != 'done' else 'apply'}")(2)
+        # The log takes whatever is left, newest lines at the bottom
+        if top < height - 3:
+            self.put(top, 0, "─ Log " + "─" * max(width - 6, 0))
+            with self.lock:
+                visible = self.log_lines[-(height - top - 2):]
+            for i, line in enumerate(visible):
+                self.put(top + 1 + i, 0, line)
+        self.put(height - 1, 0, KEYS[self.mode].ljust(width - 1), curses.A_REVERSE)
+        self.screen.refresh()
+
+    def draw_drives(self, y):
+        self.put(y, 0, f"RAID level: {self.level}   Filesystem: {self.filesystem}   Mount point: {self.mount_point or '(not mounted)'}")
+        y += 2
+        if not self.drives:
+            self.put(y, 2, "No available unmounted physical drives detected, r rescans.")
+            return y + 2
+        for i, (name, drive) in enumerate(self.drives.items()):
+            mark = "S" if name in self.spares else ("x" if name in self.selected else " ")
+            health = drive['smart']['health'] or "unknown"
+            line = f"[{mark}] {name:<16} {drive['model'] or '':<28.28} {units.format_size(drive['size_bytes']):>10}  SMART {health}"
+            if drive['has_data']:
+                line += "  holds data"
+            self.put(y + i, 2, line, curses.A_REVERSE if i == self.cursor else 0)
+        y += len(self.drives) + 1
+        members = [self.drives[name] for name in self.members()]
+        if members:
+            summary = backend.capacity_summary(self.level, members, [self.drives[name] for name in self.spares])
+            self.put(y, 2, f"{len(members)} members" + (f" + {len(self.spares)} spare" if self.spares else "") +
+                     f": {backend.describe_capacity(summary)}")
+        return y + 2
+
+    def draw_plan(self, y):
+        proposal, report = self.proposal, self.report
+        for error in report['errors']:
+            self.put(y, 2, f"ERROR: {error['message']}", curses.A_BOLD)
+            y += 1
+        for warning in report['warnings']:
+            self.put(y, 2, f"WARNING [{warning['code']}]: {warning['message']}")
+            y += 1
+        if report['errors']:
+            return y + 1
+        self.put(y, 2, f"Plan for {proposal['array']}: RAID {proposal['level']} on {', '.join(proposal['devices'])}"
+                 + (f" + spare {', '.join(proposal['spares'])}" if proposal['spares'] else ""))
+        self.put(y + 1, 2, f"  Capacity: {backend.describe_capacity(report['summary'])}")
+        self.put(y + 2, 2, f"  Filesystem: {proposal['filesystem']}, mount point: {proposal['mount_point'] or '(not mounted)'}")
+        self.put(y + 3, 2, "Steps:")
+        y += 4
+        for i, step in enumerate(plan.plan_steps(proposal), 1):
+            self.put(y, 4, f"{i}. {step}")
+            y += 1
+        return y + 1
+
+    def draw_apply(self, y):
+        progress = self.progress or {'percent': 0.0, 'steps': []}
+        width = self.screen.getmaxyx()[1] - 12
+        filled = int(width * progress['percent'] / 100)
+        self.put(y, 2, f"[{'#' * filled}{'.' * (width - filled)}] {progress['percent']:5.1f}%")
+        y += 2
+        for step in progress['steps']:
+            within = f" ({step['step_percent']:.1f}%)" if step.get('step_percent') is not None and step['state'] == "running" else ""
+            self.put(y, 2, f"{STEP_MARKS.get(step['state'], '?')} {step['name']}{within}")
+            y += 1
+        if self.result:
+            self.put(y + 1, 2, self.result, curses.A_BOLD)
+            y += 2
+        return y + 1
+
+    # --- input ---
+
+    def prompt(self, question):
+        """
+        Asks for a line of text on the bottom line. Returns it stripped, '' when left empty.
+        """
+        height, width = self.screen.getmaxyx()
+        self.put(height - 1, 0, question.ljust(width - 1), curses.A_REVERSE)
+        curses.echo()
+        curses.curs_set(1)
+        self.screen.timeout(-1)
+        try:
+            answer = self.screen.getstr(height - 1, min(len(question), width - 2), 200).decode(errors="replace")
+        finally:
+            curses.noecho()
+            curses.curs_set(0)
+            self.screen.timeout(200)
+        return answer.strip()
+
+    def confirm(self, question, destructive):
+        # confirm_policy decides whether to ask at all, like the CLI and the GUI
+        if not config.should_confirm(destructive):
+            return True
+        return self.prompt(f"{question} [y/N] ").lower() in ("y", "yes")
+
+    def preview(self):
+        self.proposal = plan.new_plan(self.level, self.members(), filesystem=self.filesystem, mount_point=self.mount_point,
+                                      spares=sorted(self.spares))
+        self.report = plan.validate_plan(self.proposal, self.drives)
+        self.mode = 'plan'
+
+    def on_progress(self, event):
+        # Executor events of the apply thread, folded into one record per step
+        with self.lock:
+            steps = self.progress['steps']
+            if not steps or len(steps) != event['steps']:
+                steps[:] = [{'name': "", 'state': "pending"} for _ in range(event['steps'])]
+            steps[event['step'] - 1] = {'name': event['name'], 'state': event['state'], 'step_percent': event.get('step_percent')}
+            self.progress['percent'] = event['percent']
+
+    def apply(self):
+        proposal = self.proposal
+        members = proposal['devices'] + proposal['spares']
+        has_data = [dev for dev in members if self.drives[dev]['has_data']]
+        question = f"Apply this plan to {', '.join(members)}?"
+        if self.report['warnings']:
+            question = f"Apply this plan to {', '.join(members)} anyway, despite {len(self.report['warnings'])} warning(s)?"
+        if has_data:
+            question = f"{', '.join(has_data)} still hold data that will be destroyed. " + question
+        if not self.confirm(question, destructive=bool(has_data)):
+            self.log("Aborted.\n")
+            return
+        stale = self.service.stale_md_devices() if proposal['backend'] == "mdadm" else []
+        if stale and self.confirm(f"{len(stale)} leftovers of earlier md runs found ({', '.join(e['path'] for e in stale)}), "
+                                  "stop/remove them first?", destructive=True):
+            self.service.cleanup_stale_md(stale)
+        identities = {dev: backend.drive_identity(self.drives[dev]) for dev in members}
+        self.progress = {'percent': 0.0, 'steps': [{'name': step, 'state': "pending"} for step in plan.plan_steps(proposal)]}
+        self.result = None
+        self.mode = 'apply'
+
+        def run():
+            # Whatever happens in the thread, the screen leaves the apply mode and the log says why
+            self.result = "ERROR: Applying the plan failed, see the log for the rollback."
+            try:
+                success, job = self.service.apply_plan(proposal, identities, on_progress=self.on_progress)
+                if success:
+                    self.result = f"SUCCESS: {proposal['array']} created" + (f" and mounted on {proposal['mount_point']}." if proposal['mount_point'] else ".")
+                    if job:
+                        self.result += f" Initial sync continues as job {job['id']}."
+            except Exception as e:
+                self.log(f"ERROR: Applying the plan stopped with an unexpected error: {e}\n")
+            finally:
+                self.mode = 'done'
+
+        threading.Thread(target=run, daemon=True).start()
+
+    def handle(self, key):
+        """
+        Returns False to quit.
+        """
+        if self.mode == 'apply':
+            return True
+        if key in (ord("q"), ord("Q")):
+            return False
+        if self.mode in ('plan', 'done') and key == ord("b"):
+            if self.mode == 'done':
+                self.selected, self.spares = [], set()
+                self.rescan()
+            self.mode = 'drives'
+        elif self.mode == 'plan' and key == ord("a") and not self.report['errors']:
+            self.apply()
+        elif self.mode == 'drives':
+            names = list(self.drives)
+            if key in (curses.KEY_UP, ord("k")):
+                self.cursor = max(self.cursor - 1, 0)
+            elif key in (curses.KEY_DOWN, ord("j")):
+                self.cursor = min(self.cursor + 1, max(len(names) - 1, 0))
+            elif key == ord(" ") and names:
+                name = names[self.cursor]
+                if name in self.selected:
+                    self.selected.remove(name)
+                    self.spares.discard(name)
+                else:
+                    self.selected.append(name)
+            elif key == ord("s") and names and names[self.cursor] in self.selected:
+                self.spares ^= {names[self.cursor]}
+            elif key == ord("l"):
+                self.level = LEVELS[(LEVELS.index(self.level) + 1) % len(LEVELS)]
+            elif key == ord("f"):
+                filesystems = config.SUPPORTED_FILESYSTEMS
+                self.filesystem = filesystems[(filesystems.index(self.filesystem) + 1) % len(filesystems)]
+            elif key == ord("m"):
+                self.mount_point = self.prompt("Mount point (empty: not mounted): ") or None
+            elif key == ord("r"):
+                self.rescan()
+            elif key == ord("p"):
+                if self.members():
+                    self.preview()
+                else:
+                    self.log("Select the member drives first (Space).\n")
+        return True
+
+    def run(self):
+        curses.curs_set(0)
+        # Redraw a few times a second so the apply progress moves without a key press
+        self.screen.timeout(200)
+        while True:
+            self.draw()
+            key = self.screen.getch()
+            if key != -1 and not self.handle(key):
+                return
+
+def main():
+    parser = argparse.ArgumentParser(description="LiveRAID terminal UI")
+    parser.add_argument("--dry-run", action="store_true", help="Log commands and file diffs without touching any disk or file")
+    parser.add_argument("--admin", action="store_true", help="Unlock admin mode in an operator session (safety_mode in config.toml)")
+    parser.add_argument("--simulate", choices=simulate.MODES, help="Run against simulated drives, see cli.py --simulate")
+    args = parser.parse_args()
+    backend.DRY_RUN = args.dry_run
+    simulation = None
+    if args.simulate:
+        simulation, out = simulate.start(args.simulate)
+        if simulation is None:
+            print(out, end="")
+            return 1
+    try:
+        curses.wrapper(lambda screen: TuiApp(screen, True if args.admin else None, simulation['runner'] if simulation else None).run())
+    finally:
+        if simulation:
+            print(simulate.stop(simulation), end="")
+    return 0
+
+if __name__ == "__main__":
+    raise SystemExit(main())
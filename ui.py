@@ -1,19 +1,135 @@
 import gi
 gi.require_version('Gtk', '3.0')
-from gi.repository import Gtk, GLib
+from gi.repository import Gtk, GLib, Gdk
+import os
 import threading
+import time
+import arraynames
+import background
 import backend
+import bootloader
+import config
+import devicefilter
+import doctor
+import hooks
+import luks
+import migrate
+import notifications
+import partition
+import plan
+import policy
+import remedies
+import script
+from service import RaidService
+import units
+
+# Drive list badges per backend.smart_health() verdict: color and tooltip
+HEALTH_BADGES = {
+    'healthy': ("#27ae60", "SMART: healthy"),
+    'warning': ("#f39c12", "SMART: sector errors, watch this drive"),
+    'failing': ("#c0392b", "SMART: failing, don't build on this drive"),
+    None: ("#95a5a6", "SMART: unavailable"),
+}
+
+# Quick-filter chips above the drive list, combined with each other and with the text filter: label and tooltip
+DRIVE_CHIPS = {
+    'HDD': "Only rotational drives",
+    'SSD/NVMe': "Only solid state drives (SATA/SAS SSDs and NVMe)",
+    '≥4TB': "Only drives of at least 4 TB",
+    'Blank disks only': "Only drives without a partition table or filesystem",
+    'Same model': "Only drives of the same model as the selected ones (with none selected, the most common model)",
+}
+
+# Smallest drive the ≥4TB chip lets through, decimal like the drive labels
+LARGE_DRIVE_BYTES = 4 * 1000**4
 
 class LiveRaidWindow(Gtk.Window):
-    def __init__(self):
+    def __init__(self, desktop_notifications=True):
         super().__init__(title="LiveRAID Configurator")
         self.set_border_width(15)
         self.set_default_size(640, 600)  # Increased default window size
 
+        # Operations run in worker threads, their output is marshalled back into the log view
+        self.service = RaidService(log=lambda msg: GLib.idle_add(self.append_log, msg))
+
         # Main Vertical Box
         vbox = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=15)
         self.add(vbox)
 
+        # --- ENVIRONMENT REPORT ---
+        self.env_checks = doctor.run_checks()
+        blocking = doctor.has_blocking_errors(self.env_checks)
+        self.blocking = blocking
+        self.capabilities = doctor.get_capabilities()
+        missing_tools = [tool for tool, cap in self.capabilities.items() if not cap['available']]
+        if blocking:
+            env_title = "Environment: problems detected"
+        elif missing_tools:
+            env_title = f"Environment: ready, {len(missing_tools)} optional tool(s) missing"
+        else:
+            env_title = "Environment: ready"
+        expander_env = Gtk.Expander(label=env_title)
+        expander_env.set_expanded(blocking)
+        vbox.pack_start(expander_env, False, False, 0)
+
+        icons = {'ok': '✔', 'warn': '⚠', 'error': '✖'}
+        colors = {'ok': '#27ae60', 'warn': '#e67e22', 'error': '#c0392b'}
+        rows = []
+        for check in self.env_checks:
+            icon = f"<span foreground='{colors[check['status']]}'>{icons[check['status']]}</span>"
+            rows.append(f"{icon} <b>{GLib.markup_escape_text(check['name'])}</b>: {GLib.markup_escape_text(check['detail'])}")
+        lbl_env = Gtk.Label(xalign=0)
+        lbl_env.set_markup("\n".join(rows))
+        lbl_env.set_line_wrap(True)
+        vbox_env = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=8)
+        vbox_env.pack_start(lbl_env, False, False, 0)
+
+        # Checklist of every tool the controls below depend on, with how to install the missing ones
+        rows = ["<b>Tools</b>"]
+        for tool, cap in self.capabilities.items():
+            status = 'ok' if cap['available'] else 'warn'
+            icon = f"<span foreground='{colors[status]}'>{icons[status]}</span>"
+            row = f"{icon} <b>{GLib.markup_escape_text(tool)}</b>: {GLib.markup_escape_text(cap['purpose'])}"
+            if cap['hint']:
+                row += f"\n      <small>not installed: <tt>{GLib.markup_escape_text(cap['hint'])}</tt></small>"
+            rows.append(row)
+        lbl_tools = Gtk.Label(xalign=0)
+        lbl_tools.set_markup("\n".join(rows))
+        lbl_tools.set_line_wrap(True)
+        lbl_tools.set_selectable(True)
+        vbox_env.pack_start(lbl_tools, False, False, 0)
+        expander_env.add(vbox_env)
+
+        # --- SAFETY MODE ---
+        # Operator sessions (safety_mode = "operator") can plan, check and monitor; everything else stays disabled until unlocked
+        self.session_bar = Gtk.InfoBar(message_type=Gtk.MessageType.INFO)
+        self.session_bar.set_no_show_all(True)
+        lbl_session = Gtk.Label(label="Operator mode: arrays can be planned, checked and monitored. "
+                                      "Creating, formatting, deleting and repairing needs admin mode.", xalign=0)
+        lbl_session.set_line_wrap(True)
+        lbl_session.show()
+        self.session_bar.get_content_area().pack_start(lbl_session, True, True, 0)
+        self.session_bar.add_button("Unlock Admin…", Gtk.ResponseType.OK)
+        self.session_bar.connect("response", self.on_unlock_admin_response)
+        vbox.pack_start(self.session_bar, False, False, 0)
+        if not self.service.admin:
+            self.session_bar.show()
+
+        # --- HOT-ADD OFFER ---
+        # Shown when a blank disk is plugged in while an array is degraded (hot_add_policy = "prompt")
+        self.hot_add_offer = None
+        self.hot_add_scan = None
+        self.hot_add_bar = Gtk.InfoBar(message_type=Gtk.MessageType.WARNING)
+        self.hot_add_bar.set_no_show_all(True)
+        self.lbl_hot_add = Gtk.Label(xalign=0)
+        self.lbl_hot_add.set_line_wrap(True)
+        self.lbl_hot_add.show()
+        self.hot_add_bar.get_content_area().pack_start(self.lbl_hot_add, True, True, 0)
+        self.hot_add_bar.add_button("Add as replacement", Gtk.ResponseType.OK)
+        self.hot_add_bar.add_button("Dismiss", Gtk.ResponseType.CLOSE)
+        self.hot_add_bar.connect("response", self.on_hot_add_response)
+        vbox.pack_start(self.hot_add_bar, False, False, 0)
+
         # --- SECTION 1: ARRAY CREATION ---
         frame_create = Gtk.Frame(label=" 1. Create Array ")
         frame_create.set_shadow_type(Gtk.ShadowType.ETCHED_IN)
@@ -25,22 +141,75 @@ class LiveRaidWindow(Gtk.Window):
 
         lbl_drives = Gtk.Label(label="Select Target Drives:", xalign=0)
         vbox_create.pack_start(lbl_drives, False, False, 0)
-        
+
+        # Narrow a heterogeneous chassis down to a matched set: chips and text filter only hide drives, selected ones stay shown
+        hbox_filter = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=6)
+        self.drive_chips = {}
+        for label, tooltip in DRIVE_CHIPS.items():
+            chip = Gtk.ToggleButton(label=label)
+            chip.set_tooltip_text(tooltip)
+            chip.connect("toggled", self.apply_drive_filter)
+            hbox_filter.pack_start(chip, False, False, 0)
+            self.drive_chips[label] = chip
+        self.entry_drive_filter = Gtk.SearchEntry()
+        self.entry_drive_filter.set_placeholder_text("Name, model or serial")
+        self.entry_drive_filter.connect("search-changed", self.apply_drive_filter)
+        hbox_filter.pack_start(self.entry_drive_filter, True, True, 0)
+        vbox_create.pack_start(hbox_filter, False, False, 0)
+        self.lbl_drive_filter = Gtk.Label(xalign=0)
+        vbox_create.pack_start(self.lbl_drive_filter, False, False, 0)
+
         self.drive_box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=0)
         vbox_create.pack_start(self.drive_box, False, False, 0)
+
+        # Live preview of the selection: usable capacity and how many failed drives the array survives
+        self.lbl_capacity = Gtk.Label(xalign=0)
+        self.lbl_capacity.set_line_wrap(True)
+        vbox_create.pack_start(self.lbl_capacity, False, False, 0)
+
+        # Leftover arrays found on the selected drives, each with its own guided teardown
+        self.old_arrays_box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=5)
+        vbox_create.pack_start(self.old_arrays_box, False, False, 0)
         
         self.drive_checkboxes = {}
-        self.refresh_drives()
+        self.spare_checkboxes = {}
+        # Drives are populated by refresh_arrays() once the rest of the window is built
+
+        # Built-in presets prefill level, chunk and filesystem (see plan.PRESETS)
+        hbox_preset = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+        hbox_preset.pack_start(Gtk.Label(label="Preset:", xalign=0), False, False, 0)
+        self.combo_preset = Gtk.ComboBoxText()
+        self.combo_preset.append("custom", "Custom")
+        for name, preset in plan.PRESETS.items():
+            self.combo_preset.append(name, preset['title'])
+        self.combo_preset.set_active_id("custom")
+        self.combo_preset.connect("changed", self.on_preset_changed)
+        hbox_preset.pack_start(self.combo_preset, True, True, 0)
+        btn_compare = Gtk.Button(label="Compare Plans…")
+        btn_compare.set_tooltip_text("Show two plan files, or a plan and a running array, side by side")
+        btn_compare.connect("clicked", self.on_compare_clicked)
+        hbox_preset.pack_start(btn_compare, False, False, 0)
+        vbox_create.pack_start(hbox_preset, False, False, 0)
+        self.lbl_preset = self.make_hint("")
+        vbox_create.pack_start(self.lbl_preset, False, False, 0)
 
         hbox_raid = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
         lbl_raid = Gtk.Label(label="RAID Level:", xalign=0)
         hbox_raid.pack_start(lbl_raid, False, False, 0)
         
         self.combo_raid = Gtk.ComboBoxText()
-        for level in ["0", "1", "5", "10"]:
+        for level in ["0", "1", "5", "6", "10"]:
             self.combo_raid.append_text(level)
         self.combo_raid.set_active(1)
+        self.combo_raid.connect("changed", self.update_drive_conflicts)
         hbox_raid.pack_start(self.combo_raid, True, True, 0)
+
+        # RAID 10 mirrors each member with the next one, the layout editor decides which disks pair up
+        self.member_order = []
+        self.btn_pairs = Gtk.Button(label="Mirror Pairs…")
+        self.btn_pairs.set_tooltip_text("Drag the selected drives into mirror pairs, e.g. to mirror across controllers")
+        self.btn_pairs.connect("clicked", self.on_pairs_clicked)
+        hbox_raid.pack_start(self.btn_pairs, False, False, 0)
         
         lbl_chunk = Gtk.Label(label="Chunk Size:", xalign=0)
         hbox_raid.pack_start(lbl_chunk, False, False, 0)
@@ -54,10 +223,105 @@ class LiveRaidWindow(Gtk.Window):
         self.chk_ssd = Gtk.CheckButton(label="Assume SSD (Skip initial sync)")
         vbox_create.pack_start(self.chk_ssd, False, False, 0)
 
+        self.chk_preserve = Gtk.CheckButton(label="Keep existing partitions (use free space only)")
+        self.chk_preserve.set_tooltip_text("Leaves the ESP and any other partitions alone and builds the array on a new partition in each disk's free space")
+        self.chk_preserve.connect("toggled", self.update_drive_conflicts)
+        vbox_create.pack_start(self.chk_preserve, False, False, 0)
+
+        # Advanced member/array flags, collapsed by default
+        expander_adv = Gtk.Expander(label="Advanced Options")
+        vbox_create.pack_start(expander_adv, False, False, 0)
+
+        grid_adv = Gtk.Grid(column_spacing=10, row_spacing=5)
+        grid_adv.set_border_width(5)
+        expander_adv.add(grid_adv)
+
+        grid_adv.attach(Gtk.Label(label="Write-mostly member:", xalign=0), 0, 0, 1, 1)
+        self.combo_write_mostly = Gtk.ComboBoxText()
+        self.combo_write_mostly.append_text("None")
+        self.combo_write_mostly.set_active(0)
+        grid_adv.attach(self.combo_write_mostly, 1, 0, 1, 1)
+        grid_adv.attach(self.make_hint("RAID 1 only. Reads avoid this member, useful for a slow USB or remote mirror."), 0, 1, 2, 1)
+
+        grid_adv.attach(Gtk.Label(label="Write-behind:", xalign=0), 0, 2, 1, 1)
+        self.spin_write_behind = Gtk.SpinButton.new_with_range(0, backend.MAX_WRITE_BEHIND, 1)
+        grid_adv.attach(self.spin_write_behind, 1, 2, 1, 1)
+        grid_adv.attach(self.make_hint("Outstanding writes allowed to the write-mostly member (0 = off). Adds an internal bitmap."), 0, 3, 2, 1)
+
+        self.chk_auto_ro = Gtk.CheckButton(label="Start array in auto-read-only mode")
+        grid_adv.attach(self.chk_auto_ro, 0, 4, 2, 1)
+        grid_adv.attach(self.make_hint("The array stays read-only (no resync, no metadata writes) until the first write."), 0, 5, 2, 1)
+
+        # mdadm tunables, "Default" leaves the choice to mdadm
+        grid_adv.attach(Gtk.Label(label="Metadata version:", xalign=0), 0, 6, 1, 1)
+        self.combo_metadata = self.make_option_combo(backend.METADATA_VERSIONS)
+        grid_adv.attach(self.combo_metadata, 1, 6, 1, 1)
+        grid_adv.attach(self.make_hint("0.90 and 1.0 sit at the end of the members, so each one also reads as a plain disk (e.g. for /boot)."), 0, 7, 2, 1)
+
+        grid_adv.attach(Gtk.Label(label="Write-intent bitmap:", xalign=0), 0, 8, 1, 1)
+        self.combo_bitmap = self.make_option_combo(backend.BITMAP_MODES)
+        grid_adv.attach(self.combo_bitmap, 1, 8, 1, 1)
+        grid_adv.attach(self.make_hint("internal: resync only the dirty regions after a crash, at a small write cost. Not for RAID 0."), 0, 9, 2, 1)
+
+        grid_adv.attach(Gtk.Label(label="RAID 10 layout:", xalign=0), 0, 10, 1, 1)
+        self.combo_layout = self.make_option_combo(list(backend.RAID10_LAYOUTS))
+        grid_adv.attach(self.combo_layout, 1, 10, 1, 1)
+        grid_adv.attach(self.make_hint("far and offset spread the second copy for faster reads, far arrays can't be reshaped later."), 0, 11, 2, 1)
+
+        grid_adv.attach(Gtk.Label(label="Array name:", xalign=0), 0, 12, 1, 1)
+        self.entry_md_name = Gtk.Entry()
+        self.entry_md_name.set_placeholder_text("optional, linked as /dev/md/<name>")
+        grid_adv.attach(self.entry_md_name, 1, 12, 1, 1)
+        self.combo_raid.connect("changed", lambda combo: self.combo_layout.set_sensitive(combo.get_active_text() == "10"))
+        self.combo_layout.set_sensitive(self.combo_raid.get_active_text() == "10")
+
+        hbox_create = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=6)
         self.btn_create = Gtk.Button(label="Create RAID Array")
         self.btn_create.connect("clicked", self.on_create_clicked)
         self.btn_create.get_style_context().add_class("suggested-action")
-        vbox_create.pack_start(self.btn_create, False, False, 5)
+        hbox_create.pack_start(self.btn_create, True, True, 0)
+        # Sets the selection aside as an array of its own, so several can be laid out before any is built
+        btn_add_plan = Gtk.Button(label="Add to Planned Arrays")
+        btn_add_plan.set_tooltip_text("Reserve the selected drives and the next free md device for this array, then select the next one")
+        btn_add_plan.connect("clicked", self.on_add_plan_clicked)
+        hbox_create.pack_start(btn_add_plan, False, False, 0)
+        # The running create or format, a background.Operation the Cancel and Pause buttons act on
+        self.operation = None
+        self.cancel_buttons = []
+        self.pause_buttons = []
+        hbox_create.pack_start(self.make_operation_buttons(), False, False, 0)
+        vbox_create.pack_start(hbox_create, False, False, 5)
+
+        # One row per planned array: the device it becomes, its drives and how far it got
+        self.planned = []
+        self.planned_box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=2)
+        vbox_create.pack_start(self.planned_box, False, False, 0)
+        self.btn_create_planned = Gtk.Button(label="Create Planned Arrays")
+        self.btn_create_planned.connect("clicked", self.on_create_planned_clicked)
+        # Shown by render_planned() once something is planned
+        self.btn_create_planned.set_no_show_all(True)
+        vbox_create.pack_start(self.btn_create_planned, False, False, 0)
+
+        # One row per step of the running operation: spinner while it runs, then its outcome
+        self.steps_box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=2)
+        vbox_create.pack_start(self.steps_box, False, False, 0)
+        self.step_rows = []
+        self.sync_rows = {}
+        if blocking:
+            self.btn_create.set_sensitive(False)
+            self.btn_create.set_tooltip_text("Disabled: see the environment report above")
+        else:
+            self.gate_control(self.btn_create, operation="create")
+
+        # Files on a hardware RAID volume can be moved onto an array built from the selected drives
+        self.hw_volumes = migrate.detect_hardware_raid_volumes()
+        if self.hw_volumes:
+            volumes = ", ".join(f"{v['name']} ({v['model']})" for v in self.hw_volumes)
+            vbox_create.pack_start(self.make_hint(f"Hardware RAID volumes detected: {volumes}"), False, False, 0)
+            self.btn_migrate = Gtk.Button(label="Migrate from Hardware RAID…")
+            self.btn_migrate.connect("clicked", self.on_migrate_clicked)
+            self.gate_control(self.btn_migrate, operation="create", sensitive=not blocking)
+            vbox_create.pack_start(self.btn_migrate, False, False, 0)
 
         # --- SECTION 2: ARRAY MANAGEMENT & FORMATTING ---
         frame_manage = Gtk.Frame(label=" 2. Manage & Format Arrays ")
@@ -68,6 +332,12 @@ class LiveRaidWindow(Gtk.Window):
         vbox_manage.set_border_width(10)
         frame_manage.add(vbox_manage)
 
+        # Live health of every array (state, rebuild progress and ETA, failed members), refreshed by refresh_health()
+        self.lbl_health = Gtk.Label(xalign=0)
+        self.lbl_health.set_selectable(True)
+        vbox_manage.pack_start(self.lbl_health, False, False, 0)
+        self.health_refreshing = False
+
         hbox_arrays = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
         lbl_arrays = Gtk.Label(label="Detected Active Arrays:", xalign=0)
         hbox_arrays.pack_start(lbl_arrays, False, False, 0)
@@ -79,12 +349,40 @@ class LiveRaidWindow(Gtk.Window):
         self.btn_refresh.connect("clicked", self.refresh_arrays)
         hbox_arrays.pack_start(self.btn_refresh, False, False, 0)
         
+        self.btn_scrub = Gtk.Button(label="Scrub")
+        self.btn_scrub.set_tooltip_text("Read every sector and count mismatches; offers remediation if problems are found")
+        self.btn_scrub.connect("clicked", self.on_scrub_clicked)
+        hbox_arrays.pack_start(self.btn_scrub, False, False, 0)
+
         self.btn_delete = Gtk.Button(label="Stop & Delete Array")
         self.btn_delete.connect("clicked", self.on_delete_clicked)
         self.btn_delete.get_style_context().add_class("destructive-action")
         hbox_arrays.pack_start(self.btn_delete, False, False, 0)
         vbox_manage.pack_start(hbox_arrays, False, False, 0)
 
+        # Progress of a running scrub with the mismatches found so far, hidden while none runs
+        self.scrub_progress = Gtk.ProgressBar(show_text=True)
+        self.scrub_progress.set_no_show_all(True)
+        vbox_manage.pack_start(self.scrub_progress, False, False, 0)
+        hbox_scrub_schedule = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=6)
+        hbox_scrub_schedule.pack_start(Gtk.Label(label="Scheduled scrub:"), False, False, 0)
+        self.combo_scrub_schedule = Gtk.ComboBoxText()
+        for schedule in ["off"] + plan.SCRUB_SCHEDULES:
+            self.combo_scrub_schedule.append_text(schedule)
+        self.combo_scrub_schedule.set_active(0)
+        hbox_scrub_schedule.pack_start(self.combo_scrub_schedule, False, False, 0)
+        btn_scrub_schedule = Gtk.Button(label="Set Schedule")
+        btn_scrub_schedule.set_tooltip_text("Install (or remove) the systemd timer that checks the selected array on this schedule")
+        btn_scrub_schedule.connect("clicked", self.on_scrub_schedule_clicked)
+        hbox_scrub_schedule.pack_start(btn_scrub_schedule, False, False, 0)
+        vbox_manage.pack_start(hbox_scrub_schedule, False, False, 0)
+
+        # Topology of the selected array: which physical disk sits in which slot
+        self.lbl_members = Gtk.Label(xalign=0)
+        self.lbl_members.set_selectable(True)
+        vbox_manage.pack_start(self.lbl_members, False, False, 0)
+        self.combo_arrays.connect("changed", self.update_array_members)
+
         # Formatting Options
         hbox_fs = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
         lbl_fs = Gtk.Label(label="Filesystem:", xalign=0)
@@ -94,6 +392,7 @@ class LiveRaidWindow(Gtk.Window):
         for fs in ["ext4", "btrfs", "xfs", "zfs", "f2fs", "exfat", "ntfs", "vfat"]:
             self.combo_fs.append_text(fs)
         self.combo_fs.set_active(0)
+        self.combo_fs.connect("changed", self.update_format_button)
         hbox_fs.pack_start(self.combo_fs, True, True, 0)
         vbox_manage.pack_start(hbox_fs, False, False, 0)
 
@@ -106,10 +405,49 @@ class LiveRaidWindow(Gtk.Window):
         self.chk_trim = Gtk.CheckButton(label="Enable TRIM/Discard support")
         grid_opts.attach(self.chk_trim, 0, 1, 1, 1)
 
+        # The passphrase is asked for when Format is clicked, it is never stored
+        self.chk_encrypt = Gtk.CheckButton(label="Encrypt (LUKS)")
+        self.chk_encrypt.set_tooltip_text(f"Put the filesystem in a {luks.describe(luks.new_encryption())} container, "
+                                          "added to /etc/crypttab so the passphrase is asked for at boot")
+        self.gate_control(self.chk_encrypt, ["cryptsetup"])
+        grid_opts.attach(self.chk_encrypt, 1, 0, 1, 1)
+
         self.btn_format = Gtk.Button(label="Format Selected Array")
         self.btn_format.connect("clicked", self.on_format_clicked)
         self.btn_format.get_style_context().add_class("suggested-action")
         vbox_manage.pack_start(self.btn_format, False, False, 5)
+        vbox_manage.pack_start(self.make_operation_buttons(), False, False, 0)
+
+        # Boot-time mount of the selected (formatted) array, kept as one managed fstab entry per array
+        hbox_fstab = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+        hbox_fstab.pack_start(Gtk.Label(label="Mount at boot on:", xalign=0), False, False, 0)
+        self.entry_fstab = Gtk.Entry()
+        self.entry_fstab.set_placeholder_text("/srv/data")
+        hbox_fstab.pack_start(self.entry_fstab, True, True, 0)
+        hbox_fstab.pack_start(Gtk.Label(label="Options:", xalign=0), False, False, 0)
+        # Comma-separated, on top of defaults; a preset fills in its own
+        self.entry_mount_options = Gtk.Entry()
+        self.entry_mount_options.set_placeholder_text("noatime")
+        hbox_fstab.pack_start(self.entry_mount_options, False, False, 0)
+        self.btn_fstab_add = Gtk.Button(label="Add to fstab")
+        self.btn_fstab_add.set_tooltip_text("Adds or updates the array's entry in /etc/fstab, referenced by filesystem UUID")
+        self.btn_fstab_add.connect("clicked", self.on_fstab_add_clicked)
+        hbox_fstab.pack_start(self.btn_fstab_add, False, False, 0)
+        self.btn_fstab_remove = Gtk.Button(label="Remove from fstab")
+        self.btn_fstab_remove.connect("clicked", self.on_fstab_remove_clicked)
+        hbox_fstab.pack_start(self.btn_fstab_remove, False, False, 0)
+        vbox_manage.pack_start(hbox_fstab, False, False, 0)
+
+        # Booting from the selected array: the bootloader (GRUB, systemd-boot or EFI stub, see bootloader.py) assembles it by UUID
+        hbox_boot = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+        hbox_boot.pack_start(Gtk.Label(label=f"Boot from it ({bootloader.get_bootloader().title}):", xalign=0), False, False, 0)
+        for label, action, tooltip in (("Add to Bootloader", "add-raid", "Assembles the array by UUID at boot (rd.md.uuid=), with root=UUID= where the loader needs it"),
+                                       ("Remove from Bootloader", "remove-raid", "Drops the array's rd.md.uuid=, other settings are never touched")):
+            btn = Gtk.Button(label=label)
+            btn.set_tooltip_text(tooltip)
+            btn.connect("clicked", self.on_boot_clicked, action)
+            hbox_boot.pack_start(btn, False, False, 0)
+        vbox_manage.pack_start(hbox_boot, False, False, 0)
 
         self.refresh_arrays()
 
@@ -132,12 +470,368 @@ class LiveRaidWindow(Gtk.Window):
         self.text_view.modify_font(gi.repository.Pango.FontDescription('Monospace 10'))
         
         scrolled_window.add(self.text_view)
+
+        # --- MESSAGE CENTER ---
+        # Outcomes (errors, warnings, successes) are kept with timestamps so the next action can't bury them
+        self.messages = []
+        # Latest log lines, matched against known failures when an error comes in
+        self.recent_output = []
+        hbox_status = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+        vbox.pack_start(hbox_status, False, False, 0)
+
+        self.lbl_status = Gtk.Label(xalign=0)
+        self.lbl_status.set_ellipsize(gi.repository.Pango.EllipsizeMode.END)
+        hbox_status.pack_start(self.lbl_status, True, True, 0)
+
+        self.btn_copy_error = Gtk.Button(label="Copy Error")
+        self.btn_copy_error.set_sensitive(False)
+        self.btn_copy_error.connect("clicked", self.on_copy_error_clicked)
+        hbox_status.pack_end(self.btn_copy_error, False, False, 0)
+
+        btn_history = Gtk.MenuButton(label="History")
+        self.history_box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=4)
+        self.history_box.set_border_width(8)
+        scrolled_history = Gtk.ScrolledWindow()
+        scrolled_history.set_min_content_width(500)
+        scrolled_history.set_min_content_height(250)
+        scrolled_history.add(self.history_box)
+        scrolled_history.show_all()
+        popover_history = Gtk.Popover()
+        popover_history.add(scrolled_history)
+        btn_history.set_popover(popover_history)
+        hbox_status.pack_end(btn_history, False, False, 0)
         
         if backend.DRY_RUN:
             self.append_log("--- DRY RUN MODE IS ACTIVE ---\n")
             self.append_log("System commands will be logged but NOT executed against disks.\n\n")
 
+        # Disks already attached at startup are never offered as replacements, only new arrivals
+        self.known_drives = set(self.drive_info)
+        GLib.timeout_add_seconds(10, self.check_hot_add)
+        self.event_detector = hooks.EventDetector()
+        # desktop sinks of [notification] in config.toml, off when the tray icon already shows the events
+        self.desktop_notifications = desktop_notifications
+        GLib.timeout_add_seconds(10, self.check_array_events)
+        self.refresh_health()
+        GLib.timeout_add_seconds(5, self.refresh_health)
+
+    def make_option_combo(self, choices):
+        combo = Gtk.ComboBoxText()
+        for choice in ["Default"] + choices:
+            combo.append_text(choice)
+        combo.set_active(0)
+        return combo
+
+    def option_value(self, combo):
+        # None for "Default", mdadm decides
+        text = combo.get_active_text()
+        return None if text in (None, "Default") or not combo.get_sensitive() else text
+
+    def set_combo_text(self, combo, text):
+        for i, row in enumerate(combo.get_model()):
+            if row[0] == text:
+                combo.set_active(i)
+                return
+
+    def on_preset_changed(self, combo):
+        preset = plan.PRESETS.get(combo.get_active_id())
+        if preset is None:
+            self.lbl_preset.set_text("")
+            return
+        self.set_combo_text(self.combo_raid, preset['level'])
+        self.set_combo_text(self.combo_chunk, preset.get('chunk', "Default"))
+        self.set_combo_text(self.combo_fs, preset['filesystem'])
+        # Used once the array exists, by Add to fstab and Set Schedule below
+        self.entry_mount_options.set_text(",".join(preset.get('mount_options', [])))
+        self.set_combo_text(self.combo_scrub_schedule, preset.get('scrub') or "off")
+        extras = []
+        if preset.get('mount_options'):
+            extras.append(f"mount with {','.join(preset['mount_options'])}")
+        if preset.get('scrub'):
+            extras.append(f"scrub {preset['scrub']}")
+        hint = preset['description'] + (f" Filled in below: {'; '.join(extras)}." if extras else "")
+        self.lbl_preset.set_markup(f"<small><i>{GLib.markup_escape_text(hint)}</i></small>")
+
+    def gate_control(self, widget, tools=(), operation=None, sensitive=True):
+        """
+        Enables a control only when every tool it runs is installed and the session may run its operation
+        (see RaidService.allowed()); otherwise it is disabled with the reasons as its tooltip.
+        Returns: True when the control was enabled
+        """
+        reasons = [f"{tool} is not installed ({self.capabilities[tool]['hint']})"
+                   for tool in tools if tool in self.capabilities and not self.capabilities[tool]['available']]
+        if operation and not self.service.allowed(operation):
+            reasons.append("needs admin mode, this is an operator session (Unlock Admin at the top)")
+        if reasons:
+            widget.set_sensitive(False)
+            widget.set_tooltip_text("Disabled: " + "; ".join(reasons))
+            return False
+        # Drop a reason set earlier, the control's own tooltip (if any) is left alone
+        if (widget.get_tooltip_text() or "").startswith("Disabled: "):
+            widget.set_tooltip_text(None)
+        widget.set_sensitive(sensitive)
+        return sensitive
+
+    def update_format_button(self, widget=None):
+        arr_text = self.combo_arrays.get_active_text()
+        has_array = bool(arr_text) and "No active arrays" not in arr_text
+        fs_tool = doctor.FILESYSTEM_TOOLS.get(self.combo_fs.get_active_text())
+        self.gate_control(self.btn_format, ["parted"] + ([fs_tool] if fs_tool else []), "format", has_array)
+
+    def on_unlock_admin_response(self, bar, response):
+        if response != Gtk.ResponseType.OK:
+            return
+        bar.set_sensitive(False)
+
+        # pkexec waits for the polkit agent's password dialog
+        def worker():
+            success, out = self.service.unlock_admin()
+            GLib.idle_add(self.finish_unlock_admin, success, out)
+        threading.Thread(target=worker, daemon=True).start()
+
+    def finish_unlock_admin(self, success, out):
+        self.append_log(out)
+        self.session_bar.set_sensitive(True)
+        if success:
+            self.session_bar.hide()
+            self.post_message('info', "Admin mode unlocked")
+            if not self.blocking:
+                self.gate_control(self.btn_create, operation="create")
+                if self.hw_volumes:
+                    self.gate_control(self.btn_migrate, operation="create")
+                self.render_planned()
+            self.refresh_arrays()
+        else:
+            self.post_message('warn', "Still in operator mode", out.strip())
+        return False
+
+    def make_hint(self, text):
+        lbl = Gtk.Label(xalign=0)
+        lbl.set_line_wrap(True)
+        lbl.set_markup(f"<small><i>{GLib.markup_escape_text(text)}</i></small>")
+        return lbl
+
+    def show_diff_dialog(self, title, diff_text, confirm=None):
+        # With a confirm label the dialog asks whether to make the change. Returns True when it was confirmed.
+        dialog = Gtk.Dialog(title=title, transient_for=self, flags=0)
+        if confirm:
+            dialog.add_button("Cancel", Gtk.ResponseType.CANCEL)
+            dialog.add_button(confirm, Gtk.ResponseType.OK)
+        else:
+            dialog.add_button("Close", Gtk.ResponseType.CLOSE)
+        dialog.set_default_size(700, 400)
+
+        scrolled = Gtk.ScrolledWindow()
+        scrolled.set_vexpand(True)
+        dialog.get_content_area().pack_start(scrolled, True, True, 0)
+
+        view = Gtk.TextView()
+        view.set_editable(False)
+        view.modify_font(gi.repository.Pango.FontDescription('Monospace 10'))
+        buf = view.get_buffer()
+        tags = {
+            '+': buf.create_tag("add", foreground="#27ae60"),
+            '-': buf.create_tag("del", foreground="#c0392b"),
+            '@': buf.create_tag("hunk", foreground="#2980b9")
+        }
+        for line in (diff_text or "No changes.\n").splitlines(keepends=True):
+            tag = tags.get(line[0]) if not line.startswith(("+++", "---")) else None
+            if tag:
+                buf.insert_with_tags(buf.get_end_iter(), line, tag)
+            else:
+                buf.insert(buf.get_end_iter(), line)
+        scrolled.add(view)
+
+        dialog.show_all()
+        response = dialog.run()
+        dialog.destroy()
+        return response == Gtk.ResponseType.OK
+
+    def show_script_dialog(self, title, recorded):
+        # The commands and file edits a dry run skipped (script.py), as the bash script that makes them
+        dialog = Gtk.Dialog(title=title, transient_for=self, flags=0)
+        dialog.add_button("Save Script...", Gtk.ResponseType.APPLY)
+        dialog.add_button("Close", Gtk.ResponseType.CLOSE)
+        dialog.set_default_size(700, 400)
+        scrolled = Gtk.ScrolledWindow()
+        scrolled.set_vexpand(True)
+        dialog.get_content_area().pack_start(scrolled, True, True, 0)
+        view = Gtk.TextView()
+        view.set_editable(False)
+        view.modify_font(gi.repository.Pango.FontDescription('Monospace 10'))
+        text = recorded.to_bash()
+        view.get_buffer().set_text(text)
+        scrolled.add(view)
+        dialog.show_all()
+        while dialog.run() == Gtk.ResponseType.APPLY:
+            chooser = Gtk.FileChooserDialog(title="Save Script", transient_for=dialog, action=Gtk.FileChooserAction.SAVE)
+            chooser.add_buttons("Cancel", Gtk.ResponseType.CANCEL, "Save", Gtk.ResponseType.OK)
+            chooser.set_do_overwrite_confirmation(True)
+            chooser.set_current_name("liveraid-create.sh")
+            if chooser.run() == Gtk.ResponseType.OK:
+                path = chooser.get_filename()
+                try:
+                    with open(path, 'w') as f:
+                        f.write(text)
+                    os.chmod(path, 0o755)
+                    self.append_log(f"Script saved to {path}.\n")
+                except OSError as e:
+                    self.append_log(f"ERROR: Could not save the script: {e}\n")
+            chooser.destroy()
+        dialog.destroy()
+        return False
+
+    def on_compare_clicked(self, widget):
+        dialog = Gtk.Dialog(title="Compare Plans", transient_for=self, flags=0)
+        dialog.add_button("Close", Gtk.ResponseType.CLOSE)
+        dialog.set_default_size(800, 400)
+        box = dialog.get_content_area()
+        box.set_spacing(10)
+        box.set_border_width(10)
+
+        # Each side is a plan file or the current state of a running array
+        arrays = [arr['name'] for arr in backend.get_active_arrays()]
+        sides = []
+        hbox_sources = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+        for title in ("Left", "Right"):
+            vbox_side = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=5)
+            vbox_side.pack_start(Gtk.Label(label=f"{title}:", xalign=0), False, False, 0)
+            combo = Gtk.ComboBoxText()
+            combo.append("file", "Plan file")
+            for name in arrays:
+                combo.append(name, f"Running array {name}")
+            combo.set_active_id("file")
+            vbox_side.pack_start(combo, False, False, 0)
+            chooser = Gtk.FileChooserButton(title=f"{title} plan file", action=Gtk.FileChooserAction.OPEN)
+            vbox_side.pack_start(chooser, False, False, 0)
+            hbox_sources.pack_start(vbox_side, True, True, 0)
+            sides.append((combo, chooser))
+        box.pack_start(hbox_sources, False, False, 0)
+
+        grid = Gtk.Grid(column_spacing=20, row_spacing=5)
+        box.pack_start(grid, True, True, 0)
+
+        def load_side(combo, chooser):
+            source = combo.get_active_id()
+            if source != "file":
+                return plan.plan_from_array(source), source
+            path = chooser.get_filename()
+            if not path:
+                return None, "(no file chosen)"
+            return plan.load_plan(path), path
+
+        def render(*_):
+            for child in grid.get_children():
+                grid.remove(child)
+            try:
+                (left, left_name), (right, right_name) = [load_side(*side) for side in sides]
+            except (OSError, ValueError) as e:
+                grid.attach(Gtk.Label(label=f"Could not load plan: {e}", xalign=0), 0, 0, 3, 1)
+                grid.show_all()
+                return
+            for col, text in enumerate(("Field", left_name, right_name)):
+                header = Gtk.Label(xalign=0)
+                header.set_markup(f"<b>{GLib.markup_escape_text(text)}</b>")
+                grid.attach(header, col, 0, 1, 1)
+            if left is None or right is None:
+                grid.show_all()
+                return
+            for row, diff in enumerate(plan.diff_plans(left, right), 1):
+                grid.attach(Gtk.Label(label=diff['field'], xalign=0), 0, row, 1, 1)
+                for col, value in ((1, diff['left']), (2, diff['right'])):
+                    lbl = Gtk.Label(xalign=0)
+                    lbl.set_selectable(True)
+                    text = GLib.markup_escape_text(plan.format_value(value))
+                    lbl.set_markup(f"<span foreground='#c0392b'><b>{text}</b></span>" if diff['changed'] else text)
+                    grid.attach(lbl, col, row, 1, 1)
+            grid.show_all()
+
+        for combo, chooser in sides:
+            combo.connect("changed", render)
+            chooser.connect("file-set", render)
+        render()
+
+        dialog.show_all()
+        dialog.run()
+        dialog.destroy()
+
+    def on_pairs_clicked(self, widget):
+        dialog = Gtk.Dialog(title="RAID 10 Mirror Pairs", transient_for=self, flags=0)
+        dialog.add_button("Cancel", Gtk.ResponseType.CANCEL)
+        dialog.add_button("Use This Order", Gtk.ResponseType.OK)
+        dialog.set_default_size(520, 320)
+        box = dialog.get_content_area()
+        box.set_spacing(10)
+        box.set_border_width(10)
+        box.pack_start(self.make_hint("Drag drives up or down. Each pair of rows mirrors each other, the array stripes across the pairs. "
+                                      "Put the two disks of a pair on different controllers or cables so one failure can't take both."), False, False, 0)
+
+        # Pair, device, size, model, controller
+        store = Gtk.ListStore(str, str, str, str, str)
+
+        def fill(names):
+            store.clear()
+            for name in names:
+                d = self.drive_info[name]
+                store.append(["", name, units.format_size(d['size_bytes']), d.get('model') or "", d.get('controller') or "unknown"])
+
+        fill(self.selected_members())
+        view = Gtk.TreeView(model=store)
+        view.set_reorderable(True)
+        for col, title in enumerate(("Pair", "Drive", "Size", "Model", "Controller")):
+            view.append_column(Gtk.TreeViewColumn(title, Gtk.CellRendererText(), text=col))
+        box.pack_start(view, True, True, 0)
+        btn_suggest = Gtk.Button(label="Pair Across Controllers")
+        btn_suggest.set_tooltip_text("Put the two halves of each pair on different controllers where possible")
+        btn_suggest.connect("clicked", lambda *_: fill([d['name'] for d in backend.suggest_mirror_order([self.drive_info[n] for n in order()])]))
+        btn_suggest.set_sensitive(len(backend.controllers([self.drive_info[n] for n in self.selected_members()])) > 1)
+        box.pack_start(btn_suggest, False, False, 0)
+        lbl_check = Gtk.Label(xalign=0)
+        lbl_check.set_line_wrap(True)
+        box.pack_start(lbl_check, False, False, 0)
+
+        def order():
+            return [row[1] for row in store]
+
+        def render(*_):
+            for i, row in enumerate(store):
+                row[0] = str(i // 2 + 1)
+            report = backend.validate_array("10", [self.drive_info[name] for name in order()])
+            pair_codes = ("pair-size", "unbalanced-legs", "pair-controller")
+            warnings = [w['message'] for w in report['warnings'] if w['code'] in pair_codes]
+            text = GLib.markup_escape_text("\n".join(warnings) or "Pairs look balanced.")
+            lbl_check.set_markup(f"<span foreground='#e67e22'>⚠ {text}</span>" if warnings else text)
+
+        # A drag-and-drop reorder ends with the source row being deleted, refilling adds rows back
+        store.connect("row-deleted", render)
+        store.connect("row-inserted", lambda *_: GLib.idle_add(render))
+        render()
+
+        dialog.show_all()
+        if dialog.run() == Gtk.ResponseType.OK:
+            self.member_order = order()
+            pairs = ", ".join(" + ".join(pair) for pair in backend.mirror_pairs(self.member_order))
+            self.append_log(f"RAID 10 mirror pairs: {pairs}\n")
+        dialog.destroy()
+
     def append_log(self, text):
+        self.recent_output = (self.recent_output + text.splitlines())[-50:]
+        # Outcome lines also go to the message center
+        for line in text.splitlines():
+            line = line.strip()
+            if line.startswith("ERROR"):
+                diagnosis = remedies.diagnose("\n".join(self.recent_output))
+                if diagnosis:
+                    # The plain-language title replaces the raw error, which stays in the log and the copied text
+                    self.recent_output = []
+                    self.post_message('error', diagnosis['title'], detail=line)
+                    GLib.idle_add(self.show_remedy_dialog, diagnosis, line)
+                    continue
+            for prefix, level in (("ERROR", "error"), ("WARNING", "warn"), ("SUCCESS", "info")):
+                if line.startswith(prefix):
+                    self.post_message(level, line)
+                    break
+
         end_iter = self.text_buffer.get_end_iter()
         self.text_buffer.insert(end_iter, text)
         
@@ -145,100 +839,993 @@ class LiveRaidWindow(Gtk.Window):
         mark = self.text_buffer.create_mark(None, self.text_buffer.get_end_iter(), False)
         self.text_view.scroll_to_mark(mark, 0.0, True, 0.0, 1.0)
 
+    def post_message(self, level, text, detail=None):
+        message = {'time': time.strftime("%H:%M:%S"), 'level': level, 'text': text if detail is None else f"{text}: {detail}"}
+        self.messages.append(message)
+        # Keep the history bounded, the full output stays in the execution log
+        if len(self.messages) > 200:
+            self.messages.pop(0)
+            self.history_box.remove(self.history_box.get_children()[0])
+
+        icons = {'info': ('✔', '#27ae60'), 'warn': ('⚠', '#e67e22'), 'error': ('✖', '#c0392b')}
+        icon, color = icons[level]
+        markup = f"<span foreground='{color}'>{icon}</span> <small>{message['time']}</small> {GLib.markup_escape_text(text)}"
+        self.lbl_status.set_markup(markup)
+
+        row = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=6)
+        lbl_row = Gtk.Label(xalign=0)
+        lbl_row.set_markup(markup)
+        lbl_row.set_line_wrap(True)
+        lbl_row.set_selectable(True)
+        row.pack_start(lbl_row, True, True, 0)
+        if level == 'error':
+            btn_copy = Gtk.Button(label="Copy")
+            btn_copy.connect("clicked", lambda w: self.copy_to_clipboard(f"[{message['time']}] {message['text']}"))
+            row.pack_end(btn_copy, False, False, 0)
+            self.btn_copy_error.set_sensitive(True)
+        self.history_box.pack_start(row, False, False, 0)
+        row.show_all()
+
+    def show_remedy_dialog(self, diagnosis, error_line):
+        dialog = Gtk.MessageDialog(transient_for=self, flags=0, message_type=Gtk.MessageType.ERROR,
+                                   buttons=Gtk.ButtonsType.CLOSE, text=diagnosis['title'])
+        steps = "\n".join(f"{i}. {step}" for i, step in enumerate(diagnosis['steps'], 1))
+        dialog.format_secondary_text(f"{diagnosis['explanation']}\n\nWhat to do:\n{steps}\n\nOriginal error: {error_line}")
+        action = diagnosis['action']
+        if action and not self.service.allowed(action['operation']):
+            # Operator sessions still get the explanation, just not the button
+            action = None
+        if action:
+            dialog.add_button(action['label'], Gtk.ResponseType.APPLY)
+            dialog.set_default_response(Gtk.ResponseType.APPLY)
+        response = dialog.run()
+        dialog.destroy()
+
+        if action and response == Gtk.ResponseType.APPLY:
+            def worker():
+                success, log = remedies.apply_action(action)
+                status = "SUCCESS" if success else "ERROR"
+                GLib.idle_add(self.append_log, f"{log}{status}: {action['label']} {'done' if success else 'failed'}.\n")
+            threading.Thread(target=worker, daemon=True).start()
+        return False
+
+    def copy_to_clipboard(self, text):
+        Gtk.Clipboard.get(Gdk.SELECTION_CLIPBOARD).set_text(text, -1)
+
+    def on_copy_error_clicked(self, widget):
+        errors = [m for m in self.messages if m['level'] == 'error']
+        if errors:
+            self.copy_to_clipboard(f"[{errors[-1]['time']}] {errors[-1]['text']}")
+
     def refresh_drives(self):
         # Clear existing checkboxes
         for child in self.drive_box.get_children():
             self.drive_box.remove(child)
             
         self.drive_checkboxes = {}
-        drives = backend.get_available_drives()
-        
+        self.spare_checkboxes = {}
+        self.drive_conflict_labels = {}
+        self.drive_info = {}
+        self.drive_rows = {}
+        self.drive_box.pack_start(Gtk.Label(label="Scanning drives...", xalign=0), False, False, 0)
+        self.drive_box.show_all()
+        # lsblk and smartctl can take seconds per disk, the window stays responsive meanwhile
+        self.drive_scan = self.service.inventory_async(on_done=lambda operation: GLib.idle_add(self.render_drives, operation))
+
+    def render_drives(self, operation):
+        # A later refresh_drives() started another scan, only its result counts
+        if operation is not self.drive_scan:
+            return False
+        for child in self.drive_box.get_children():
+            self.drive_box.remove(child)
+        if operation.error is not None:
+            self.append_log(f"ERROR: Listing the drives failed: {operation.error}\n")
+        drives = list((operation.result or {}).values())
+
         if not drives:
             lbl_no_drives = Gtk.Label(label="No available unmounted physical drives detected.", xalign=0)
             self.drive_box.pack_start(lbl_no_drives, False, False, 0)
         else:
             for d in drives:
-                cb = Gtk.CheckButton(label=f"{d['name']} ({d['size_gb']} GB)")
+                hbox_drive = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+                hbox_drive.pack_start(self.health_badge(d['smart']), False, False, 0)
+                model = f" {d['model']}" if d['model'] else ""
+                cb = Gtk.CheckButton(label=f"{d['name']}{model} ({units.format_size(d['size_bytes'])})")
+                cb.connect("toggled", self.update_drive_conflicts)
+                cb.connect("toggled", self.apply_drive_filter)
+                hbox_drive.pack_start(cb, False, False, 0)
+
+                # A selected drive can join as a hot spare instead of an active member
+                chk_spare = Gtk.CheckButton(label="Spare")
+                chk_spare.set_tooltip_text("Add this drive as a hot spare, rebuilt onto automatically when a member fails")
+                chk_spare.set_sensitive(False)
+                chk_spare.connect("toggled", self.update_drive_conflicts)
+                hbox_drive.pack_start(chk_spare, False, False, 0)
+
+                # Consequence labels for the selected drive, filled in live as the selection changes
+                lbl_conflicts = Gtk.Label(xalign=0)
+                hbox_drive.pack_start(lbl_conflicts, False, False, 0)
+
                 self.drive_checkboxes[d['name']] = cb
-                self.drive_box.pack_start(cb, False, False, 0)
+                self.spare_checkboxes[d['name']] = chk_spare
+                self.drive_conflict_labels[d['name']] = lbl_conflicts
+                self.drive_info[d['name']] = d
+                self.drive_rows[d['name']] = hbox_drive
+                self.drive_box.pack_start(hbox_drive, False, False, 0)
         
         self.drive_box.show_all()
+        self.apply_drive_filter()
+        self.update_drive_conflicts()
+        self.render_planned()
+        return False
+
+    def reference_model(self):
+        # The model the 'Same model' chip keeps: that of the first selected drive, else the most common one
+        selected = [name for name, cb in self.drive_checkboxes.items() if cb.get_active()]
+        if selected:
+            return self.drive_info[selected[0]]['model']
+        models = [d['model'] for d in self.drive_info.values() if d['model']]
+        return max(set(models), key=models.count) if models else None
+
+    def apply_drive_filter(self, widget=None):
+        active = {label for label, chip in self.drive_chips.items() if chip.get_active()}
+        drive_filter = devicefilter.DeviceFilter()
+        # The media chips are alternatives, both together are the same as neither
+        if "HDD" in active and "SSD/NVMe" not in active:
+            drive_filter.hdd()
+        elif "SSD/NVMe" in active and "HDD" not in active:
+            drive_filter.ssd()
+        if "≥4TB" in active:
+            drive_filter.min_size(LARGE_DRIVE_BYTES)
+        if "Blank disks only" in active:
+            drive_filter.blank()
+        model = self.reference_model() if "Same model" in active else None
+        text = self.entry_drive_filter.get_text().strip().lower()
+
+        shown = 0
+        for name, row in self.drive_rows.items():
+            d = self.drive_info[name]
+            searchable = " ".join(filter(None, (d['name'], d['model'], d['serial'], d['transport']))).lower()
+            visible = self.drive_checkboxes[name].get_active() or (
+                drive_filter.matches(d) and (model is None or d['model'] == model) and text in searchable)
+            row.set_visible(visible)
+            shown += visible
+        hidden = len(self.drive_rows) - shown
+        self.lbl_drive_filter.set_text(f"{hidden} of {len(self.drive_rows)} drives hidden by the filter" if hidden else "")
+
+    def health_badge(self, smart):
+        # Green, yellow or red dot for the SMART health discovery read, grey when it is unknown
+        color, text = HEALTH_BADGES.get(smart['health'], HEALTH_BADGES[None])
+        badge = Gtk.Label()
+        badge.set_markup(f"<span foreground='{color}'>●</span>")
+        details = [text]
+        if smart['reallocated'] is not None or smart['pending'] is not None:
+            details.append(f"{smart['reallocated'] or 0} reallocated, {smart['pending'] or 0} pending sectors")
+        if smart['power_on_hours'] is not None:
+            details.append(f"{smart['power_on_hours']} power-on hours")
+        badge.set_tooltip_text("\n".join(details))
+        return badge
+
+    def selected_members(self):
+        members = [name for name, cb in self.drive_checkboxes.items() if cb.get_active() and not self.spare_checkboxes[name].get_active()]
+        # The order arranged in the mirror pair editor, as long as it still covers exactly the selected drives
+        return list(self.member_order) if sorted(self.member_order) == sorted(members) else members
+
+    def selected_spares(self):
+        return [name for name, cb in self.drive_checkboxes.items() if cb.get_active() and self.spare_checkboxes[name].get_active()]
+
+    def update_drive_conflicts(self, widget=None):
+        for name, cb in self.drive_checkboxes.items():
+            self.spare_checkboxes[name].set_sensitive(cb.get_active())
+        selected = [self.drive_info[name] for name in self.selected_members()]
+        self.btn_pairs.set_sensitive(self.combo_raid.get_active_text() == "10" and len(selected) >= 4 and len(selected) % 2 == 0)
+        self.render_capacity(selected)
+        for name, lbl in self.drive_conflict_labels.items():
+            if not self.drive_checkboxes[name].get_active():
+                lbl.set_markup("")
+                continue
+            # Spares are whole disks, "Keep existing partitions" doesn't apply to them
+            preserve = self.chk_preserve.get_active() and not self.spare_checkboxes[name].get_active()
+            labels = backend.predict_drive_conflicts(self.drive_info[name], selected, preserve)
+            text = GLib.markup_escape_text(", ".join(labels))
+            lbl.set_markup(f"<span foreground='#c0392b'><i>{text}</i></span>" if labels else "")
+        self.refresh_write_mostly_choices()
+        self.refresh_old_arrays()
+
+    def render_capacity(self, selected):
+        level = self.combo_raid.get_active_text()
+        if not selected:
+            self.lbl_capacity.set_markup("")
+            return
+        if len(selected) < backend.MIN_DEVICES[level]:
+            self.lbl_capacity.set_markup(f"<i>RAID {level} needs at least {backend.MIN_DEVICES[level]} drives, {len(selected)} selected.</i>")
+            return
+        summary = backend.capacity_summary(level, selected, self.selected_spares())
+        text = GLib.markup_escape_text(backend.describe_capacity(summary))
+        self.lbl_capacity.set_markup(f"<b>RAID {level}</b>: {text}")
+
+    def show_steps(self, names):
+        for child in self.steps_box.get_children():
+            self.steps_box.remove(child)
+        self.step_rows = []
+        # Initial syncs followed in a row after the steps, see follow_sync()
+        self.sync_rows = {}
+        for name in names:
+            self.add_step_row(name)
+
+    def add_step_row(self, name):
+        hbox = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=8)
+        spinner = Gtk.Spinner()
+        icon = Gtk.Label(label="○")
+        label = Gtk.Label(label=name, xalign=0)
+        hbox.pack_start(spinner, False, False, 0)
+        hbox.pack_start(icon, False, False, 0)
+        hbox.pack_start(label, False, False, 0)
+        self.steps_box.pack_start(hbox, False, False, 0)
+        hbox.show_all()
+        spinner.hide()
+        self.step_rows.append((spinner, icon, label, name))
+        return len(self.step_rows)
+
+    def on_step_event(self, event):
+        """
+        Updates the row of a step from an executor.Executor progress event (also used for the GUI's own steps).
+        """
+        spinner, icon, label, name = self.step_rows[event['step'] - 1]
+        running = event['state'] == "running"
+        spinner.set_visible(running)
+        if running:
+            spinner.start()
+        else:
+            spinner.stop()
+        icon.set_visible(not running)
+        marks = {'done': "<span foreground='#27ae60'>✔</span>", 'failed': "<span foreground='#c0392b'>✖</span>", 'cancelled': "<span foreground='#e67e22'>⊘</span>",
+                 'rolled-back': "<span foreground='#e67e22'>↺</span>", 'left': "<span foreground='#c0392b'>!</span>"}
+        icon.set_markup(marks.get(event['state'], "○"))
+        label.set_text(f"{name} ({event['step_percent']:.1f}%)" if running and 'step_percent' in event else name)
+        return False
+
+    def follow_sync(self, array_name):
+        # The initial sync goes on after the create returned, its row follows the sync progress render_health() gets
+        step = self.add_step_row(f"initial sync of {array_name}")
+        self.sync_rows[array_name] = step
+        self.on_step_event({'step': step, 'state': "running"})
+        return False
+
+    def update_sync_rows(self, statuses):
+        # /proc/mdstat names the arrays /dev/mdN, created ones may be /dev/md/<name>
+        syncing = {arr['name']: arr['sync'] for arr in statuses}
+        for array_name, step in list(self.sync_rows.items()):
+            md_name = os.path.realpath(array_name)
+            progress = syncing.get(md_name)
+            if progress:
+                self.on_step_event({'step': step, 'state': "running", 'step_percent': progress['percent']})
+                continue
+            # Done, or the array was stopped meanwhile
+            self.on_step_event({'step': step, 'state': "done" if md_name in syncing else "failed"})
+            del self.sync_rows[array_name]
+
+    def refresh_old_arrays(self):
+        for child in self.old_arrays_box.get_children():
+            self.old_arrays_box.remove(child)
+
+        selected = [name for name, cb in self.drive_checkboxes.items() if cb.get_active()]
+        for old in backend.find_old_arrays(selected):
+            hbox_old = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=10)
+            desc = f"Old {old['level']} array '{old['name'] or old['uuid']}' found on {', '.join(old['members'])}"
+            lbl_old = Gtk.Label(xalign=0)
+            lbl_old.set_markup(f"<span foreground='#e67e22'>⚠ {GLib.markup_escape_text(desc)}</span>")
+            hbox_old.pack_start(lbl_old, False, False, 0)
+
+            btn_teardown = Gtk.Button(label="Tear down…")
+            btn_teardown.connect("clicked", self.on_teardown_old_clicked, old)
+            self.gate_control(btn_teardown, operation="teardown-old-array")
+            hbox_old.pack_end(btn_teardown, False, False, 0)
+            self.old_arrays_box.pack_start(hbox_old, False, False, 0)
+        self.old_arrays_box.show_all()
+
+    def on_teardown_old_clicked(self, widget, old):
+        members = ", ".join(old['members'])
+        dialog = Gtk.MessageDialog(
+            transient_for=self,
+            flags=0,
+            message_type=Gtk.MessageType.WARNING,
+            buttons=Gtk.ButtonsType.OK_CANCEL,
+            text=f"Tear down old {old['level']} array?"
+        )
+        dialog.format_secondary_text(
+            f"Name: {old['name'] or '(none)'}\nUUID: {old['uuid']}\n"
+            f"Members found: {members} (of {old['devices'] or '?'} devices)\n\n"
+            f"Steps: stop the array if assembled, zero the md superblocks of {members} "
+            f"and remove its mdadm.conf entry. Other partitions on {', '.join(old['disks'])} are kept."
+        )
+        response = dialog.run()
+        dialog.destroy()
+        if response != Gtk.ResponseType.OK:
+            return
+
+        widget.set_sensitive(False)
+        self.append_log(f"\n--- Tearing down old array {old['uuid']} ---\n")
+        identities = self.drive_identities(old['disks'])
+        thread = threading.Thread(target=self.execute_teardown_old, args=(old, identities))
+        thread.daemon = True
+        thread.start()
+
+    def execute_teardown_old(self, old, identities):
+        def update_ui(msg, finish=False):
+            GLib.idle_add(self.append_log, msg)
+            if finish:
+                GLib.idle_add(self.refresh_arrays)
+
+        if self.service.teardown_old_array(old, identities):
+            update_ui(f"SUCCESS: Old array {old['uuid']} removed.\n", True)
+        else:
+            update_ui(f"ERROR: Old array {old['uuid']} was not fully removed.\n", True)
+
+    def drive_identities(self, names):
+        # Serial/WWN/size as shown when the user made the selection, re-checked before anything is wiped
+        return {name: backend.drive_identity(self.drive_info[name]) for name in names if name in self.drive_info}
+
+    def check_hot_add(self):
+        # Polled by a GLib timer; returning True keeps it running
+        # Nothing to offer while mdadm, which does the adding, is missing or in an operator session
+        if self.hot_add_offer is not None or not self.capabilities['mdadm']['available'] or not self.service.allowed("hot-add"):
+            return True
+        # lsblk runs off the UI thread and a slow scan is never stacked, like refresh_health
+        if self.hot_add_scan is None or self.hot_add_scan.done():
+            self.hot_add_scan = background.Operation(self.find_hot_add_candidate, set(self.known_drives),
+                                                     on_done=lambda operation: GLib.idle_add(self.offer_hot_add, operation)).start()
+        return True
+
+    def find_hot_add_candidate(self, known):
+        # Runs on the scan's thread: the first degraded array and a newly attached blank disk for it, or None
+        for arr in backend.get_active_arrays():
+            if not backend.needs_replacement(arr['name']):
+                continue
+            for drive in backend.find_replacement_candidates(arr['name'], known):
+                return arr['name'], drive
+        return None
+
+    def offer_hot_add(self, operation):
+        if operation.error is not None:
+            self.append_log(f"ERROR: Looking for replacement disks failed: {operation.error}\n")
+        if not operation.result or self.hot_add_offer is not None or operation.result[1]['name'] in self.known_drives:
+            return False
+        array_name, drive = operation.result
+        self.known_drives.add(drive['name'])
+        self.append_log(f"\n{array_name} is degraded and blank disk {drive['name']} ({units.format_size(drive['size_bytes'])}) was attached.\n")
+        identity = backend.drive_identity(drive)
+        hot_add_policy = config.get_setting('hot_add_policy')
+        if hot_add_policy == "auto":
+            self.start_hot_add(array_name, drive['name'], identity)
+        elif hot_add_policy == "prompt":
+            self.hot_add_offer = (array_name, drive['name'], identity)
+            self.lbl_hot_add.set_text(f"{array_name} is degraded. Add the newly attached {drive['name']} ({units.format_size(drive['size_bytes'])}) as a replacement and start rebuilding?")
+            self.hot_add_bar.show()
+        return False
+
+    def check_array_events(self):
+        # Polled by a GLib timer like check_hot_add; hook commands run off the UI thread
+        for event in self.event_detector.poll([arr['name'] for arr in backend.get_active_arrays()]):
+            self.post_message('warn' if event['event'] == "degraded" else 'info', hooks.describe(event))
+            thread = threading.Thread(target=self.dispatch_array_event, args=(event,))
+            thread.daemon = True
+            thread.start()
+        return True
+
+    def dispatch_array_event(self, event):
+        GLib.idle_add(self.append_log, hooks.run_hooks(event)[1])
+        if self.desktop_notifications:
+            GLib.idle_add(self.append_log, notifications.dispatch(event, ("desktop",))[1])
+
+    def refresh_health(self):
+        # Polled by a GLib timer; mdadm --detail runs off the UI thread and a slow poll is never stacked
+        if not self.health_refreshing:
+            self.health_refreshing = True
+            thread = threading.Thread(target=lambda: GLib.idle_add(self.render_health, self.service.status()))
+            thread.daemon = True
+            thread.start()
+        return True
+
+    def render_health(self, statuses):
+        self.health_refreshing = False
+        colors = {'healthy': '#27ae60', 'resyncing': '#2980b9', 'rebuilding': '#e67e22', 'degraded': '#c0392b',
+                  'inactive': '#c0392b', 'missing': '#c0392b'}
+        rows = []
+        for arr in statuses:
+            text = f"{arr['name']} {arr['type'] or ''}: {arr['health']}"
+            if arr['raid_disks'] is not None and arr['working'] < arr['raid_disks']:
+                text += f", {arr['working']}/{arr['raid_disks']} members"
+            if arr['sync']:
+                eta = arr['sync']['eta_seconds']
+                text += f", {arr['sync']['action']} {arr['sync']['percent']:.1f}%" + (f", {units.format_duration(eta)} left" if eta is not None else "")
+            if arr['failed']:
+                text += f", failed: {', '.join(arr['failed'])}"
+            rows.append(f"<span foreground='{colors[arr['health']]}'>●</span> {GLib.markup_escape_text(text)}")
+        self.lbl_health.set_markup("\n".join(rows) if rows else "<i>No md arrays running.</i>")
+        self.update_sync_rows(statuses)
+        return False
+
+    def on_hot_add_response(self, bar, response):
+        array_name, device, identity = self.hot_add_offer
+        self.hot_add_offer = None
+        bar.hide()
+        if response == Gtk.ResponseType.OK:
+            self.start_hot_add(array_name, device, identity)
+
+    def start_hot_add(self, array_name, device, identity):
+        self.append_log(f"--- Adding {device} to {array_name} ---\n")
+        thread = threading.Thread(target=self.execute_hot_add, args=(array_name, device, identity))
+        thread.daemon = True
+        thread.start()
+
+    def execute_hot_add(self, array_name, device, identity):
+        def update_ui(msg, finish=False):
+            GLib.idle_add(self.append_log, msg)
+            if finish:
+                GLib.idle_add(self.refresh_arrays)
+
+        success, job = self.service.add_replacement(array_name, device, identity)
+        if success and config.get_setting('verify_rebuilds'):
+            # Not done until the rebuilt array reads back cleanly and the new disk passes a self-test
+            update_ui(f"{device} added to {array_name}, rebuild running as job {job['id']}. It will be verified once done.\n", True)
+            verified, summary = self.service.verify_rebuild(array_name, device)
+            update_ui(f"{'SUCCESS' if verified else 'ERROR'}: {summary}\n", True)
+        elif success:
+            update_ui(f"SUCCESS: {device} added to {array_name}, rebuild running as job {job['id']}.\n", True)
+        else:
+            update_ui(f"ERROR: Failed to add {device} to {array_name}.\n", True)
+
+    def refresh_write_mostly_choices(self):
+        # Only currently selected drives can be flagged as write-mostly
+        current = self.combo_write_mostly.get_active_text()
+        selected = self.selected_members()
+        self.combo_write_mostly.remove_all()
+        self.combo_write_mostly.append_text("None")
+        for name in selected:
+            self.combo_write_mostly.append_text(name)
+        self.combo_write_mostly.set_active(selected.index(current) + 1 if current in selected else 0)
 
     def refresh_arrays(self, widget=None):
         self.combo_arrays.remove_all()
-        active_arrays = backend.get_active_arrays()
+        active_arrays = list(backend.parse_mdstat().values())
         self.refresh_drives() # Always refresh the available physical disks too
         
         if not active_arrays:
             self.combo_arrays.append_text("No active arrays found")
             self.combo_arrays.set_sensitive(False)
+            self.btn_scrub.set_sensitive(False)
             self.btn_delete.set_sensitive(False)
             self.btn_format.set_sensitive(False)
+            self.btn_fstab_add.set_sensitive(False)
+            self.btn_fstab_remove.set_sensitive(False)
         else:
             for arr in active_arrays:
-                self.combo_arrays.append_text(f"{arr['name']} ({arr['type']} - {arr['status']})")
+                state = arr['status']
+                if arr['raid_disks'] is not None and arr['working'] < arr['raid_disks']:
+                    state += f", {arr['working']}/{arr['raid_disks']} members"
+                if arr['sync']:
+                    state += f", {arr['sync']['action']} {arr['sync']['percent']:.1f}%"
+                self.combo_arrays.append_text(f"{arr['name']} ({arr['type'] or 'no level'} - {state})")
             self.combo_arrays.set_sensitive(True)
-            self.btn_delete.set_sensitive(True)
-            self.btn_format.set_sensitive(True)
+            self.gate_control(self.btn_scrub, ["mdadm"])
+            self.gate_control(self.btn_delete, ["mdadm"], "destroy")
+            self.gate_control(self.btn_fstab_add, [], "fstab")
+            self.gate_control(self.btn_fstab_remove, [], "fstab")
         self.combo_arrays.set_active(0)
+        self.update_format_button()
 
-    def on_create_clicked(self, widget):
-        selected_drives = [name for name, cb in self.drive_checkboxes.items() if cb.get_active()]
+    def update_array_members(self, widget=None):
+        arr_text = self.combo_arrays.get_active_text()
+        if not arr_text or "No active arrays" in arr_text:
+            self.lbl_members.set_markup("")
+            return
+
+        array_name = arr_text.split(" ")[0] # Extract '/dev/md0'
+        rows = []
+        for m in backend.get_array_members(array_name):
+            slot = f"Slot {m['slot']}" if m['slot'] is not None else m['role'].capitalize()
+            serial = f" S/N {m['serial']}" if m['serial'] else ""
+            rows.append(f"{slot}: {m['device'] or '(empty)'}{serial} — {m['state']}")
+        self.lbl_members.set_markup(f"<small><tt>{GLib.markup_escape_text(chr(10).join(rows))}</tt></small>")
+
+    def create_request(self):
+        """
+        Validates the drives and options selected for a new array. Problems are logged.
+        Returns: {'drives': [...], 'level': '1', 'chunk': 'Default', 'ssd': False, 'advanced': {...}, 'identities': {...},
+                  'warnings': [...], 'has_data': ['/dev/sdb'], 'array': None, 'state': None} or None
+        """
+        selected_drives = self.selected_members()
+        spares = self.selected_spares()
         
         if len(selected_drives) == 0:
             self.append_log("ERROR: No drives selected for creation.\n")
-            return
+            return None
             
         raid_level = self.combo_raid.get_active_text()
-        chunk_size = self.combo_chunk.get_active_text()
-        ssd_mode = self.chk_ssd.get_active()
-        
+        write_mostly = self.combo_write_mostly.get_active_text()
+        advanced = {
+            'write_mostly': [write_mostly] if write_mostly and write_mostly != "None" else [],
+            'write_behind': self.spin_write_behind.get_value_as_int(),
+            'auto_read_only': self.chk_auto_ro.get_active(),
+            'preserve_partitions': self.chk_preserve.get_active(),
+            'spares': spares,
+            'metadata': self.option_value(self.combo_metadata),
+            'bitmap': self.option_value(self.combo_bitmap),
+            'layout': self.option_value(self.combo_layout),
+            'name': self.entry_md_name.get_text().strip() or None
+        }
+
+        members = [self.drive_info[name] for name in selected_drives]
+        errors = [message for _, message in backend.check_md_options(raid_level, advanced['metadata'], advanced['bitmap'],
+                                                                       advanced['layout'], advanced['name'])]
+        if advanced['preserve_partitions'] and not errors:
+            # Sized by the free space each new RAID partition gets
+            members, errors = partition.free_space_drives(members)
+        spare_drives = [self.drive_info[name] for name in spares]
+        report = backend.validate_array(raid_level, members, spares=spare_drives, metadata=advanced['metadata']) if not errors else {'errors': errors, 'warnings': []}
+        if not report['errors']:
+            # Arrays created here are formatted and mounted from this window later, they count as holding data
+            verdict = policy.evaluate(raid_level, members, spare_drives, self.entry_fstab.get_text().strip() or True)
+            report['errors'] += [v['message'] for v in verdict['errors']]
+            report['warnings'] += verdict['warnings']
+        if report['errors']:
+            for error in report['errors']:
+                self.append_log(f"ERROR: {error}\n")
+            return None
+
+        has_data = [name for name in ([] if advanced['preserve_partitions'] else selected_drives) + spares if self.drive_info[name].get('has_data')]
+        return {'drives': selected_drives, 'level': raid_level, 'chunk': self.combo_chunk.get_active_text(), 'ssd': self.chk_ssd.get_active(),
+                'advanced': advanced, 'identities': self.drive_identities(selected_drives + spares), 'warnings': report['warnings'],
+                'has_data': has_data, 'array': None, 'state': None}
+
+    def confirm_create(self, text, requests):
+        # Warnings never block creation, but they are always shown before anything is touched, as a checklist
+        # that has to be confirmed anyway
+        warnings = [w for request in requests for w in request['warnings']]
+        has_data = [name for request in requests for name in request['has_data']]
+        if not warnings and not config.should_confirm(destructive=bool(has_data)):
+            return True
+        dialog = Gtk.Dialog(title="Before Creating", transient_for=self, flags=0)
+        dialog.add_button("Cancel", Gtk.ResponseType.CANCEL)
+        dialog.add_button("Create Anyway" if warnings else "Create", Gtk.ResponseType.OK)
+        dialog.set_default_response(Gtk.ResponseType.CANCEL if warnings or has_data else Gtk.ResponseType.OK)
+        dialog.set_default_size(520, -1)
+        box = dialog.get_content_area()
+        box.set_spacing(10)
+        box.set_border_width(10)
+        lbl_text = Gtk.Label(xalign=0)
+        lbl_text.set_markup(f"<b>{GLib.markup_escape_text(text)}</b>")
+        lbl_text.set_line_wrap(True)
+        box.pack_start(lbl_text, False, False, 0)
+
+        icons = {'ok': '✔', 'warn': '⚠'}
+        colors = {'ok': '#27ae60', 'warn': '#e67e22'}
+        rows = []
+        for item in backend.validation_checklist({'errors': [], 'warnings': warnings}):
+            icon = f"<span foreground='{colors[item['status']]}'>{icons[item['status']]}</span>"
+            rows.append(f"{icon} <b>{GLib.markup_escape_text(item['title'])}</b>")
+            rows += [f"      <small>{GLib.markup_escape_text(message)}</small>" for message in item['messages']]
+        lbl_checks = Gtk.Label(xalign=0)
+        lbl_checks.set_markup("\n".join(rows))
+        lbl_checks.set_line_wrap(True)
+        box.pack_start(lbl_checks, False, False, 0)
+
+        details = []
+        if has_data:
+            details.append(f"{', '.join(has_data)} still hold partitions or filesystems that will be destroyed.")
+            foreign = [backend.describe_foreign_data(e) for entries in self.service.foreign_data(has_data).values() for e in entries]
+            if foreign:
+                details.append("Data of another operating system, copy it off first if it is still needed:\n" + "\n".join(foreign))
+        if details:
+            lbl_data = Gtk.Label(xalign=0)
+            lbl_data.set_markup(f"<span foreground='#c0392b'>{GLib.markup_escape_text(chr(10).join(details))}</span>")
+            lbl_data.set_line_wrap(True)
+            box.pack_start(lbl_data, False, False, 0)
+        dialog.show_all()
+        response = dialog.run()
+        dialog.destroy()
+        return response == Gtk.ResponseType.OK
+
+    def ask_stale_cleanup(self):
+        # Leftovers of failed runs make mdadm report the new array as "already in use"
+        cleanup = self.service.stale_md_devices()
+        if cleanup:
+            dialog = Gtk.MessageDialog(transient_for=self, flags=0, message_type=Gtk.MessageType.QUESTION,
+                                       buttons=Gtk.ButtonsType.YES_NO, text="Clean up leftovers of earlier md runs first?")
+            dialog.format_secondary_text("\n".join(f"{e['path']}: {e['kind']}, {e['detail']}" for e in cleanup)
+                                         + "\n\nInactive arrays are only stopped, their disks are not wiped.")
+            if dialog.run() != Gtk.ResponseType.YES:
+                cleanup = []
+            dialog.destroy()
+        return cleanup
+
+    def on_create_clicked(self, widget):
+        request = self.create_request()
+        if request is None:
+            return
+        spares = request['advanced']['spares']
+        if not self.confirm_create(f"Create RAID {request['level']} from {len(request['drives'])} drives"
+                                   + (f" with {len(spares)} spare(s)?" if spares else "?"), [request]):
+            return
+        cleanup = self.ask_stale_cleanup()
+
         self.btn_create.set_sensitive(False)
-        self.append_log(f"\n--- Creating Array ---\nTasks: RAID {raid_level} -> {len(selected_drives)} devices\n")
+        self.render_planned()
+        self.append_log(f"\n--- Creating Array ---\nTasks: RAID {request['level']} -> {len(request['drives'])} devices"
+                        + (f" + {len(spares)} spare(s)" if spares else "") + "\n")
         
-        thread = threading.Thread(
-            target=self.execute_create,
-            args=(selected_drives, raid_level, chunk_size, ssd_mode)
-        )
+        self.start_operation(self.execute_create, request, cleanup, pausable=True)
+
+    def make_operation_buttons(self):
+        hbox = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=6)
+        btn_cancel = Gtk.Button(label="Cancel")
+        btn_cancel.set_tooltip_text("Stop the running create or format at the command it is running (mdadm, mkfs, ...) and roll back its completed steps")
+        btn_cancel.set_sensitive(False)
+        btn_cancel.connect("clicked", self.on_cancel_clicked)
+        self.cancel_buttons.append(btn_cancel)
+        hbox.pack_start(btn_cancel, False, False, 0)
+        btn_pause = Gtk.Button(label="Pause")
+        btn_pause.set_tooltip_text("Hold the running create before its next step and freeze the resync of the arrays it created")
+        btn_pause.set_sensitive(False)
+        btn_pause.connect("clicked", self.on_pause_clicked)
+        self.pause_buttons.append(btn_pause)
+        hbox.pack_start(btn_pause, False, False, 0)
+        return hbox
+
+    def start_operation(self, target, *args, pausable=False):
+        """
+        Runs a create or format as a background.Operation the Cancel buttons can stop. A pausable one holds at
+        the points where it calls self.operation.handle.wait_while_paused().
+        """
+        self.operation = background.Operation(target, *args, on_done=lambda operation: GLib.idle_add(self.end_operation, operation))
+        self.operation.start()
+        for button in self.cancel_buttons:
+            button.set_sensitive(True)
+        for button in self.pause_buttons:
+            button.set_label("Pause")
+            button.set_sensitive(pausable)
+
+    def end_operation(self, operation):
+        if operation is self.operation:
+            self.operation = None
+            for button in self.cancel_buttons + self.pause_buttons:
+                button.set_sensitive(False)
+            for button in self.pause_buttons:
+                button.set_label("Pause")
+        return False
+
+    def on_cancel_clicked(self, widget):
+        if self.operation is None:
+            return
+        self.operation.cancel()
+        for button in self.cancel_buttons + self.pause_buttons:
+            button.set_sensitive(False)
+        self.append_log("Cancelling: the running step is stopped and the completed ones rolled back...\n")
+
+    def on_pause_clicked(self, widget):
+        if self.operation is None:
+            return
+        if self.operation.paused:
+            success, out = self.operation.resume()
+            self.append_log(out + "Resumed.\n")
+        else:
+            success, out = self.operation.pause()
+            self.append_log(out + "Pausing: the running step finishes, then the create holds until resumed.\n")
+        if not success:
+            self.append_log("ERROR: Not every resync could be frozen or resumed, see above.\n")
+        for button in self.pause_buttons:
+            button.set_label("Resume" if self.operation.paused else "Pause")
+
+    def planned_arrays(self):
+        # Devices taken by planned arrays, new arrays and plans skip them
+        return [request['array'] for request in self.planned]
+
+    def on_add_plan_clicked(self, widget):
+        request = self.create_request()
+        if request is None:
+            return
+        request['array'] = arraynames.next_array_name(request['advanced']['name'], self.planned_arrays())
+        reason = arraynames.collision(request['array'], planned=self.planned_arrays())
+        if reason:
+            self.append_log(f"ERROR: {request['array']} is {reason}, pick another array name.\n")
+            return
+        request['state'] = "planned"
+        self.planned.append(request)
+        self.append_log(f"Planned {request['array']}: RAID {request['level']} on {', '.join(request['drives'])}"
+                        + (f" + spare {', '.join(request['advanced']['spares'])}" if request['advanced']['spares'] else "") + ".\n")
+        # Frees the selection for the next array; the planned drives stay reserved until it is created or removed
+        for name in request['drives'] + request['advanced']['spares']:
+            self.drive_checkboxes[name].set_active(False)
+        self.entry_md_name.set_text("")
+        self.render_planned()
+
+    def render_planned(self):
+        for child in self.planned_box.get_children():
+            self.planned_box.remove(child)
+        if self.planned:
+            self.planned_box.pack_start(Gtk.Label(label="<b>Planned arrays</b>", use_markup=True, xalign=0), False, False, 0)
+        marks = {'planned': "○ planned", 'creating': "… creating", 'created': "<span foreground='#27ae60'>✔ created</span>",
+                 'failed': "<span foreground='#c0392b'>✖ failed</span>"}
+        reserved = {}
+        busy = any(r['state'] == "creating" for r in self.planned)
+        for request in self.planned:
+            spares = request['advanced']['spares']
+            hbox = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=8)
+            text = GLib.markup_escape_text(f"{request['array']}: RAID {request['level']} on {', '.join(request['drives'])}"
+                                           + (f" + spare {', '.join(spares)}" if spares else ""))
+            hbox.pack_start(Gtk.Label(label=f"{text}  {marks[request['state']]}", use_markup=True, xalign=0), True, True, 0)
+            if request['state'] in ("planned", "failed"):
+                btn_remove = Gtk.Button(label="Remove")
+                btn_remove.connect("clicked", self.on_remove_plan_clicked, request)
+                btn_remove.set_sensitive(not busy)
+                hbox.pack_start(btn_remove, False, False, 0)
+            self.planned_box.pack_start(hbox, False, False, 0)
+            if request['state'] in ("planned", "creating", "failed"):
+                reserved.update({name: request['array'] for name in request['drives'] + spares})
+        self.planned_box.show_all()
+
+        for name, cb in self.drive_checkboxes.items():
+            cb.set_sensitive(name not in reserved)
+            cb.set_tooltip_text(f"Reserved for the planned {reserved[name]}" if name in reserved else None)
+        pending = [r for r in self.planned if r['state'] in ("planned", "failed")]
+        self.btn_create_planned.set_label(f"Create {len(pending)} Planned Array" + ("s" if len(pending) != 1 else ""))
+        self.btn_create_planned.set_visible(bool(pending))
+        self.btn_create_planned.set_sensitive(bool(pending) and self.btn_create.get_sensitive())
+        return False
+
+    def on_remove_plan_clicked(self, widget, request):
+        self.planned.remove(request)
+        self.append_log(f"Removed the planned {request['array']}.\n")
+        self.render_planned()
+
+    def on_create_planned_clicked(self, widget):
+        pending = [r for r in self.planned if r['state'] in ("planned", "failed")]
+        # Something may have taken a planned device since (another tool, a hot-plugged array)
+        for request in pending:
+            reason = arraynames.collision(request['array'], planned=[r['array'] for r in self.planned if r is not request])
+            if reason:
+                self.append_log(f"ERROR: {request['array']} is {reason} by now, remove that plan and add it again.\n")
+                return
+        if not self.confirm_create(f"Create {len(pending)} planned arrays?\n" + "\n".join(
+                f"{r['array']}: RAID {r['level']} on {', '.join(r['drives'])}" for r in pending), pending):
+            return
+        cleanup = self.ask_stale_cleanup()
+
+        self.btn_create.set_sensitive(False)
+        self.render_planned()
+        self.append_log(f"\n--- Creating {len(pending)} Planned Arrays ---\n")
+        self.start_operation(self.execute_planned, pending, cleanup, pausable=True)
+
+    def set_planned_state(self, request, state):
+        request['state'] = state
+        return self.render_planned()
+
+    def execute_planned(self, requests, cleanup=()):
+        # One after the other: the arrays may share a controller, and a failure leaves the rest planned
+        handle = self.operation.handle
+        for i, request in enumerate(requests):
+            # Pause holds here, with the resync of the arrays created so far frozen
+            if not handle.wait_while_paused():
+                GLib.idle_add(self.append_log, f"Cancelled before {request['array']}, the remaining arrays stay planned.\n")
+                break
+            GLib.idle_add(self.set_planned_state, request, "creating")
+            GLib.idle_add(self.append_log, f"\n--- Creating {request['array']} ({i + 1}/{len(requests)}) ---\n")
+            success = self.execute_create(request, cleanup if i == 0 else (), finish=False)
+            GLib.idle_add(self.set_planned_state, request, "created" if success else "failed")
+            if not success:
+                GLib.idle_add(self.append_log, f"ERROR: Stopped after {request['array']} failed, the remaining arrays stay planned.\n")
+                break
+        GLib.idle_add(self.btn_create.set_sensitive, True)
+        GLib.idle_add(self.refresh_arrays)
+
+    def on_migrate_clicked(self, widget):
+        selected_drives = self.selected_members()
+        raid_level = self.combo_raid.get_active_text()
+        report = backend.validate_array(raid_level, [self.drive_info[name] for name in selected_drives])
+        if not selected_drives or report['errors']:
+            self.append_log("ERROR: Select the drives and RAID level for the new array first.\n")
+            for error in report['errors']:
+                self.append_log(f"ERROR: {error}\n")
+            return
+
+        sources = [(f['device'], vol['name']) for vol in self.hw_volumes for f in vol['filesystems']]
+        if not sources:
+            self.append_log("ERROR: No filesystem found on the hardware RAID volumes.\n")
+            return
+
+        dialog = Gtk.Dialog(title="Migrate from Hardware RAID", transient_for=self, flags=0)
+        dialog.add_button("Cancel", Gtk.ResponseType.CANCEL)
+        dialog.add_button("Start Migration", Gtk.ResponseType.OK)
+        grid = Gtk.Grid(column_spacing=10, row_spacing=8)
+        grid.set_border_width(10)
+        dialog.get_content_area().pack_start(grid, True, True, 0)
+
+        grid.attach(Gtk.Label(label="Copy files from:", xalign=0), 0, 0, 1, 1)
+        combo_source = Gtk.ComboBoxText()
+        for device, _ in sources:
+            combo_source.append_text(device)
+        combo_source.set_active(0)
+        grid.attach(combo_source, 1, 0, 1, 1)
+
+        grid.attach(Gtk.Label(label="New filesystem:", xalign=0), 0, 1, 1, 1)
+        combo_fs = Gtk.ComboBoxText()
+        for fs in ["ext4", "btrfs", "xfs", "f2fs"]:
+            combo_fs.append_text(fs)
+        combo_fs.set_active(0)
+        grid.attach(combo_fs, 1, 1, 1, 1)
+
+        chk_repurpose = Gtk.CheckButton(label="Wipe the hardware RAID volume after a successful copy")
+        grid.attach(chk_repurpose, 0, 2, 2, 1)
+        grid.attach(self.make_hint(f"Target: new RAID {raid_level} on {', '.join(selected_drives)}, which will be wiped. "
+                                   "The source is mounted read-only and never modified during the copy."), 0, 3, 2, 1)
+
+        dialog.show_all()
+        response = dialog.run()
+        source_fs = combo_source.get_active_text()
+        fs_type = combo_fs.get_active_text()
+        old_volume = dict(sources)[source_fs] if chk_repurpose.get_active() else None
+        dialog.destroy()
+        if response != Gtk.ResponseType.OK:
+            return
+
+        self.btn_create.set_sensitive(False)
+        self.btn_migrate.set_sensitive(False)
+        self.append_log(f"\n--- Migrating {source_fs} to a new RAID {raid_level} ---\n")
+        thread = threading.Thread(target=self.execute_migrate, args=(source_fs, raid_level, selected_drives, fs_type, old_volume, self.drive_identities(selected_drives)))
         thread.daemon = True
         thread.start()
 
-    def execute_create(self, drives, raid_level, chunk_size, ssd_mode):
+    def execute_migrate(self, source_fs, raid_level, drives, fs_type, old_volume, identities):
         def update_ui(msg, finish=False):
             GLib.idle_add(self.append_log, msg)
             if finish:
                 GLib.idle_add(self.btn_create.set_sensitive, True)
+                GLib.idle_add(self.btn_migrate.set_sensitive, True)
                 GLib.idle_add(self.refresh_arrays)
-                
-        # Typically the first array defaults to /dev/md0
-        array_name = "/dev/md0"
-        
-        update_ui("-> Generating Array via mdadm...\n")
-        success, out = backend.create_raid(raid_level, drives, array_name, chunk_size, ssd_mode)
-        update_ui(out)
-        
+
+        array_name = arraynames.next_array_name()
+        if self.service.migrate_hardware_raid(source_fs, raid_level, drives, fs_type, array_name, old_volume, identities):
+            update_ui(f"\nSUCCESS: {source_fs} migrated to {array_name}.\n", True)
+        else:
+            update_ui(f"\nERROR: Migration of {source_fs} failed.\n", True)
+
+    def execute_create(self, request, cleanup=(), finish=True):
+        """
+        Creates the array of a create_request(), as its planned device if it has one, through executor steps: Cancel
+        stops the running step and rolls back the completed ones. finish re-enables the create button and refreshes
+        the lists afterwards (execute_planned() does that once at the end).
+        Returns True when the array was created.
+        """
+        def update_ui(msg, last=False):
+            GLib.idle_add(self.append_log, msg)
+            if last and finish:
+                GLib.idle_add(self.btn_create.set_sensitive, True)
+                GLib.idle_add(self.refresh_arrays)
+
+        handle = self.operation.handle
+        # The lowest free <raid_device_prefix>N (see arraynames.py) not planned for another array, /dev/md0 on a clean system
+        array_name = request['array'] or arraynames.next_array_name(request['advanced']['name'], self.planned_arrays())
+        steps, sync = self.service.create_array_steps(request['level'], request['drives'], array_name, request['chunk'], request['ssd'],
+                                                      request['identities'], cleanup, handle=handle, **request['advanced'])
+        if steps is None:
+            update_ui(f"\nERROR: Creating {array_name} was refused.\n", True)
+            return False
+        GLib.idle_add(self.show_steps, [step.name for step in steps])
+        update_ui(f"-> Generating {array_name} via mdadm...\n")
+        with script.recording() as recorded:
+            success = self.service.run_steps("create", array_name, steps, lambda event: GLib.idle_add(self.on_step_event, event), handle)
+
+        if success and backend.DRY_RUN:
+            GLib.idle_add(self.show_script_dialog, f"What creating {array_name} would do", recorded)
+
+        if success and sync['job']:
+            # The sync itself continues in the background with its own step row; Pause freezes it from here on.
+            # Lets 'cli.py jobs' follow the initial sync started by the GUI
+            GLib.idle_add(self.follow_sync, array_name)
+            update_ui(f"Initial sync running in the background as job {sync['job']['id']}.\n")
+
         if success:
-            update_ui("\nSUCCESS: Array creation dispatched.\n", True)
+            update_ui(f"\nSUCCESS: Creation of {array_name} dispatched.\n", True)
+        elif handle.cancelled:
+            update_ui(f"\nCancelled the creation of {array_name}, see the log for the rollback.\n", True)
         else:
-            update_ui("\nERROR: RAID creation failed.\n", True)
+            update_ui(f"\nERROR: Creating {array_name} failed, see the log for the rollback.\n", True)
+        return success
 
-    def on_delete_clicked(self, widget):
+    def on_scrub_clicked(self, widget):
         arr_text = self.combo_arrays.get_active_text()
         if not arr_text or "No active arrays" in arr_text:
             return
-            
-        array_name = arr_text.split(" ")[0] # Extract '/dev/md0'
-        
+        array_name = arr_text.split(" ")[0]
+
+        self.btn_scrub.set_sensitive(False)
+        self.append_log(f"\n--- Scrubbing {array_name} ---\n")
+        thread = threading.Thread(target=self.execute_scrub, args=(array_name,))
+        thread.daemon = True
+        thread.start()
+
+    def execute_scrub(self, array_name):
+        GLib.idle_add(self.show_scrub_progress, {'action': "check", 'percent': 0.0, 'mismatches': 0})
+        success, mismatches, errors = self.service.scrub(array_name, on_progress=lambda progress: GLib.idle_add(self.show_scrub_progress, progress),
+                                                         record=True)
+        GLib.idle_add(self.show_scrub_progress, None)
+        GLib.idle_add(self.btn_scrub.set_sensitive, True)
+        if not success:
+            GLib.idle_add(self.append_log, f"ERROR: The check of {array_name} did not complete.\n")
+            return
+        read_errors = sum(errors.values())
+        GLib.idle_add(self.append_log, f"{array_name}: {mismatches} mismatched sectors, {read_errors} read errors corrected.\n")
+        if mismatches or read_errors:
+            GLib.idle_add(self.offer_remediation, array_name, mismatches, errors)
+
+    def show_scrub_progress(self, progress):
+        # None hides the bar once the pass ended
+        self.scrub_progress.set_visible(progress is not None)
+        if progress:
+            self.scrub_progress.set_fraction(progress['percent'] / 100.0)
+            self.scrub_progress.set_text(f"{progress['action']} {progress['percent']:.1f}%, {progress['mismatches']} mismatched sectors"
+                                         + (f", finish in {progress['finish']}" if progress.get('finish') else ""))
+        return False
+
+    def on_scrub_schedule_clicked(self, widget):
+        arr_text = self.combo_arrays.get_active_text()
+        if not arr_text or "No active arrays" in arr_text:
+            return
+        array_name = arr_text.split(" ")[0]
+        when = self.combo_scrub_schedule.get_active_text()
+        # systemctl daemon-reload takes a moment
+        threading.Thread(target=self.service.schedule_scrub, args=(array_name, when), daemon=True).start()
+
+    def offer_remediation(self, array_name, mismatches, errors):
         dialog = Gtk.MessageDialog(
             transient_for=self,
             flags=0,
             message_type=Gtk.MessageType.WARNING,
             buttons=Gtk.ButtonsType.OK_CANCEL,
-            text=f"Delete {array_name}?"
+            text=f"The scrub of {array_name} found problems"
+        )
+        suspects = ", ".join(f"{dev} ({count})" for dev, count in errors.items() if count) or "none"
+        dialog.format_secondary_text(
+            f"Mismatched sectors: {mismatches}\nMembers with read errors: {suspects}\n\n"
+            "Run the remediation sequence? It re-checks the array, rewrites inconsistent stripes if they persist, "
+            "SMART-tests the suspect members and records a recommendation in the health history."
         )
-        dialog.format_secondary_text("This will stop the RAID array and zero the superblocks, effectively destroying the array geometry and any data spanning across the physical drives.")
         response = dialog.run()
         dialog.destroy()
+        if response != Gtk.ResponseType.OK:
+            return False
+
+        self.btn_scrub.set_sensitive(False)
+        self.append_log(f"\n--- Remediating {array_name} ---\n")
+        thread = threading.Thread(target=self.execute_remediation, args=(array_name,))
+        thread.daemon = True
+        thread.start()
+        return False
+
+    def execute_remediation(self, array_name):
+        self.service.remediate_scrub(array_name)
+        GLib.idle_add(self.btn_scrub.set_sensitive, True)
+
+    def on_delete_clicked(self, widget):
+        arr_text = self.combo_arrays.get_active_text()
+        if not arr_text or "No active arrays" in arr_text:
+            return
+            
+        array_name = arr_text.split(" ")[0] # Extract '/dev/md0'
+        
+        response = Gtk.ResponseType.OK
+        if config.should_confirm(destructive=True):
+            dialog = Gtk.MessageDialog(
+                transient_for=self,
+                flags=0,
+                message_type=Gtk.MessageType.WARNING,
+                buttons=Gtk.ButtonsType.OK_CANCEL,
+                text=f"Delete {array_name}?"
+            )
+            dialog.format_secondary_text("This will stop the RAID array and zero the superblocks, effectively destroying the array geometry and any data spanning across the physical drives.")
+            response = dialog.run()
+            dialog.destroy()
         
         if response == Gtk.ResponseType.OK:
             self.btn_delete.set_sensitive(False)
@@ -253,9 +1840,7 @@ class LiveRaidWindow(Gtk.Window):
             if finish:
                 GLib.idle_add(self.refresh_arrays)
                 
-        success, out = backend.delete_raid(array_name)
-        update_ui(out)
-        if success:
+        if self.service.destroy_array(array_name):
             update_ui(f"SUCCESS: {array_name} stopped and metadata cleared.\n", True)
         else:
             update_ui(f"ERROR: Failed to cleanly destroy {array_name}.\n", True)
@@ -269,29 +1854,139 @@ class LiveRaidWindow(Gtk.Window):
         fs_type = self.combo_fs.get_active_text()
         boot_flag = self.chk_boot.get_active()
         trim_discard = self.chk_trim.get_active()
-        
+        encryption = luks.new_encryption() if self.chk_encrypt.get_active() else None
+
+        mkfs_cmd, errors = self.service.validate_format(array_name, fs_type, trim_discard, encryption=encryption)
+        if errors:
+            dialog = Gtk.MessageDialog(
+                transient_for=self,
+                flags=0,
+                message_type=Gtk.MessageType.ERROR,
+                buttons=Gtk.ButtonsType.OK,
+                text=f"{fs_type} cannot be used on {array_name}"
+            )
+            dialog.format_secondary_text("\n".join(errors))
+            dialog.run()
+            dialog.destroy()
+            return
+
+        # Arrays pre-created outside LiveRAID can be reused, but warn before wiping existing data
+        contents = backend.get_device_contents(array_name)
+        if contents and config.should_confirm(destructive=True):
+            dialog = Gtk.MessageDialog(
+                transient_for=self,
+                flags=0,
+                message_type=Gtk.MessageType.WARNING,
+                buttons=Gtk.ButtonsType.OK_CANCEL,
+                text=f"{array_name} is not empty"
+            )
+            dialog.format_secondary_text("It currently contains: " + ", ".join(contents) + ".\nFormatting will destroy this data.")
+            response = dialog.run()
+            dialog.destroy()
+            if response != Gtk.ResponseType.OK:
+                return
+
+        passphrase = None
+        if encryption:
+            passphrase = self.ask_passphrase(array_name)
+            if passphrase is None:
+                return
+
         self.btn_format.set_sensitive(False)
         self.append_log(f"\n--- Formatting Array {array_name} ---\n")
-        self.append_log(f"Tasks: Create GPT -> Primary Partition -> mkfs.{fs_type}\n")
+        self.append_log(f"Tasks: Create GPT -> Primary Partition -> {'LUKS2 container -> ' if encryption else ''}mkfs.{fs_type}\n")
+        if mkfs_cmd:
+            self.append_log(f"mkfs command: {' '.join(mkfs_cmd)}\n")
         
-        thread = threading.Thread(
-            target=self.execute_format,
-            args=(array_name, fs_type, boot_flag, trim_discard)
-        )
-        thread.daemon = True
-        thread.start()
+        self.start_operation(self.execute_format, array_name, fs_type, boot_flag, trim_discard, encryption, passphrase)
 
-    def execute_format(self, array_name, fs_type, boot_flag, trim_discard):
+    def on_fstab_add_clicked(self, widget):
+        arr_text = self.combo_arrays.get_active_text()
+        if not arr_text or "No active arrays" in arr_text:
+            return
+        mount_point = self.entry_fstab.get_text().strip()
+        if not mount_point:
+            self.append_log("ERROR: Enter the mount point first, e.g. /srv/data.\n")
+            return
+        extra = [option.strip() for option in self.entry_mount_options.get_text().split(",") if option.strip()]
+        self.service.add_to_fstab(arr_text.split(" ")[0], mount_point, ",".join(["defaults"] + extra))
+
+    def on_fstab_remove_clicked(self, widget):
+        arr_text = self.combo_arrays.get_active_text()
+        if arr_text and "No active arrays" not in arr_text:
+            self.service.remove_from_fstab(arr_text.split(" ")[0])
+
+    def on_boot_clicked(self, widget, action):
+        arr_text = self.combo_arrays.get_active_text()
+        if not arr_text or "No active arrays" in arr_text:
+            return
+        array_name = arr_text.split(" ")[0]
+        diff, error = bootloader.diff(action, array_name)
+        if error:
+            self.append_log(f"ERROR: {error}\n")
+            return
+        if not diff:
+            self.append_log(f"The bootloader already {'assembles' if action == 'add-raid' else 'leaves out'} {array_name}.\n")
+            return
+        # The edit is shown before it is made
+        if self.show_diff_dialog(f"Bootloader change for {array_name}", diff, confirm="Write Change"):
+            self.service.update_boot(action, array_name)
+
+    def ask_passphrase(self, array_name):
+        """
+        Asks for the passphrase of a new LUKS container twice. Returns it, or None when cancelled.
+        """
+        dialog = Gtk.Dialog(title="Encryption Passphrase", transient_for=self, flags=0)
+        dialog.add_button("Cancel", Gtk.ResponseType.CANCEL)
+        dialog.add_button("Encrypt", Gtk.ResponseType.OK)
+        grid = Gtk.Grid(column_spacing=10, row_spacing=8)
+        grid.set_border_width(10)
+        dialog.get_content_area().pack_start(grid, True, True, 0)
+
+        entries = []
+        for row, text in enumerate(["Passphrase:", "Repeat:"]):
+            grid.attach(Gtk.Label(label=text, xalign=0), 0, row, 1, 1)
+            entry = Gtk.Entry()
+            entry.set_visibility(False)
+            entry.set_activates_default(True)
+            grid.attach(entry, 1, row, 1, 1)
+            entries.append(entry)
+        lbl_error = Gtk.Label(xalign=0)
+        grid.attach(lbl_error, 0, 2, 2, 1)
+        grid.attach(self.make_hint(f"Needed to unlock {array_name} at every boot. It is not stored anywhere, "
+                                   "without it the data can't be recovered."), 0, 3, 2, 1)
+        dialog.set_default_response(Gtk.ResponseType.OK)
+        dialog.show_all()
+
+        passphrase = None
+        while dialog.run() == Gtk.ResponseType.OK:
+            first, second = (e.get_text() for e in entries)
+            if first != second:
+                lbl_error.set_markup("<span foreground='#c0392b'>The passphrases don't match.</span>")
+            elif len(first) < luks.MIN_PASSPHRASE_LENGTH:
+                lbl_error.set_markup(f"<span foreground='#c0392b'>Use at least {luks.MIN_PASSPHRASE_LENGTH} characters.</span>")
+            else:
+                passphrase = first
+                break
+        dialog.destroy()
+        return passphrase
+
+    def execute_format(self, array_name, fs_type, boot_flag, trim_discard, encryption=None, passphrase=None):
         def update_ui(msg, finish=False):
             GLib.idle_add(self.append_log, msg)
             if finish:
-                GLib.idle_add(self.btn_format.set_sensitive, True)
-                
+                GLib.idle_add(self.update_format_button)
+
+        handle = self.operation.handle
+        steps = self.service.format_array_steps(array_name, fs_type, boot_flag, trim_discard, encryption=encryption, passphrase=passphrase)
+        if steps is None:
+            update_ui("\nERROR: Formatting was refused.\n", True)
+            return
+        GLib.idle_add(self.show_steps, [step.name for step in steps])
         update_ui("-> Partitioning & Formatting via parted/mkfs...\n")
-        success, out = backend.format_device(array_name, fs_type, boot_flag, trim_discard)
-        update_ui(out)
-        
-        if success:
+        if self.service.run_steps("format", array_name, steps, lambda event: GLib.idle_add(self.on_step_event, event), handle):
             update_ui("\nSUCCESS: Partitioning and formatting completed.\n", True)
+        elif handle.cancelled:
+            update_ui("\nCancelled, see the log for the rollback.\n", True)
         else:
-            update_ui("\nERROR: Failed during filesystem generation.\n", True)
+            update_ui("\nERROR: Failed during filesystem generation, see the log for the rollback.\n", True)
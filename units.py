@@ -0,0 +1,45 @@
+import re
+import config
+
+# Set by frontends (cli.py --units) to override size_units from config.toml for this run
+SYSTEM = None
+
+IEC = [("PiB", 1024**5), ("TiB", 1024**4), ("GiB", 1024**3), ("MiB", 1024**2), ("KiB", 1024)]
+SI = [("PB", 1000**5), ("TB", 1000**4), ("GB", 1000**3), ("MB", 1000**2), ("kB", 1000)]
+
+def format_size(num_bytes, system=None, precision=1):
+    """
+    Returns a human readable size, e.g. '931.5 GiB' (iec) or '1.0 TB' (si).
+    The unit system defaults to the --units flag, then size_units in config.toml.
+    """
+    system = system or SYSTEM or config.get_setting('size_units')
+    for suffix, factor in (IEC if system == "iec" else SI):
+        if abs(num_bytes) >= factor:
+            return f"{num_bytes / factor:.{precision}f} {suffix}"
+    return f"{int(num_bytes)} B"
+
+def parse_size(text):
+    """
+    Parses '500G', '1.5TiB', '800GB' or '4096' into bytes. Bare prefixes and *iB are binary, like
+    lsblk and parted print them; two-letter SI units (kB, MB, GB, TB) are decimal.
+    """
+    match = re.fullmatch(r"\s*(\d+(?:\.\d+)?)\s*([kmgtp]?)(i?b?)\s*", str(text), re.IGNORECASE)
+    if not match:
+        raise ValueError(f"Invalid size: {text}")
+    value, prefix, suffix = match.groups()
+    exponent = "kmgtp".index(prefix.lower()) + 1 if prefix else 0
+    base = 1000 if suffix.lower() == "b" else 1024
+    return int(float(value) * base**exponent)
+
+def format_duration(seconds):
+    """
+    Returns a rough duration for estimates, e.g. '45 min', '6 h 20 min' or '2 d 3 h'.
+    """
+    minutes = max(1, int(round(seconds / 60)))
+    if minutes < 60:
+        return f"{minutes} min"
+    hours, minutes = divmod(minutes, 60)
+    if hours < 24:
+        return f"{hours} h {minutes} min" if minutes else f"{hours} h"
+    days, hours = divmod(hours, 24)
+    return f"{days} d {hours} h" if hours else f"{days} d"
@@ -0,0 +1,105 @@
+import os
+import re
+import backend
+import config
+import migrate
+import units
+
+# The optional wipe stage of an apply, before anything is built on the members (and spares):
+#   none        leave the disks as they are
+#   signatures  wipefs -a, erases filesystem, RAID and partition table signatures (seconds)
+#   discard     blkdiscard, has an SSD drop every block (seconds to minutes, SSDs only)
+#   zero        overwrite every block with zeroes (hours on large HDDs)
+#   random      overwrite every block with random data (hours, for disks that held sensitive data)
+# The plan carries it as {'method': 'zero', 'block_size': '4M'}, defaults from wipe_policy and
+# wipe_block_size in config.toml.
+
+METHODS = ["none", "signatures", "discard", "zero", "random"]
+
+# Methods that write every block and report their progress
+OVERWRITE_SOURCES = {'zero': "/dev/zero", 'random': "/dev/urandom"}
+
+def new_wipe(method=None, block_size=None):
+    """
+    Returns: {'method': 'zero', 'block_size': '4M'}
+    """
+    return {'method': method or config.get_setting('wipe_policy'), 'block_size': block_size or config.get_setting('wipe_block_size')}
+
+def check(wipe):
+    """
+    Returns the problems of a wipe setting as messages, empty when it is usable.
+    """
+    errors = []
+    if wipe['method'] not in METHODS:
+        errors.append(f"Unknown wipe method '{wipe['method']}' (use {', '.join(METHODS)})")
+    if wipe['method'] in OVERWRITE_SOURCES:
+        try:
+            size = units.parse_size(wipe['block_size'])
+        except ValueError:
+            size = None
+        if not size or size % 512:
+            errors.append(f"Invalid wipe block size '{wipe['block_size']}', use a multiple of 512 bytes, e.g. 1M or 4M.")
+    return errors
+
+def wipe_command(wipe, device, size_bytes=None):
+    """
+    Returns: ['dd', 'if=/dev/zero', 'of=/dev/sdb', 'bs=4M', ...], or None for method none
+    """
+    method = wipe['method']
+    if method == "signatures":
+        return ["wipefs", "-a", device]
+    if method == "discard":
+        return ["blkdiscard", device]
+    if method in OVERWRITE_SOURCES:
+        # count_bytes stops exactly at the end of the disk instead of failing with "No space left on device"
+        return ["dd", f"if={OVERWRITE_SOURCES[method]}", f"of={device}", f"bs={units.parse_size(wipe['block_size'])}",
+                f"count={size_bytes if size_bytes else '<size of ' + device + '>'}", "iflag=fullblock,count_bytes",
+                "oflag=direct", "conv=fsync", "status=progress"]
+    return None
+
+def wipe_device(wipe, device, on_progress=None, cancelled=None):
+    """
+    Wipes one disk. on_progress receives {'phase': 'zero', 'percent': 45.0, 'speed': '180 MB/s'} during an
+    overwrite pass. Returns: (success, log_str)
+    """
+    method = wipe['method']
+    if method == "none":
+        return True, ""
+    # Nothing mounted or stacked on it (blkdiscard refuses those too, without -f): a wipe is for disks nothing uses
+    mountpoints = backend.get_mountpoints(device)
+    if mountpoints:
+        return False, f"ERROR: {device} is mounted on {', '.join(mountpoints)}, not wiping it.\n"
+    holders = backend.get_holders(os.path.basename(os.path.realpath(device)))
+    if holders:
+        return False, f"ERROR: {device} is in use by {', '.join(holders)}, not wiping it.\n"
+    if method == "discard" and not backend.device_supports_discard(device):
+        return False, f"ERROR: {device} doesn't support discard, wipe it with zero instead.\n"
+    if method not in OVERWRITE_SOURCES:
+        return backend.run_command(wipe_command(wipe, device))
+
+    size = migrate.get_device_bytes(device)
+    if not size and not backend.DRY_RUN:
+        return False, f"ERROR: Could not determine the size of {device}.\n"
+    cmd = wipe_command(wipe, device, size)
+    if backend.DRY_RUN:
+        return backend.would_execute(cmd)
+    state = {'speed': ''}
+
+    def parse_line(line):
+        speed = re.search(r", ([\d.,]+ \S+/s)", line)
+        if speed:
+            state['speed'] = speed.group(1)
+        match = re.match(r"^(\d+) bytes", line.strip())
+        if match and on_progress:
+            on_progress({'phase': method, 'percent': round(100.0 * int(match.group(1)) / size, 1), 'speed': state['speed']})
+        return bool(match or speed)
+
+    success, out = migrate.stream_command(cmd, parse_line, cancelled)
+    if not success:
+        return False, out
+    return True, f"Overwrote {device} ({units.format_size(size)}) with {'zeroes' if method == 'zero' else 'random data'}.\n"
+
+def describe(wipe):
+    if wipe['method'] in OVERWRITE_SOURCES:
+        return f"{wipe['method']} overwrite in {wipe['block_size']} blocks"
+    return wipe['method']